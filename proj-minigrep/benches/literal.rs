@@ -0,0 +1,102 @@
+//
+// `literal.rs` benchmarks the whole-buffer literal search (see `src/literal.rs`) against the
+//   line by line ways of searching: splitting with `lines()` and testing each line with
+//   `str::contains`, as `search_sensitive` first did, and streaming through `search_each_line`.
+//   Streaming through `search_reader` searches chunks of lines as a whole instead.
+//
+// Run with `cargo bench`; it is a plain binary timing each searcher over a generated text. The
+//   whole-buffer search shines on rare matches; when most lines match, the time goes into
+//   building a `Match` (with its spans) per line, which the `lines+contains` baseline skips.
+//
+
+use std::hint::black_box;
+use std::time::{Duration, Instant};
+
+use minigrep::matcher::Matcher;
+use minigrep::{Line, LineOptions, search_each_line, search_reader, search_sensitive};
+
+
+// Size of the generated text, in bytes.
+const TEXT_LEN : usize = 32 << 20;
+// Minimum time spent on each searcher, to average out the noise.
+const MIN_TIME : Duration = Duration::from_secs(2);
+
+
+// The poem of the tests over and over, with a rare line thrown in now and then.
+fn gen_text() -> String {
+    let poem = "\
+I'm nobody! Who are you?
+Are you nobody, too?
+Then there's a pair of us - don't tell!
+They'd banish us, you know.
+
+How dreary to be somebody!
+How public, like a frog
+To tell your name the livelong day
+To an admiring bog!
+";
+    let mut text = String::with_capacity(TEXT_LEN + poem.len());
+    let mut round = 0;
+    while text.len() < TEXT_LEN {
+        text.push_str(poem);
+        round += 1;
+        if round % 1000 == 0 {
+            text.push_str("Sherlock Holmes was here.\n");
+        }
+    }
+    text
+}
+
+// Time `search` over `text`, printing the throughput.
+fn bench<F : FnMut(&str) -> usize>(name : &str, text : &str, mut search : F) -> usize {
+    let start = Instant::now();
+    let mut runs = 0;
+    let mut found = 0;
+    while runs == 0 || start.elapsed() < MIN_TIME {
+        found = black_box(search(black_box(text)));
+        runs += 1;
+    }
+    let per_run = start.elapsed() / runs;
+    let throughput = text.len() as f64 / per_run.as_secs_f64() / (1 << 20) as f64;
+    println!("  {:<14} {:>10.3} ms/run {:>10.1} MiB/s   ({} lines)",
+             name, per_run.as_secs_f64() * 1000.0, throughput, found);
+    found
+}
+
+fn main() {
+    let text = gen_text();
+    println!("Searching {} MiB.", text.len() >> 20);
+
+    for &query in &["Sherlock", "nobody", "y"] {
+        println!("Query {:?}:", query);
+        let matcher = Matcher::new(query, minigrep::MatchMode::Literal, true).unwrap();
+
+        let lines = bench("lines+contains", &text, |text| {
+            text.lines().filter(|line| line.contains(query)).count()
+        });
+        let streamed = bench("streaming", &text, |text| {
+            let mut count = 0;
+            search_each_line(&matcher, text.as_bytes(), LineOptions::default(), |line| {
+                if let Line::Match(_) = line {
+                    count += 1;
+                }
+                Ok(())
+            }).unwrap();
+            count
+        });
+        let chunked = bench("chunks", &text, |text| {
+            let mut count = 0;
+            search_reader(&matcher, text.as_bytes(), LineOptions::default(), |line| {
+                if let Line::Match(_) = line {
+                    count += 1;
+                }
+                Ok(())
+            }).unwrap();
+            count
+        });
+        let whole = bench("whole buffer", &text, |text| search_sensitive(query, text).len());
+
+        assert!(lines == streamed && streamed == chunked && chunked == whole,
+                "searchers disagree");
+    }
+}
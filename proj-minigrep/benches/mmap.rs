@@ -0,0 +1,94 @@
+//
+// `mmap.rs` benchmarks searching a large file through `Searcher::search_path`, which maps it in
+//   memory when built with the `mmap` feature (see `src/mmap.rs`), against reading it through
+//   a `BufReader`, which is what `search_path` does without the feature.
+//
+// Run with `cargo bench --features mmap --bench mmap`, and without the feature to compare. Only
+//   the literal searches map the file, which is then searched in place as a whole; the regex
+//   ones read it either way, line by line.
+//
+
+use std::env;
+use std::fs::{self, File};
+use std::hint::black_box;
+use std::io::{self, BufReader};
+use std::path::Path;
+use std::process;
+use std::time::{Duration, Instant};
+
+use minigrep::{Line, MatchMode, SearchOptions, Searcher};
+
+
+// Size of the generated file, in bytes.
+const FILE_LEN : usize = 64 << 20;
+// Minimum time spent on each way, to average out the noise.
+const MIN_TIME : Duration = Duration::from_secs(2);
+
+
+// Log lines, with a rare one thrown in now and then.
+fn gen_text() -> String {
+    let mut text = String::with_capacity(FILE_LEN + 100);
+    let mut round = 0;
+    while text.len() < FILE_LEN {
+        text.push_str(&format!("2024-05-{:02} 12:{:02}:07 INFO request {} served in {} ms\n",
+                               round % 28 + 1, round % 60, round, round % 997));
+        round += 1;
+        if round % 100_000 == 0 {
+            text.push_str("2024-05-01 12:00:00 ERROR disk full\n");
+        }
+    }
+    text
+}
+
+// Time `search` over the file at `path`, printing the throughput.
+fn bench<F>(name : &str, path : &Path, mut search : F) -> usize
+    where F : FnMut(&Path) -> usize
+{
+    let start = Instant::now();
+    let mut runs = 0;
+    let mut found = 0;
+    while runs == 0 || start.elapsed() < MIN_TIME {
+        found = black_box(search(black_box(path)));
+        runs += 1;
+    }
+    let per_run = start.elapsed() / runs;
+    let throughput = FILE_LEN as f64 / per_run.as_secs_f64() / (1 << 20) as f64;
+    println!("  {:<14} {:>10.3} ms/run {:>10.1} MiB/s   ({} lines)",
+             name, per_run.as_secs_f64() * 1000.0, throughput, found);
+    found
+}
+
+// Count the selected lines.
+fn count(searcher : &Searcher, path : &Path, read : bool) -> usize {
+    let mut count = 0;
+    let mut sink = |_ : &Path, line : Line| {
+        if let Line::Match(_) = line {
+            count += 1;
+        }
+        Ok(())
+    };
+    if read {
+        let reader = BufReader::new(File::open(path).unwrap());
+        searcher.search_reader(path, reader, &mut sink).unwrap();
+    } else {
+        searcher.search_path(path, &mut sink).unwrap();
+    }
+    count
+}
+
+fn main() -> io::Result<()> {
+    let path = env::temp_dir().join(format!("minigrep-bench-mmap-{}.log", process::id()));
+    fs::write(&path, gen_text())?;
+    println!("Searching {} MiB, {}.", FILE_LEN >> 20,
+             if cfg!(feature = "mmap") { "mapped" } else { "read (without the mmap feature)" });
+
+    for &(pattern, mode) in &[("ERROR", MatchMode::Literal), ("served", MatchMode::Literal),
+                              (r"in \d{3} ms", MatchMode::Regex)] {
+        println!("Pattern {:?}:", pattern);
+        let searcher = SearchOptions::new().pattern(pattern).mode(mode).build().unwrap();
+        let read = bench("buffered", &path, |path| count(&searcher, path, true));
+        let path_searched = bench("search_path", &path, |path| count(&searcher, path, false));
+        assert_eq!(read, path_searched, "searches disagree");
+    }
+    fs::remove_file(&path)
+}
@@ -0,0 +1,274 @@
+//
+// `aho_corasick.rs` matches many literal patterns in a single pass over a text.
+//
+// The patterns are stored in a trie, whose nodes are the states of the automaton. Each state
+//   has a failure link to the state of the longest proper suffix of its path that is also in the
+//   trie, followed when no transition matches the next byte, so the text is never rescanned.
+//   A dictionary link points to the nearest state down the failure chain that ends a pattern,
+//   which lists all the patterns ending at a position without walking the whole chain.
+//
+// The root has a dense transition table, as most bytes of a text lead back to it; the other
+//   states keep their few transitions sorted.
+//
+
+use std::collections::VecDeque;
+
+
+// Index of the root state.
+const ROOT : usize = 0;
+
+#[derive(Debug, Clone, Default)]
+struct State {
+    next : Vec<(u8, usize)>,    // Trie transitions, sorted by byte.
+    fail : usize,
+    dict : usize,               // Next state down the failure chain ending a pattern, or root.
+    outputs : Vec<usize>,       // Patterns ending here.
+}
+
+impl State {
+    fn goto(&self, byte : u8) -> Option<usize> {
+        self.next.binary_search_by_key(&byte, |&(b, _)| b).ok().map(|i| self.next[i].1)
+    }
+}
+
+
+#[derive(Debug, Clone)]
+pub struct AhoCorasick {
+    states : Vec<State>,
+    root_next : [usize; 256],   // Complete transitions of the root.
+    lens : Vec<usize>,          // Pattern lengths, by pattern index.
+}
+
+impl AhoCorasick {
+
+    pub fn new<I, P>(patterns : I) -> AhoCorasick
+        where I : IntoIterator<Item = P>,
+              P : AsRef<[u8]>
+    {
+        let mut states = vec![State::default()];
+        let mut lens = vec![];
+
+        // Build the trie.
+        for (index, pattern) in patterns.into_iter().enumerate() {
+            let pattern = pattern.as_ref();
+            let mut state = ROOT;
+            for &byte in pattern {
+                state = match states[state].goto(byte) {
+                    Some(next) => next,
+                    None => {
+                        let next = states.len();
+                        states.push(State::default());
+                        let transitions = &mut states[state].next;
+                        let at = transitions.binary_search_by_key(&byte, |&(b, _)| b).unwrap_err();
+                        transitions.insert(at, (byte, next));
+                        next
+                    },
+                };
+            }
+            states[state].outputs.push(index);
+            lens.push(pattern.len());
+        }
+
+        let mut root_next = [ROOT; 256];
+        for &(byte, next) in &states[ROOT].next {
+            root_next[byte as usize] = next;
+        }
+
+        // Link the states breadth first, so that the links of shallower states are known.
+        let mut queue : VecDeque<usize> = states[ROOT].next.iter().map(|&(_, next)| next).collect();
+        while let Some(state) = queue.pop_front() {
+            for i in 0..states[state].next.len() {
+                let (byte, child) = states[state].next[i];
+                let mut fail = states[state].fail;
+                let fail = loop {
+                    if fail == ROOT {
+                        break root_next[byte as usize];
+                    }
+                    if let Some(next) = states[fail].goto(byte) {
+                        break next;
+                    }
+                    fail = states[fail].fail;
+                };
+                states[child].fail = fail;
+                states[child].dict = if states[fail].outputs.is_empty() {
+                    states[fail].dict
+                } else {
+                    fail
+                };
+                queue.push_back(child);
+            }
+        }
+
+        AhoCorasick { states, root_next, lens }
+    }
+
+    pub fn pattern_count(&self) -> usize {
+        self.lens.len()
+    }
+
+    fn step(&self, mut state : usize, byte : u8) -> usize {
+        loop {
+            if state == ROOT {
+                return self.root_next[byte as usize];
+            }
+            if let Some(next) = self.states[state].goto(byte) {
+                return next;
+            }
+            state = self.states[state].fail;
+        }
+    }
+
+    // Report every occurrence of every pattern in `haystack` as (pattern, start, end), overlapping
+    // ones included, in order of their end.
+    pub fn find_overlapping<F>(&self, haystack : &[u8], mut report : F)
+        where F : FnMut(usize, usize, usize)
+    {
+        let mut state = ROOT;
+        self.report_at(state, 0, &mut report);
+        for (i, &byte) in haystack.iter().enumerate() {
+            state = self.step(state, byte);
+            self.report_at(state, i + 1, &mut report);
+        }
+    }
+
+    // Report the patterns ending at `end`, found in `state`.
+    fn report_at<F>(&self, mut state : usize, end : usize, report : &mut F)
+        where F : FnMut(usize, usize, usize)
+    {
+        loop {
+            for &pattern in &self.states[state].outputs {
+                report(pattern, end - self.lens[pattern], end);
+            }
+            if state == ROOT {
+                break;
+            }
+            state = self.states[state].dict;
+        }
+    }
+
+    // Byte spans of the leftmost-longest non-overlapping occurrences in `haystack`, like grep
+    // reports them. Only the occurrences accepted by `keep` are considered.
+    pub fn find_iter_by<F>(&self, haystack : &[u8], mut keep : F) -> Vec<(usize, usize)>
+        where F : FnMut(usize, usize) -> bool
+    {
+        let mut found = vec![];
+        self.find_overlapping(haystack, |_, start, end| {
+            if keep(start, end) {
+                found.push((start, end));
+            }
+        });
+        select_leftmost_longest(found)
+    }
+
+    pub fn find_iter(&self, haystack : &[u8]) -> Vec<(usize, usize)> {
+        self.find_iter_by(haystack, |_, _| true)
+    }
+
+    pub fn is_match(&self, haystack : &[u8]) -> bool {
+        let mut state = ROOT;
+        if !self.states[state].outputs.is_empty() {
+            return true;
+        }
+        for &byte in haystack {
+            state = self.step(state, byte);
+            if !self.states[state].outputs.is_empty() || self.states[state].dict != ROOT {
+                return true;
+            }
+        }
+        false
+    }
+}
+
+// Pick the leftmost occurrences, the longest at each start, skipping the ones overlapping an
+// occurrence already picked. Empty occurrences are only kept if there is nothing else, as a
+// single one at the leftmost position.
+fn select_leftmost_longest(mut found : Vec<(usize, usize)>) -> Vec<(usize, usize)> {
+    found.sort_by(|a, b| a.0.cmp(&b.0).then(b.1.cmp(&a.1)));
+
+    let mut spans = vec![];
+    let mut last_end = 0;
+    for &(start, end) in &found {
+        if start < end && start >= last_end {
+            spans.push((start, end));
+            last_end = end;
+        }
+    }
+    if spans.is_empty() {
+        spans.extend(found.into_iter().take(1));
+    }
+    spans
+}
+
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    fn overlapping(patterns : &[&str], haystack : &str) -> Vec<(usize, usize, usize)> {
+        let mut found = vec![];
+        AhoCorasick::new(patterns).find_overlapping(haystack.as_bytes(), |pattern, start, end| {
+            found.push((pattern, start, end));
+        });
+        found
+    }
+
+    #[test]
+    fn classic_example() {
+        // The textbook patterns, where `she` reaches `he` through its dictionary link.
+        assert_eq!(
+            vec![(1, 1, 4), (0, 2, 4), (3, 2, 6)],
+            overlapping(&["he", "she", "his", "hers"], "ushers")
+        );
+        assert_eq!(
+            vec![(2, 0, 3), (1, 2, 5), (0, 3, 5)],
+            overlapping(&["he", "she", "his", "hers"], "hishe")
+        );
+    }
+
+    #[test]
+    fn leftmost_longest() {
+        let ac = AhoCorasick::new(["ab", "abc", "bcd", "d"]);
+        assert_eq!(vec![(0, 3), (3, 4)], ac.find_iter(b"abcd"));
+        assert_eq!(vec![(1, 4)], ac.find_iter(b"xbcd"));
+        assert!(ac.is_match(b"__d"));
+        assert!(!ac.is_match(b"acb"));
+        assert_eq!(4, ac.pattern_count());
+    }
+
+    #[test]
+    fn empty_patterns() {
+        let ac = AhoCorasick::new(Vec::<&str>::new());
+        assert!(!ac.is_match(b"abc"));
+        assert!(ac.find_iter(b"abc").is_empty());
+
+        let ac = AhoCorasick::new(["", "b"]);
+        assert!(ac.is_match(b"xyz"));
+        assert_eq!(vec![(0, 0)], ac.find_iter(b"xyz"));
+        assert_eq!(vec![(1, 2)], ac.find_iter(b"abc"));
+    }
+
+    #[test]
+    fn keep_filters_before_selecting() {
+        let ac = AhoCorasick::new(["ab", "abc"]);
+        assert_eq!(vec![(0, 2)], ac.find_iter_by(b"abc", |_, end| end == 2));
+    }
+
+    #[test]
+    fn agrees_with_naive() {
+        let patterns = ["a", "aa", "aba", "bab", "bb", "abba"];
+        let haystack = "abbababaabbaabaabababbbaba";
+        let mut expected = vec![];
+        for (index, pattern) in patterns.iter().enumerate() {
+            for start in 0..=haystack.len() - pattern.len() {
+                if haystack[start..].starts_with(pattern) {
+                    expected.push((index, start, start + pattern.len()));
+                }
+            }
+        }
+        let mut found = overlapping(&patterns, haystack);
+        expected.sort();
+        found.sort();
+        assert_eq!(expected, found);
+    }
+}
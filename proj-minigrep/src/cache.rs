@@ -0,0 +1,286 @@
+//
+// `cache.rs` keeps the results of searching each file on disk, with `--cache`, so that a file
+//   unchanged since the same search was run is answered without reading it again.
+//
+// An entry is keyed by the path of the file, its size and modification time, and the options
+//   its output depends on (the query among them). It holds what was printed for the file and its
+//   counts. Entries are the files of the cache directory, named after a hash of their key, which
+//   they also hold in full to tell collisions apart. Reading an entry touches it, so that once
+//   the entries outgrow the size of the cache, the least recently used ones go first.
+//
+// The cache is only an optimization: failing to read or write it never fails a search.
+//
+
+use std::fs::{self, File};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::process;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::search::Stats;
+
+
+// Size of the cache unless given, in bytes.
+pub const DEFAULT_SIZE : u64 = 64 << 20;
+
+// First line of the entries, to be changed along with their layout.
+const MAGIC : &[u8] = b"minigrep-cache 1\n";
+
+
+// A cache directory, for the results of searches run with the same options.
+#[derive(Debug, Clone)]
+pub struct Cache {
+    dir : PathBuf,
+    size : u64,         // Cap on the total size of the entries, in bytes.
+    options : String,   // What the results depend on, besides the file.
+}
+
+// What the results of a file were computed from, and the name of the entry holding them.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Key {
+    name : String,
+    text : Vec<u8>,
+}
+
+impl Cache {
+
+    pub fn new(dir : PathBuf, size : u64, options : String) -> Cache {
+        Cache { dir, size, options }
+    }
+
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    pub fn size(&self) -> u64 {
+        self.size
+    }
+
+    // The key of the results of the file at `path` as it is now, or `None` unless it is a regular
+    // file. Whether it was `walked` to changes how it is searched if binary.
+    pub fn key(&self, path : &Path, walked : bool) -> Option<Key> {
+        let metadata = fs::metadata(path).ok()?;
+        if !metadata.is_file() {
+            return None;
+        }
+        let modified = metadata.modified().ok()?.duration_since(UNIX_EPOCH).ok()?;
+        let canonical = fs::canonicalize(path).ok()?;
+
+        // The path as printed, and the file it names from the working directory.
+        let mut text = vec![];
+        for part in &[path.as_os_str(), canonical.as_os_str()] {
+            let bytes = part.as_encoded_bytes();
+            text.extend(format!("{}:", bytes.len()).as_bytes());
+            text.extend(bytes);
+        }
+        text.extend(format!("{} {}.{:09} {} {}", metadata.len(), modified.as_secs(),
+                            modified.subsec_nanos(), walked, self.options).as_bytes());
+        Some(Key { name : format!("{:016x}", fnv1a(&text)), text })
+    }
+
+    // The counts and output stored for `key`, if any.
+    pub fn get(&self, key : &Key) -> Option<(Stats, Vec<u8>)> {
+        let path = self.dir.join(&key.name);
+        let entry = fs::read(&path).ok()?;
+        let (text, stats, output) = parse_entry(&entry)?;
+        if text != key.text.as_slice() {
+            return None;
+        }
+        // Mark the entry as used, for it to be evicted last.
+        if let Ok(file) = File::options().append(true).open(&path) {
+            let _ = file.set_modified(SystemTime::now());
+        }
+        Some((stats, output.to_vec()))
+    }
+
+    // Store the counts and output of a file for `key`, unless larger than the whole cache.
+    pub fn put(&self, key : &Key, stats : &Stats, output : &[u8]) -> io::Result<()> {
+        let mut entry = MAGIC.to_vec();
+        entry.extend(format!("{}\n", key.text.len()).as_bytes());
+        entry.extend(&key.text);
+        entry.extend(format!("\n{} {}\n", stats.matched_lines, stats.matches).as_bytes());
+        entry.extend(output);
+        if entry.len() as u64 > self.size {
+            return Ok(());
+        }
+
+        // Written aside then renamed, as other searches may read the entry meanwhile.
+        fs::create_dir_all(&self.dir)?;
+        let temp = self.dir.join(temp_name(&key.name));
+        let result = File::options().write(true).create_new(true).open(&temp)
+                          .and_then(|mut file| file.write_all(&entry))
+                          .and_then(|_| fs::rename(&temp, self.dir.join(&key.name)));
+        if result.is_err() {
+            let _ = fs::remove_file(&temp);
+        }
+        result
+    }
+
+    // Remove the least recently used entries until they fit in the size of the cache.
+    pub fn trim(&self) -> io::Result<()> {
+        let mut entries = vec![];
+        let mut total = 0;
+        for dir_entry in fs::read_dir(&self.dir)? {
+            let dir_entry = dir_entry?;
+            let metadata = dir_entry.metadata()?;
+            // Files being written are left alone.
+            if !metadata.is_file() || dir_entry.file_name().to_string_lossy().starts_with('.') {
+                continue;
+            }
+            total += metadata.len();
+            entries.push((metadata.modified()?, metadata.len(), dir_entry.path()));
+        }
+
+        entries.sort();
+        for (_, len, path) in entries {
+            if total <= self.size {
+                break;
+            }
+            match fs::remove_file(&path) {
+                Ok(()) => total -= len,
+                Err(err) if err.kind() == io::ErrorKind::NotFound => total -= len,
+                Err(err) => return Err(err),
+            }
+        }
+        Ok(())
+    }
+}
+
+
+// The key text, counts and output of an entry, if well formed.
+fn parse_entry(entry : &[u8]) -> Option<(&[u8], Stats, &[u8])> {
+    let rest = entry.strip_prefix(MAGIC)?;
+    let (len, rest) = split_line(rest)?;
+    let len : usize = len.parse().ok()?;
+    if rest.len() <= len || rest[len] != b'\n' {
+        return None;
+    }
+    let (text, rest) = (&rest[..len], &rest[len + 1..]);
+
+    let (counts, output) = split_line(rest)?;
+    let (matched_lines, matches) = counts.split_once(' ')?;
+    let stats = Stats {
+        matched_lines : matched_lines.parse().ok()?,
+        matches : matches.parse().ok()?,
+    };
+    Some((text, stats, output))
+}
+
+// The first line of `bytes`, if text, and what follows it.
+fn split_line(bytes : &[u8]) -> Option<(&str, &[u8])> {
+    let end = bytes.iter().position(|&b| b == b'\n')?;
+    Some((std::str::from_utf8(&bytes[..end]).ok()?, &bytes[end + 1..]))
+}
+
+// Hidden name, unique to this write, of the file an entry is written to before it is renamed.
+fn temp_name(name : &str) -> String {
+    static WRITES : AtomicUsize = AtomicUsize::new(0);
+    format!(".{}.{}-{}.tmp", name, process::id(), WRITES.fetch_add(1, Ordering::Relaxed))
+}
+
+// 64-bit FNV-1a, which unlike the hasher of the standard library is the same in every build.
+fn fnv1a(bytes : &[u8]) -> u64 {
+    bytes.iter().fold(0xcbf2_9ce4_8422_2325, |hash, &b| {
+        (hash ^ b as u64).wrapping_mul(0x0100_0000_01b3)
+    })
+}
+
+
+#[cfg(test)]
+mod tests {
+
+    use std::env;
+    use std::time::Duration;
+
+    use super::*;
+
+    fn make_dir(name : &str) -> PathBuf {
+        let dir = env::temp_dir().join(format!("minigrep-cache-{}-{}", name, process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    fn set_used(path : &Path, secs : u64) {
+        let file = File::options().append(true).open(path).unwrap();
+        file.set_modified(UNIX_EPOCH + Duration::from_secs(secs)).unwrap();
+    }
+
+    #[test]
+    fn keys() {
+        let dir = make_dir("keys");
+        let file = dir.join("file");
+        fs::write(&file, "a\n").unwrap();
+        let cache = Cache::new(dir.join("cache"), DEFAULT_SIZE, "options".to_string());
+
+        let key = cache.key(&file, false).unwrap();
+        assert_eq!(Some(&key), cache.key(&file, false).as_ref());
+        assert_ne!(key, cache.key(&file, true).unwrap());
+        let other = Cache::new(dir.join("cache"), DEFAULT_SIZE, "other".to_string());
+        assert_ne!(key, other.key(&file, false).unwrap());
+
+        // Changing the size or the time of the file changes the key.
+        fs::write(&file, "ab\n").unwrap();
+        let longer = cache.key(&file, false).unwrap();
+        assert_ne!(key, longer);
+        set_used(&file, 1000);
+        assert_ne!(longer, cache.key(&file, false).unwrap());
+
+        assert_eq!(None, cache.key(&dir, false));
+        assert_eq!(None, cache.key(&dir.join("missing"), false));
+    }
+
+    #[test]
+    fn entries() {
+        let dir = make_dir("entries");
+        let file = dir.join("file");
+        fs::write(&file, "a\n").unwrap();
+        let cache = Cache::new(dir.join("cache"), DEFAULT_SIZE, "options".to_string());
+        let key = cache.key(&file, false).unwrap();
+
+        assert_eq!(None, cache.get(&key));
+        let stats = Stats { matched_lines : 2, matches : 3 };
+        cache.put(&key, &stats, b"file:a\nfile:\xff\n").unwrap();
+        assert_eq!(Some((stats, b"file:a\nfile:\xff\n".to_vec())), cache.get(&key));
+
+        // An entry of another key under the same name is not used.
+        let collision = Key { name : key.name.clone(), text : b"other".to_vec() };
+        assert_eq!(None, cache.get(&collision));
+        // Neither is a damaged one.
+        fs::write(dir.join("cache").join(&key.name), &MAGIC[..4]).unwrap();
+        assert_eq!(None, cache.get(&key));
+
+        // Entries larger than the cache are not stored.
+        let small = Cache::new(dir.join("small"), 10, "options".to_string());
+        small.put(&key, &stats, b"").unwrap();
+        assert_eq!(None, small.get(&key));
+    }
+
+    #[test]
+    fn least_recently_used() {
+        let dir = make_dir("lru");
+        let cache = Cache::new(dir.join("cache"), 1 << 20, String::new());
+        let mut keys = vec![];
+        for (i, name) in ["a", "b", "c"].iter().enumerate() {
+            let file = dir.join(name);
+            fs::write(&file, name).unwrap();
+            let key = cache.key(&file, false).unwrap();
+            cache.put(&key, &Stats::default(), &[b'x'; 100]).unwrap();
+            set_used(&cache.dir().join(&key.name), 1000 + i as u64);
+            keys.push(key);
+        }
+        let len = fs::metadata(cache.dir().join(&keys[0].name)).unwrap().len();
+
+        // Reading `a` makes `b` the least recently used.
+        assert!(cache.get(&keys[0]).is_some());
+        let cache = Cache::new(dir.join("cache"), 2 * len, String::new());
+        cache.trim().unwrap();
+        let kept : Vec<_> = keys.iter().map(|key| cache.get(key).is_some()).collect();
+        assert_eq!(vec![true, false, true], kept);
+
+        let cache = Cache::new(dir.join("cache"), 0, String::new());
+        cache.trim().unwrap();
+        assert_eq!(0, fs::read_dir(cache.dir()).unwrap().count());
+    }
+}
@@ -0,0 +1,875 @@
+//
+// `config.rs` is responsible for parsing command line arguments into a `Config`.
+//
+// Grammar: `minigrep [OPTIONS] PATTERN [FILE...]`, where options may appear anywhere before a `--`,
+//   short flags may be combined (e.g. `-inv`), and long flags are spelled out (`--count`). When
+//   patterns are given with `-e` or `-f`, all the positionals are files.
+//
+// `minigrep index [DIR...]` is the only subcommand, told apart by its first argument.
+//
+
+use std::env;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use crate::cache;
+use crate::error::MinigrepError;
+use crate::filter::{self, Filter};
+use crate::record::{Format, Selector};
+use crate::searcher::SearchOptions;
+
+
+pub const USAGE : &str = "\
+Usage: minigrep [OPTIONS] PATTERN [FILE...]
+   or: minigrep [OPTIONS] (-e PATTERN | -f FILE)... [FILE...]
+   or: minigrep index [DIR...]
+
+Search for PATTERN (or any of the patterns) in each FILE and print the matching lines. With
+no FILE or `-`, read the standard input (or search the working directory with `-r`).
+
+The `index` subcommand builds the trigram index of each DIR (by default, the working
+directory) for `--indexed`, or updates it, only reading the files changed since. Search for
+the pattern `index` with `-e index`.
+
+Options:
+  -e, --regexp=PATTERN       Search for PATTERN; may be given several times
+  -f, --file=FILE            Search for the patterns in FILE, one per line
+  -i, --ignore-case          Match case insensitively (fallback: env CASE_INSENSITIVE)
+  -E, --regex                Interpret PATTERN as a regular expression
+      --fuzzy                Select the lines holding the characters of PATTERN in order,
+                             best matches first; case insensitive unless PATTERN has
+                             uppercase letters, and `-m N` keeps the N best lines
+  -U, --multiline            Let matches span lines, searching each file as a whole, where
+                             `^` and `$` match at every line; the lines a match covers are
+                             printed as one block
+  -w, --word-regexp          Only match whole words
+  -x, --line-regexp          Only match whole lines
+      --field=NAME           Only match within the field NAME of each record: a column
+                             named by the header of CSV and TSV inputs, or a logfmt key
+      --column=N             Only match within the Nth field of each record
+      --print-fields=LIST    Only print the fields of LIST, names or positions separated
+                             by commas, of the selected records
+      --format=FORMAT        Read the records as csv, tsv or logfmt; by default, `.csv`
+                             and `.tsv` files are CSV and TSV, and other inputs logfmt.
+                             The first line of CSV and TSV inputs is a header, not searched
+  -n, --line-number          Prefix each output line with its line number
+  -b, --byte-offset          Prefix each output line with its byte offset
+  -A, --after-context=N      Print N lines of context after each selected line
+  -B, --before-context=N     Print N lines of context before each selected line
+  -C, --context=N            Print N lines of context around each selected line
+  -v, --invert-match         Select the lines that do not match
+  -c, --count                Only print the number of selected lines per file
+  -l, --files-with-matches   Only print the paths of files with selected lines
+  -L, --files-without-match  Only print the paths of files without selected lines
+  -o, --only-matching        Only print the matched parts of the lines
+      --json                 Print the results as JSON Lines, one event per line
+      --replace=TEXT         Print the lines with their matches replaced by TEXT, where `$N`
+                             or `${N}` is group N in regex mode and `$$` is `$`
+      --in-place             Write the replaced lines back to the files instead
+      --dry-run              Print a unified diff of what `--in-place` would change
+  -m, --max-count=N          Stop reading a file after N selected lines
+  -r, --recursive            Search directories recursively
+      --indexed              With `-r`, skip the files the index of each directory tells
+                             cannot match; files changed since indexed are searched
+      --include=GLOB         Only search the files found with `-r` matching GLOB, which
+                             matches the file name, or the relative path if it has a `/`;
+                             supports `*`, `?`, `[...]`, `**` and `{a,b}`
+      --exclude=GLOB         Skip the files found with `-r` matching GLOB, and the contents
+                             of the directories matching it
+  -t, --type=TYPE            Only search the files found with `-r` of TYPE, like rust or py;
+                             `--include` and `--type` may be given several times
+      --max-filesize=SIZE    Skip the files found with `-r` larger than SIZE bytes, with an
+                             optional K, M or G suffix
+  -z, --search-zip           Search the contents of gzip and zlib compressed inputs
+      --binary-files=TYPE    How to search files with NUL bytes: binary (only tell whether
+                             they match), text, or without-match (skip them); the default
+                             is binary, and without-match for files found with `-r`
+      --follow               Keep reading the files once searched, printing the lines
+                             appended to them that are selected, until interrupted; a
+                             truncated or replaced file is read again from its start
+      --watch                Search again whenever the inputs change, until interrupted
+  -j, --threads=N            Search N files in parallel (default: number of CPUs)
+      --cache                Keep the results of each file in a cache, answering from it
+                             for the files unchanged since the same search; the cache is
+                             in $XDG_CACHE_HOME/minigrep, or ~/.cache/minigrep
+      --cache-dir=DIR        Keep the cache in DIR
+      --cache-size=SIZE      Cap the cache at SIZE bytes, with an optional K, M or G suffix,
+                             evicting the least recently used results first (default: 64M);
+                             `--cache-dir` and `--cache-size` imply `--cache`
+      --no-cache             Neither read nor write the cache, even with `--cache`
+      --color=WHEN           Highlight matches: auto (default), always or never
+  -h, --help                 Print this help and exit
+  -V, --version              Print version information and exit
+      --                     Treat all following arguments as positional
+
+Exit status is 0 if a line is selected, 1 if none is, and 2 if an error occurred.";
+
+pub fn version() -> String {
+    format!("minigrep {}", env!("CARGO_PKG_VERSION"))
+}
+
+
+// How the query is interpreted.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum MatchMode {
+    Literal,    // Plain substring.
+    Regex,      // Regular expression, see `regex.rs`.
+    Fuzzy,      // Characters in order, lines ranked by score, see `fuzzy.rs`.
+}
+
+// Where matches may start and end.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Boundary {
+    Anywhere,
+    Word,       // Neither preceded nor followed by a word character.
+    Line,       // The whole line.
+}
+
+// What is printed for the selected lines.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum OutputMode {
+    Lines,              // The lines themselves, with context.
+    Count,              // The number of selected lines per file.
+    FilesWithMatches,   // The paths of files with at least one selected line.
+    FilesWithoutMatch,  // The paths of files without any.
+    OnlyMatching,       // Each matched part, on its own line.
+    Json,               // The lines and their context as JSON Lines events, see `json.rs`.
+}
+
+// How the inputs that look binary are searched.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum BinaryFiles {
+    Binary,         // Only tell whether they match, without printing their lines.
+    Text,           // Like any other input.
+    WithoutMatch,   // Not at all, as if they did not match.
+}
+
+// When to highlight the output with ANSI colors.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ColorChoice {
+    Auto,       // Only when writing to a terminal, unless `NO_COLOR` is set.
+    Always,
+    Never,
+}
+
+impl ColorChoice {
+
+    // Whether to color output going to a terminal (or not, per `is_terminal`).
+    pub fn enabled(self, is_terminal : bool) -> bool {
+        match self {
+            ColorChoice::Auto => {
+                // See https://no-color.org: any non-empty value disables colors by default.
+                is_terminal && env::var_os("NO_COLOR").is_none_or(|value| value.is_empty())
+            },
+            ColorChoice::Always => true,
+            ColorChoice::Never => false,
+        }
+    }
+}
+
+// What `main` should do with the parsed arguments.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Action {
+    Search,
+    Index,      // Build or update the indexes of the directories given.
+    Help,
+    Version,
+}
+
+
+// Short (if any) and long names of the boolean flags.
+const FLAGS : [(Option<char>, &str); 25] = [
+    (Some('i'), "ignore-case"),
+    (Some('E'), "regex"),
+    (None, "fuzzy"),
+    (Some('U'), "multiline"),
+    (Some('w'), "word-regexp"),
+    (Some('x'), "line-regexp"),
+    (Some('n'), "line-number"),
+    (Some('b'), "byte-offset"),
+    (Some('c'), "count"),
+    (Some('l'), "files-with-matches"),
+    (Some('L'), "files-without-match"),
+    (Some('o'), "only-matching"),
+    (None, "json"),
+    (None, "in-place"),
+    (None, "dry-run"),
+    (Some('v'), "invert-match"),
+    (Some('r'), "recursive"),
+    (None, "indexed"),
+    (Some('z'), "search-zip"),
+    (None, "follow"),
+    (None, "watch"),
+    (None, "cache"),
+    (None, "no-cache"),
+    (Some('h'), "help"),
+    (Some('V'), "version"),
+];
+
+// Short (if any) and long names of the options taking a value.
+const VALUE_OPTIONS : [(Option<char>, &str); 20] = [
+    (Some('e'), "regexp"),
+    (Some('f'), "file"),
+    (Some('A'), "after-context"),
+    (Some('B'), "before-context"),
+    (Some('C'), "context"),
+    (Some('j'), "threads"),
+    (Some('m'), "max-count"),
+    (None, "replace"),
+    (None, "field"),
+    (None, "column"),
+    (None, "print-fields"),
+    (None, "format"),
+    (None, "binary-files"),
+    (None, "include"),
+    (None, "exclude"),
+    (Some('t'), "type"),
+    (None, "max-filesize"),
+    (None, "cache-dir"),
+    (None, "cache-size"),
+    (None, "color"),
+];
+
+
+// Config parsing.
+pub struct Config<'a> {
+    pub(crate) patterns : Vec<String>,
+    pub(crate) filenames : Vec<&'a str>,
+    pub(crate) sensitive : bool,
+    pub(crate) mode : MatchMode,
+    pub(crate) boundary : Boundary,
+    pub(crate) multiline : bool,
+    pub(crate) field : Option<Selector>,    // Of the records, to match within.
+    pub(crate) print_fields : Vec<Selector>,
+    pub(crate) format : Option<Format>,     // Of the records, unless detected.
+    pub(crate) line_number : bool,
+    pub(crate) byte_offset : bool,
+    pub(crate) before : usize,
+    pub(crate) after : usize,
+    pub(crate) output : OutputMode,
+    pub(crate) max_count : Option<usize>,
+    pub(crate) invert : bool,
+    pub(crate) replacement : Option<String>,
+    pub(crate) in_place : bool,
+    pub(crate) dry_run : bool,
+    pub(crate) recursive : bool,
+    pub(crate) indexed : bool,      // Files are pruned with the indexes of the directories.
+    pub(crate) filter : Filter,     // Of the files found recursively.
+    pub(crate) decompress : bool,
+    pub(crate) binary_files : Option<BinaryFiles>,     // Unless given, depends on the input.
+    pub(crate) follow : bool,
+    pub(crate) watch : bool,
+    pub(crate) threads : usize,     // Zero for one per CPU.
+    pub(crate) cache : Option<PathBuf>,     // The directory of the cache, if used.
+    pub(crate) cache_size : u64,
+    pub(crate) color : ColorChoice,
+    pub(crate) action : Action,
+}
+
+// Settings that only matter while parsing.
+#[derive(Default)]
+struct Pending {
+    patterns_given : bool,  // With `-e` or `-f`, so the first positional is not the pattern.
+    ignore_case : bool,
+    word : bool,
+    line : bool,
+    context : Option<usize>,
+    before : Option<usize>,
+    after : Option<usize>,
+    cache : bool,
+    cache_dir : Option<PathBuf>,
+    no_cache : bool,
+}
+
+impl<'a> Config<'a> {
+
+    // Parse the arguments, `args[0]` being the program name.
+    pub fn new(args : &'a [String]) -> Result<Config<'a>, MinigrepError> {
+        let mut config = Config {
+            patterns : vec![],
+            filenames : vec![],
+            sensitive : true,
+            mode : MatchMode::Literal,
+            boundary : Boundary::Anywhere,
+            multiline : false,
+            field : None,
+            print_fields : vec![],
+            format : None,
+            line_number : false,
+            byte_offset : false,
+            before : 0,
+            after : 0,
+            output : OutputMode::Lines,
+            max_count : None,
+            invert : false,
+            replacement : None,
+            in_place : false,
+            dry_run : false,
+            recursive : false,
+            indexed : false,
+            filter : Filter::default(),
+            decompress : false,
+            binary_files : None,
+            follow : false,
+            watch : false,
+            threads : 0,
+            cache : None,
+            cache_size : cache::DEFAULT_SIZE,
+            color : ColorChoice::Auto,
+            action : Action::Search,
+        };
+        if args.get(1).is_some_and(|arg| arg == "index") {
+            return Config::new_index(config, &args[2..]);
+        }
+
+        let mut pending = Pending::default();
+        let mut positionals = vec![];
+        let mut only_positionals = false;
+
+        let mut rest = args.iter().skip(1);
+        while let Some(arg) = rest.next() {
+            if only_positionals || arg == "-" || !arg.starts_with('-') {
+                positionals.push(arg.as_str());
+            } else if arg == "--" {
+                only_positionals = true;
+            } else if let Some(long) = arg.strip_prefix("--") {
+                // Values are given either inline (`--context=2`) or as the next argument.
+                let (name, inline) = match long.find('=') {
+                    Some(i) => (&long[..i], Some(&long[i + 1..])),
+                    None => (long, None),
+                };
+                let flag = format!("--{}", name);
+
+                if VALUE_OPTIONS.iter().any(|&(_, long)| long == name) {
+                    let value = match inline {
+                        Some(value) => value,
+                        None => next_value(&mut rest, &flag)?,
+                    };
+                    config.set_value(&mut pending, name, &flag, value)?;
+                } else if FLAGS.iter().any(|&(_, long)| long == name) {
+                    if inline.is_some() {
+                        return usage(format!("Option '{}' does not take a value.", flag));
+                    }
+                    config.set_flag(&mut pending, name);
+                } else {
+                    return usage(format!("Unknown option '{}'.", flag));
+                }
+            } else {
+                // A cluster of short flags, e.g. `-inv`. An option taking a value consumes the
+                // rest of the cluster (`-A2`), or the next argument if nothing is left (`-A 2`).
+                for (i, short) in arg.char_indices().skip(1) {
+                    let flag = format!("-{}", short);
+                    if let Some(&(_, name)) = VALUE_OPTIONS.iter().find(|&&(s, _)| s == Some(short)) {
+                        let value = match &arg[i + short.len_utf8()..] {
+                            "" => next_value(&mut rest, &flag)?,
+                            value => value,
+                        };
+                        config.set_value(&mut pending, name, &flag, value)?;
+                        break;
+                    } else if let Some(&(_, name)) = FLAGS.iter().find(|&&(s, _)| s == Some(short)) {
+                        config.set_flag(&mut pending, name);
+                    } else if arg.chars().count() > 2 {
+                        return usage(format!("Unknown option '{}' in '{}'.", flag, arg));
+                    } else {
+                        return usage(format!("Unknown option '{}'.", arg));
+                    }
+                }
+            }
+
+            // Help and version short-circuit whatever follows.
+            if config.action != Action::Search {
+                return Ok(config);
+            }
+        }
+
+        // Explicit `-A` / `-B` take precedence over `-C`.
+        config.before = pending.before.or(pending.context).unwrap_or(0);
+        config.after = pending.after.or(pending.context).unwrap_or(0);
+
+        // Whole lines imply whole words.
+        config.boundary = if pending.line {
+            Boundary::Line
+        } else if pending.word {
+            Boundary::Word
+        } else {
+            Boundary::Anywhere
+        };
+
+        // Files are edited from the replaced lines alone.
+        if config.in_place || config.dry_run {
+            if config.replacement.is_none() {
+                let flag = if config.in_place { "--in-place" } else { "--dry-run" };
+                return usage(format!("Option '{}' requires '--replace'.", flag));
+            }
+            if config.output != OutputMode::Lines {
+                return usage("Options '--in-place' and '--dry-run' cannot be combined with another \
+                              output mode.".to_string());
+            }
+            // The unselected lines are the ones left as they are.
+            if config.invert {
+                return usage("Options '--in-place' and '--dry-run' cannot be combined with \
+                              '-v'.".to_string());
+            }
+        }
+        // JSON events hold the lines as they are.
+        if config.replacement.is_some() && config.output == OutputMode::Json {
+            return usage("Option '--replace' cannot be combined with '--json'.".to_string());
+        }
+
+        // Lines are ranked, and selected on their own.
+        if config.mode == MatchMode::Fuzzy
+           && (config.boundary != Boundary::Anywhere || config.invert || config.before > 0
+               || config.after > 0 || config.replacement.is_some()) {
+            return usage("Option '--fuzzy' cannot be combined with '-w', '-x', '-v', context, or \
+                          '--replace'.".to_string());
+        }
+
+        // Whole inputs are searched at once, while appended lines and edits go line by line.
+        if config.multiline && (config.mode == MatchMode::Fuzzy || config.follow || config.in_place
+                                || config.dry_run) {
+            return usage("Option '--multiline' cannot be combined with '--fuzzy', '--follow', \
+                          '--in-place' or '--dry-run'.".to_string());
+        }
+
+        // Records are searched line by line, and replaced or ranked as whole lines.
+        let records = config.field.is_some() || !config.print_fields.is_empty();
+        if records && (config.multiline || config.mode == MatchMode::Fuzzy || config.follow
+                       || config.replacement.is_some()) {
+            return usage("Options '--field', '--column' and '--print-fields' cannot be combined \
+                          with '-U', '--fuzzy', '--follow' or '--replace'.".to_string());
+        }
+        if !config.print_fields.is_empty() && config.output == OutputMode::OnlyMatching {
+            return usage("Option '--print-fields' cannot be combined with '-o'.".to_string());
+        }
+        if config.format.is_some() && !records {
+            return usage("Option '--format' requires '--field', '--column' or '--print-fields'."
+                         .to_string());
+        }
+
+        if !pending.patterns_given {
+            if positionals.is_empty() {
+                return usage("Wrong number of arguments: missing PATTERN.".to_string());
+            }
+            config.patterns.push(positionals.remove(0).to_string());
+        }
+
+        // Without FILE, read stdin, or search the working directory when recursive.
+        config.filenames = positionals;
+        if config.filenames.is_empty() {
+            config.filenames.push(if config.recursive { "." } else { "-" });
+        }
+
+        // Appended lines are searched on their own, and printed as they come.
+        if config.follow {
+            if config.watch {
+                return usage("Options '--follow' and '--watch' cannot be combined.".to_string());
+            }
+            if config.filenames.contains(&"-") || config.recursive {
+                return usage("Option '--follow' requires files to follow, and cannot be combined \
+                              with '-r'.".to_string());
+            }
+            if !matches!(config.output, OutputMode::Lines | OutputMode::OnlyMatching)
+               || config.before > 0 || config.after > 0 || config.replacement.is_some()
+               || config.mode == MatchMode::Fuzzy {
+                return usage("Option '--follow' cannot be combined with another output mode, \
+                              context, '--replace' or '--fuzzy'.".to_string());
+            }
+        }
+        // The inputs are searched again, and edits would change them.
+        if config.watch {
+            if config.filenames.contains(&"-") {
+                return usage("Option '--watch' cannot search the standard input.".to_string());
+            }
+            if config.in_place {
+                return usage("Option '--watch' cannot be combined with '--in-place'.".to_string());
+            }
+        }
+
+        // Only walked files are pruned, and the indexes are read once per search.
+        if config.indexed && (!config.recursive || config.watch) {
+            return usage("Option '--indexed' requires '-r', and cannot be combined with \
+                          '--watch'.".to_string());
+        }
+
+        // Only the results printed once per file are cached, and `--no-cache` wins.
+        if pending.cache && !pending.no_cache {
+            if config.follow || config.watch || config.in_place || config.dry_run {
+                return usage("Option '--cache' cannot be combined with '--follow', '--watch', \
+                              '--in-place' or '--dry-run'.".to_string());
+            }
+            match pending.cache_dir.or_else(default_cache_dir) {
+                Some(dir) => config.cache = Some(dir),
+                None => return usage("Option '--cache' requires '--cache-dir' when neither \
+                                      XDG_CACHE_HOME nor HOME is set.".to_string()),
+            }
+        }
+
+        // The flag wins; otherwise check for env variable.
+        config.sensitive = !pending.ignore_case && env::var("CASE_INSENSITIVE").is_err();
+
+        Ok(config)
+    }
+
+    // Parse the arguments of `minigrep index`, the directories to index.
+    fn new_index(mut config : Config<'a>, args : &'a [String])
+                 -> Result<Config<'a>, MinigrepError> {
+        if let Some(option) = args.iter().find(|arg| arg.starts_with('-')) {
+            return usage(format!("Unknown option '{}': 'minigrep index' only takes directories.",
+                                 option));
+        }
+        config.filenames = args.iter().map(String::as_str).collect();
+        if config.filenames.is_empty() {
+            config.filenames.push(".");
+        }
+        config.action = Action::Index;
+        Ok(config)
+    }
+
+    // Apply a boolean flag given by its long name.
+    fn set_flag(&mut self, pending : &mut Pending, name : &str) {
+        match name {
+            "ignore-case" => pending.ignore_case = true,
+            "regex" => self.mode = MatchMode::Regex,
+            "fuzzy" => self.mode = MatchMode::Fuzzy,
+            "multiline" => self.multiline = true,
+            "word-regexp" => pending.word = true,
+            "line-regexp" => pending.line = true,
+            "line-number" => self.line_number = true,
+            "byte-offset" => self.byte_offset = true,
+            "count" => self.output = OutputMode::Count,
+            "files-with-matches" => self.output = OutputMode::FilesWithMatches,
+            "files-without-match" => self.output = OutputMode::FilesWithoutMatch,
+            "only-matching" => self.output = OutputMode::OnlyMatching,
+            "json" => self.output = OutputMode::Json,
+            "in-place" => self.in_place = true,
+            "dry-run" => self.dry_run = true,
+            "invert-match" => self.invert = true,
+            "recursive" => self.recursive = true,
+            "indexed" => self.indexed = true,
+            "search-zip" => self.decompress = true,
+            "follow" => self.follow = true,
+            "watch" => self.watch = true,
+            "cache" => pending.cache = true,
+            "no-cache" => pending.no_cache = true,
+            "help" => self.action = Action::Help,
+            "version" => self.action = Action::Version,
+            _ => unreachable!(),
+        }
+    }
+
+    // Apply an option given by its long name; `flag` is how the user spelled it.
+    fn set_value(&mut self, pending : &mut Pending, name : &str, flag : &str, value : &str)
+                 -> Result<(), MinigrepError> {
+        match name {
+            "regexp" => {
+                self.patterns.push(value.to_string());
+                pending.patterns_given = true;
+            },
+            "file" => {
+                self.patterns.extend(read_patterns(value)?);
+                pending.patterns_given = true;
+            },
+            "after-context" => pending.after = Some(parse_count(value, flag)?),
+            "before-context" => pending.before = Some(parse_count(value, flag)?),
+            "context" => pending.context = Some(parse_count(value, flag)?),
+            "threads" => self.threads = parse_count(value, flag)?,
+            "max-count" => self.max_count = Some(parse_count(value, flag)?),
+            "replace" => self.replacement = Some(value.to_string()),
+            "field" => self.field = Some(Selector::Name(value.to_string())),
+            "column" => match parse_count(value, flag)? {
+                0 => return usage(format!("Invalid value '0' for '{}': columns are numbered \
+                                           from 1.", flag)),
+                column => self.field = Some(Selector::Column(column)),
+            },
+            "print-fields" => match Selector::parse_list(value) {
+                Some(fields) => self.print_fields = fields,
+                None => return usage(format!("Invalid value '{}' for '{}': expected field names \
+                                              or positions from 1, separated by commas.",
+                                             value, flag)),
+            },
+            "format" => {
+                self.format = Some(match value {
+                    "csv" => Format::Csv,
+                    "tsv" => Format::Tsv,
+                    "logfmt" => Format::Logfmt,
+                    _ => return usage(format!("Invalid value '{}' for '{}': expected csv, tsv \
+                                               or logfmt.", value, flag)),
+                });
+            },
+            "binary-files" => {
+                self.binary_files = Some(match value {
+                    "binary" => BinaryFiles::Binary,
+                    "text" => BinaryFiles::Text,
+                    "without-match" => BinaryFiles::WithoutMatch,
+                    _ => return usage(format!("Invalid value '{}' for '{}': expected binary, \
+                                               text or without-match.", value, flag)),
+                });
+            },
+            "include" | "exclude" => {
+                let added = if name == "include" {
+                    self.filter.include(value)
+                } else {
+                    self.filter.exclude(value)
+                };
+                if let Err(err) = added {
+                    return usage(format!("Invalid value '{}' for '{}': {}.", value, flag, err));
+                }
+            },
+            "type" => {
+                if !self.filter.include_type(value) {
+                    let names : Vec<_> = filter::TYPES.iter().map(|&(name, _)| name).collect();
+                    return usage(format!("Invalid value '{}' for '{}': expected one of {}.",
+                                         value, flag, names.join(", ")));
+                }
+            },
+            "max-filesize" => match filter::parse_size(value) {
+                Some(size) => self.filter.max_filesize(Some(size)),
+                None => return usage(format!("Invalid value '{}' for '{}': expected a size in \
+                                              bytes, with an optional K, M or G suffix.",
+                                             value, flag)),
+            },
+            "cache-dir" => {
+                pending.cache = true;
+                pending.cache_dir = Some(PathBuf::from(value));
+            },
+            "cache-size" => match filter::parse_size(value) {
+                Some(size) => {
+                    pending.cache = true;
+                    self.cache_size = size;
+                },
+                None => return usage(format!("Invalid value '{}' for '{}': expected a size in \
+                                              bytes, with an optional K, M or G suffix.",
+                                             value, flag)),
+            },
+            "color" => {
+                self.color = match value {
+                    "auto" => ColorChoice::Auto,
+                    "always" => ColorChoice::Always,
+                    "never" => ColorChoice::Never,
+                    _ => return usage(format!("Invalid value '{}' for '{}': expected auto, \
+                                               always or never.", value, flag)),
+                };
+            },
+            _ => unreachable!(),
+        }
+        Ok(())
+    }
+
+    // The options of the search, to build a `Searcher` with. Those of the output are not part
+    // of it, nor is the choice of the inputs.
+    pub fn search_options(&self) -> SearchOptions {
+        let mut options = SearchOptions::new();
+        for pattern in &self.patterns {
+            options.pattern(pattern.as_str());
+        }
+        options.mode(self.mode)
+               .ignore_case(!self.sensitive)
+               .boundary(self.boundary)
+               .multiline(self.multiline)
+               .field(self.field.clone())
+               .print_fields(self.print_fields.clone())
+               .format(self.format)
+               .invert(self.invert)
+               .context(self.before, self.after)
+               .max_count(self.max_count)
+               .decompress(self.decompress)
+               .binary_files(self.binary_files.unwrap_or(BinaryFiles::Binary));
+        options
+    }
+
+    pub fn patterns(&self) -> &[String] {
+        &self.patterns
+    }
+
+    pub fn filenames(&self) -> &[&str] {
+        &self.filenames
+    }
+
+    pub fn sensitive(&self) -> bool {
+        self.sensitive
+    }
+
+    pub fn mode(&self) -> MatchMode {
+        self.mode
+    }
+
+    pub fn boundary(&self) -> Boundary {
+        self.boundary
+    }
+
+    pub fn line_number(&self) -> bool {
+        self.line_number
+    }
+
+    pub fn byte_offset(&self) -> bool {
+        self.byte_offset
+    }
+
+    // Lines of context before and after each selected line.
+    pub fn context(&self) -> (usize, usize) {
+        (self.before, self.after)
+    }
+
+    pub fn output(&self) -> OutputMode {
+        self.output
+    }
+
+    pub fn max_count(&self) -> Option<usize> {
+        self.max_count
+    }
+
+    pub fn invert(&self) -> bool {
+        self.invert
+    }
+
+    // The replacement text of the matches, if any.
+    pub fn replacement(&self) -> Option<&str> {
+        self.replacement.as_deref()
+    }
+
+    // Whether the files are rewritten with the replaced lines.
+    pub fn in_place(&self) -> bool {
+        self.in_place
+    }
+
+    // Whether the change to the files is only printed, as a diff.
+    pub fn dry_run(&self) -> bool {
+        self.dry_run
+    }
+
+    pub fn recursive(&self) -> bool {
+        self.recursive
+    }
+
+    // Whether the files found recursively are pruned with indexes, see `index.rs`.
+    pub fn indexed(&self) -> bool {
+        self.indexed
+    }
+
+    // Which of the files found recursively are searched.
+    pub fn filter(&self) -> &Filter {
+        &self.filter
+    }
+
+    // Whether compressed inputs are decompressed, see `inflate.rs`.
+    pub fn decompress(&self) -> bool {
+        self.decompress
+    }
+
+    // How binary inputs are searched, if given.
+    pub fn binary_files(&self) -> Option<BinaryFiles> {
+        self.binary_files
+    }
+
+    // Whether matches may span lines, see `search::search_multiline`.
+    pub fn multiline(&self) -> bool {
+        self.multiline
+    }
+
+    // The field of the records to match within, if any, see `record.rs`.
+    pub fn field(&self) -> Option<&Selector> {
+        self.field.as_ref()
+    }
+
+    // The fields of the selected records to print, all of the line if none.
+    pub fn print_fields(&self) -> &[Selector] {
+        &self.print_fields
+    }
+
+    // The format of the records, unless detected from the path of each input.
+    pub fn format(&self) -> Option<Format> {
+        self.format
+    }
+
+    // Whether the files are followed as they grow, see `watch.rs`.
+    pub fn follow(&self) -> bool {
+        self.follow
+    }
+
+    // Whether the search is run again whenever the inputs change, see `watch.rs`.
+    pub fn watch(&self) -> bool {
+        self.watch
+    }
+
+    // Number of worker threads, zero meaning one per CPU.
+    pub fn threads(&self) -> usize {
+        self.threads
+    }
+
+    // The directory of the results cached, if used, see `cache.rs`.
+    pub fn cache_dir(&self) -> Option<&Path> {
+        self.cache.as_deref()
+    }
+
+    // Cap on the size of the cache, in bytes.
+    pub fn cache_size(&self) -> u64 {
+        self.cache_size
+    }
+
+    // What the output of a file depends on besides the file, for its results to be cached.
+    // Options changing the output must be added here.
+    pub(crate) fn cache_options(&self) -> String {
+        format!("{} {:?}", env!("CARGO_PKG_VERSION"),
+                ((&self.patterns, self.sensitive, self.mode, self.boundary, self.multiline),
+                 (&self.field, &self.print_fields, self.format),
+                 (self.line_number, self.byte_offset, self.before, self.after, self.output),
+                 (self.max_count, self.invert, &self.replacement, self.decompress,
+                  self.binary_files)))
+    }
+
+    pub fn color(&self) -> ColorChoice {
+        self.color
+    }
+
+    pub fn action(&self) -> Action {
+        self.action
+    }
+}
+
+
+// Where the cache is kept unless given: under `XDG_CACHE_HOME`, or else `~/.cache`.
+fn default_cache_dir() -> Option<PathBuf> {
+    let base = match env::var_os("XDG_CACHE_HOME") {
+        Some(dir) if !dir.is_empty() => PathBuf::from(dir),
+        _ => PathBuf::from(env::var_os("HOME").filter(|home| !home.is_empty())?).join(".cache"),
+    };
+    Some(base.join("minigrep"))
+}
+
+// Take the value of an option from the next argument.
+fn next_value<'a, I>(rest : &mut I, flag : &str) -> Result<&'a str, MinigrepError>
+    where I : Iterator<Item = &'a String>
+{
+    match rest.next() {
+        Some(value) => Ok(value.as_str()),
+        None => usage(format!("Option '{}' requires a value.", flag)),
+    }
+}
+
+// Read the patterns of a pattern file, one per line; `-` is the standard input.
+fn read_patterns(path : &str) -> Result<Vec<String>, MinigrepError> {
+    let text = if path == "-" {
+        io::read_to_string(io::stdin())
+    } else {
+        fs::read_to_string(path)
+    };
+    match text {
+        Ok(text) => Ok(text.lines().map(|line| line.to_string()).collect()),
+        Err(err) if err.kind() == io::ErrorKind::InvalidData => {
+            Err(MinigrepError::Encoding { path : PathBuf::from(path), message : "not valid UTF-8" })
+        },
+        Err(err) => Err(MinigrepError::Io { path : Some(PathBuf::from(path)), err }),
+    }
+}
+
+fn parse_count(value : &str, flag : &str) -> Result<usize, MinigrepError> {
+    value.parse().or_else(|_| {
+        usage(format!("Invalid value '{}' for '{}': expected a non-negative number.", value, flag))
+    })
+}
+
+// A usage error.
+fn usage<T>(message : String) -> Result<T, MinigrepError> {
+    Err(MinigrepError::Usage(message))
+}
@@ -0,0 +1,231 @@
+//
+// `encoding.rs` turns the inputs into UTF-8 before they are searched.
+//
+// An input starting with a UTF-16 byte order mark (`ff fe` for little endian, `fe ff` for big
+//   endian) is transcoded to UTF-8 on the fly; a UTF-8 byte order mark is dropped. Anything else
+//   is taken as UTF-8, and the search replaces invalid sequences with U+FFFD, line by line.
+//
+// Offsets are the ones of the UTF-8 text searched, without the byte order mark.
+//
+
+use std::io::{self, BufRead, BufReader, Read};
+
+
+const UTF8_BOM : &[u8] = b"\xef\xbb\xbf";
+const UTF16LE_BOM : &[u8] = b"\xff\xfe";
+const UTF16BE_BOM : &[u8] = b"\xfe\xff";
+
+
+// Reader of the UTF-8 encoding of a UTF-16 stream. Unpaired surrogates and a trailing odd byte
+// are replaced with U+FFFD.
+pub struct Utf16<R> {
+    inner : R,
+    big_endian : bool,
+    pending : Option<u16>,  // Unit read ahead after an unpaired high surrogate.
+    encoded : [u8; 4],      // The UTF-8 of the current character,
+    start : usize,          //   from here
+    end : usize,            //   to there, not yet read.
+}
+
+impl<R : BufRead> Utf16<R> {
+
+    pub fn new(inner : R, big_endian : bool) -> Utf16<R> {
+        Utf16 { inner, big_endian, pending : None, encoded : [0; 4], start : 0, end : 0 }
+    }
+
+    // The next code unit, `Err(())` standing for a trailing odd byte.
+    fn unit(&mut self) -> io::Result<Option<Result<u16, ()>>> {
+        let buf = self.inner.fill_buf()?;
+        let unit = match *buf {
+            [] => return Ok(None),
+            [_] => {
+                // The other byte may be in the next buffer.
+                let first = buf[0];
+                self.inner.consume(1);
+                let buf = self.inner.fill_buf()?;
+                if buf.is_empty() {
+                    return Ok(Some(Err(())));
+                }
+                [first, buf[0]]
+            },
+            [a, b, ..] => {
+                self.inner.consume(1);
+                [a, b]
+            },
+        };
+        self.inner.consume(1);
+        Ok(Some(Ok(if self.big_endian { u16::from_be_bytes(unit) } else { u16::from_le_bytes(unit) })))
+    }
+
+    // The next character, if any.
+    fn next_char(&mut self) -> io::Result<Option<char>> {
+        let unit = match self.pending.take() {
+            Some(unit) => unit,
+            None => match self.unit()? {
+                Some(Ok(unit)) => unit,
+                Some(Err(())) => return Ok(Some(char::REPLACEMENT_CHARACTER)),
+                None => return Ok(None),
+            },
+        };
+        if !(0xd800..0xdc00).contains(&unit) {
+            return Ok(Some(char::from_u32(u32::from(unit)).unwrap_or(char::REPLACEMENT_CHARACTER)));
+        }
+        match self.unit()? {
+            Some(Ok(low)) if (0xdc00..0xe000).contains(&low) => {
+                let code = 0x10000 + ((u32::from(unit) - 0xd800) << 10) + (u32::from(low) - 0xdc00);
+                Ok(Some(char::from_u32(code).unwrap()))
+            },
+            Some(Ok(other)) => {
+                self.pending = Some(other);
+                Ok(Some(char::REPLACEMENT_CHARACTER))
+            },
+            _ => Ok(Some(char::REPLACEMENT_CHARACTER)),
+        }
+    }
+}
+
+impl<R : BufRead> Read for Utf16<R> {
+
+    fn read(&mut self, buf : &mut [u8]) -> io::Result<usize> {
+        let mut n = 0;
+        while n < buf.len() {
+            if self.start == self.end {
+                match self.next_char()? {
+                    Some(ch) => {
+                        self.start = 0;
+                        self.end = ch.encode_utf8(&mut self.encoded).len();
+                    },
+                    None => break,
+                }
+            }
+            let len = (self.end - self.start).min(buf.len() - n);
+            buf[n..n + len].copy_from_slice(&self.encoded[self.start..self.start + len]);
+            self.start += len;
+            n += len;
+        }
+        Ok(n)
+    }
+}
+
+
+// Where the UTF-8 text of a whole input starts, past its byte order mark, unless it starts with
+// a UTF-16 one.
+pub fn utf8_start(bytes : &[u8]) -> Option<usize> {
+    if bytes.starts_with(UTF16LE_BOM) || bytes.starts_with(UTF16BE_BOM) {
+        return None;
+    }
+    Some(if bytes.starts_with(UTF8_BOM) { UTF8_BOM.len() } else { 0 })
+}
+
+
+// A reader of the UTF-8 encoding of its input, which is transcoded if it starts with a UTF-16
+// byte order mark.
+pub enum MaybeTranscoded<R> {
+    Utf8(R),
+    Utf16(Box<BufReader<Utf16<R>>>),
+}
+
+impl<R : BufRead> MaybeTranscoded<R> {
+
+    // Detect the encoding from the byte order mark of `inner`, if any, which is skipped.
+    pub fn new(mut inner : R) -> io::Result<MaybeTranscoded<R>> {
+        let prefix = inner.fill_buf()?;
+        if prefix.starts_with(UTF8_BOM) {
+            inner.consume(UTF8_BOM.len());
+            return Ok(MaybeTranscoded::Utf8(inner));
+        }
+        let big_endian = if prefix.starts_with(UTF16LE_BOM) {
+            false
+        } else if prefix.starts_with(UTF16BE_BOM) {
+            true
+        } else {
+            return Ok(MaybeTranscoded::Utf8(inner));
+        };
+        inner.consume(2);
+        Ok(MaybeTranscoded::Utf16(Box::new(BufReader::new(Utf16::new(inner, big_endian)))))
+    }
+}
+
+impl<R : BufRead> Read for MaybeTranscoded<R> {
+    fn read(&mut self, buf : &mut [u8]) -> io::Result<usize> {
+        match self {
+            MaybeTranscoded::Utf8(inner) => inner.read(buf),
+            MaybeTranscoded::Utf16(inner) => inner.read(buf),
+        }
+    }
+}
+
+impl<R : BufRead> BufRead for MaybeTranscoded<R> {
+
+    fn fill_buf(&mut self) -> io::Result<&[u8]> {
+        match self {
+            MaybeTranscoded::Utf8(inner) => inner.fill_buf(),
+            MaybeTranscoded::Utf16(inner) => inner.fill_buf(),
+        }
+    }
+
+    fn consume(&mut self, amount : usize) {
+        match self {
+            MaybeTranscoded::Utf8(inner) => inner.consume(amount),
+            MaybeTranscoded::Utf16(inner) => inner.consume(amount),
+        }
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    fn decode(data : &[u8]) -> String {
+        let mut text = String::new();
+        MaybeTranscoded::new(data).unwrap().read_to_string(&mut text).unwrap();
+        text
+    }
+
+    fn utf16(text : &str, big_endian : bool) -> Vec<u8> {
+        let mut data = if big_endian { UTF16BE_BOM.to_vec() } else { UTF16LE_BOM.to_vec() };
+        for unit in text.encode_utf16() {
+            data.extend_from_slice(&if big_endian { unit.to_be_bytes() } else { unit.to_le_bytes() });
+        }
+        data
+    }
+
+    #[test]
+    fn byte_order_marks() {
+        let text = "plain ascii\nünïcödé 🐸\n";
+        assert_eq!(text, decode(text.as_bytes()));
+        assert_eq!(text, decode(&[UTF8_BOM, text.as_bytes()].concat()));
+        assert_eq!(text, decode(&utf16(text, false)));
+        assert_eq!(text, decode(&utf16(text, true)));
+        assert_eq!("", decode(UTF16LE_BOM));
+    }
+
+    #[test]
+    fn invalid_utf16() {
+        // Unpaired surrogates, and an odd byte at the end.
+        assert_eq!("a\u{fffd}b", decode(b"\xff\xfea\x00\x00\xd8b\x00"));
+        assert_eq!("\u{fffd}a", decode(b"\xff\xfe\x00\xdca\x00"));
+        assert_eq!("a\u{fffd}", decode(b"\xff\xfea\x00\x00\xd8"));
+        assert_eq!("a\u{fffd}", decode(b"\xfe\xff\x00a\x00"));
+    }
+
+    #[test]
+    fn small_reads() {
+        // The characters are split across reads, and the units across input buffers.
+        let text = "🐸 frog, 🐸 frog\n".repeat(100);
+        let data = utf16(&text, false);
+        let inner = BufReader::with_capacity(3, &data[2..]);
+        let mut reader = Utf16::new(inner, false);
+        let mut out = vec![];
+        let mut buf = [0; 3];
+        loop {
+            match reader.read(&mut buf).unwrap() {
+                0 => break,
+                n => out.extend_from_slice(&buf[..n]),
+            }
+        }
+        assert_eq!(text.as_bytes(), out.as_slice());
+    }
+}
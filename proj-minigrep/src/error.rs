@@ -0,0 +1,74 @@
+//
+// `error.rs` defines the errors of minigrep, so that callers can tell a bad command line from a
+//   file that could not be read, and which file it was.
+//
+// Failures to read one input do not stop the others from being searched: they are collected in
+//   the `Report` of the run, while the errors stopping a run are returned as such.
+//
+
+use std::error::Error;
+use std::fmt;
+use std::io;
+use std::path::PathBuf;
+
+use crate::regex::RegexError;
+
+
+#[derive(Debug)]
+pub enum MinigrepError {
+    // Bad command line, the message telling what is wrong.
+    Usage(String),
+    // Failure to read an input, or to write the output when there is no path.
+    Io { path : Option<PathBuf>, err : io::Error },
+    // Input that must be valid UTF-8 but is not.
+    Encoding { path : PathBuf, message : &'static str },
+    // Regular expression that does not compile; the position is within `pattern`.
+    Pattern { pattern : String, err : RegexError },
+}
+
+impl MinigrepError {
+
+    // Whether the reader of the output went away (e.g. `| head`), which is not worth reporting.
+    pub fn is_broken_pipe(&self) -> bool {
+        match self {
+            MinigrepError::Io { err, .. } => err.kind() == io::ErrorKind::BrokenPipe,
+            _ => false,
+        }
+    }
+
+    // Whether writing the output failed, which stops the run instead of failing an input.
+    pub fn is_output(&self) -> bool {
+        matches!(self, MinigrepError::Io { path : None, .. })
+    }
+}
+
+impl fmt::Display for MinigrepError {
+    fn fmt(&self, f : &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            MinigrepError::Usage(message) => write!(f, "{}", message),
+            MinigrepError::Io { path : Some(path), err } => write!(f, "{}: {}", path.display(), err),
+            MinigrepError::Io { path : None, err } => write!(f, "{}", err),
+            MinigrepError::Encoding { path, message } => write!(f, "{}: {}", path.display(), message),
+            MinigrepError::Pattern { pattern, err } => {
+                write!(f, "Invalid pattern '{}': {}", pattern, err)
+            },
+        }
+    }
+}
+
+impl Error for MinigrepError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            MinigrepError::Io { err, .. } => Some(err),
+            MinigrepError::Pattern { err, .. } => Some(err),
+            _ => None,
+        }
+    }
+}
+
+// Failure to write the output.
+impl From<io::Error> for MinigrepError {
+    fn from(err : io::Error) -> MinigrepError {
+        MinigrepError::Io { path : None, err }
+    }
+}
@@ -0,0 +1,184 @@
+//
+// `filter.rs` chooses which of the files found by the recursive search are searched, with
+//   `--include`, `--exclude`, `--type` and `--max-filesize`.
+//
+// A file is searched if it matches one of the included globs or types (when any is given),
+//   none of the excluded globs, and is not larger than the maximum size. The globs (see
+//   `glob.rs`) match the path relative to the directory searched; an excluded glob also
+//   excludes everything within the directories it matches.
+//
+
+use crate::glob::{Glob, GlobError};
+
+
+// File types, and the globs of the files they are made of.
+pub const TYPES : [(&str, &[&str]); 17] = [
+    ("c", &["*.{c,h}"]),
+    ("cpp", &["*.{cc,cpp,cxx,c++,hh,hpp,hxx,h++,inl}"]),
+    ("css", &["*.{css,scss,sass,less}"]),
+    ("go", &["*.go"]),
+    ("html", &["*.{htm,html,xhtml}"]),
+    ("java", &["*.java"]),
+    ("js", &["*.{js,mjs,cjs,jsx}"]),
+    ("json", &["*.{json,jsonl}"]),
+    ("markdown", &["*.{md,markdown,mdown,mkd}"]),
+    ("py", &["*.{py,pyi}"]),
+    ("rust", &["*.rs"]),
+    ("sh", &["*.{sh,bash,zsh}", ".{bashrc,bash_profile,profile,zshrc}"]),
+    ("toml", &["*.toml", "Cargo.lock"]),
+    ("ts", &["*.{ts,mts,cts,tsx}"]),
+    ("txt", &["*.txt"]),
+    ("xml", &["*.{xml,xsd,xsl,xslt,svg}"]),
+    ("yaml", &["*.{yaml,yml}"]),
+];
+
+// The globs of the file type `name`, if known.
+pub fn type_globs(name : &str) -> Option<&'static [&'static str]> {
+    TYPES.iter().find(|&&(type_name, _)| type_name == name).map(|&(_, globs)| globs)
+}
+
+
+// Which files to search, everything by default.
+#[derive(Debug, Clone, Default)]
+pub struct Filter {
+    includes : Vec<Glob>,
+    excludes : Vec<Glob>,
+    max_filesize : Option<u64>,     // In bytes.
+}
+
+impl Filter {
+
+    pub fn include(&mut self, pattern : &str) -> Result<(), GlobError> {
+        self.includes.push(Glob::new(pattern)?);
+        Ok(())
+    }
+
+    pub fn exclude(&mut self, pattern : &str) -> Result<(), GlobError> {
+        self.excludes.push(Glob::new(pattern)?);
+        Ok(())
+    }
+
+    // Include the files of a type; false if unknown.
+    pub fn include_type(&mut self, name : &str) -> bool {
+        match type_globs(name) {
+            Some(globs) => {
+                self.includes.extend(globs.iter().map(|glob| Glob::new(glob).unwrap()));
+                true
+            },
+            None => false,
+        }
+    }
+
+    pub fn max_filesize(&mut self, max_filesize : Option<u64>) {
+        self.max_filesize = max_filesize;
+    }
+
+    // Whether to search the file at the `/`-separated `relative` path, whose size in bytes is
+    // only asked for when limited.
+    pub fn allows<F>(&self, relative : &str, size : F) -> bool
+        where F : FnOnce() -> Option<u64>
+    {
+        if !self.includes.is_empty() && !self.includes.iter().any(|glob| glob.is_match(relative)) {
+            return false;
+        }
+        // The path itself, then its directories.
+        let mut prefix = relative;
+        loop {
+            if self.excludes.iter().any(|glob| glob.is_match(prefix)) {
+                return false;
+            }
+            match prefix.rfind('/') {
+                Some(slash) => prefix = &prefix[..slash],
+                None => break,
+            }
+        }
+        match self.max_filesize {
+            Some(max) => size().is_none_or(|size| size <= max),
+            None => true,
+        }
+    }
+}
+
+
+// Parse a size in bytes, with an optional `K`, `M` or `G` suffix (powers of 1024).
+pub fn parse_size(value : &str) -> Option<u64> {
+    let (digits, shift) = match value.char_indices().last()? {
+        (at, 'K') | (at, 'k') => (&value[..at], 10),
+        (at, 'M') | (at, 'm') => (&value[..at], 20),
+        (at, 'G') | (at, 'g') => (&value[..at], 30),
+        _ => (value, 0),
+    };
+    if digits.is_empty() || !digits.bytes().all(|b| b.is_ascii_digit()) {
+        return None;
+    }
+    digits.parse::<u64>().ok()?.checked_mul(1 << shift)
+}
+
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    fn allowed(filter : &Filter, relative : &str) -> bool {
+        filter.allows(relative, || panic!("size checked"))
+    }
+
+    #[test]
+    fn includes_and_excludes() {
+        let mut filter = Filter::default();
+        assert!(allowed(&filter, "anything/at/all"));
+
+        filter.include("*.rs").unwrap();
+        filter.include("docs/*.md").unwrap();
+        assert!(allowed(&filter, "src/main.rs"));
+        assert!(allowed(&filter, "docs/guide.md"));
+        assert!(!allowed(&filter, "src/docs/guide.md"));
+        assert!(!allowed(&filter, "README.md"));
+
+        filter.exclude("*_test.rs").unwrap();
+        filter.exclude("target").unwrap();
+        assert!(!allowed(&filter, "src/search_test.rs"));
+        assert!(!allowed(&filter, "target/debug/build.rs"));
+        assert!(!allowed(&filter, "sub/target/gen.rs"));
+        assert!(allowed(&filter, "src/targets.rs"));
+    }
+
+    #[test]
+    fn types() {
+        assert_eq!(TYPES.len(), TYPES.iter().filter_map(|&(name, _)| type_globs(name)).count());
+        for (_, globs) in TYPES.iter() {
+            assert!(globs.iter().all(|glob| Glob::new(glob).is_ok()));
+        }
+
+        let mut filter = Filter::default();
+        assert!(!filter.include_type("cobol"));
+        assert!(filter.include_type("rust"));
+        assert!(filter.include_type("toml"));
+        assert!(allowed(&filter, "src/lib.rs"));
+        assert!(allowed(&filter, "Cargo.toml"));
+        assert!(allowed(&filter, "Cargo.lock"));
+        assert!(!allowed(&filter, "src/lib.rs.orig"));
+    }
+
+    #[test]
+    fn sizes() {
+        let mut filter = Filter::default();
+        filter.max_filesize(Some(100));
+        assert!(filter.allows("a", || Some(100)));
+        assert!(!filter.allows("a", || Some(101)));
+        assert!(filter.allows("a", || None));
+
+        assert_eq!(Some(0), parse_size("0"));
+        assert_eq!(Some(1536), parse_size("1536"));
+        assert_eq!(Some(10 << 10), parse_size("10K"));
+        assert_eq!(Some(2 << 20), parse_size("2m"));
+        assert_eq!(Some(1 << 30), parse_size("1G"));
+        assert_eq!(None, parse_size(""));
+        assert_eq!(None, parse_size("K"));
+        assert_eq!(None, parse_size("-1"));
+        assert_eq!(None, parse_size("1.5M"));
+        assert_eq!(None, parse_size("1T"));
+        assert_eq!(None, parse_size("99999999999999999999G"));
+    }
+}
@@ -0,0 +1,113 @@
+//
+// `fold.rs` implements Unicode case folding, used for case insensitive matching.
+//
+// Lowercasing is not enough to compare texts case insensitively: `ß` uppercases to `SS`, the
+//   Greek final `ς` and `σ` are the same letter, and the Kelvin sign `K` is a `k`. Case folding
+//   maps all the case variants of a character to the same string, which is what the standard
+//   library does not offer directly. It is derived here from the standard case mappings:
+//
+//   fold(c) = lowercase(uppercase(lowercase(c)))
+//
+//   which gives the same classes as the full folding of `CaseFolding.txt`, except for the
+//   Turkish dotless `ı`, which folds to itself there. The default (non-Turkic) folding is used,
+//   so `I` folds to `i` and the dotted `İ` to `i` followed by a combining dot above.
+//
+// Full folding may change the length of a text (`ß` folds to `ss`); simple folding maps each
+//   character to exactly one character, leaving the ones without such a folding alone.
+//
+
+// Dotless i, the only character whose folding is not derived from the case mappings.
+const DOTLESS_I : char = 'ı';
+
+
+// Push the full case folding of `ch` to `out`.
+pub fn fold_char(ch : char, out : &mut String) {
+    if ch == DOTLESS_I || ch.is_ascii() {
+        out.push(ch.to_ascii_lowercase());
+        return;
+    }
+    for lower in ch.to_lowercase() {
+        for upper in lower.to_uppercase() {
+            out.extend(upper.to_lowercase());
+        }
+    }
+}
+
+// Full case folding of a whole text.
+pub fn fold(text : &str) -> String {
+    let mut folded = String::with_capacity(text.len());
+    for ch in text.chars() {
+        fold_char(ch, &mut folded);
+    }
+    folded
+}
+
+// Simple case folding of `ch`: a single character, `ch` itself when its folding would take more.
+pub fn simple_fold(ch : char) -> char {
+    if ch == DOTLESS_I || ch.is_ascii() {
+        return ch.to_ascii_lowercase();
+    }
+    let lower = match single(ch.to_lowercase()) {
+        Some(lower) => lower,
+        None => return ch,
+    };
+    match single(lower.to_uppercase()) {
+        Some(upper) => single(upper.to_lowercase()).unwrap_or(lower),
+        None => lower,
+    }
+}
+
+// The only item of `iter`, if it has exactly one.
+fn single<I : Iterator<Item = char>>(mut iter : I) -> Option<char> {
+    match (iter.next(), iter.next()) {
+        (Some(ch), None) => Some(ch),
+        _ => None,
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    #[test]
+    fn ascii() {
+        assert_eq!("hello, world!", fold("Hello, WORLD!"));
+        assert_eq!('a', simple_fold('A'));
+    }
+
+    #[test]
+    fn length_changing() {
+        assert_eq!("strasse", fold("Straße"));
+        assert_eq!(fold("STRASSE"), fold("straße"));
+        assert_eq!(fold("ẞ"), fold("ss"));
+        assert_eq!("ffi", fold("ﬃ"));
+        assert_eq!('ß', simple_fold('ẞ'));
+        assert_eq!('ß', simple_fold('ß'));
+    }
+
+    #[test]
+    fn same_letter_variants() {
+        assert_eq!(fold("ΣΊΣΥΦΟΣ"), fold("σίσυφος"));
+        assert_eq!('σ', simple_fold('ς'));
+        assert_eq!('k', simple_fold('\u{212a}'));   // Kelvin sign.
+        assert_eq!('s', simple_fold('ſ'));          // Long s.
+    }
+
+    #[test]
+    fn turkish_i() {
+        assert_eq!("i", fold("I"));
+        assert_eq!("ı", fold("ı"));
+        assert_eq!("i\u{307}", fold("İ"));
+        assert_eq!('ı', simple_fold('ı'));
+        assert_eq!('İ', simple_fold('İ'));
+        assert_ne!(fold("ırmak"), fold("IRMAK"));
+    }
+
+    #[test]
+    fn caseless_scripts() {
+        assert_eq!("नमस्ते", fold("नमस्ते"));
+        assert_eq!("здравствуйте", fold("Здравствуйте"));
+    }
+}
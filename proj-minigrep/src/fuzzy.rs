@@ -0,0 +1,249 @@
+//
+// `fuzzy.rs` scores how well a line matches a query whose characters it holds in order, though
+//   not necessarily next to each other, the way interactive finders do.
+//
+// Each matched character scores, and more so when it starts a word (after a non-alphanumeric
+//   character, or at the start of the line), a camelCase hump, or a number, or when it follows
+//   the previous matched character; gaps between matched characters cost, the first more. The
+//   bonus of the first character of the query counts double. Among all the ways to match the
+//   query, the best scoring one is found by dynamic programming, in O(query × line).
+//
+// Matching is case insensitive (with the simple case folding of `fold.rs`), unless asked to be
+//   sensitive and the query has an uppercase letter: "smart case".
+//
+
+use crate::fold;
+
+
+const SCORE_MATCH : i64 = 16;
+const PENALTY_GAP_START : i64 = 3;
+const PENALTY_GAP_EXTENSION : i64 = 1;
+const BONUS_BOUNDARY : i64 = 8;     // Start of a word.
+const BONUS_CAMEL : i64 = 7;        // Lowercase to uppercase, or letter to digit.
+const BONUS_CONSECUTIVE : i64 = 4;  // Right after the previous matched character.
+const BONUS_FIRST_FACTOR : i64 = 2;
+
+// Score of the cells that cannot match.
+const NONE : i64 = i64::MIN / 2;
+
+
+// Fuzzy query, matching the lines holding all its characters in order.
+#[derive(Debug, Clone)]
+pub struct Fuzzy {
+    query : Vec<char>,
+    sensitive : bool,
+}
+
+impl Fuzzy {
+
+    pub fn new(query : &str, sensitive : bool) -> Fuzzy {
+        let sensitive = sensitive && query.chars().any(char::is_uppercase);
+        let query = query.chars().map(|ch| fold_if(ch, !sensitive)).collect();
+        Fuzzy { query, sensitive }
+    }
+
+    // Whether `line` holds the characters of the query in order, without scoring it.
+    pub fn is_match(&self, line : &str) -> bool {
+        let mut chars = line.chars().map(|ch| fold_if(ch, !self.sensitive));
+        self.query.iter().all(|&wanted| chars.any(|ch| ch == wanted))
+    }
+
+    // The best score of `line` and the byte spans of the characters matched that way, or `None`
+    // if it does not match. The empty query matches any line with a zero score.
+    pub fn score(&self, line : &str) -> Option<(i64, Vec<(usize, usize)>)> {
+        if !self.is_match(line) {
+            return None;
+        }
+        if self.query.is_empty() {
+            return Some((0, vec![]));
+        }
+
+        let chars : Vec<(usize, char)> = line.char_indices().collect();
+        let bonuses : Vec<i64> = (0..chars.len()).map(|j| {
+            bonus(j.checked_sub(1).map(|before| chars[before].1), chars[j].1)
+        }).collect();
+        let n = chars.len();
+
+        // `scores[j]` is the best score of the query so far with its last character matched at
+        // `j`, `runs[j]` the bonus of the run of consecutive characters ending there, and
+        // `from[i][j]` where the previous character was then matched.
+        let mut scores = vec![NONE; n];
+        let mut runs = vec![0; n];
+        let mut from = vec![vec![0; n]; self.query.len()];
+        for (i, &wanted) in self.query.iter().enumerate() {
+            let mut next = vec![NONE; n];
+            let mut next_runs = vec![0; n];
+            // Best score of the previous characters ending before a gap up to `j`, and where.
+            let mut gapped = (NONE, 0);
+            for j in 0..n {
+                if j >= 2 && scores[j - 2] > NONE {
+                    let start = scores[j - 2] - PENALTY_GAP_START;
+                    if start >= gapped.0 - PENALTY_GAP_EXTENSION {
+                        gapped = (start, j - 2);
+                    } else {
+                        gapped.0 -= PENALTY_GAP_EXTENSION;
+                    }
+                } else if gapped.0 > NONE {
+                    gapped.0 -= PENALTY_GAP_EXTENSION;
+                }
+                if fold_if(chars[j].1, !self.sensitive) != wanted {
+                    continue;
+                }
+
+                if i == 0 {
+                    next[j] = SCORE_MATCH + bonuses[j] * BONUS_FIRST_FACTOR;
+                    next_runs[j] = bonuses[j];
+                    continue;
+                }
+                // The characters of a run all get the bonus of its start, if better.
+                let (consecutive, run) = match j.checked_sub(1) {
+                    Some(before) if scores[before] > NONE => {
+                        let run = runs[before].max(bonuses[j]);
+                        (scores[before] + SCORE_MATCH + run.max(BONUS_CONSECUTIVE), run)
+                    },
+                    _ => (NONE, 0),
+                };
+                let after_gap = match gapped.0 {
+                    NONE => NONE,
+                    score => score + SCORE_MATCH + bonuses[j],
+                };
+                if consecutive > NONE && consecutive >= after_gap {
+                    next[j] = consecutive;
+                    next_runs[j] = run;
+                    from[i][j] = j - 1;
+                } else if after_gap > NONE {
+                    next[j] = after_gap;
+                    next_runs[j] = bonuses[j];
+                    from[i][j] = gapped.1;
+                }
+            }
+            scores = next;
+            runs = next_runs;
+        }
+
+        // The earliest of the best last characters, then back to the first.
+        let (mut j, &best) = scores.iter().enumerate().rev().max_by_key(|&(_, score)| score)?;
+        if best <= NONE {
+            return None;
+        }
+        let mut positions = vec![j];
+        for i in (1..self.query.len()).rev() {
+            j = from[i][j];
+            positions.push(j);
+        }
+        positions.reverse();
+
+        // Merge the adjacent characters into spans.
+        let mut spans : Vec<(usize, usize)> = vec![];
+        for j in positions {
+            let (start, ch) = chars[j];
+            let end = start + ch.len_utf8();
+            match spans.last_mut() {
+                Some(last) if last.1 == start => last.1 = end,
+                _ => spans.push((start, end)),
+            }
+        }
+        Some((best, spans))
+    }
+}
+
+// Bonus for matching `ch`, right after `before` (if not at the start of the line).
+fn bonus(before : Option<char>, ch : char) -> i64 {
+    let before = match before {
+        Some(before) => before,
+        None => return BONUS_BOUNDARY,
+    };
+    if !ch.is_alphanumeric() {
+        0
+    } else if !before.is_alphanumeric() {
+        BONUS_BOUNDARY
+    } else if (before.is_lowercase() && ch.is_uppercase())
+              || (!before.is_numeric() && ch.is_numeric()) {
+        BONUS_CAMEL
+    } else {
+        0
+    }
+}
+
+fn fold_if(ch : char, insensitive : bool) -> char {
+    if insensitive { fold::simple_fold(ch) } else { ch }
+}
+
+
+// The items of `scored` by decreasing score, keeping the order of the ties, and at most `top`
+// of them.
+pub fn rank<T>(mut scored : Vec<(i64, T)>, top : Option<usize>) -> Vec<T> {
+    scored.sort_by_key(|&(score, _)| -score);
+    scored.into_iter().take(top.unwrap_or(usize::MAX)).map(|(_, item)| item).collect()
+}
+
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    // The parts of `line` matched, separated with `|`.
+    fn matched(query : &str, line : &str) -> Option<String> {
+        Fuzzy::new(query, true).score(line).map(|(_, spans)| {
+            let parts : Vec<_> = spans.into_iter().map(|(start, end)| &line[start..end]).collect();
+            parts.join("|")
+        })
+    }
+
+    fn score(query : &str, line : &str) -> i64 {
+        Fuzzy::new(query, true).score(line).unwrap().0
+    }
+
+    #[test]
+    fn subsequences() {
+        assert_eq!(None, matched("abc", "acb"));
+        assert_eq!(None, matched("abc", ""));
+        assert_eq!(Some(""), matched("", "anything").as_deref());
+        assert_eq!(Some("a|b|c"), matched("abc", "a-b-c").as_deref());
+        assert_eq!(Some("abc"), matched("abc", "xxabcxx").as_deref());
+        assert_eq!(Some("Ü|é"), matched("üé", "Über café").as_deref());
+    }
+
+    #[test]
+    fn best_alignment() {
+        // The word starts are preferred to the first occurrences.
+        assert_eq!(Some("f|b"), matched("fb", "xfxb foo_bar").as_deref());
+        assert_eq!(Some("foo|b"), matched("foob", "foo fob foo_bar").as_deref());
+        assert_eq!(Some("S|M"), matched("sm", "useless SearchMatcher").as_deref());
+        // A run is preferred to scattered characters.
+        assert_eq!(Some("bar"), matched("bar", "b a r bar").as_deref());
+    }
+
+    #[test]
+    fn scores() {
+        // Runs first, then word starts and humps, then closer characters.
+        assert!(score("abc", "abc") > score("abc", "a_b_c"));
+        assert!(score("abc", "a_b_c") > score("abc", "axbxc"));
+        assert!(score("sm", "search_matcher") > score("sm", "sum"));
+        assert!(score("sm", "SearchMatcher") > score("sm", "seam"));
+        assert!(score("v2", "vx2") > score("v2", "v12"));
+        assert!(score("fb", "f_b") > score("fb", "f____b"));
+
+        // The run `ab` at the start of the line, then a gap of 2 before the start of a word.
+        let expected = SCORE_MATCH + BONUS_BOUNDARY * BONUS_FIRST_FACTOR
+                       + SCORE_MATCH + BONUS_BOUNDARY
+                       + SCORE_MATCH + BONUS_BOUNDARY - PENALTY_GAP_START - PENALTY_GAP_EXTENSION;
+        assert_eq!(expected, score("abc", "ab__c"));
+    }
+
+    #[test]
+    fn smart_case() {
+        assert!(Fuzzy::new("frog", true).is_match("FROG"));
+        assert!(!Fuzzy::new("Frog", true).is_match("FROG"));
+        assert!(Fuzzy::new("Frog", true).is_match("Frogs"));
+        assert!(Fuzzy::new("Frog", false).is_match("FROG"));
+    }
+
+    #[test]
+    fn ranking() {
+        let scored = vec![(1, "a"), (3, "b"), (1, "c"), (3, "d"), (2, "e")];
+        assert_eq!(vec!["b", "d", "e", "a", "c"], rank(scored.clone(), None));
+        assert_eq!(vec!["b", "d"], rank(scored, Some(2)));
+    }
+}
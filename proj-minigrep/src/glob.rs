@@ -0,0 +1,316 @@
+//
+// `glob.rs` matches paths against shell-like glob patterns, for `--include` / `--exclude` and
+//   the file types, and matches the segments of the ignore rules (see `ignore.rs`).
+//
+// Patterns are made of:
+//   - `*`, matching any run of characters within a path segment, and `?` any single one;
+//   - `[...]`, matching a class of characters, like `[a-z_]`, or not in it with `[!...]`
+//     (or `[^...]`); a leading `]` is part of the class;
+//   - `**` as a whole segment, matching any number of directories;
+//   - `{a,b}`, matching any of the comma separated alternatives, which may nest;
+//   - `\`, escaping the character after it.
+//
+// A pattern without `/` matches the file name at any depth; otherwise it matches the whole
+//   path, relative to the directory searched, and a leading `/` is ignored.
+//
+
+use std::fmt;
+
+
+// A compiled glob pattern.
+#[derive(Debug, Clone)]
+pub struct Glob {
+    pattern : String,
+    alternatives : Vec<Vec<Vec<char>>>,     // The brace expansions, split at `/`.
+}
+
+impl Glob {
+
+    pub fn new(pattern : &str) -> Result<Glob, GlobError> {
+        let alternatives = expand_braces(pattern)?.into_iter().map(|alternative| {
+            let anchored = alternative.contains('/');
+            let alternative = alternative.strip_prefix('/').unwrap_or(&alternative);
+            let mut segments : Vec<Vec<char>> = vec![];
+            if !anchored {
+                segments.push(vec!['*', '*']);
+            }
+            segments.extend(alternative.split('/').map(|segment| segment.chars().collect()));
+            segments
+        }).collect();
+        Ok(Glob { pattern : pattern.to_string(), alternatives })
+    }
+
+    pub fn as_str(&self) -> &str {
+        &self.pattern
+    }
+
+    // Whether the `/`-separated relative `path` matches.
+    pub fn is_match(&self, path : &str) -> bool {
+        let path : Vec<Vec<char>> = path.split('/').map(|seg| seg.chars().collect()).collect();
+        self.alternatives.iter().any(|segments| match_segments(segments, &path))
+    }
+}
+
+
+// Pattern that cannot be compiled.
+#[derive(Debug, Clone, PartialEq)]
+pub struct GlobError {
+    pub pos : usize,    // In characters.
+    pub msg : &'static str,
+}
+
+impl fmt::Display for GlobError {
+    fn fmt(&self, f : &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} at position {}", self.msg, self.pos)
+    }
+}
+
+
+// Expand the `{a,b}` alternatives of `pattern`, nested ones included, in order. Escapes are
+// kept, for the matcher to honor. A `,` or `}` outside of braces is a literal.
+pub fn expand_braces(pattern : &str) -> Result<Vec<String>, GlobError> {
+    let chars : Vec<char> = pattern.chars().collect();
+    let mut pos = 0;
+    expand_sequence(&chars, &mut pos, false)
+}
+
+// Expand from `pos` to the end of the pattern or, if `nested`, of the current alternative.
+fn expand_sequence(chars : &[char], pos : &mut usize, nested : bool)
+                   -> Result<Vec<String>, GlobError> {
+    let mut expansions = vec![String::new()];
+    while *pos < chars.len() {
+        match chars[*pos] {
+            '\\' if *pos + 1 < chars.len() => {
+                for expansion in &mut expansions {
+                    expansion.push('\\');
+                    expansion.push(chars[*pos + 1]);
+                }
+                *pos += 2;
+            },
+            '{' => {
+                let open = *pos;
+                *pos += 1;
+                let mut alternatives = vec![];
+                loop {
+                    alternatives.extend(expand_sequence(chars, pos, true)?);
+                    match chars.get(*pos) {
+                        Some(',') => *pos += 1,
+                        Some('}') => {
+                            *pos += 1;
+                            break;
+                        },
+                        _ => return Err(GlobError { pos : open, msg : "Unclosed brace" }),
+                    }
+                }
+                expansions = expansions.iter().flat_map(|prefix| {
+                    alternatives.iter().map(move |alternative| format!("{}{}", prefix, alternative))
+                }).collect();
+            },
+            ',' | '}' if nested => break,
+            ch => {
+                for expansion in &mut expansions {
+                    expansion.push(ch);
+                }
+                *pos += 1;
+            },
+        }
+    }
+    Ok(expansions)
+}
+
+
+// Match path segments, where a `**` pattern segment spans any number of path segments.
+pub fn match_segments(pattern : &[Vec<char>], path : &[Vec<char>]) -> bool {
+    match pattern.split_first() {
+        None => path.is_empty(),
+        Some((first, rest)) if first.as_slice() == ['*', '*'] => {
+            (0..=path.len()).any(|skip| match_segments(rest, &path[skip..]))
+        },
+        Some((first, rest)) => match path.split_first() {
+            Some((seg, path_rest)) => match_wildcard(first, seg) && match_segments(rest, path_rest),
+            None => false,
+        },
+    }
+}
+
+// Match one segment against a pattern made of literals, `*`, `?`, `[...]` and `\` escapes.
+pub fn match_wildcard(pattern : &[char], text : &[char]) -> bool {
+    match pattern.split_first() {
+        None => text.is_empty(),
+        Some(('*', rest)) => (0..=text.len()).any(|skip| match_wildcard(rest, &text[skip..])),
+        Some(('?', rest)) => !text.is_empty() && match_wildcard(rest, &text[1..]),
+        Some(('[', _)) if class_end(pattern).is_some() => {
+            let end = class_end(pattern).unwrap();
+            match text.split_first() {
+                Some((&ch, text_rest)) => {
+                    match_class(&pattern[1..end], ch) && match_wildcard(&pattern[end + 1..], text_rest)
+                },
+                None => false,
+            }
+        },
+        Some(('\\', rest)) if !rest.is_empty() => {
+            text.first() == Some(&rest[0]) && match_wildcard(&rest[1..], &text[1..])
+        },
+        Some((&lit, rest)) => text.first() == Some(&lit) && match_wildcard(rest, &text[1..]),
+    }
+}
+
+// Index of the `]` closing the class opened at `pattern[0]`, if any.
+fn class_end(pattern : &[char]) -> Option<usize> {
+    let mut i = 1;
+    if matches!(pattern.get(i), Some('!') | Some('^')) {
+        i += 1;
+    }
+    if pattern.get(i) == Some(&']') {
+        i += 1;     // A leading `]` is literal.
+    }
+    while i < pattern.len() {
+        match pattern[i] {
+            ']' => return Some(i),
+            '\\' => i += 2,
+            _ => i += 1,
+        }
+    }
+    None
+}
+
+// Match a character against the inside of a `[...]` class.
+fn match_class(class : &[char], ch : char) -> bool {
+    let (negated, mut class) = match class.split_first() {
+        Some(('!', rest)) | Some(('^', rest)) => (true, rest),
+        _ => (false, class),
+    };
+
+    let mut hit = false;
+    while let Some((&first, rest)) = class.split_first() {
+        let (lo, rest) = match first {
+            '\\' if !rest.is_empty() => (rest[0], &rest[1..]),
+            _ => (first, rest),
+        };
+        if rest.len() >= 2 && rest[0] == '-' {
+            hit |= (lo..=rest[1]).contains(&ch);
+            class = &rest[2..];
+        } else {
+            hit |= lo == ch;
+            class = rest;
+        }
+    }
+    hit != negated
+}
+
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    fn matches(pattern : &str, path : &str) -> bool {
+        Glob::new(pattern).unwrap().is_match(path)
+    }
+
+    fn wildcard(pattern : &str, text : &str) -> bool {
+        let pattern : Vec<char> = pattern.chars().collect();
+        let text : Vec<char> = text.chars().collect();
+        match_wildcard(&pattern, &text)
+    }
+
+    #[test]
+    fn stars_and_questions() {
+        assert!(wildcard("*", ""));
+        assert!(wildcard("*", "anything"));
+        assert!(wildcard("*.rs", "main.rs"));
+        assert!(wildcard("*.rs", ".rs"));
+        assert!(!wildcard("*.rs", "main.rs.orig"));
+        assert!(wildcard("a*b*c", "aXXbYYc"));
+        assert!(wildcard("a*b*c", "abc"));
+        assert!(!wildcard("a*b*c", "acb"));
+        assert!(wildcard("?", "é"));
+        assert!(!wildcard("?", ""));
+        assert!(!wildcard("??", "a"));
+        assert!(wildcard("file?.txt", "file1.txt"));
+    }
+
+    #[test]
+    fn classes() {
+        assert!(wildcard("[abc]", "b"));
+        assert!(!wildcard("[abc]", "d"));
+        assert!(wildcard("[a-cx-z]", "y"));
+        assert!(!wildcard("[a-c]", "-"));
+        assert!(wildcard("[!a-c]", "d"));
+        assert!(wildcard("[^a-c]", "d"));
+        assert!(!wildcard("[^a-c]", "a"));
+        assert!(wildcard("[]]", "]"));
+        assert!(wildcard("[!]]", "a"));
+        assert!(wildcard("[a-]", "-"));
+        assert!(wildcard("[\\]]", "]"));
+        // An unclosed class is a literal `[`.
+        assert!(wildcard("[ab", "[ab"));
+        assert!(!wildcard("[ab", "a"));
+    }
+
+    #[test]
+    fn escapes() {
+        assert!(wildcard("\\*", "*"));
+        assert!(!wildcard("\\*", "a"));
+        assert!(wildcard("a\\?", "a?"));
+        assert!(wildcard("\\[x]", "[x]"));
+        assert!(matches("\\{a,b}", "{a,b}"));
+        assert!(!matches("\\{a,b}", "a"));
+        // A trailing backslash is a literal.
+        assert!(wildcard("a\\", "a\\"));
+    }
+
+    #[test]
+    fn file_names_at_any_depth() {
+        assert!(matches("*.rs", "main.rs"));
+        assert!(matches("*.rs", "src/deep/main.rs"));
+        assert!(!matches("*.rs", "src.rs/main.txt"));
+        assert!(matches("Makefile", "sub/Makefile"));
+        // `*` does not cross directories.
+        assert!(!matches("src*rs", "src/main.rs"));
+    }
+
+    #[test]
+    fn anchored_paths() {
+        assert!(matches("src/*.rs", "src/main.rs"));
+        assert!(!matches("src/*.rs", "src/bin/main.rs"));
+        assert!(!matches("src/*.rs", "lib/src/main.rs"));
+        assert!(matches("/src/*.rs", "src/main.rs"));
+        assert!(matches("src/**/*.rs", "src/main.rs"));
+        assert!(matches("src/**/*.rs", "src/a/b/main.rs"));
+        assert!(matches("**/tests/*", "a/tests/x"));
+        assert!(matches("**/tests/*", "tests/x"));
+        assert!(matches("target/**", "target/debug/build/x"));
+        assert!(!matches("target/**", "src/target/x"));
+        // `**` within a segment is two `*`.
+        assert!(matches("a**b/c", "axxb/c"));
+        assert!(!matches("a**b/c", "ax/xb/c"));
+    }
+
+    #[test]
+    fn braces() {
+        assert_eq!(vec!["a.rs", "a.toml"], expand_braces("a.{rs,toml}").unwrap());
+        assert_eq!(vec!["ac", "ad", "bc", "bd"], expand_braces("{a,b}{c,d}").unwrap());
+        assert_eq!(vec!["x", "ya", "yb", "z"], expand_braces("{x,y{a,b},z}").unwrap());
+        assert_eq!(vec!["file", "file.bak"], expand_braces("file{,.bak}").unwrap());
+        assert_eq!(vec!["a"], expand_braces("{a}").unwrap());
+        // Commas and closing braces outside of braces, and escaped ones, are literals.
+        assert_eq!(vec!["a,b}"], expand_braces("a,b}").unwrap());
+        assert_eq!(vec!["\\{a", "b\\}"], expand_braces("{\\{a,b\\}}").unwrap());
+
+        assert!(matches("*.{rs,toml}", "src/main.rs"));
+        assert!(matches("*.{rs,toml}", "Cargo.toml"));
+        assert!(!matches("*.{rs,toml}", "README.md"));
+        // Alternatives with a `/` are anchored, the others are not.
+        assert!(matches("{docs/*.md,*.txt}", "docs/a.md"));
+        assert!(!matches("{docs/*.md,*.txt}", "src/docs/a.md"));
+        assert!(matches("{docs/*.md,*.txt}", "src/notes.txt"));
+    }
+
+    #[test]
+    fn errors() {
+        assert_eq!(Err(GlobError { pos : 2, msg : "Unclosed brace" }), expand_braces("*.{rs"));
+        assert_eq!(Err(GlobError { pos : 0, msg : "Unclosed brace" }), expand_braces("{a,{b}"));
+        assert_eq!("Unclosed brace at position 3", Glob::new("a/b{").unwrap_err().to_string());
+    }
+}
@@ -0,0 +1,232 @@
+//
+// `ignore.rs` implements `.gitignore` / `.ignore` style rules.
+//
+// Each rule is a glob pattern, relative to the directory holding the ignore file:
+//   - Blank lines and lines starting with `#` are skipped;
+//   - A leading `!` negates the rule, re-including what an earlier rule excluded;
+//   - A trailing `/` restricts the rule to directories;
+//   - A pattern containing a `/` (other than a trailing one) is anchored to the directory of
+//     the ignore file, otherwise it matches a file name at any depth;
+//   - `*` and `?` never cross a `/`, `[...]` matches a class, and `**` as a whole path
+//     segment matches any number of directories (see `glob.rs`).
+//
+// Within one file the last matching rule wins, and files in deeper directories take
+//   precedence over those in their ancestors.
+//
+
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+
+use crate::glob::match_segments;
+
+
+// Names of the ignore files honored, in increasing order of precedence.
+pub const IGNORE_FILES : [&str; 2] = [".gitignore", ".ignore"];
+
+
+// Outcome of matching a path against the rules.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Decision {
+    None,       // No rule applies.
+    Ignore,     // The last applicable rule excludes the path.
+    Include,    // The last applicable rule is a negated one.
+}
+
+#[derive(Debug)]
+struct Rule {
+    segments : Vec<Vec<char>>,  // Pattern split at `/`.
+    anchored : bool,
+    negated : bool,
+    dir_only : bool,
+}
+
+impl Rule {
+
+    // Parse one line of an ignore file; `None` for blanks and comments.
+    fn parse(line : &str) -> Option<Rule> {
+        let line = line.trim_end_matches(['\r', ' ']);
+        if line.is_empty() || line.starts_with('#') {
+            return None;
+        }
+
+        let (negated, line) = match line.strip_prefix('!') {
+            Some(rest) => (true, rest),
+            None => (false, line.strip_prefix('\\').filter(|rest| {
+                rest.starts_with('!') || rest.starts_with('#')
+            }).unwrap_or(line)),
+        };
+        let (dir_only, line) = match line.strip_suffix('/') {
+            Some(rest) => (true, rest),
+            None => (false, line),
+        };
+        if line.is_empty() {
+            return None;
+        }
+
+        let anchored = line.contains('/');
+        let line = line.strip_prefix('/').unwrap_or(line);
+        let segments = line.split('/').map(|seg| seg.chars().collect()).collect();
+
+        Some(Rule { segments, anchored, negated, dir_only })
+    }
+
+    // Check against a `/`-separated path relative to the ignore file's directory.
+    fn matches(&self, relative : &str, is_dir : bool) -> bool {
+        if self.dir_only && !is_dir {
+            return false;
+        }
+
+        let path : Vec<Vec<char>> = relative.split('/').map(|seg| seg.chars().collect()).collect();
+        if self.anchored {
+            match_segments(&self.segments, &path)
+        } else {
+            match_segments(&self.segments, &path[path.len() - 1..])
+        }
+    }
+}
+
+
+// Rules of one directory, chained to those of its ancestors.
+#[derive(Debug, Default)]
+pub struct Ignore {
+    parent : Option<Rc<Ignore>>,
+    dir : PathBuf,
+    rules : Vec<Rule>,
+}
+
+impl Ignore {
+
+    // Empty rule set, ignoring nothing.
+    pub fn empty() -> Rc<Ignore> {
+        Rc::new(Ignore::default())
+    }
+
+    // Build rules from the text of an ignore file located in `dir`.
+    pub fn parse(dir : &Path, text : &str) -> Ignore {
+        Ignore {
+            parent : None,
+            dir : dir.to_path_buf(),
+            rules : text.lines().filter_map(Rule::parse).collect(),
+        }
+    }
+
+    // Load the ignore files of `dir` on top of `parent`. Returns the parent itself if `dir`
+    // has no (readable) ignore files.
+    pub fn load(parent : &Rc<Ignore>, dir : &Path) -> Rc<Ignore> {
+        let mut rules = vec![];
+        for name in IGNORE_FILES.iter() {
+            if let Ok(text) = fs::read_to_string(dir.join(name)) {
+                rules.extend(text.lines().filter_map(Rule::parse));
+            }
+        }
+
+        if rules.is_empty() {
+            Rc::clone(parent)
+        } else {
+            Rc::new(Ignore { parent : Some(Rc::clone(parent)), dir : dir.to_path_buf(), rules })
+        }
+    }
+
+    // Decide whether `path` (under this rule set's directory) is ignored.
+    pub fn decide(&self, path : &Path, is_dir : bool) -> Decision {
+        if let Ok(relative) = path.strip_prefix(&self.dir) {
+            let relative : Vec<_> = relative.iter().map(|part| part.to_string_lossy()).collect();
+            let relative = relative.join("/");
+            if let Some(rule) = self.rules.iter().rev().find(|rule| rule.matches(&relative, is_dir)) {
+                return if rule.negated { Decision::Include } else { Decision::Ignore };
+            }
+        }
+
+        match &self.parent {
+            Some(parent) => parent.decide(path, is_dir),
+            None => Decision::None,
+        }
+    }
+
+    pub fn is_ignored(&self, path : &Path, is_dir : bool) -> bool {
+        self.decide(path, is_dir) == Decision::Ignore
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    fn ignored(rules : &str, path : &str, is_dir : bool) -> bool {
+        Ignore::parse(Path::new("root"), rules).is_ignored(&Path::new("root").join(path), is_dir)
+    }
+
+    #[test]
+    fn comments_and_blanks() {
+        let ignore = Ignore::parse(Path::new("root"), "# comment\n\n   \n\\#hash\n");
+        assert_eq!(1, ignore.rules.len());
+        assert!(ignore.is_ignored(Path::new("root/#hash"), false));
+    }
+
+    #[test]
+    fn basename_at_any_depth() {
+        assert!(ignored("*.log", "a.log", false));
+        assert!(ignored("*.log", "deep/down/a.log", false));
+        assert!(!ignored("*.log", "a.log.txt", false));
+        assert!(ignored("target", "sub/target", true));
+    }
+
+    #[test]
+    fn anchored_patterns() {
+        assert!(ignored("/build", "build", true));
+        assert!(!ignored("/build", "src/build", true));
+        assert!(ignored("doc/*.html", "doc/index.html", false));
+        assert!(!ignored("doc/*.html", "doc/api/index.html", false));
+    }
+
+    #[test]
+    fn double_star() {
+        assert!(ignored("**/cache", "cache", true));
+        assert!(ignored("**/cache", "a/b/cache", true));
+        assert!(ignored("logs/**", "logs/2021/jan.txt", false));
+        assert!(ignored("a/**/z", "a/z", false));
+        assert!(ignored("a/**/z", "a/b/c/z", false));
+        assert!(!ignored("a/**/z", "b/a/z", false));
+    }
+
+    #[test]
+    fn directory_only() {
+        assert!(ignored("out/", "out", true));
+        assert!(!ignored("out/", "out", false));
+    }
+
+    #[test]
+    fn negation_last_rule_wins() {
+        let rules = "*.txt\n!keep.txt\n";
+        assert!(ignored(rules, "drop.txt", false));
+        assert!(!ignored(rules, "keep.txt", false));
+        assert!(ignored("!keep.txt\n*.txt\n", "keep.txt", false));
+        assert!(ignored("\\!bang", "!bang", false));
+    }
+
+    #[test]
+    fn wildcards_and_classes() {
+        assert!(ignored("file?.rs", "file1.rs", false));
+        assert!(!ignored("file?.rs", "file10.rs", false));
+        assert!(ignored("[abc]*.md", "b-notes.md", false));
+        assert!(!ignored("[!abc]*.md", "b-notes.md", false));
+        assert!(ignored("[0-9][0-9].csv", "42.csv", false));
+        assert!(ignored("\\*star", "*star", false));
+        assert!(!ignored("\\*star", "xstar", false));
+    }
+
+    #[test]
+    fn deeper_rules_take_precedence() {
+        let outer = Rc::new(Ignore::parse(Path::new("root"), "*.gen\n"));
+        let inner = Ignore {
+            parent : Some(outer),
+            dir : PathBuf::from("root/keep"),
+            rules : Ignore::parse(Path::new("root/keep"), "!*.gen\n").rules,
+        };
+        assert!(inner.is_ignored(Path::new("root/a.gen"), false));
+        assert_eq!(Decision::Include, inner.decide(Path::new("root/keep/a.gen"), false));
+    }
+}
@@ -0,0 +1,501 @@
+//
+// `index.rs` builds the trigram index of a directory, with which `--indexed` skips the files
+//   that cannot match without reading them.
+//
+// `minigrep index DIR` indexes the files a recursive search of DIR walks to, in
+//   `DIR/.minigrep-index`: for each trigram (three consecutive bytes) of their case folded text,
+//   the files holding it. Files are then only searched if they hold all the trigrams of one of
+//   the texts their matches must contain (see `Query`), once folded the same way. Folding the
+//   text keeps the index fit for case insensitive searches.
+//
+// An index is only trusted for the files it knows unchanged, by their size and modification
+//   time; files changed since, or in the second the index was built, are searched anyway, as
+//   are new files, binary or compressed ones (which are not indexed), and all the files when the
+//   query has no text of three bytes or more. Updating an index only reads such files again.
+//
+// Layout: the magic line, then the time the index was built, the files (path relative to DIR,
+//   size, modification time, and whether indexed), and each trigram in increasing order with
+//   its postings, the ids of its files in increasing order. Numbers are LEB128 varints, and
+//   trigrams and ids are delta encoded.
+//
+
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::convert::TryFrom;
+use std::fs::{self, File, Metadata};
+use std::io::{self, Read, Write};
+use std::mem;
+use std::path::Path;
+use std::process;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::config::{Config, MatchMode};
+use crate::encoding::MaybeTranscoded;
+use crate::error::MinigrepError;
+use crate::fold;
+use crate::inflate;
+use crate::regex;
+use crate::walk::{self, Walk, WalkError, is_binary};
+
+
+// Name of the index in the directory it indexes, which walks skip.
+pub const INDEX_NAME : &str = ".minigrep-index";
+
+// First line of an index, to be changed along with its layout.
+const MAGIC : &[u8] = b"minigrep-index 1\n";
+
+
+// Literal texts the matches of a search must contain.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Query {
+    All(Vec<Query>),    // Each of them; every text satisfies the empty one.
+    Any(Vec<Query>),    // At least one of them.
+    Text(String),
+}
+
+impl Query {
+
+    // The query of the searches run with `config`. Only literal and regex searches of whole
+    // lines have one: inverted searches select the lines without texts, and fields are matched
+    // once their quotes are removed.
+    pub fn new(config : &Config) -> Query {
+        if config.invert || config.field.is_some() {
+            return Query::All(vec![]);
+        }
+        match config.mode {
+            MatchMode::Literal => {
+                let texts = config.patterns.iter().map(|pattern| Query::Text(pattern.clone()));
+                Query::Any(texts.collect())
+            },
+            MatchMode::Regex => {
+                regex::query(&config.patterns, !config.sensitive).unwrap_or(Query::All(vec![]))
+            },
+            MatchMode::Fuzzy => Query::All(vec![]),
+        }
+    }
+}
+
+
+// What tells whether a file changed since indexed.
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct Stamp {
+    size : u64,
+    secs : u64,     // Modification time since the epoch.
+    nanos : u32,
+}
+
+impl Stamp {
+    fn of(metadata : &Metadata) -> Stamp {
+        let modified = metadata.modified().ok()
+                               .and_then(|time| time.duration_since(UNIX_EPOCH).ok())
+                               .unwrap_or_default();
+        Stamp { size : metadata.len(), secs : modified.as_secs(), nanos : modified.subsec_nanos() }
+    }
+}
+
+// A file of an index.
+#[derive(Debug, Clone, PartialEq)]
+struct Entry {
+    path : String,      // Relative to the indexed directory, with `/` separators.
+    stamp : Stamp,
+    indexed : bool,     // Its trigrams are in the postings.
+}
+
+// The trigram index of a directory.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Index {
+    built : u64,                            // When, in seconds since the epoch.
+    files : Vec<Entry>,                     // By id.
+    postings : BTreeMap<u32, Vec<u32>>,     // The ids of the files holding each trigram.
+}
+
+impl Index {
+
+    // Read the index of `dir`.
+    pub fn open(dir : &Path) -> io::Result<Index> {
+        let bytes = fs::read(dir.join(INDEX_NAME))?;
+        Index::decode(&bytes).ok_or_else(|| {
+            io::Error::new(io::ErrorKind::InvalidData, "not an index, or a damaged one")
+        })
+    }
+
+    // Index files along with their trigrams, `None` for the files not indexed.
+    fn new(built : u64, files : Vec<(String, Stamp, Option<Vec<u32>>)>) -> Index {
+        let mut postings = BTreeMap::<u32, Vec<u32>>::new();
+        let mut entries = Vec::with_capacity(files.len());
+        for (id, (path, stamp, trigrams)) in files.into_iter().enumerate() {
+            for &trigram in trigrams.iter().flatten() {
+                postings.entry(trigram).or_default().push(id as u32);
+            }
+            entries.push(Entry { path, stamp, indexed : trigrams.is_some() });
+        }
+        Index { built, files : entries, postings }
+    }
+
+    // Whether the file of `entry`, now with `stamp`, is known not to have changed since indexed.
+    // The ones changed in the second the index was built may have been changed after it.
+    fn trusts(&self, entry : &Entry, stamp : Stamp) -> bool {
+        entry.stamp == stamp && stamp.secs < self.built
+    }
+
+    // The ids of the files that may hold a match of `query`, in increasing order, or `None` if
+    // any may.
+    fn matching(&self, query : &Query) -> Option<Vec<u32>> {
+        match query {
+            Query::Text(text) => {
+                let folded = fold::fold(text);
+                trigrams(folded.as_bytes()).fold(None, |matching, trigram| {
+                    let ids = self.postings.get(&trigram).map_or(&[][..], |ids| ids);
+                    Some(match matching {
+                        Some(matching) => intersect(&matching, ids),
+                        None => ids.to_vec(),
+                    })
+                })
+            },
+            Query::All(queries) => {
+                queries.iter().filter_map(|query| self.matching(query))
+                       .reduce(|matching, ids| intersect(&matching, &ids))
+            },
+            Query::Any(queries) => {
+                let mut matching = vec![];
+                for query in queries {
+                    matching = union(&matching, &self.matching(query)?);
+                }
+                Some(matching)
+            },
+        }
+    }
+
+    fn encode(&self) -> Vec<u8> {
+        let mut bytes = MAGIC.to_vec();
+        put_varint(&mut bytes, self.built);
+        put_varint(&mut bytes, self.files.len() as u64);
+        for entry in &self.files {
+            put_varint(&mut bytes, entry.path.len() as u64);
+            bytes.extend(entry.path.as_bytes());
+            put_varint(&mut bytes, entry.stamp.size);
+            put_varint(&mut bytes, entry.stamp.secs);
+            put_varint(&mut bytes, u64::from(entry.stamp.nanos));
+            bytes.push(entry.indexed as u8);
+        }
+
+        put_varint(&mut bytes, self.postings.len() as u64);
+        let mut last_trigram = 0;
+        for (&trigram, ids) in &self.postings {
+            put_varint(&mut bytes, u64::from(trigram - last_trigram));
+            last_trigram = trigram;
+            put_varint(&mut bytes, ids.len() as u64);
+            let mut last_id = 0;
+            for &id in ids {
+                put_varint(&mut bytes, u64::from(id - last_id));
+                last_id = id;
+            }
+        }
+        bytes
+    }
+
+    fn decode(bytes : &[u8]) -> Option<Index> {
+        let mut rest = bytes.strip_prefix(MAGIC)?;
+        let built = take_varint(&mut rest)?;
+        let count = take_varint(&mut rest)?;
+        let mut files = vec![];
+        for _ in 0..count {
+            let len = take_varint(&mut rest)? as usize;
+            let path = std::str::from_utf8(rest.get(..len)?).ok()?.to_string();
+            rest = &rest[len..];
+            let size = take_varint(&mut rest)?;
+            let secs = take_varint(&mut rest)?;
+            let nanos = u32::try_from(take_varint(&mut rest)?).ok()?;
+            let (&indexed, tail) = rest.split_first()?;
+            rest = tail;
+            files.push(Entry { path, stamp : Stamp { size, secs, nanos }, indexed : indexed == 1 });
+        }
+
+        let mut postings = BTreeMap::new();
+        let mut trigram = 0u32;
+        for _ in 0..take_varint(&mut rest)? {
+            trigram = trigram.checked_add(u32::try_from(take_varint(&mut rest)?).ok()?)?;
+            let mut ids = vec![];
+            let mut id = 0u32;
+            for _ in 0..take_varint(&mut rest)? {
+                id = id.checked_add(u32::try_from(take_varint(&mut rest)?).ok()?)?;
+                if id as usize >= files.len() {
+                    return None;
+                }
+                ids.push(id);
+            }
+            postings.insert(trigram, ids);
+        }
+        if !rest.is_empty() {
+            return None;
+        }
+        Some(Index { built, files, postings })
+    }
+}
+
+
+// The files of an index that may hold a match of a query.
+pub struct Candidates {
+    index : Index,
+    ids : HashMap<String, u32>,     // Of the files, by path.
+    matching : Option<Vec<u32>>,    // All of them if `None`.
+}
+
+impl Candidates {
+
+    pub fn new(index : Index, query : &Query) -> Candidates {
+        let ids = index.files.iter().enumerate()
+                       .map(|(id, entry)| (entry.path.clone(), id as u32))
+                       .collect();
+        let matching = index.matching(query);
+        Candidates { index, ids, matching }
+    }
+
+    // Whether the file at `path`, at `relative` from the indexed directory (see
+    // `walk::relative_path`), can be skipped: it was indexed, has not changed since, and is not a
+    // candidate.
+    pub fn skips(&self, relative : &str, path : &Path) -> bool {
+        let (matching, &id) = match (&self.matching, self.ids.get(relative)) {
+            (Some(matching), Some(id)) => (matching, id),
+            _ => return false,
+        };
+        let entry = &self.index.files[id as usize];
+        if !entry.indexed || matching.binary_search(&id).is_ok() {
+            return false;
+        }
+        fs::metadata(path).is_ok_and(|metadata| self.index.trusts(entry, Stamp::of(&metadata)))
+    }
+}
+
+
+// What updating an index did.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct Update {
+    pub files : usize,      // In the index.
+    pub read : usize,       // Of them, the ones read, new or changed since the last update.
+    pub removed : usize,    // Files of the last update gone since.
+}
+
+// Build the index of `dir`, or update it: only the files new or changed since are read.
+pub fn update(dir : &Path) -> Result<Update, MinigrepError> {
+    let failed = |path : &Path| {
+        let path = path.to_path_buf();
+        move |err| MinigrepError::Io { path : Some(path), err }
+    };
+    if !dir.is_dir() {
+        let err = io::Error::new(io::ErrorKind::InvalidInput, "not a directory");
+        return Err(failed(dir)(err));
+    }
+    let built = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default().as_secs();
+
+    // A damaged index is built again from scratch.
+    let mut last = match Index::open(dir) {
+        Ok(index) => Some(index),
+        Err(err) if matches!(err.kind(), io::ErrorKind::NotFound | io::ErrorKind::InvalidData) => {
+            None
+        },
+        Err(err) => return Err(failed(&dir.join(INDEX_NAME))(err)),
+    };
+    // The trigrams of the files of the last update, by path.
+    let mut known = HashMap::new();
+    if let Some(last) = &mut last {
+        let mut trigrams = vec![vec![]; last.files.len()];
+        for (&trigram, ids) in &last.postings {
+            for &id in ids {
+                trigrams[id as usize].push(trigram);
+            }
+        }
+        for (entry, trigrams) in last.files.iter().zip(trigrams) {
+            known.insert(entry.path.clone(), (entry.clone(), trigrams));
+        }
+    }
+
+    let mut update = Update::default();
+    let mut files = vec![];
+    for found in Walk::new(dir) {
+        let path = found.map_err(|WalkError { path, err }| failed(&path)(err))?;
+        // Paths that are not UTF-8 are left out, and always searched.
+        if path.to_str().is_none() {
+            continue;
+        }
+        let relative = walk::relative_path(dir, &path);
+        let stamp = Stamp::of(&fs::metadata(&path).map_err(failed(&path))?);
+        let trigrams = match (&last, known.get_mut(&relative)) {
+            (Some(last), Some((entry, trigrams))) if last.trusts(entry, stamp) => {
+                entry.indexed.then(|| mem::take(trigrams))
+            },
+            _ => {
+                update.read += 1;
+                read_trigrams(&path).map_err(failed(&path))?
+            },
+        };
+        files.push((relative, stamp, trigrams));
+    }
+
+    let paths : HashSet<_> = files.iter().map(|(path, _, _)| path.as_str()).collect();
+    update.removed = known.keys().filter(|path| !paths.contains(path.as_str())).count();
+    update.files = files.len();
+    let index = Index::new(built, files);
+    write_index(dir, &index.encode()).map_err(failed(&dir.join(INDEX_NAME)))?;
+    Ok(update)
+}
+
+// The trigrams of the text of the file at `path`, sorted, or `None` if not indexed. The text is
+// the one searched: transcoded to UTF-8, its invalid sequences replaced, then case folded.
+fn read_trigrams(path : &Path) -> io::Result<Option<Vec<u32>>> {
+    let bytes = fs::read(path)?;
+    if inflate::detect(&bytes).is_some() {
+        return Ok(None);
+    }
+    let mut text = vec![];
+    MaybeTranscoded::new(&bytes[..])?.read_to_end(&mut text)?;
+    if is_binary(&text) {
+        return Ok(None);
+    }
+    let folded = fold::fold(&String::from_utf8_lossy(&text));
+    let mut trigrams : Vec<_> = trigrams(folded.as_bytes()).collect();
+    trigrams.sort_unstable();
+    trigrams.dedup();
+    Ok(Some(trigrams))
+}
+
+// Replace the index of `dir` with `bytes`, written aside then renamed for searches not to read
+// half of it.
+fn write_index(dir : &Path, bytes : &[u8]) -> io::Result<()> {
+    let temp = dir.join(format!("{}.{}.tmp", INDEX_NAME, process::id()));
+    let result = File::create(&temp).and_then(|mut file| file.write_all(bytes))
+                                    .and_then(|_| fs::rename(&temp, dir.join(INDEX_NAME)));
+    if result.is_err() {
+        let _ = fs::remove_file(&temp);
+    }
+    result
+}
+
+
+// The trigrams of `bytes`, in order, each as a big endian number.
+fn trigrams(bytes : &[u8]) -> impl Iterator<Item = u32> + '_ {
+    bytes.windows(3).map(|w| u32::from(w[0]) << 16 | u32::from(w[1]) << 8 | u32::from(w[2]))
+}
+
+// The ids in both of two sorted lists.
+fn intersect(a : &[u32], b : &[u32]) -> Vec<u32> {
+    a.iter().copied().filter(|id| b.binary_search(id).is_ok()).collect()
+}
+
+// The ids in either of two sorted lists, sorted.
+fn union(a : &[u32], b : &[u32]) -> Vec<u32> {
+    let mut ids = [a, b].concat();
+    ids.sort_unstable();
+    ids.dedup();
+    ids
+}
+
+fn put_varint(bytes : &mut Vec<u8>, mut n : u64) {
+    while n >= 0x80 {
+        bytes.push(n as u8 | 0x80);
+        n >>= 7;
+    }
+    bytes.push(n as u8);
+}
+
+// Read a varint off the front of `bytes`.
+fn take_varint(bytes : &mut &[u8]) -> Option<u64> {
+    let mut n = 0u64;
+    for (i, &byte) in bytes.iter().enumerate().take(10) {
+        n |= u64::from(byte & 0x7f).checked_shl(7 * i as u32)?;
+        if byte & 0x80 == 0 {
+            *bytes = &bytes[i + 1..];
+            return Some(n);
+        }
+    }
+    None
+}
+
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    fn index(files : &[(&str, Option<&str>)]) -> Index {
+        let stamp = Stamp { size : 0, secs : 0, nanos : 0 };
+        Index::new(1, files.iter().map(|&(path, text)| {
+            let trigrams = text.map(|text| {
+                let mut trigrams : Vec<_> = trigrams(fold::fold(text).as_bytes()).collect();
+                trigrams.sort_unstable();
+                trigrams.dedup();
+                trigrams
+            });
+            (path.to_string(), stamp, trigrams)
+        }).collect())
+    }
+
+    fn text(text : &str) -> Query {
+        Query::Text(text.to_string())
+    }
+
+    #[test]
+    fn queries() {
+        let index = index(&[
+            ("a", Some("fn main() {}")),
+            ("b", Some("Struct Config")),
+            ("c", None),
+            ("d", Some("config = MAIN")),
+        ]);
+        assert_eq!(Some(vec![0, 3]), index.matching(&text("main")));
+        assert_eq!(Some(vec![1, 3]), index.matching(&text("CONFIG")));
+        assert_eq!(None, index.matching(&text("fn")));
+        assert_eq!(Some(vec![]), index.matching(&text("missing")));
+
+        let both = Query::All(vec![text("config"), text("main"), text("=")]);
+        assert_eq!(Some(vec![3]), index.matching(&both));
+        let either = Query::Any(vec![text("struct"), text("fn main")]);
+        assert_eq!(Some(vec![0, 1]), index.matching(&either));
+        assert_eq!(None, index.matching(&Query::Any(vec![text("struct"), text("fn")])));
+        assert_eq!(None, index.matching(&Query::All(vec![])));
+    }
+
+    #[test]
+    fn regex_queries() {
+        let query = |pattern : &str, insensitive| regex::query(&[pattern], insensitive).unwrap();
+        assert_eq!(Query::Any(vec![Query::Any(vec![text("abc")])]), query("abc", false));
+        assert_eq!(Query::Any(vec![Query::Any(vec![text("ab1"), text("ab2"), text("xy")])]),
+                   query("ab[12]|xy", false));
+        assert_eq!(Query::Any(vec![Query::All(vec![Query::Any(vec![text("foo")]),
+                                                   Query::All(vec![]),
+                                                   Query::Any(vec![text("(")])])]),
+                   query(r"foo\w*\(", false));
+        // Repeated at least once, or not at all.
+        assert_eq!(Query::Any(vec![Query::All(vec![Query::Any(vec![text("")]),
+                                                   Query::Any(vec![text("ab")]),
+                                                   Query::Any(vec![text("c")])])]),
+                   query("(ab)+c", false));
+        assert_eq!(Query::Any(vec![Query::All(vec![Query::Any(vec![text("a")]),
+                                                   Query::All(vec![]),
+                                                   Query::Any(vec![text("")])])]),
+                   query("ab?", false));
+        // Case variants of `é` may fold to other texts.
+        assert_eq!(Query::Any(vec![Query::All(vec![Query::Any(vec![text("caf")]),
+                                                   Query::All(vec![]),
+                                                   Query::Any(vec![text("")])])]),
+                   query("café", true));
+    }
+
+    #[test]
+    fn layout() {
+        let mut index = index(&[("src/a.rs", Some("fn main() {}")), ("b.bin", None)]);
+        index.files[0].stamp = Stamp { size : 12, secs : 1_700_000_000, nanos : 999_999_999 };
+        let bytes = index.encode();
+        assert!(bytes.starts_with(MAGIC));
+        assert_eq!(Some(index), Index::decode(&bytes));
+        assert_eq!(None, Index::decode(&bytes[..bytes.len() - 1]));
+        assert_eq!(None, Index::decode(&[&bytes[..], b"x"].concat()));
+
+        let mut bytes = vec![];
+        for &n in &[0, 127, 128, 300, u64::MAX] {
+            put_varint(&mut bytes, n);
+        }
+        let mut rest = &bytes[..];
+        let numbers : Vec<_> = std::iter::from_fn(|| take_varint(&mut rest)).collect();
+        assert_eq!(vec![0, 127, 128, 300, u64::MAX], numbers);
+    }
+}
@@ -0,0 +1,799 @@
+//
+// `inflate.rs` decompresses gzip and zlib streams on the fly, so compressed files can be searched
+//   through the same streaming path as plain ones.
+//
+// Both formats wrap a DEFLATE stream (RFC 1951) with a header and a checksum trailer: gzip
+//   (RFC 1952) starts with the bytes `1f 8b` and ends with a CRC-32, zlib (RFC 1950) starts with
+//   a two byte header whose value is a multiple of 31 and ends with an Adler-32. Concatenated
+//   gzip members are decoded one after the other, like `gzip -d` does.
+//
+// The decoder reads the compressed input bit by bit, and decodes the Huffman codes one bit at a
+//   time from the code length counts, the canonical way; the last 32 KiB of output are kept for
+//   the back references.
+//
+
+use std::io::{self, BufRead, BufReader, Read};
+
+
+// Size of the back reference window.
+const WINDOW : usize = 1 << 15;
+
+// Base lengths and extra bits of the length symbols 257 to 285.
+const LENGTH_BASE : [u16; 29] = [
+    3, 4, 5, 6, 7, 8, 9, 10, 11, 13, 15, 17, 19, 23, 27, 31, 35, 43, 51, 59, 67, 83, 99, 115,
+    131, 163, 195, 227, 258,
+];
+const LENGTH_EXTRA : [u8; 29] = [
+    0, 0, 0, 0, 0, 0, 0, 0, 1, 1, 1, 1, 2, 2, 2, 2, 3, 3, 3, 3, 4, 4, 4, 4, 5, 5, 5, 5, 0,
+];
+// Base distances and extra bits of the distance symbols 0 to 29.
+const DIST_BASE : [u16; 30] = [
+    1, 2, 3, 4, 5, 7, 9, 13, 17, 25, 33, 49, 65, 97, 129, 193, 257, 385, 513, 769, 1025, 1537,
+    2049, 3073, 4097, 6145, 8193, 12289, 16385, 24577,
+];
+const DIST_EXTRA : [u8; 30] = [
+    0, 0, 0, 0, 1, 1, 2, 2, 3, 3, 4, 4, 5, 5, 6, 6, 7, 7, 8, 8, 9, 9, 10, 10, 11, 11, 12, 12, 13, 13,
+];
+// Order in which the code lengths of the code length alphabet are given.
+const CODE_LENGTH_ORDER : [usize; 19] = [
+    16, 17, 18, 0, 8, 7, 9, 6, 10, 5, 11, 4, 12, 3, 13, 2, 14, 1, 15,
+];
+
+
+// Number of first bytes telling the formats apart.
+const HEADER_LEN : usize = 2;
+
+// Compressed formats, told apart by their first bytes.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Format {
+    Gzip,
+    Zlib,
+}
+
+// The format of a stream starting with `prefix`, if compressed.
+pub fn detect(prefix : &[u8]) -> Option<Format> {
+    match *prefix {
+        [0x1f, 0x8b, ..] => Some(Format::Gzip),
+        // Deflate method with a 32 KiB window, no preset dictionary, and the flags of one of the
+        // four compression levels encoders write. Other headers are allowed by the format but
+        // not looked for, as plain texts could start like them (`80`, `x = 1` and `x?` do).
+        [0x78, 0x01 | 0x5e | 0x9c | 0xda, ..] => Some(Format::Zlib),
+        _ => None,
+    }
+}
+
+fn invalid(message : &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, format!("invalid compressed data: {}", message))
+}
+
+
+// Reader of the bits of a byte stream, least significant bit first.
+struct Bits<R> {
+    inner : R,
+    buf : Box<[u8]>,
+    pos : usize,
+    len : usize,
+    bitbuf : u32,
+    bitcnt : u32,   // Always less than 8 between calls, so a byte boundary is easy to reach.
+}
+
+impl<R : Read> Bits<R> {
+
+    fn new(inner : R) -> Bits<R> {
+        Bits { inner, buf : vec![0; 1 << 15].into_boxed_slice(), pos : 0, len : 0, bitbuf : 0,
+               bitcnt : 0 }
+    }
+
+    // The next input byte, if any.
+    fn next_byte(&mut self) -> io::Result<Option<u8>> {
+        if self.pos == self.len {
+            self.len = loop {
+                match self.inner.read(&mut self.buf) {
+                    Err(err) if err.kind() == io::ErrorKind::Interrupted => continue,
+                    result => break result?,
+                }
+            };
+            self.pos = 0;
+            if self.len == 0 {
+                return Ok(None);
+            }
+        }
+        self.pos += 1;
+        Ok(Some(self.buf[self.pos - 1]))
+    }
+
+    fn need_byte(&mut self) -> io::Result<u8> {
+        self.next_byte()?.ok_or_else(|| {
+            io::Error::new(io::ErrorKind::UnexpectedEof, "compressed stream ended early")
+        })
+    }
+
+    // The next `n` bits, at most 16, as a number.
+    fn bits(&mut self, n : u32) -> io::Result<u32> {
+        while self.bitcnt < n {
+            self.bitbuf |= u32::from(self.need_byte()?) << self.bitcnt;
+            self.bitcnt += 8;
+        }
+        let value = self.bitbuf & ((1 << n) - 1);
+        self.bitbuf >>= n;
+        self.bitcnt -= n;
+        Ok(value)
+    }
+
+    // Skip to the next byte boundary.
+    fn align(&mut self) {
+        self.bitbuf = 0;
+        self.bitcnt = 0;
+    }
+
+    // The next whole byte; only at a byte boundary.
+    fn byte(&mut self) -> io::Result<u8> {
+        self.need_byte()
+    }
+
+    // The next byte at a byte boundary, without consuming it.
+    fn peek_byte(&mut self) -> io::Result<Option<u8>> {
+        let byte = self.next_byte()?;
+        if byte.is_some() {
+            self.pos -= 1;
+        }
+        Ok(byte)
+    }
+
+    fn u16_le(&mut self) -> io::Result<u16> {
+        Ok(u16::from_le_bytes([self.byte()?, self.byte()?]))
+    }
+
+    fn u32_le(&mut self) -> io::Result<u32> {
+        Ok(u32::from_le_bytes([self.byte()?, self.byte()?, self.byte()?, self.byte()?]))
+    }
+}
+
+
+// A canonical Huffman code, given by the number of codes of each length and the symbols sorted
+// by code.
+#[derive(Debug, Clone, Default)]
+struct Huffman {
+    counts : [u16; 16],
+    symbols : Vec<u16>,
+}
+
+impl Huffman {
+
+    // The code giving each symbol the length at its index, zero meaning unused. Incomplete codes
+    // are allowed, as a single distance code is.
+    fn new(lengths : &[u8]) -> io::Result<Huffman> {
+        let mut counts = [0u16; 16];
+        for &len in lengths {
+            counts[len as usize] += 1;
+        }
+        let mut left : i32 = 1;
+        for &count in &counts[1..] {
+            left = 2 * left - i32::from(count);
+            if left < 0 {
+                return Err(invalid("over-subscribed code"));
+            }
+        }
+
+        let mut offsets = [0u16; 16];
+        for len in 1..15 {
+            offsets[len + 1] = offsets[len] + counts[len];
+        }
+        let mut symbols = vec![0; lengths.len()];
+        for (symbol, &len) in lengths.iter().enumerate() {
+            if len != 0 {
+                symbols[offsets[len as usize] as usize] = symbol as u16;
+                offsets[len as usize] += 1;
+            }
+        }
+        counts[0] = 0;
+        Ok(Huffman { counts, symbols })
+    }
+
+    // Decode one symbol. The codes of each length are consecutive numbers, following the ones
+    // of the shorter lengths, so the code can be located one bit at a time.
+    fn decode<R : Read>(&self, bits : &mut Bits<R>) -> io::Result<u16> {
+        let mut code : i32 = 0;     // Bits read so far.
+        let mut first : i32 = 0;    // First code of the current length.
+        let mut index : i32 = 0;    // Index of that code among the symbols.
+        for &count in &self.counts[1..] {
+            code |= bits.bits(1)? as i32;
+            let count = i32::from(count);
+            if code - first < count {
+                return Ok(self.symbols[(index + code - first) as usize]);
+            }
+            index += count;
+            first = (first + count) << 1;
+            code <<= 1;
+        }
+        Err(invalid("unknown code"))
+    }
+}
+
+fn fixed_codes() -> (Huffman, Huffman) {
+    let mut lengths = [0u8; 288];
+    lengths[..144].fill(8);
+    lengths[144..256].fill(9);
+    lengths[256..280].fill(7);
+    lengths[280..].fill(8);
+    (Huffman::new(&lengths).unwrap(), Huffman::new(&[5; 30]).unwrap())
+}
+
+
+// Running checksum of the decompressed data.
+#[derive(Debug, Clone, Copy)]
+enum Checksum {
+    Crc32(u32),
+    Adler32(u32, u32),
+}
+
+impl Checksum {
+
+    fn new(format : Format) -> Checksum {
+        match format {
+            Format::Gzip => Checksum::Crc32(!0),
+            Format::Zlib => Checksum::Adler32(1, 0),
+        }
+    }
+
+    fn update(&mut self, data : &[u8]) {
+        match self {
+            Checksum::Crc32(crc) => {
+                for &byte in data {
+                    *crc = CRC_TABLE[((*crc ^ u32::from(byte)) & 0xff) as usize] ^ (*crc >> 8);
+                }
+            },
+            Checksum::Adler32(a, b) => {
+                // The sums fit in 32 bits for this many bytes before being reduced.
+                for chunk in data.chunks(5552) {
+                    for &byte in chunk {
+                        *a += u32::from(byte);
+                        *b += *a;
+                    }
+                    *a %= 65521;
+                    *b %= 65521;
+                }
+            },
+        }
+    }
+
+    fn value(self) -> u32 {
+        match self {
+            Checksum::Crc32(crc) => !crc,
+            Checksum::Adler32(a, b) => b << 16 | a,
+        }
+    }
+}
+
+// CRC-32 of each byte value, with the reversed polynomial of gzip.
+const CRC_TABLE : [u32; 256] = crc_table();
+
+const fn crc_table() -> [u32; 256] {
+    let mut table = [0; 256];
+    let mut n = 0;
+    while n < 256 {
+        let mut crc = n as u32;
+        let mut k = 0;
+        while k < 8 {
+            crc = if crc & 1 != 0 { 0xedb8_8320 ^ (crc >> 1) } else { crc >> 1 };
+            k += 1;
+        }
+        table[n] = crc;
+        n += 1;
+    }
+    table
+}
+
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum State {
+    Header,             // Before the header of a gzip member or zlib stream.
+    Block,              // Before a block header.
+    Stored(usize),      // Inside a stored block, with this many bytes left.
+    Codes,              // Inside a Huffman coded block.
+    Trailer,            // After the last block.
+    Done,
+}
+
+// Reader of the decompressed contents of a gzip or zlib stream.
+pub struct Decoder<R> {
+    bits : Bits<R>,
+    format : Format,
+    state : State,
+    last : bool,                // The current block is the last one.
+    lit : Huffman,              // Literal/length code of the current block.
+    dist : Huffman,             // Distance code of the current block.
+    copy : (usize, usize),      // Length and distance of the back reference being copied.
+    window : Vec<u8>,           // The last `WINDOW` bytes of output, as a ring.
+    total : usize,              // Bytes of output of the current member.
+    checksum : Checksum,
+}
+
+impl<R : Read> Decoder<R> {
+
+    pub fn new(inner : R, format : Format) -> Decoder<R> {
+        Decoder {
+            bits : Bits::new(inner),
+            format,
+            state : State::Header,
+            last : false,
+            lit : Huffman::default(),
+            dist : Huffman::default(),
+            copy : (0, 0),
+            window : vec![0; WINDOW],
+            total : 0,
+            checksum : Checksum::new(format),
+        }
+    }
+
+    fn header(&mut self) -> io::Result<()> {
+        let bits = &mut self.bits;
+        match self.format {
+            Format::Gzip => {
+                if [bits.byte()?, bits.byte()?] != [0x1f, 0x8b] {
+                    return Err(invalid("not a gzip member"));
+                }
+                if bits.byte()? != 8 {
+                    return Err(invalid("unknown compression method"));
+                }
+                let flags = bits.byte()?;
+                if flags & 0xe0 != 0 {
+                    return Err(invalid("reserved gzip flags set"));
+                }
+                for _ in 0..6 {
+                    bits.byte()?;   // Modification time, extra flags, and operating system.
+                }
+                if flags & 0x04 != 0 {
+                    let len = bits.u16_le()?;
+                    for _ in 0..len {
+                        bits.byte()?;
+                    }
+                }
+                // Zero terminated file name and comment.
+                for flag in [0x08, 0x10] {
+                    if flags & flag != 0 {
+                        while bits.byte()? != 0 {}
+                    }
+                }
+                if flags & 0x02 != 0 {
+                    bits.u16_le()?;     // Header CRC.
+                }
+            },
+            Format::Zlib => {
+                let (cmf, flg) = (bits.byte()?, bits.byte()?);
+                if detect(&[cmf, flg]) != Some(Format::Zlib) {
+                    return Err(invalid("not a zlib stream"));
+                }
+            },
+        }
+        self.total = 0;
+        self.checksum = Checksum::new(self.format);
+        Ok(())
+    }
+
+    fn block(&mut self) -> io::Result<()> {
+        self.last = self.bits.bits(1)? == 1;
+        match self.bits.bits(2)? {
+            0 => {
+                self.bits.align();
+                let len = self.bits.u16_le()?;
+                if self.bits.u16_le()? != !len {
+                    return Err(invalid("stored block length does not check"));
+                }
+                self.state = State::Stored(len as usize);
+            },
+            1 => {
+                let (lit, dist) = fixed_codes();
+                self.lit = lit;
+                self.dist = dist;
+                self.state = State::Codes;
+            },
+            2 => {
+                self.dynamic_codes()?;
+                self.state = State::Codes;
+            },
+            _ => return Err(invalid("unknown block type")),
+        }
+        Ok(())
+    }
+
+    // Read the code descriptions of a dynamic block, themselves Huffman coded.
+    fn dynamic_codes(&mut self) -> io::Result<()> {
+        let bits = &mut self.bits;
+        let nlen = bits.bits(5)? as usize + 257;
+        let ndist = bits.bits(5)? as usize + 1;
+        let ncode = bits.bits(4)? as usize + 4;
+        if nlen > 286 || ndist > 30 {
+            return Err(invalid("too many codes"));
+        }
+
+        let mut lengths = [0u8; 19];
+        for &symbol in &CODE_LENGTH_ORDER[..ncode] {
+            lengths[symbol] = bits.bits(3)? as u8;
+        }
+        let code_lengths = Huffman::new(&lengths)?;
+
+        let mut lengths = vec![0u8; nlen + ndist];
+        let mut i = 0;
+        while i < lengths.len() {
+            let symbol = code_lengths.decode(bits)?;
+            let (len, repeat) = match symbol {
+                0..=15 => (symbol as u8, 1),
+                16 if i == 0 => return Err(invalid("repeated length without a first one")),
+                16 => (lengths[i - 1], 3 + bits.bits(2)? as usize),
+                17 => (0, 3 + bits.bits(3)? as usize),
+                _ => (0, 11 + bits.bits(7)? as usize),
+            };
+            if i + repeat > lengths.len() {
+                return Err(invalid("too many code lengths"));
+            }
+            lengths[i..i + repeat].fill(len);
+            i += repeat;
+        }
+        if lengths[256] == 0 {
+            return Err(invalid("no end of block code"));
+        }
+
+        self.lit = Huffman::new(&lengths[..nlen])?;
+        self.dist = Huffman::new(&lengths[nlen..])?;
+        Ok(())
+    }
+
+    // Decode the next symbol of a Huffman coded block: a literal byte to output, or the start of
+    // a back reference, or the end of the block.
+    fn symbol(&mut self) -> io::Result<Option<u8>> {
+        let symbol = self.lit.decode(&mut self.bits)? as usize;
+        if symbol < 256 {
+            return Ok(Some(symbol as u8));
+        }
+        if symbol == 256 {
+            self.state = State::Block;
+            return Ok(None);
+        }
+
+        let symbol = symbol - 257;
+        if symbol >= LENGTH_BASE.len() {
+            return Err(invalid("unknown length symbol"));
+        }
+        let len = LENGTH_BASE[symbol] as usize
+                  + self.bits.bits(u32::from(LENGTH_EXTRA[symbol]))? as usize;
+        let symbol = self.dist.decode(&mut self.bits)? as usize;
+        if symbol >= DIST_BASE.len() {
+            return Err(invalid("unknown distance symbol"));
+        }
+        let dist = DIST_BASE[symbol] as usize
+                   + self.bits.bits(u32::from(DIST_EXTRA[symbol]))? as usize;
+        if dist > self.total {
+            return Err(invalid("distance too far back"));
+        }
+        self.copy = (len, dist);
+        Ok(None)
+    }
+
+    fn trailer(&mut self) -> io::Result<()> {
+        self.bits.align();
+        match self.format {
+            Format::Gzip => {
+                if self.bits.u32_le()? != self.checksum.value() {
+                    return Err(invalid("CRC-32 mismatch"));
+                }
+                if self.bits.u32_le()? != self.total as u32 {
+                    return Err(invalid("length mismatch"));
+                }
+                // Another member may follow; anything else is ignored, like `gzip -d` does.
+                self.state = match self.bits.peek_byte()? {
+                    Some(0x1f) => State::Header,
+                    _ => State::Done,
+                };
+            },
+            Format::Zlib => {
+                let bytes = [self.bits.byte()?, self.bits.byte()?, self.bits.byte()?,
+                             self.bits.byte()?];
+                if u32::from_be_bytes(bytes) != self.checksum.value() {
+                    return Err(invalid("Adler-32 mismatch"));
+                }
+                self.state = State::Done;
+            },
+        }
+        Ok(())
+    }
+}
+
+impl<R : Read> Read for Decoder<R> {
+
+    fn read(&mut self, buf : &mut [u8]) -> io::Result<usize> {
+        let mut n = 0;
+        while n < buf.len() {
+            // Output the pending back reference first, or the next byte.
+            let byte = if self.copy.0 > 0 {
+                self.copy.0 -= 1;
+                self.window[self.total.wrapping_sub(self.copy.1) % WINDOW]
+            } else {
+                let byte = match self.state {
+                    State::Header => {
+                        self.header()?;
+                        self.state = State::Block;
+                        None
+                    },
+                    State::Block if self.last => {
+                        self.last = false;
+                        self.state = State::Trailer;
+                        None
+                    },
+                    State::Block => {
+                        self.block()?;
+                        None
+                    },
+                    State::Stored(0) => {
+                        self.state = State::Block;
+                        None
+                    },
+                    State::Stored(left) => {
+                        self.state = State::Stored(left - 1);
+                        Some(self.bits.byte()?)
+                    },
+                    State::Codes => self.symbol()?,
+                    // The bytes already in `buf` go into the checksum first, on return.
+                    State::Trailer if n > 0 => break,
+                    State::Trailer => {
+                        self.trailer()?;
+                        None
+                    },
+                    State::Done => break,
+                };
+                match byte {
+                    Some(byte) => byte,
+                    None => continue,
+                }
+            };
+
+            buf[n] = byte;
+            self.window[self.total % WINDOW] = byte;
+            self.total += 1;
+            n += 1;
+        }
+        self.checksum.update(&buf[..n]);
+        Ok(n)
+    }
+}
+
+
+// A reader of the decompressed contents of its input if compressed, else of the input itself.
+pub enum MaybeCompressed<R> {
+    Plain(Peeked<R>),
+    Compressed(Box<BufReader<Decoder<Peeked<R>>>>),
+}
+
+impl<R : BufRead> MaybeCompressed<R> {
+
+    // Detect the format from the first bytes of `inner`. A pipe may buffer fewer bytes than
+    // the header at first, which are then taken out of it until the header is whole, or the
+    // input ends.
+    pub fn new(mut inner : R) -> io::Result<MaybeCompressed<R>> {
+        let mut head = vec![];
+        let format = loop {
+            let buf = inner.fill_buf()?;
+            if head.is_empty() && (buf.is_empty() || buf.len() >= HEADER_LEN) {
+                break detect(buf);
+            }
+            if buf.is_empty() {
+                break detect(&head);
+            }
+            let taken = buf.len().min(HEADER_LEN - head.len());
+            head.extend_from_slice(&buf[..taken]);
+            inner.consume(taken);
+            if head.len() == HEADER_LEN {
+                break detect(&head);
+            }
+        };
+
+        let inner = Peeked { head, pos : 0, inner };
+        Ok(match format {
+            Some(format) => {
+                MaybeCompressed::Compressed(Box::new(BufReader::new(Decoder::new(inner, format))))
+            },
+            None => MaybeCompressed::Plain(inner),
+        })
+    }
+}
+
+// A reader giving back the first bytes taken out of `inner` to detect its format, then the
+// rest of it.
+pub struct Peeked<R> {
+    head : Vec<u8>,
+    pos : usize,
+    inner : R,
+}
+
+impl<R : BufRead> Read for Peeked<R> {
+    fn read(&mut self, buf : &mut [u8]) -> io::Result<usize> {
+        if self.pos == self.head.len() {
+            return self.inner.read(buf);
+        }
+        let n = (&self.head[self.pos..]).read(buf)?;
+        self.pos += n;
+        Ok(n)
+    }
+}
+
+impl<R : BufRead> BufRead for Peeked<R> {
+
+    fn fill_buf(&mut self) -> io::Result<&[u8]> {
+        if self.pos == self.head.len() {
+            return self.inner.fill_buf();
+        }
+        Ok(&self.head[self.pos..])
+    }
+
+    fn consume(&mut self, amount : usize) {
+        if self.pos == self.head.len() {
+            self.inner.consume(amount);
+        } else {
+            self.pos = (self.pos + amount).min(self.head.len());
+        }
+    }
+}
+
+impl<R : BufRead> Read for MaybeCompressed<R> {
+    fn read(&mut self, buf : &mut [u8]) -> io::Result<usize> {
+        match self {
+            MaybeCompressed::Plain(inner) => inner.read(buf),
+            MaybeCompressed::Compressed(inner) => inner.read(buf),
+        }
+    }
+}
+
+impl<R : BufRead> BufRead for MaybeCompressed<R> {
+
+    fn fill_buf(&mut self) -> io::Result<&[u8]> {
+        match self {
+            MaybeCompressed::Plain(inner) => inner.fill_buf(),
+            MaybeCompressed::Compressed(inner) => inner.fill_buf(),
+        }
+    }
+
+    fn consume(&mut self, amount : usize) {
+        match self {
+            MaybeCompressed::Plain(inner) => inner.consume(amount),
+            MaybeCompressed::Compressed(inner) => inner.consume(amount),
+        }
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    const STORED : [u8; 22] = [
+        0x78, 0x01, 0x01, 0x0b, 0x00, 0xf4, 0xff, 0x73, 0x74, 0x6f, 0x72, 0x65, 0x64, 0x20, 0x74,
+        0x65, 0x78, 0x74, 0x1b, 0x19, 0x04, 0x77,
+    ];
+    const FIXED : [u8; 17] = [
+        0x78, 0xda, 0xcb, 0x48, 0xcd, 0xc9, 0xc9, 0x57, 0xc8, 0x40, 0x27, 0x15, 0x01, 0x70, 0xd5,
+        0x08, 0xd2,
+    ];
+    const FIRST : [u8; 26] = [
+        0x1f, 0x8b, 0x08, 0x00, 0x00, 0x00, 0x00, 0x00, 0x02, 0x03, 0x4b, 0xcb, 0x2c, 0x2a, 0x2e,
+        0xe1, 0x02, 0x00, 0x2a, 0xb3, 0x4a, 0xc7, 0x06, 0x00, 0x00, 0x00,
+    ];
+    const SECOND : [u8; 27] = [
+        0x1f, 0x8b, 0x08, 0x00, 0x00, 0x00, 0x00, 0x00, 0x02, 0x03, 0x2b, 0x4e, 0x4d, 0xce, 0xcf,
+        0x4b, 0xe1, 0x02, 0x00, 0x7e, 0xc0, 0x0f, 0x06, 0x07, 0x00, 0x00, 0x00,
+    ];
+    // With a file name in the header.
+    const NAMED : [u8; 35] = [
+        0x1f, 0x8b, 0x08, 0x08, 0x00, 0x00, 0x00, 0x00, 0x02, 0xff, 0x70, 0x6f, 0x65, 0x6d, 0x2e,
+        0x74, 0x78, 0x74, 0x00, 0xcb, 0x4b, 0xcc, 0x4d, 0x4d, 0xe1, 0x02, 0x00, 0xdc, 0xf0, 0x7a,
+        0x41, 0x06, 0x00, 0x00, 0x00,
+    ];
+
+    fn inflate(data : &[u8]) -> io::Result<String> {
+        let mut text = String::new();
+        MaybeCompressed::new(data)?.read_to_string(&mut text)?;
+        Ok(text)
+    }
+
+    #[test]
+    fn detection() {
+        assert_eq!(Some(Format::Gzip), detect(&FIRST));
+        assert_eq!(Some(Format::Zlib), detect(&STORED));
+        assert_eq!(Some(Format::Zlib), detect(&FIXED));
+        assert_eq!(None, detect(b"x"));
+        assert_eq!(None, detect(b"plain text"));
+        assert_eq!(None, detect(b"80 lines"));
+        // Text whose first two bytes pass the header check.
+        for text in [&b"x = 1"[..], b"x?", b"x}"] {
+            assert_eq!(None, detect(text));
+        }
+        assert_eq!("x = 1\n", inflate(b"x = 1\n").unwrap());
+        assert_eq!("plain text", inflate(b"plain text").unwrap());
+    }
+
+    // Reader giving one byte at a time, as a slow pipe may.
+    struct Trickle<'a>(&'a [u8]);
+
+    impl<'a> Read for Trickle<'a> {
+        fn read(&mut self, buf : &mut [u8]) -> io::Result<usize> {
+            let n = self.0.len().min(buf.len()).min(1);
+            buf[..n].copy_from_slice(&self.0[..n]);
+            self.0 = &self.0[n..];
+            Ok(n)
+        }
+    }
+
+    #[test]
+    fn trickled_header() {
+        let inflate = |data : &[u8]| {
+            let mut text = String::new();
+            MaybeCompressed::new(BufReader::new(Trickle(data)))?.read_to_string(&mut text)?;
+            io::Result::Ok(text)
+        };
+        assert_eq!("first\n", inflate(&FIRST).unwrap());
+        assert_eq!("hello hello hello hello!", inflate(&FIXED).unwrap());
+        assert_eq!("plain text", inflate(b"plain text").unwrap());
+        assert_eq!("x", inflate(b"x").unwrap());
+        assert_eq!("", inflate(b"").unwrap());
+    }
+
+    #[test]
+    fn blocks() {
+        assert_eq!("stored text", inflate(&STORED).unwrap());
+        assert_eq!("hello hello hello hello!", inflate(&FIXED).unwrap());
+        assert_eq!("named\n", inflate(&NAMED).unwrap());
+
+        // Dynamic blocks, with back references across the whole window.
+        let text : String = (0..5000).map(|i| {
+            format!("line {}: {} {}\n", i, "abc".repeat(i % 5), i * i % 977)
+        }).collect();
+        let data = include_bytes!("../tests/golden/lines.txt.gz");
+        assert_eq!(text, inflate(data).unwrap());
+
+        // Read in small pieces, back references get split across reads.
+        let mut decoder = Decoder::new(&data[..], Format::Gzip);
+        let mut out = vec![];
+        let mut buf = [0; 7];
+        loop {
+            match decoder.read(&mut buf).unwrap() {
+                0 => break,
+                n => out.extend_from_slice(&buf[..n]),
+            }
+        }
+        assert_eq!(text.as_bytes(), out.as_slice());
+    }
+
+    #[test]
+    fn members() {
+        let mut data = FIRST.to_vec();
+        data.extend_from_slice(&SECOND);
+        assert_eq!("first\nsecond\n", inflate(&data).unwrap());
+
+        // Trailing bytes that do not start a member are ignored.
+        data.extend_from_slice(&[0, 0, 0]);
+        assert_eq!("first\nsecond\n", inflate(&data).unwrap());
+    }
+
+    #[test]
+    fn corrupt() {
+        let mut data = FIRST.to_vec();
+        data[18] ^= 1;
+        assert_eq!("invalid compressed data: CRC-32 mismatch", inflate(&data).unwrap_err().to_string());
+
+        let mut data = STORED.to_vec();
+        data[21] ^= 1;
+        assert_eq!("invalid compressed data: Adler-32 mismatch", inflate(&data).unwrap_err().to_string());
+
+        let mut data = STORED.to_vec();
+        data[5] ^= 1;
+        assert_eq!("invalid compressed data: stored block length does not check",
+                   inflate(&data).unwrap_err().to_string());
+
+        let err = inflate(&FIRST[..15]).unwrap_err();
+        assert_eq!(io::ErrorKind::UnexpectedEof, err.kind());
+
+        // A back reference before the start of the output.
+        let err = inflate(&[0x78, 0x9c, 0x03, 0x02, 0x00]).unwrap_err();
+        assert_eq!("invalid compressed data: distance too far back", err.to_string());
+    }
+}
@@ -0,0 +1,209 @@
+//
+// `json.rs` writes search results as JSON Lines: one JSON object per event, one event per line.
+//
+//   {"type":"begin","path":P}
+//   {"type":"match","path":P,"line_number":N,"offset":N,"line":S,
+//    "submatches":[{"text":S,"start":N,"end":N},...]}
+//   {"type":"context","path":P,"line_number":N,"offset":N,"line":S}
+//   {"type":"end","path":P,"matched_lines":N,"matches":N}
+//   {"type":"summary","searches":N,"searches_with_match":N,"matched_lines":N,"matches":N}
+//
+// An input gets `begin` and `end` events only if some of its lines are reported, and a single
+//   `summary` closes the run. Offsets are in bytes; `start` and `end` are within the line.
+//
+// A block of lines selected in multi-line mode is a single `match`, whose `line` holds all its
+//   lines and which has an `"end_line_number":N` after its `line_number`.
+//
+// The serializer is hand-written, as the output only needs strings, numbers, objects, and
+//   arrays.
+//
+
+use std::io::{self, Write};
+use std::path::Path;
+
+use crate::search::{Line, Stats, Summary};
+
+
+// Writer of one JSON object, whose fields are written in order.
+pub struct Object<'w, W : Write> {
+    out : &'w mut W,
+    empty : bool,
+}
+
+impl<'w, W : Write> Object<'w, W> {
+
+    pub fn new(out : &'w mut W) -> io::Result<Object<'w, W>> {
+        out.write_all(b"{")?;
+        Ok(Object { out, empty : true })
+    }
+
+    fn key(&mut self, key : &str) -> io::Result<()> {
+        if !self.empty {
+            self.out.write_all(b",")?;
+        }
+        self.empty = false;
+        write_string(self.out, key)?;
+        self.out.write_all(b":")
+    }
+
+    pub fn string(&mut self, key : &str, value : &str) -> io::Result<&mut Self> {
+        self.key(key)?;
+        write_string(self.out, value)?;
+        Ok(self)
+    }
+
+    pub fn number(&mut self, key : &str, value : usize) -> io::Result<&mut Self> {
+        self.key(key)?;
+        write!(self.out, "{}", value)?;
+        Ok(self)
+    }
+
+    // A field whose value is written by `value`.
+    pub fn raw<F>(&mut self, key : &str, value : F) -> io::Result<&mut Self>
+        where F : FnOnce(&mut W) -> io::Result<()>
+    {
+        self.key(key)?;
+        value(self.out)?;
+        Ok(self)
+    }
+
+    pub fn end(&mut self) -> io::Result<()> {
+        self.out.write_all(b"}")
+    }
+}
+
+// Write `value` as a JSON string, escaping quotes, backslashes, and control characters.
+pub fn write_string<W : Write>(out : &mut W, value : &str) -> io::Result<()> {
+    out.write_all(b"\"")?;
+    let mut clean = 0;  // Start of the run of characters needing no escape.
+    for (i, ch) in value.char_indices() {
+        let escape = match ch {
+            '"' => "\\\"",
+            '\\' => "\\\\",
+            '\n' => "\\n",
+            '\r' => "\\r",
+            '\t' => "\\t",
+            '\u{8}' => "\\b",
+            '\u{c}' => "\\f",
+            ch if (ch as u32) < 0x20 => "",     // Other C0 controls, as `\u00XX`.
+            _ => continue,
+        };
+        out.write_all(&value.as_bytes()[clean..i])?;
+        if escape.is_empty() {
+            write!(out, "\\u{:04x}", ch as u32)?;
+        } else {
+            out.write_all(escape.as_bytes())?;
+        }
+        clean = i + ch.len_utf8();
+    }
+    out.write_all(&value.as_bytes()[clean..])?;
+    out.write_all(b"\"")
+}
+
+
+pub fn write_begin<W : Write>(out : &mut W, path : &Path) -> io::Result<()> {
+    Object::new(out)?.string("type", "begin")?
+                     .string("path", &path.display().to_string())?
+                     .end()?;
+    writeln!(out)
+}
+
+// Write a selected or context line; breaks are not events.
+pub fn write_line<W : Write>(out : &mut W, path : &Path, line : &Line) -> io::Result<()> {
+    let path = path.display().to_string();
+    match line {
+        Line::Match(m) => {
+            let mut object = Object::new(out)?;
+            object.string("type", "match")?
+                  .string("path", &path)?
+                  .number("line_number", m.line_number)?;
+            if m.end_line_number() > m.line_number {
+                object.number("end_line_number", m.end_line_number())?;
+            }
+            object.number("offset", m.offset)?
+                  .string("line", m.line)?
+                  .raw("submatches", |out| {
+                      out.write_all(b"[")?;
+                      let spans = m.spans.iter().filter(|(start, end)| start < end);
+                      for (i, &(start, end)) in spans.enumerate() {
+                          if i > 0 {
+                              out.write_all(b",")?;
+                          }
+                          Object::new(out)?.string("text", &m.line[start..end])?
+                                           .number("start", start)?
+                                           .number("end", end)?
+                                           .end()?;
+                      }
+                      out.write_all(b"]")
+                  })?
+                  .end()?;
+        },
+        Line::Context { line_number, offset, line } => {
+            Object::new(out)?.string("type", "context")?
+                             .string("path", &path)?
+                             .number("line_number", *line_number)?
+                             .number("offset", *offset)?
+                             .string("line", line)?
+                             .end()?;
+        },
+        Line::Break => return Ok(()),
+    }
+    writeln!(out)
+}
+
+pub fn write_end<W : Write>(out : &mut W, path : &Path, stats : &Stats) -> io::Result<()> {
+    Object::new(out)?.string("type", "end")?
+                     .string("path", &path.display().to_string())?
+                     .number("matched_lines", stats.matched_lines)?
+                     .number("matches", stats.matches)?
+                     .end()?;
+    writeln!(out)
+}
+
+pub fn write_summary<W : Write>(out : &mut W, summary : &Summary) -> io::Result<()> {
+    Object::new(out)?.string("type", "summary")?
+                     .number("searches", summary.searches)?
+                     .number("searches_with_match", summary.searches_with_match)?
+                     .number("matched_lines", summary.matched_lines)?
+                     .number("matches", summary.matches)?
+                     .end()?;
+    writeln!(out)
+}
+
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    fn to_json(value : &str) -> String {
+        let mut out = vec![];
+        write_string(&mut out, value).unwrap();
+        String::from_utf8(out).unwrap()
+    }
+
+    #[test]
+    fn string_escapes() {
+        assert_eq!(r#""plain""#, to_json("plain"));
+        assert_eq!(r#""say \"hi\" \\ bye""#, to_json(r#"say "hi" \ bye"#));
+        assert_eq!(r#""a\tb\r\n\b\f""#, to_json("a\tb\r\n\u{8}\u{c}"));
+        assert_eq!(r#""\u0000\u001b[0m""#, to_json("\0\u{1b}[0m"));
+        assert_eq!(r#""Здравствуйте, नमस्ते""#, to_json("Здравствуйте, नमस्ते"));
+        assert_eq!("\"\u{7f}\"", to_json("\u{7f}"));     // Only C0 controls must be escaped.
+    }
+
+    #[test]
+    fn objects() {
+        let mut out = vec![];
+        Object::new(&mut out).unwrap()
+                             .string("a", "x").unwrap()
+                             .number("b", 42).unwrap()
+                             .raw("c", |out| out.write_all(b"[]")).unwrap()
+                             .end().unwrap();
+        assert_eq!(r#"{"a":"x","b":42,"c":[]}"#, String::from_utf8(out).unwrap());
+
+        let mut out = vec![];
+        Object::new(&mut out).unwrap().end().unwrap();
+        assert_eq!("{}", String::from_utf8(out).unwrap());
+    }
+}
@@ -2,56 +2,779 @@
 // `lib.rs` is responsible for the main logic.
 //
 
-use std::env;
-use std::error::Error;
-use std::fs;
+use std::collections::BTreeMap;
+use std::fs::{self, File};
+use std::io::{self, BufRead, BufReader, BufWriter, IsTerminal, Read, Write};
+use std::mem;
+use std::panic::{self, AssertUnwindSafe};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{mpsc, Arc};
+use std::thread;
 
+pub mod aho_corasick;
+pub mod cache;
+mod config;
+pub mod encoding;
+pub mod error;
+pub mod filter;
+pub mod fold;
+pub mod fuzzy;
+pub mod glob;
+pub mod ignore;
+pub mod index;
+pub mod inflate;
+pub mod json;
+pub mod literal;
+pub mod matcher;
+#[cfg(feature = "mmap")]
+pub mod mmap;
+pub mod pool;
+pub mod printer;
+pub mod record;
+pub mod regex;
+pub mod replace;
+pub mod search;
+pub mod searcher;
+pub mod walk;
+pub mod watch;
 
-// Config parsing.
-pub struct Config<'a> {
-    query : &'a str,
-    filename : &'a str,
-    sensitive : bool,
+pub use config::{Config, MatchMode, Boundary, OutputMode, BinaryFiles, ColorChoice, Action, USAGE,
+                 version};
+pub use error::MinigrepError;
+pub use search::{Match, Line, LineOptions, Stats, Summary, find_matches, search_lines,
+                 search_multiline, search_reader, search_each_line};
+pub use searcher::{SearchOptions, Searcher, Sink};
+use cache::Cache;
+use filter::Filter;
+use index::{Candidates, Index, Query};
+use inflate::MaybeCompressed;
+use fuzzy::Fuzzy;
+use literal::Finder;
+use matcher::Matcher;
+use pool::ThreadPool;
+use printer::Printer;
+use regex::Regex;
+use replace::{Edit, Replacement};
+use walk::{Walk, WalkError, is_binary, relative_path};
+
+
+// What a run did: the counts over the inputs searched, and the failures of the ones that
+// could not be, which did not stop the others.
+#[derive(Debug, Default)]
+pub struct Report {
+    pub summary : Summary,
+    pub errors : Vec<MinigrepError>,
 }
 
-impl<'a> Config<'a> {
+impl Report {
 
-    pub fn new(args : &Vec<String>) -> Result<Config, &'static str> {
-        if args.len() != 3 {
-            return Err("Wrong number of arguments.")
+    // Exit status like grep's: 0 if some line was selected, 1 if none was, and 2 if some input
+    // failed, whatever was selected in the others.
+    pub fn exit_code(&self) -> i32 {
+        if !self.errors.is_empty() {
+            2
+        } else if self.summary.matched_lines > 0 {
+            0
+        } else {
+            1
         }
+    }
+
+    // Record the failure of an input, told by `run` once the run is done.
+    fn fail(&mut self, err : MinigrepError) {
+        self.errors.push(err);
+    }
+}
+
+
+// Main logic. The inputs that could not be searched are told once the run is done, which with
+// `--watch` means those of the last search.
+pub fn run(config : Config) -> Result<Report, MinigrepError> {
+    let stdout = io::stdout();
+    let color = config.color.enabled(stdout.is_terminal());
+    let mut out = BufWriter::new(stdout.lock());
+    if config.follow || config.watch {
+        watch::catch_interrupt();
+    }
+    let report = search_all(&config, color, &mut out, watch::interrupted())?;
+    out.flush()?;
+    for err in &report.errors {
+        eprintln!("ERROR reading {}", err);
+    }
+    Ok(report)
+}
 
-        let query = &args[1];
-        let filename = &args[2];
-        let sensitive = env::var("CASE_INSENSITIVE").is_err();  // Check for env variable.
+// Search all the inputs, writing the results to `out` grouped per input, in the order the
+// inputs were given (and walked), however many threads are used. `out` is not a terminal, so
+// the output is only colored with `--color=always`. With `--follow` or `--watch`, runs until
+// interrupted (see `watch::catch_interrupt`).
+pub fn run_to<W : Write>(config : &Config, out : &mut W) -> Result<Report, MinigrepError> {
+    run_until(config, out, watch::interrupted())
+}
 
-        Ok(Config { query, filename, sensitive })
+// Like `run_to`, `--follow` and `--watch` running until `stop` is set instead.
+pub fn run_until<W : Write>(config : &Config, out : &mut W, stop : &AtomicBool)
+                            -> Result<Report, MinigrepError> {
+    search_all(config, config.color.enabled(false), out, stop)
+}
+
+fn search_all<W : Write>(config : &Config, color : bool, out : &mut W, stop : &AtomicBool)
+                         -> Result<Report, MinigrepError> {
+    if config.follow {
+        watch::follow(config, color, out, stop)
+    } else if config.watch {
+        watch::watch(config, color, out, stop)
+    } else {
+        search_once(config, color, out)
     }
 }
 
+fn search_once<W : Write>(config : &Config, color : bool, out : &mut W)
+                          -> Result<Report, MinigrepError> {
+    let searcher = config.search_options().build()?;
+    let replacement = build_replacement(config, searcher.matcher())?;
+    let inputs = collect_inputs(config)?;
 
-// Main logic.
-pub fn run(config : Config) -> Result<(), Box<dyn Error>> {
-    let contents = fs::read_to_string(config.filename)?;
+    // Prefix hits with their path as soon as more than one file may be searched.
+    let with_filename = config.filenames.len() > 1
+                        || inputs.iter().any(|input| matches!(input, Input::Walked(_)));
+    let settings = Settings::new(config, searcher, replacement, with_filename, color);
 
-    let results = if config.sensitive {
-        search_sensitive(config.query, contents.as_str())
+    let threads = match config.threads {
+        0 => thread::available_parallelism().map_or(1, |n| n.get()),
+        n => n,
+    };
+
+    let report = if threads == 1 || inputs.len() <= 1 {
+        search_serial(&settings, inputs, out)?
     } else {
-        search_insensitive(config.query, contents.as_str())
+        search_parallel(&Arc::new(settings.clone()), inputs, threads, out)?
+    };
+    finish(&settings, &report.summary, out)?;
+    if let Some(cache) = &settings.cache {
+        let _ = cache.trim();
+    }
+    Ok(report)
+}
+
+
+// Group references are only expanded in regex mode, and must name a group of the patterns.
+fn build_replacement(config : &Config, matcher : &Matcher)
+                     -> Result<Option<Arc<Replacement>>, MinigrepError> {
+    let template = match &config.replacement {
+        Some(template) => template,
+        None => return Ok(None),
+    };
+    let replacement = Replacement::new(template, config.mode == MatchMode::Regex);
+    replacement.check(matcher.captures_len()).map_err(MinigrepError::Usage)?;
+    Ok(Some(Arc::new(replacement)))
+}
+
+
+// How the standard input is named in the output.
+const STDIN_NAME : &str = "(standard input)";
+
+// Something to search.
+enum Input {
+    Stdin,
+    File(PathBuf),          // Given on the command line.
+    Walked(PathBuf),        // Found by walking a directory; skipped if binary by default.
+    Pruned(PathBuf),        // Found by walking a directory, and known from its index not to match.
+    Unreadable(WalkError),  // A directory that could not be walked.
+}
+
+impl Input {
+
+    // The path of the input, as its failures name it.
+    fn path(&self) -> PathBuf {
+        match self {
+            Input::Stdin => PathBuf::from(STDIN_NAME),
+            Input::File(path) | Input::Walked(path) | Input::Pruned(path) => path.clone(),
+            Input::Unreadable(WalkError { path, .. }) => path.clone(),
+        }
+    }
+}
+
+// Expand the command line operands into inputs, in order. With `--indexed`, the directories
+// must have an index, which tells the files that cannot match.
+fn collect_inputs(config : &Config) -> Result<Vec<Input>, MinigrepError> {
+    let query = if config.indexed { Some(Query::new(config)) } else { None };
+    let mut inputs = vec![];
+    for &filename in &config.filenames {
+        let path = Path::new(filename);
+        if filename == "-" {
+            inputs.push(Input::Stdin);
+        } else if config.recursive && path.is_dir() {
+            let candidates = match &query {
+                Some(query) => match Index::open(path) {
+                    Ok(index) => Some(Candidates::new(index, query)),
+                    Err(err) => {
+                        let path = Some(path.join(index::INDEX_NAME));
+                        return Err(MinigrepError::Io { path, err });
+                    },
+                },
+                None => None,
+            };
+            for entry in Walk::new(path) {
+                inputs.push(match entry {
+                    Ok(found) => {
+                        let relative = relative_path(path, &found);
+                        if !allowed(config.filter(), &relative, &found) {
+                            continue;
+                        }
+                        match &candidates {
+                            Some(candidates) if candidates.skips(&relative, &found) => {
+                                Input::Pruned(found)
+                            },
+                            _ => Input::Walked(found),
+                        }
+                    },
+                    Err(err) => Input::Unreadable(err),
+                });
+            }
+        } else {
+            inputs.push(Input::File(path.to_path_buf()));
+        }
+    }
+    Ok(inputs)
+}
+
+// Whether `filter` lets the file `found`, at `relative` from the directory walked, be searched.
+fn allowed(filter : &Filter, relative : &str, found : &Path) -> bool {
+    filter.allows(relative, || fs::metadata(found).ok().map(|metadata| metadata.len()))
+}
+
+
+// Everything needed to search one input, owned so it can be shared with worker threads.
+#[derive(Clone)]
+struct Settings {
+    searcher : Searcher,    // With the options the output mode needs.
+    printer : Printer,
+    output : OutputMode,
+    replacement : Option<Arc<Replacement>>,
+    in_place : bool,
+    dry_run : bool,
+    binary_files : Option<BinaryFiles>,     // Unless given, depends on the input.
+    ranked : bool,                          // Print the best fuzzy matches first,
+    top : Option<usize>,                    //   and only that many of them.
+    cache : Option<Cache>,
+}
+
+impl Settings {
+    fn new(config : &Config, mut searcher : Searcher, replacement : Option<Arc<Replacement>>,
+           with_filename : bool, color : bool) -> Settings {
+        // Only lines and JSON events have context, and a single line tells whether a file matches.
+        let options = &mut searcher.options;
+        if !matches!(config.output, OutputMode::Lines | OutputMode::Json) {
+            options.before = 0;
+            options.after = 0;
+        }
+        if matches!(config.output, OutputMode::FilesWithMatches | OutputMode::FilesWithoutMatch) {
+            options.max_count = Some(options.max_count.map_or(1, |max| max.min(1)));
+        }
+        // Fuzzy matches are ranked once all are found, the maximum count keeping the best ones.
+        let ranked = config.mode == MatchMode::Fuzzy
+                     && matches!(config.output, OutputMode::Lines | OutputMode::OnlyMatching
+                                                | OutputMode::Json);
+        let top = if ranked { options.max_count.take() } else { None };
+        let cache = config.cache.clone().map(|dir| {
+            let options = format!("{} {} {}", config.cache_options(), with_filename, color);
+            Cache::new(dir, config.cache_size, options)
+        });
+
+        Settings {
+            searcher,
+            printer : Printer {
+                line_number : config.line_number,
+                byte_offset : config.byte_offset,
+                with_filename,
+                color,
+            },
+            output : config.output,
+            replacement,
+            in_place : config.in_place,
+            dry_run : config.dry_run,
+            binary_files : config.binary_files,
+            ranked,
+            top,
+            cache,
+        }
+    }
+
+    // How an input that looks binary is searched, the ones `walked` to being skipped by default.
+    fn binary_policy(&self, walked : bool) -> BinaryFiles {
+        let default = if walked { BinaryFiles::WithoutMatch } else { BinaryFiles::Binary };
+        let printed = matches!(self.output, OutputMode::Lines | OutputMode::OnlyMatching);
+        match self.binary_files.unwrap_or(default) {
+            // Counts, paths, and JSON events do not garble the output.
+            BinaryFiles::Binary if !printed => BinaryFiles::Text,
+            policy => policy,
+        }
+    }
+}
+
+// Search the inputs one after the other, streaming results to `out`. Returns the report of
+// the inputs searched and of those that could not be.
+fn search_serial<W : Write>(settings : &Settings, inputs : Vec<Input>, out : &mut W)
+                            -> Result<Report, MinigrepError> {
+    let mut report = Report::default();
+    for input in inputs {
+        match search_one(settings, input, out) {
+            Ok(stats) => report.summary.add(&stats),
+            Err(err) if err.is_output() => return Err(err),
+            Err(err) => report.fail(err),
+        }
+    }
+    Ok(report)
+}
+
+// Fan the inputs out to a pool of workers, each buffering the results of one input. Buffers
+// are written to `out` as soon as all the inputs before them are done, so the output is the
+// same as a serial search. Returns the same as `search_serial`, an input whose search panicked
+// failing without output.
+fn search_parallel<W : Write>(settings : &Arc<Settings>, inputs : Vec<Input>, threads : usize,
+                              out : &mut W) -> Result<Report, MinigrepError> {
+    let pool = ThreadPool::new(threads);
+    let (sender, receiver) = mpsc::channel();
+    let cancelled = Arc::new(AtomicBool::new(false));
+
+    for (index, input) in inputs.into_iter().enumerate() {
+        let sender = sender.clone();
+        let settings = Arc::clone(settings);
+        let cancelled = Arc::clone(&cancelled);
+        pool.exec(move || {
+            let mut buf = vec![];
+            let result = if cancelled.load(Ordering::Relaxed) {
+                Ok(Stats::default())
+            } else {
+                let path = input.path();
+                let search = AssertUnwindSafe(|| search_one(&settings, input, &mut buf));
+                panic::catch_unwind(search).unwrap_or_else(|payload| {
+                    buf.clear();
+                    Err(panicked(path, payload))
+                })
+            };
+            let _ = sender.send((index, buf, result));  // The receiver may have given up.
+        });
+    }
+    drop(sender);
+
+    // Reorder the finished inputs.
+    let mut done = BTreeMap::new();
+    let mut next = 0;
+    let mut report = Report::default();
+    for (index, buf, result) in receiver {
+        done.insert(index, (buf, result));
+        while let Some((buf, result)) = done.remove(&next) {
+            if let Err(err) = out.write_all(&buf) {
+                cancelled.store(true, Ordering::Relaxed);
+                return Err(err.into());
+            }
+            match result {
+                Ok(stats) => report.summary.add(&stats),
+                Err(err) if err.is_output() => {
+                    cancelled.store(true, Ordering::Relaxed);
+                    return Err(err);
+                },
+                Err(err) => report.fail(err),
+            }
+            next += 1;
+        }
+    }
+    Ok(report)
+}
+
+// The failure of the input at `path`, whose search panicked with `payload`.
+fn panicked(path : PathBuf, payload : Box<dyn std::any::Any + Send>) -> MinigrepError {
+    // Panics carry a `&str` when given a literal message, and a `String` when formatted.
+    let message = match (payload.downcast_ref::<&str>(), payload.downcast_ref::<String>()) {
+        (Some(message), _) => message,
+        (None, Some(message)) => message.as_str(),
+        (None, None) => "unknown cause",
+    };
+    let err = io::Error::other(format!("search panicked: {}", message));
+    MinigrepError::Io { path : Some(path), err }
+}
+
+// Search one input, writing its results to `out`. Failing to write them is not the input's
+// failure, and has no path.
+fn search_one<W : Write>(settings : &Settings, input : Input, out : &mut W)
+                         -> Result<Stats, MinigrepError> {
+    let mut out = Tracked { out, failed : false };
+    let editing = settings.replacement.is_some() && (settings.in_place || settings.dry_run);
+    if let Input::Pruned(path) = input {
+        let result = search_pruned(settings, editing, &path, &mut out);
+        return result.map_err(|err| out.blame(path, err));
+    }
+    if let Some(replacement) = settings.replacement.as_deref() {
+        if editing {
+            return edit_one(settings, replacement, input, &mut out);
+        }
+    }
+
+    let (path, result) = match input {
+        Input::Stdin => {
+            let path = PathBuf::from(STDIN_NAME);
+            let result = search_printed(settings, io::stdin().lock(), &path, false, &mut out);
+            (path, result)
+        },
+        Input::File(path) => {
+            let result = search_cached(settings, &path, false, &mut out);
+            (path, result)
+        },
+        Input::Walked(path) => {
+            let result = search_cached(settings, &path, true, &mut out);
+            (path, result)
+        },
+        Input::Unreadable(WalkError { path, err }) => (path, Err(err)),
+        Input::Pruned(_) => unreachable!(),
     };
+    result.map_err(|err| out.blame(path, err))
+}
+
+// Writer telling whether writing to `out` failed, to know whom to blame for an error.
+struct Tracked<'s, W> {
+    out : &'s mut W,
+    failed : bool,
+}
+
+impl<'s, W> Tracked<'s, W> {
 
-    for line in results {
-        println!("{}", line);
+    // The error of reading the input at `path`, unless it is the output that failed.
+    fn blame(&self, path : PathBuf, err : io::Error) -> MinigrepError {
+        let path = if self.failed { None } else { Some(path) };
+        MinigrepError::Io { path, err }
     }
 
+    fn check<T>(&mut self, result : io::Result<T>) -> io::Result<T> {
+        // Interrupted writes are tried again.
+        if result.as_ref().is_err_and(|err| err.kind() != io::ErrorKind::Interrupted) {
+            self.failed = true;
+        }
+        result
+    }
+}
+
+impl<'s, W : Write> Write for Tracked<'s, W> {
+
+    fn write(&mut self, buf : &[u8]) -> io::Result<usize> {
+        let result = self.out.write(buf);
+        self.check(result)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        let result = self.out.flush();
+        self.check(result)
+    }
+}
+
+// Report a file known not to match like any searched without a match, without reading it.
+fn search_pruned<W : Write>(settings : &Settings, editing : bool, path : &Path, out : &mut W)
+                            -> io::Result<Stats> {
+    let stats = Stats::default();
+    if !editing {
+        let mut output = Output { settings, out, begun : false, ranked : vec![] };
+        output.finish(path, &stats)?;
+    }
+    Ok(stats)
+}
+
+fn search_path<W : Write>(settings : &Settings, path : &Path, walked : bool, out : &mut W)
+                          -> io::Result<Stats> {
+    let policy = settings.binary_policy(walked);
+    let mut output = Output { settings, out, begun : false, ranked : vec![] };
+    settings.searcher.search_file(path, policy, &mut output)
+}
+
+// Search a file like `search_path`, through the cache if any: a file unchanged since it was
+// searched with the same options is answered from it, and the results of the others are stored.
+fn search_cached<W : Write>(settings : &Settings, path : &Path, walked : bool, out : &mut W)
+                            -> io::Result<Stats> {
+    let (cache, key) = match &settings.cache {
+        Some(cache) => match cache.key(path, walked) {
+            Some(key) => (cache, key),
+            None => return search_path(settings, path, walked, out),
+        },
+        None => return search_path(settings, path, walked, out),
+    };
+    if let Some((stats, output)) = cache.get(&key) {
+        out.write_all(&output)?;
+        return Ok(stats);
+    }
+
+    let mut recorder = Recorder { out, recorded : Some(vec![]), limit : cache.size() };
+    let stats = search_path(settings, path, walked, &mut recorder)?;
+    // Unless the file changed while searched.
+    if let Some(output) = recorder.recorded {
+        if cache.key(path, walked).as_ref() == Some(&key) {
+            let _ = cache.put(&key, &stats, &output);
+        }
+    }
+    Ok(stats)
+}
+
+// Writer passing the output through, and keeping a copy of it unless it grows past `limit`.
+struct Recorder<'s, W> {
+    out : &'s mut W,
+    recorded : Option<Vec<u8>>,
+    limit : u64,
+}
+
+impl<'s, W : Write> Write for Recorder<'s, W> {
+
+    fn write(&mut self, buf : &[u8]) -> io::Result<usize> {
+        let written = self.out.write(buf)?;
+        if let Some(recorded) = &mut self.recorded {
+            if (recorded.len() + written) as u64 > self.limit {
+                self.recorded = None;
+            } else {
+                recorded.extend(&buf[..written]);
+            }
+        }
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.out.flush()
+    }
+}
+
+// Search an input, printing its results to `out`. Whether it was `walked` to matters if it is
+// binary.
+fn search_printed<R : BufRead, W : Write>(settings : &Settings, reader : R, path : &Path,
+                                          walked : bool, out : &mut W) -> io::Result<Stats> {
+    let policy = settings.binary_policy(walked);
+    let mut output = Output { settings, out, begun : false, ranked : vec![] };
+    settings.searcher.search_decoded(reader, path, policy, &mut output)
+}
+
+// Sink printing the results of one input according to the output mode.
+struct Output<'s, W> {
+    settings : &'s Settings,
+    out : &'s mut W,
+    begun : bool,                   // The JSON events of the input have begun.
+    ranked : Vec<(i64, Ranked)>,    // The fuzzy matches and their score, until all are found.
+}
+
+// A selected line kept until the input is ranked.
+struct Ranked {
+    line_number : usize,
+    offset : usize,
+    line : String,
+    spans : Vec<(usize, usize)>,
+}
+
+impl<'s, W : Write> Output<'s, W> {
+
+    fn print(&mut self, path : &Path, line : Line) -> io::Result<()> {
+        let settings = self.settings;
+        let printer = settings.printer;
+        let matcher = settings.searcher.matcher();
+
+        match settings.output {
+            OutputMode::Lines => match (&settings.replacement, line) {
+                (Some(replacement), Line::Match(m)) => {
+                    let (replaced, spans) = replacement.replace_line(matcher, m.line);
+                    let m = Match { line : &replaced, spans, ..m };
+                    printer.print_line(self.out, path, &Line::Match(m))
+                },
+                (_, line) => printer.print_line(self.out, path, &line),
+            },
+            OutputMode::OnlyMatching => match (&settings.replacement, line) {
+                (Some(replacement), Line::Match(m)) => {
+                    let (replaced, spans) = replacement.replace_line(matcher, m.line);
+                    let m = Match { line : &replaced, spans, ..m };
+                    printer.print_only_matching(self.out, path, &m)
+                },
+                (None, Line::Match(m)) => printer.print_only_matching(self.out, path, &m),
+                _ => Ok(()),
+            },
+            OutputMode::Json => {
+                // The input only shows up once it has something to report.
+                if let Line::Break = line {
+                    return Ok(());
+                }
+                if !self.begun {
+                    json::write_begin(self.out, path)?;
+                    self.begun = true;
+                }
+                json::write_line(self.out, path, &line)
+            },
+            // Printed once the input is searched.
+            OutputMode::Count | OutputMode::FilesWithMatches | OutputMode::FilesWithoutMatch => {
+                Ok(())
+            },
+        }
+    }
+}
+
+impl<'s, W : Write> Sink for Output<'s, W> {
+
+    fn line(&mut self, path : &Path, line : Line) -> io::Result<()> {
+        match line {
+            Line::Match(m) if self.settings.ranked => {
+                let matcher = self.settings.searcher.matcher();
+                let score = matcher.score(m.line).map_or(0, |(score, _)| score);
+                let Match { line_number, offset, line, spans } = m;
+                let line = line.to_string();
+                self.ranked.push((score, Ranked { line_number, offset, line, spans }));
+                Ok(())
+            },
+            line => self.print(path, line),
+        }
+    }
+
+    fn binary_match(&mut self, path : &Path) -> io::Result<()> {
+        self.settings.printer.print_binary_match(self.out, path)
+    }
+
+    fn finish(&mut self, path : &Path, stats : &Stats) -> io::Result<()> {
+        for ranked in fuzzy::rank(mem::take(&mut self.ranked), self.settings.top) {
+            let Ranked { line_number, offset, line, spans } = ranked;
+            self.print(path, Line::Match(Match { line_number, offset, line : &line, spans }))?;
+        }
+
+        let printer = self.settings.printer;
+        let count = stats.matched_lines;
+        match self.settings.output {
+            OutputMode::Json if self.begun => json::write_end(self.out, path, stats),
+            OutputMode::Count => printer.print_count(self.out, path, count),
+            OutputMode::FilesWithMatches if count > 0 => printer.print_path(self.out, path),
+            OutputMode::FilesWithoutMatch if count == 0 => printer.print_path(self.out, path),
+            _ => Ok(()),
+        }
+    }
+}
+
+// Replace the matches of one whole input, then print the diff of the change with `--dry-run`,
+// or write it back to the file with `--in-place`. The standard input can only be diffed.
+fn edit_one<W : Write>(settings : &Settings, replacement : &Replacement, input : Input,
+                       out : &mut W) -> Result<Stats, MinigrepError> {
+    let (path, stdin, skip_binary) = match input {
+        Input::Stdin => (PathBuf::from(STDIN_NAME), true, false),
+        Input::File(path) => (path, false, false),
+        Input::Walked(path) => (path, false, true),
+        Input::Unreadable(WalkError { path, err }) => {
+            return Err(MinigrepError::Io { path : Some(path), err });
+        },
+        Input::Pruned(_) => unreachable!(),
+    };
+    let bytes = match read_edited(settings, (!stdin).then_some(&path), skip_binary) {
+        Ok(Some(bytes)) => bytes,
+        Ok(None) => return Ok(Stats::default()),
+        Err(err) => return Err(MinigrepError::Io { path : Some(path), err }),
+    };
+    let text = match String::from_utf8(bytes) {
+        Ok(text) => text,
+        Err(_) => return Err(MinigrepError::Encoding { path, message : "not valid UTF-8" }),
+    };
+
+    let searcher = &settings.searcher;
+    let edit = Edit::new(replacement, searcher.matcher(), &text, searcher.options.max_count);
+    if settings.dry_run {
+        edit.write_diff(out, &path)?;
+    } else if edit.is_changed() {
+        replace::write_atomically(&path, edit.text().as_bytes())
+            .map_err(|err| MinigrepError::Io { path : Some(path), err })?;
+    }
+    Ok(edit.stats)
+}
+
+// Read the whole input to edit (the standard input without `path`), or nothing if asked to skip
+// it and it looks binary. The standard input and compressed files can only be diffed, as they
+// are not written back.
+fn read_edited(settings : &Settings, path : Option<&PathBuf>, skip_binary : bool)
+               -> io::Result<Option<Vec<u8>>> {
+    let mut reader : Box<dyn BufRead> = match path {
+        Some(path) => Box::new(BufReader::new(File::open(path)?)),
+        None if settings.in_place => {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, "cannot be edited in place"));
+        },
+        None => Box::new(io::stdin().lock()),
+    };
+
+    let mut bytes = vec![];
+    if settings.searcher.decompress {
+        let mut reader = MaybeCompressed::new(reader)?;
+        if settings.in_place && matches!(reader, MaybeCompressed::Compressed(_)) {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput,
+                                      "compressed files cannot be edited in place"));
+        }
+        reader.read_to_end(&mut bytes)?;
+    } else {
+        reader.read_to_end(&mut bytes)?;
+    }
+    if skip_binary && is_binary(&bytes) {
+        return Ok(None);
+    }
+    Ok(Some(bytes))
+}
+
+// Print what closes the output of a run, once all the inputs are searched.
+fn finish<W : Write>(settings : &Settings, summary : &Summary, out : &mut W) -> io::Result<()> {
+    if settings.output == OutputMode::Json {
+        json::write_summary(out, summary)?;
+    }
+    Ok(())
+}
+
+// Build or update the index of each directory given to `minigrep index`, telling what was done.
+pub fn run_index<W : Write>(config : &Config, out : &mut W) -> Result<(), MinigrepError> {
+    for &dir in &config.filenames {
+        let update = index::update(Path::new(dir))?;
+        writeln!(out, "{}: {} files indexed, {} read, {} removed", dir, update.files, update.read,
+                 update.removed)?;
+    }
     Ok(())
 }
 
+// Search a single reader as if it were the only input, named `name`. Handy to embed the
+// command line behavior on in-memory texts. Like `run_to`, only colored with `--color=always`.
+pub fn run_reader<R : BufRead, W : Write>(config : &Config, name : &str, reader : R, out : &mut W)
+                                          -> Result<Report, MinigrepError> {
+    let searcher = config.search_options().build()?;
+    let replacement = build_replacement(config, searcher.matcher())?;
+    let settings = Settings::new(config, searcher, replacement, false, config.color.enabled(false));
+    let path = Path::new(name);
+    let mut tracked = Tracked { out : &mut *out, failed : false };
+    let stats = search_printed(&settings, reader, path, false, &mut tracked)
+        .map_err(|err| tracked.blame(path.to_path_buf(), err))?;
+
+    let mut report = Report::default();
+    report.summary.add(&stats);
+    finish(&settings, &report.summary, out)?;
+    Ok(report)
+}
+
 pub fn search_sensitive<'a>(query : &str, contents : &'a str) -> Vec<&'a str> {
-    contents.lines().filter(|line| line.contains(query)).collect()
+    let matcher = Matcher::Literal(Finder::new(query));
+    find_matches(&matcher, contents, false).into_iter().map(|m| m.line).collect()
 }
 
 pub fn search_insensitive<'a>(query : &str, contents : &'a str) -> Vec<&'a str> {
-    let query = query.to_lowercase();
-    contents.lines().filter(|line| line.to_lowercase().contains(&query)).collect()
+    let matcher = Matcher::LiteralInsensitive(Finder::new(fold::fold(query)));
+    find_matches(&matcher, contents, false).into_iter().map(|m| m.line).collect()
+}
+
+// Lines of `contents` holding the characters of `query` in order, best first, and at most `top`
+// of them. The spans are those of the characters matched, see `fuzzy.rs` for the scores.
+pub fn search_fuzzy<'a>(query : &str, contents : &'a str, top : Option<usize>) -> Vec<Match<'a>> {
+    let fuzzy = Fuzzy::new(query, true);
+    let mut scored = vec![];
+    let mut offset = 0;
+    for (index, raw) in contents.split_inclusive('\n').enumerate() {
+        let line = raw.strip_suffix('\n').unwrap_or(raw);
+        let line = line.strip_suffix('\r').unwrap_or(line);
+        if let Some((score, spans)) = fuzzy.score(line) {
+            scored.push((score, Match { line_number : index + 1, offset, line, spans }));
+        }
+        offset += raw.len();
+    }
+    fuzzy::rank(scored, top)
+}
+
+pub fn search_regex<'a>(regex : &Regex, contents : &'a str) -> Vec<&'a str> {
+    let matcher = Matcher::Regex(regex.clone());
+    find_matches(&matcher, contents, false).into_iter().map(|m| m.line).collect()
 }
@@ -0,0 +1,211 @@
+//
+// `literal.rs` finds a literal byte string in a whole buffer, without splitting it into lines
+//   first.
+//
+// The searcher combines two skips:
+//   - A memchr-style scan for the first byte of the needle, testing a machine word of the
+//     haystack at once for the byte (see `memchr`), jumps to the next candidate window;
+//   - When the window does not match, the Boyer-Moore-Horspool shift of its last byte moves past
+//     all the windows that cannot match either.
+//
+// Line boundaries are only looked for around the matches found, so lines without a match are
+//   never even split out of the buffer.
+//
+
+use std::convert::TryInto;
+use std::mem;
+
+
+// Bytes in a machine word, scanned at once.
+const WORD : usize = mem::size_of::<usize>();
+// A word with every byte set to 0x01, respectively 0x80.
+const LO : usize = usize::MAX / 255;
+const HI : usize = LO << 7;
+
+// Whether any byte of `word` is zero.
+fn has_zero(word : usize) -> bool {
+    word.wrapping_sub(LO) & !word & HI != 0
+}
+
+// Position of the first `byte` in `haystack`.
+pub fn memchr(byte : u8, haystack : &[u8]) -> Option<usize> {
+    let repeated = LO * byte as usize;
+    let mut chunks = haystack.chunks_exact(WORD);
+    for (i, chunk) in chunks.by_ref().enumerate() {
+        let word = usize::from_ne_bytes(chunk.try_into().unwrap());
+        if has_zero(word ^ repeated) {
+            return chunk.iter().position(|&b| b == byte).map(|pos| i * WORD + pos);
+        }
+    }
+    let done = haystack.len() - chunks.remainder().len();
+    chunks.remainder().iter().position(|&b| b == byte).map(|pos| done + pos)
+}
+
+// Position of the last `byte` in `haystack`.
+pub fn memrchr(byte : u8, haystack : &[u8]) -> Option<usize> {
+    let repeated = LO * byte as usize;
+    let mut chunks = haystack.rchunks_exact(WORD);
+    for (i, chunk) in chunks.by_ref().enumerate() {
+        let word = usize::from_ne_bytes(chunk.try_into().unwrap());
+        if has_zero(word ^ repeated) {
+            let start = haystack.len() - (i + 1) * WORD;
+            return chunk.iter().rposition(|&b| b == byte).map(|pos| start + pos);
+        }
+    }
+    chunks.remainder().iter().rposition(|&b| b == byte)
+}
+
+// Number of `byte` in `haystack`.
+pub fn count(byte : u8, haystack : &[u8]) -> usize {
+    let repeated = LO * byte as usize;
+    let mut chunks = haystack.chunks_exact(WORD);
+    let mut total = 0;
+    for chunk in chunks.by_ref() {
+        // Set the high bit of every non-zero byte, exactly (unlike `has_zero`).
+        let word = usize::from_ne_bytes(chunk.try_into().unwrap()) ^ repeated;
+        let non_zero = ((word & !HI).wrapping_add(!HI) | word) & HI;
+        total += WORD - non_zero.count_ones() as usize;
+    }
+    total + chunks.remainder().iter().filter(|&&b| b == byte).count()
+}
+
+
+// Searcher for one needle, built once and used on any number of haystacks.
+#[derive(Debug, Clone)]
+pub struct Finder {
+    needle : Vec<u8>,
+    shift : [usize; 256],   // Horspool shift for each possible last byte of a window.
+}
+
+impl Finder {
+
+    pub fn new<B : AsRef<[u8]>>(needle : B) -> Finder {
+        let needle = needle.as_ref().to_vec();
+        let len = needle.len();
+
+        // A window ending with a byte can be shifted until that byte lines up with its last
+        // occurrence in the needle (not counting the last position), or past it entirely.
+        let mut shift = [len.max(1); 256];
+        for (i, &byte) in needle.iter().enumerate().take(len.saturating_sub(1)) {
+            shift[byte as usize] = len - 1 - i;
+        }
+
+        Finder { needle, shift }
+    }
+
+    pub fn needle(&self) -> &[u8] {
+        &self.needle
+    }
+
+    // Position of the first occurrence of the needle in `haystack` at or after `start`.
+    pub fn find(&self, haystack : &[u8], start : usize) -> Option<usize> {
+        let len = self.needle.len();
+        if len == 0 {
+            return if start <= haystack.len() { Some(start) } else { None };
+        }
+        if haystack.len() < len {
+            return None;
+        }
+
+        let first = self.needle[0];
+        let last_window = haystack.len() - len;
+        let mut pos = start;
+        while pos <= last_window {
+            // Jump to the next window starting with the right byte.
+            pos += memchr(first, &haystack[pos..=last_window])?;
+
+            let window = &haystack[pos..pos + len];
+            if window == self.needle.as_slice() {
+                return Some(pos);
+            }
+            pos += self.shift[window[len - 1] as usize];
+        }
+        None
+    }
+
+    // Byte spans of all the non-overlapping occurrences in `haystack`, left to right. An empty
+    // needle matches once, at the start.
+    pub fn find_iter(&self, haystack : &[u8]) -> Vec<(usize, usize)> {
+        let len = self.needle.len();
+        if len == 0 {
+            return vec![(0, 0)];
+        }
+        let mut spans = vec![];
+        let mut pos = 0;
+        while let Some(start) = self.find(haystack, pos) {
+            spans.push((start, start + len));
+            pos = start + len;
+        }
+        spans
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    // Reference implementation.
+    fn naive(needle : &[u8], haystack : &[u8]) -> Vec<(usize, usize)> {
+        let mut spans = vec![];
+        let mut pos = 0;
+        while pos + needle.len() <= haystack.len() {
+            if &haystack[pos..pos + needle.len()] == needle {
+                spans.push((pos, pos + needle.len()));
+                pos += needle.len();
+            } else {
+                pos += 1;
+            }
+        }
+        spans
+    }
+
+    // Deterministic pseudo-random bytes over a small alphabet, so that partial matches abound.
+    fn gen_bytes(len : usize, seed : u64) -> Vec<u8> {
+        let mut state = seed;
+        (0..len).map(|_| {
+            state = state.wrapping_mul(6364136223846793005).wrapping_add(1442695040888963407);
+            b"abc\n"[(state >> 33) as usize % 4]
+        }).collect()
+    }
+
+    #[test]
+    fn byte_scans() {
+        let haystack = b"0123456789abcdef0123456789abcdef";
+        for (i, &byte) in haystack.iter().enumerate() {
+            assert_eq!(Some(i % 16), memchr(byte, haystack));
+            assert_eq!(Some(i % 16 + 16), memrchr(byte, haystack));
+            assert_eq!(Some(i), memchr(byte, &haystack[i..]).map(|pos| pos + i));
+        }
+        assert_eq!(None, memchr(b'x', haystack));
+        assert_eq!(None, memrchr(b'x', haystack));
+        assert_eq!(None, memchr(b'x', b""));
+        assert_eq!(2, count(b'a', haystack));
+        assert_eq!(0, count(b'\n', b"no newline"));
+        assert_eq!(17, count(0, &[0; 17]));
+        assert_eq!(3, count(0x80, &[0x80, 0, 0x7f, 0x80, 1, 0xff, 0x81, 0x80, 0x01]));
+    }
+
+    #[test]
+    fn agrees_with_naive() {
+        for seed in 0..20 {
+            let haystack = gen_bytes(1000 + seed as usize, seed);
+            for len in 1..6 {
+                let needle = gen_bytes(len, seed + 100);
+                assert_eq!(naive(&needle, &haystack), Finder::new(&needle).find_iter(&haystack),
+                           "needle {:?}", String::from_utf8_lossy(&needle));
+            }
+        }
+    }
+
+    #[test]
+    fn edge_cases() {
+        assert_eq!(vec![(0, 0)], Finder::new("").find_iter(b"abc"));
+        assert_eq!(Vec::<(usize, usize)>::new(), Finder::new("abcd").find_iter(b"abc"));
+        assert_eq!(vec![(0, 3)], Finder::new("abc").find_iter(b"abc"));
+        assert_eq!(vec![(0, 2), (2, 4)], Finder::new("aa").find_iter(b"aaaaa"));
+        assert_eq!(Some(4), Finder::new("ab").find(b"ab__ab", 1));
+        assert_eq!(None, Finder::new("ab").find(b"ab__ab", 5));
+    }
+}
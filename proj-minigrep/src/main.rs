@@ -4,11 +4,12 @@
 //   1. Call the argument parsing logic;
 //   2. Set up any other configurations;
 //   3. Call `run()`;
-//   3. Handle errors returned from `run()`;
+//   3. Handle errors returned from `run()`, and exit with grep's status codes;
 //
 
 
 use std::env;
+use std::io;
 use std::process;
 use minigrep::*;
 
@@ -18,11 +19,39 @@ fn main() {
 
     let config = Config::new(&args).unwrap_or_else(|err| {
         eprintln!("ERROR parsing arguments: {}", err);  // Write to stderr.
-        process::exit(1);
+        eprintln!("Try 'minigrep --help' for more information.");
+        process::exit(2);
     });
 
-    if let Err(err) = run(config) {
-        eprintln!("ERROR when running: {}", err);
-        process::exit(1);
+    match config.action() {
+        Action::Help => {
+            println!("{}", USAGE);
+            return;
+        },
+        Action::Version => {
+            println!("{}", version());
+            return;
+        },
+        Action::Index => {
+            if let Err(err) = run_index(&config, &mut io::stdout()) {
+                eprintln!("ERROR when indexing: {}", err);
+                process::exit(2);
+            }
+            return;
+        },
+        Action::Search => {},
+    }
+
+    // Exit like grep: 0 if some line was selected, 1 if none was, 2 on errors.
+    match run(config) {
+        Ok(report) => process::exit(report.exit_code()),
+
+        // The reader of our output went away (e.g. `| head`), which is not an error.
+        Err(err) if err.is_broken_pipe() => {},
+
+        Err(err) => {
+            eprintln!("ERROR when running: {}", err);
+            process::exit(2);
+        },
     }
 }
@@ -0,0 +1,298 @@
+//
+// `matcher.rs` finds the byte spans of the patterns inside a single line, whatever the match mode.
+//
+// A multi-line matcher searches a whole text instead, its matches possibly spanning lines (see
+//   `Matcher::multi_line`), while a matcher in a field only searches one field of each record
+//   (see `record.rs`).
+//
+// A single literal is looked for with a `Finder`, many (or ones restricted to whole words or
+//   lines) with an Aho-Corasick automaton, all in one pass; regular expressions are compiled
+//   into one program matching any of them. Fuzzy queries match the characters of the best
+//   scoring alignment, see `fuzzy.rs`.
+//
+
+use std::sync::Arc;
+
+use crate::aho_corasick::AhoCorasick;
+use crate::config::{Boundary, MatchMode};
+use crate::fold;
+use crate::fuzzy::Fuzzy;
+use crate::literal::Finder;
+use crate::record::{self, Format, Target};
+use crate::regex::{self, Regex, RegexError};
+
+
+pub enum Matcher {
+    Literal(Finder),
+    LiteralInsensitive(Finder),     // Looks for the case folded query, see `fold.rs`.
+    Literals(Literals),
+    Regex(Regex),
+    Fuzzy(Vec<Fuzzy>),
+    InField(InField),
+}
+
+impl Matcher {
+
+    // Matcher for a single pattern.
+    pub fn new(query : &str, mode : MatchMode, sensitive : bool) -> Result<Matcher, RegexError> {
+        Matcher::with_patterns(&[query], mode, sensitive, Boundary::Anywhere)
+    }
+
+    // Matcher for any of `patterns`. No pattern matches nothing.
+    pub fn with_patterns<S : AsRef<str>>(patterns : &[S], mode : MatchMode, sensitive : bool,
+                                         boundary : Boundary) -> Result<Matcher, RegexError> {
+        let matcher = match mode {
+            MatchMode::Literal if patterns.len() == 1 && boundary == Boundary::Anywhere => {
+                let query = patterns[0].as_ref();
+                if sensitive {
+                    Matcher::Literal(Finder::new(query))
+                } else {
+                    Matcher::LiteralInsensitive(Finder::new(fold::fold(query)))
+                }
+            },
+            MatchMode::Literal => Matcher::Literals(Literals::new(patterns, sensitive, boundary)),
+            MatchMode::Regex => Matcher::Regex(Regex::new_many(patterns, !sensitive, boundary)?),
+            MatchMode::Fuzzy => {
+                Matcher::Fuzzy(patterns.iter().map(|query| Fuzzy::new(query.as_ref(), sensitive))
+                                              .collect())
+            },
+        };
+        Ok(matcher)
+    }
+
+    // Matcher for any of `patterns` within a whole text, where `^`, `$` and whole lines match
+    // around each line terminator as well. Fuzzy queries still match a line at a time.
+    pub fn multi_line<S : AsRef<str>>(patterns : &[S], mode : MatchMode, sensitive : bool,
+                                      boundary : Boundary) -> Result<Matcher, RegexError> {
+        let regex = match (mode, boundary) {
+            (MatchMode::Regex, _) => Regex::new_many(patterns, !sensitive, boundary)?,
+            (MatchMode::Literal, Boundary::Line) => {
+                let escaped : Vec<String> = patterns.iter()
+                                                    .map(|pattern| regex::escape(pattern.as_ref()))
+                                                    .collect();
+                Regex::new_many(&escaped, !sensitive, boundary)?
+            },
+            _ => return Matcher::with_patterns(patterns, mode, sensitive, boundary),
+        };
+        Ok(Matcher::Regex(regex.multi_line(true)))
+    }
+
+    pub fn is_match(&self, line : &str) -> bool {
+        match self {
+            Matcher::Literal(finder) => finder.find(line.as_bytes(), 0).is_some(),
+            Matcher::LiteralInsensitive(finder) => {
+                finder.find(fold::fold(line).as_bytes(), 0).is_some()
+            },
+            Matcher::Literals(literals) => literals.is_match(line),
+            Matcher::Regex(regex) => regex.is_match(line),
+            Matcher::Fuzzy(queries) => queries.iter().any(|query| query.is_match(line)),
+            Matcher::InField(field) => !field.find_iter(line).is_empty(),
+        }
+    }
+
+    // Byte spans of all non-overlapping matches in `line`, left to right.
+    pub fn find_iter(&self, line : &str) -> Vec<(usize, usize)> {
+        match self {
+            Matcher::Literal(finder) => finder.find_iter(line.as_bytes()),
+            Matcher::LiteralInsensitive(finder) => {
+                let folded = Folded::new(line);
+                folded.originals(finder.find_iter(folded.text.as_bytes()))
+            },
+            Matcher::Literals(literals) => literals.find_iter(line),
+            Matcher::Regex(regex) => regex.find_iter(line),
+            Matcher::Fuzzy(_) => self.score(line).map_or(vec![], |(_, spans)| spans),
+            Matcher::InField(field) => field.find_iter(line),
+        }
+    }
+
+    // Best fuzzy score of `line` and the spans of the characters matched, if it matches. Only
+    // fuzzy queries score.
+    pub fn score(&self, line : &str) -> Option<(i64, Vec<(usize, usize)>)> {
+        match self {
+            Matcher::Fuzzy(queries) => {
+                // The first of the best queries.
+                queries.iter().filter_map(|query| query.score(line))
+                              .rev()
+                              .max_by_key(|&(score, _)| score)
+            },
+            Matcher::InField(field) => field.score(line),
+            _ => None,
+        }
+    }
+
+    // Capture groups of all non-overlapping matches in `line`, left to right. Group 0 is the
+    // whole match, and only regular expressions have other groups.
+    pub fn captures_iter(&self, line : &str) -> Vec<Vec<Option<(usize, usize)>>> {
+        match self {
+            Matcher::Regex(regex) => regex.captures_iter(line),
+            Matcher::InField(field) => field.captures_iter(line),
+            _ => self.find_iter(line).into_iter().map(|span| vec![Some(span)]).collect(),
+        }
+    }
+
+    // Number of capture groups, including group 0.
+    pub fn captures_len(&self) -> usize {
+        match self {
+            Matcher::Regex(regex) => regex.captures_len(),
+            Matcher::InField(field) => field.inner.captures_len(),
+            _ => 1,
+        }
+    }
+}
+
+
+// A matcher only looking within one field of the records, its spans being those of the line.
+// Records without the field match nothing.
+pub struct InField {
+    inner : Arc<Matcher>,
+    format : Format,
+    target : Target,
+}
+
+impl InField {
+
+    pub fn new(inner : Arc<Matcher>, format : Format, target : Target) -> InField {
+        InField { inner, format, target }
+    }
+
+    // What `find` returns for the value of the field, if the record has it, with the spans
+    // mapped back to the line by `map`.
+    fn in_field<T, F, M>(&self, line : &str, find : F, map : M) -> Vec<T>
+        where F : FnOnce(&Matcher, &str) -> Vec<T>,
+              M : Fn(T, &record::Field) -> T
+    {
+        let fields = record::split(line, self.format);
+        match self.target.find(&fields) {
+            Some(field) => find(&self.inner, &field.value).into_iter()
+                                                          .map(|found| map(found, field))
+                                                          .collect(),
+            None => vec![],
+        }
+    }
+
+    fn find_iter(&self, line : &str) -> Vec<(usize, usize)> {
+        self.in_field(line, Matcher::find_iter, |(start, end), field| field.original(start, end))
+    }
+
+    fn captures_iter(&self, line : &str) -> Vec<Vec<Option<(usize, usize)>>> {
+        self.in_field(line, Matcher::captures_iter, |captures, field| {
+            captures.into_iter()
+                    .map(|span| span.map(|(start, end)| field.original(start, end)))
+                    .collect()
+        })
+    }
+
+    fn score(&self, line : &str) -> Option<(i64, Vec<(usize, usize)>)> {
+        let fields = record::split(line, self.format);
+        let field = self.target.find(&fields)?;
+        let (score, spans) = self.inner.score(&field.value)?;
+        Some((score, spans.into_iter().map(|(start, end)| field.original(start, end)).collect()))
+    }
+}
+
+
+// Many literal patterns, matched at once.
+pub struct Literals {
+    automaton : AhoCorasick,
+    insensitive : bool,     // The patterns are case folded.
+    boundary : Boundary,
+}
+
+impl Literals {
+
+    fn new<S : AsRef<str>>(patterns : &[S], sensitive : bool, boundary : Boundary) -> Literals {
+        let automaton = if sensitive {
+            AhoCorasick::new(patterns.iter().map(|pattern| pattern.as_ref()))
+        } else {
+            AhoCorasick::new(patterns.iter().map(|pattern| fold::fold(pattern.as_ref())))
+        };
+        Literals { automaton, insensitive : !sensitive, boundary }
+    }
+
+    fn is_match(&self, line : &str) -> bool {
+        match self.boundary {
+            Boundary::Anywhere if self.insensitive => {
+                self.automaton.is_match(fold::fold(line).as_bytes())
+            },
+            Boundary::Anywhere => self.automaton.is_match(line.as_bytes()),
+            _ => !self.find_iter(line).is_empty(),
+        }
+    }
+
+    // The leftmost-longest matches, among those within the boundaries.
+    fn find_iter(&self, line : &str) -> Vec<(usize, usize)> {
+        if !self.insensitive {
+            return self.automaton.find_iter_by(line.as_bytes(), |start, end| {
+                fits(line, start, end, self.boundary)
+            });
+        }
+
+        let folded = Folded::new(line);
+        folded.originals(self.automaton.find_iter_by(folded.text.as_bytes(), |start, end| {
+            let (start, end) = folded.original(start, end);
+            fits(line, start, end, self.boundary)
+        }))
+    }
+}
+
+// Whether the span of `line` is within the boundaries.
+fn fits(line : &str, start : usize, end : usize, boundary : Boundary) -> bool {
+    match boundary {
+        Boundary::Anywhere => true,
+        Boundary::Word => {
+            let before = line[..start].chars().next_back();
+            let after = line[end..].chars().next();
+            !before.is_some_and(regex::is_word_char) && !after.is_some_and(regex::is_word_char)
+        },
+        Boundary::Line => start == 0 && end == line.len(),
+    }
+}
+
+
+// A case folded line, with the offset in the original line of each of its bytes, as folding
+// may change the length of some characters.
+struct Folded {
+    text : String,
+    origin : Vec<usize>,
+}
+
+impl Folded {
+
+    fn new(line : &str) -> Folded {
+        let mut text = String::with_capacity(line.len());
+        let mut origin = Vec::with_capacity(line.len() + 1);
+        for (index, ch) in line.char_indices() {
+            let len = text.len();
+            fold::fold_char(ch, &mut text);
+            origin.resize(origin.len() + text.len() - len, index);
+        }
+        origin.push(line.len());
+        Folded { text, origin }
+    }
+
+    // Original span for a folded span: a match may end in the middle of the folding of a single
+    // character (like the first `s` of `ß`), in which case the whole character is covered.
+    fn original(&self, start : usize, end : usize) -> (usize, usize) {
+        let origin = &self.origin;
+        if start == end {
+            return (origin[start], origin[end]);
+        }
+        let last = origin[end - 1];
+        let end = origin[end..].iter().copied().find(|&index| index != last).unwrap_or(last);
+        (origin[start], end)
+    }
+
+    // Original spans for folded ones, left to right. Matches within the folding of the same
+    // character (both `s` of `ß`) cover it once.
+    fn originals(&self, spans : Vec<(usize, usize)>) -> Vec<(usize, usize)> {
+        let mut originals : Vec<(usize, usize)> = Vec::with_capacity(spans.len());
+        for (start, end) in spans {
+            let (start, end) = self.original(start, end);
+            match originals.last_mut() {
+                Some(last) if start < last.1 || (start, end) == *last => last.1 = last.1.max(end),
+                _ => originals.push((start, end)),
+            }
+        }
+        originals
+    }
+}
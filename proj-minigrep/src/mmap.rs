@@ -0,0 +1,309 @@
+//
+// `mmap.rs` maps large local files into memory, to search them in place, without copying them
+//   into buffers first (see `Searcher::search_file`). Only built with the `mmap` feature, and
+//   only maps on Linux (x86-64 and AArch64, whose constants and calling conventions are declared
+//   here by hand, without depending on the `libc` crate); elsewhere, and for pipes, special
+//   files, and files too small to be worth it, `Mmap::open` declines and the file is read as
+//   usual.
+//
+// A mapped file truncated while searched would crash the process with SIGBUS once the pages
+//   past its new end are touched. A SIGBUS handler instead maps zeroed pages over the part of
+//   the mappings past the end of their file, then lets the access resume; the mapping is then
+//   flagged, and `Mmap::is_truncated` tells that its contents are not those of the file. Any
+//   other SIGBUS is left to the handler there was before.
+//
+
+use std::fs::File;
+use std::io;
+use std::ops::Deref;
+
+
+// Files smaller than this are read, which is as fast for them.
+pub const MIN_LEN : u64 = 256 << 10;
+
+
+// A file mapped in memory, read only.
+pub struct Mmap {
+    ptr : *const u8,
+    len : usize,
+    slot : usize,   // Where it is registered for the SIGBUS handler.
+    _file : File,   // Kept open for the handler to check its size.
+}
+
+impl Mmap {
+
+    /// Map `file`, or return `None` if it should be read instead: not a regular file, smaller
+    /// than `MIN_LEN`, on a platform without support, or if mapping it fails.
+    ///
+    /// # Safety
+    ///
+    /// The bytes of the map are those of the file, which other processes can write to or
+    /// truncate while it is mapped, past the borrow checker: they change under the slices of
+    /// the map, the ones past a new end turning to zeros. The caller must make sure the file is
+    /// not changed meanwhile, or that nothing relies on the bytes read staying the same, such as
+    /// a `&str` built over them.
+    pub unsafe fn open(file : &File) -> io::Result<Option<Mmap>> {
+        let metadata = file.metadata()?;
+        if !metadata.is_file() || metadata.len() < MIN_LEN || metadata.len() > usize::MAX as u64 {
+            return Ok(None);
+        }
+        Ok(sys::map(file.try_clone()?, metadata.len() as usize))
+    }
+
+    // Whether the file was truncated while mapped, the bytes past its end reading as zeros.
+    pub fn is_truncated(&self) -> bool {
+        sys::is_truncated(self.slot)
+    }
+}
+
+impl Deref for Mmap {
+    type Target = [u8];
+
+    fn deref(&self) -> &[u8] {
+        unsafe { std::slice::from_raw_parts(self.ptr, self.len) }
+    }
+}
+
+impl Drop for Mmap {
+    fn drop(&mut self) {
+        sys::unmap(self);
+    }
+}
+
+
+#[cfg(all(target_os = "linux", any(target_arch = "x86_64", target_arch = "aarch64")))]
+mod sys {
+
+    use std::fs::File;
+    use std::os::raw::{c_int, c_long, c_void};
+    use std::os::unix::io::AsRawFd;
+    use std::ptr;
+    use std::sync::Once;
+    use std::sync::atomic::{AtomicI32, AtomicUsize, Ordering};
+
+    use super::Mmap;
+
+    const PROT_READ : c_int = 1;
+    const MAP_PRIVATE : c_int = 0x02;
+    const MAP_FIXED : c_int = 0x10;
+    const MAP_ANONYMOUS : c_int = 0x20;
+    const MAP_FAILED : *mut c_void = !0 as *mut c_void;
+    const SIGBUS : c_int = 7;
+
+    extern "C" {
+        fn mmap(addr : *mut c_void, len : usize, prot : c_int, flags : c_int, fd : c_int,
+                offset : c_long) -> *mut c_void;
+        fn munmap(addr : *mut c_void, len : usize) -> c_int;
+        fn fstat(fd : c_int, buf : *mut Stat) -> c_int;
+        fn getpagesize() -> c_int;
+        fn signal(signum : c_int, handler : usize) -> usize;
+    }
+
+    // The `struct stat` of the kernel, of 144 bytes on x86-64 and 128 on AArch64, both with the
+    // size of the file as their seventh 8-byte field.
+    #[repr(C)]
+    struct Stat {
+        fields : [i64; 18],
+    }
+
+    impl Stat {
+        fn size(&self) -> i64 {
+            self.fields[6]
+        }
+    }
+
+    // The mappings, registered in slots the SIGBUS handler can read without locking. A slot
+    // goes from `FREE` to `CLAIMED` while filled, to `ACTIVE`, to `FIXING` while the handler
+    // works on it, and back to `FREE` when unmapped. Without a free slot, files are read.
+    const SLOTS : usize = 64;
+    const FREE : usize = 0;
+    const CLAIMED : usize = 1;
+    const ACTIVE : usize = 2;
+    const FIXING : usize = 3;
+
+    struct Slot {
+        state : AtomicUsize,
+        addr : AtomicUsize,
+        len : AtomicUsize,
+        fd : AtomicI32,
+        truncated : AtomicUsize,    // Non-zero once zeroed pages are mapped past the end.
+    }
+
+    #[allow(clippy::declare_interior_mutable_const)]
+    const SLOT : Slot = Slot {
+        state : AtomicUsize::new(FREE),
+        addr : AtomicUsize::new(0),
+        len : AtomicUsize::new(0),
+        fd : AtomicI32::new(-1),
+        truncated : AtomicUsize::new(0),
+    };
+
+    static REGISTRY : [Slot; SLOTS] = [SLOT; SLOTS];
+    static PAGE_SIZE : AtomicUsize = AtomicUsize::new(0);
+    static PREVIOUS : AtomicUsize = AtomicUsize::new(0);     // The handler replaced.
+
+    pub fn map(file : File, len : usize) -> Option<Mmap> {
+        static INSTALLED : Once = Once::new();
+        INSTALLED.call_once(|| unsafe {
+            PAGE_SIZE.store(getpagesize() as usize, Ordering::SeqCst);
+            let previous = signal(SIGBUS, handle as extern "C" fn(c_int) as usize);
+            PREVIOUS.store(previous, Ordering::SeqCst);
+        });
+
+        let slot = REGISTRY.iter().position(|slot| {
+            slot.state.compare_exchange(FREE, CLAIMED, Ordering::SeqCst, Ordering::SeqCst).is_ok()
+        })?;
+        let fd = file.as_raw_fd();
+        let addr = unsafe { mmap(ptr::null_mut(), len, PROT_READ, MAP_PRIVATE, fd, 0) };
+        let registered = &REGISTRY[slot];
+        if addr == MAP_FAILED {
+            registered.state.store(FREE, Ordering::SeqCst);
+            return None;
+        }
+        registered.addr.store(addr as usize, Ordering::SeqCst);
+        registered.len.store(len, Ordering::SeqCst);
+        registered.fd.store(fd, Ordering::SeqCst);
+        registered.truncated.store(0, Ordering::SeqCst);
+        registered.state.store(ACTIVE, Ordering::SeqCst);
+        Some(Mmap { ptr : addr as *const u8, len, slot, _file : file })
+    }
+
+    pub fn is_truncated(slot : usize) -> bool {
+        REGISTRY[slot].truncated.load(Ordering::SeqCst) != 0
+    }
+
+    pub fn unmap(map : &Mmap) {
+        let slot = &REGISTRY[map.slot];
+        // Wait for the handler, were it fixing this mapping from another thread.
+        while slot.state.compare_exchange(ACTIVE, CLAIMED, Ordering::SeqCst, Ordering::SeqCst)
+                        .is_err() {
+            std::hint::spin_loop();
+        }
+        unsafe {
+            munmap(map.ptr as *mut c_void, map.len);
+        }
+        slot.state.store(FREE, Ordering::SeqCst);
+    }
+
+    // Map zeroed pages past the end of the truncated files. If none was, the fault is not ours:
+    // the previous handler is restored, and gets it when the access faults again.
+    extern "C" fn handle(_signum : c_int) {
+        let page = PAGE_SIZE.load(Ordering::SeqCst);
+        let mut fixed = false;
+        for slot in REGISTRY.iter() {
+            if slot.state.compare_exchange(ACTIVE, FIXING, Ordering::SeqCst, Ordering::SeqCst)
+                   .is_err() {
+                continue;
+            }
+            let addr = slot.addr.load(Ordering::SeqCst);
+            let len = slot.len.load(Ordering::SeqCst);
+            let mut stat = Stat { fields : [0; 18] };
+            let end = match unsafe { fstat(slot.fd.load(Ordering::SeqCst), &mut stat) } {
+                0 => stat.size(),
+                _ => -1,
+            };
+            if end >= 0 && (end as usize) < len {
+                let start = (end as usize).div_ceil(page) * page;
+                if start < len {
+                    let zeroed = unsafe {
+                        mmap((addr + start) as *mut c_void, len - start, PROT_READ,
+                             MAP_PRIVATE | MAP_ANONYMOUS | MAP_FIXED, -1, 0)
+                    };
+                    if zeroed != MAP_FAILED {
+                        slot.truncated.store(1, Ordering::SeqCst);
+                        fixed = true;
+                    }
+                }
+            }
+            slot.state.store(ACTIVE, Ordering::SeqCst);
+        }
+        if !fixed {
+            unsafe {
+                signal(SIGBUS, PREVIOUS.load(Ordering::SeqCst));
+            }
+        }
+    }
+}
+
+
+#[cfg(not(all(target_os = "linux", any(target_arch = "x86_64", target_arch = "aarch64"))))]
+mod sys {
+
+    use std::fs::File;
+
+    use super::Mmap;
+
+    pub fn map(_file : File, _len : usize) -> Option<Mmap> {
+        None
+    }
+
+    pub fn is_truncated(_slot : usize) -> bool {
+        false
+    }
+
+    pub fn unmap(_map : &Mmap) {}
+}
+
+
+#[cfg(test)]
+mod tests {
+
+    use std::env;
+    use std::fs::{self, File};
+    use std::io::Seek;
+    use std::process;
+
+    use super::*;
+
+    // The files of the tests are only changed by them, and read as bytes.
+    fn map(file : &File) -> Option<Mmap> {
+        unsafe { Mmap::open(file) }.unwrap()
+    }
+
+    #[test]
+    fn maps_large_files_only() {
+        let dir = env::temp_dir().join(format!("minigrep-mmap-{}", process::id()));
+        fs::create_dir_all(&dir).unwrap();
+
+        let small = dir.join("small");
+        fs::write(&small, b"tiny\n").unwrap();
+        assert!(map(&File::open(&small).unwrap()).is_none());
+
+        let large = dir.join("large");
+        let contents : Vec<u8> = (0..MIN_LEN as usize * 2).map(|i| (i % 251) as u8).collect();
+        fs::write(&large, &contents).unwrap();
+        if let Some(map) = map(&File::open(&large).unwrap()) {
+            assert_eq!(&contents[..], &map[..]);
+            assert!(!map.is_truncated());
+        }
+
+        // Neither directories nor character devices are mapped.
+        assert!(map(&File::open(&dir).unwrap()).is_none());
+        if let Ok(zero) = File::open("/dev/zero") {
+            assert!(map(&zero).is_none());
+        }
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn survives_truncation() {
+        let dir = env::temp_dir().join(format!("minigrep-mmap-cut-{}", process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("cut");
+        fs::write(&path, vec![b'x'; MIN_LEN as usize * 4]).unwrap();
+
+        let file = File::open(&path).unwrap();
+        if let Some(map) = map(&file) {
+            assert_eq!(b'x', map[0]);
+            fs::OpenOptions::new().write(true).open(&path).unwrap().set_len(100).unwrap();
+            // The end of the file is still there, the rest reads as zeros.
+            assert_eq!(b'x', map[99]);
+            assert_eq!(0, map[map.len() - 1]);
+            assert!(map[100..].iter().all(|&byte| byte == 0));
+            assert!(map.is_truncated());
+            // Without moving the offset of the file.
+            assert_eq!(0, (&file).stream_position().unwrap());
+        }
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
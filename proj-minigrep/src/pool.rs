@@ -0,0 +1,128 @@
+//
+// `pool.rs` is a fixed-size pool of worker threads, in the spirit of the one in the web server
+//   project: jobs are sent over a channel, whose receiving end is shared by all the workers.
+//
+
+use std::panic::{self, AssertUnwindSafe};
+use std::thread;
+use std::sync::mpsc;
+use std::sync::{Mutex, Arc};
+
+
+// Thread pool.
+pub struct ThreadPool {
+    workers : Vec<Worker>,
+    sender : mpsc::Sender<Message>,
+}
+
+impl ThreadPool {
+
+    // Initialize a thread pool with given size number of threads.
+    pub fn new(size : usize) -> ThreadPool {
+
+        // Size must be positive.
+        assert!(size > 0);
+
+        // The receiver is shared among threads, therefore wrapped in a mutex lock and shared
+        // using atomic reference counting.
+        let (sender, receiver) = mpsc::channel::<Message>();
+        let receiver = Arc::new(Mutex::new(receiver));
+
+        let workers = (0..size).map(|_| Worker::new(Arc::clone(&receiver))).collect();
+
+        ThreadPool { workers, sender }
+    }
+
+    // Trigger a vacant worker to execute a closure. Jobs are started in submission order. A job
+    // that panics only ends itself, telling whoever waits for it being up to the job.
+    pub fn exec<F>(&self, func : F)
+        where F : FnOnce() + Send + 'static
+    {
+        self.sender.send(Message::NewJob(Box::new(func))).unwrap();
+    }
+}
+
+impl Drop for ThreadPool {
+
+    // Graceful shutdown: jobs already submitted are run to completion before the workers exit.
+    fn drop(&mut self) {
+
+        // Send terminate messages, one for each worker. They queue up behind pending jobs.
+        // Workers that are gone anyway do not receive theirs.
+        for _ in &self.workers {
+            let _ = self.sender.send(Message::Terminate);
+        }
+
+        // For each worker, take its thread handle and join it. Panicking while dropping would
+        // abort, so a worker that died is only not waited for.
+        for worker in &mut self.workers {
+            if let Some(handle) = worker.handle.take() {
+                let _ = handle.join();
+            }
+        }
+    }
+}
+
+
+// Worker wrapping over a thread.
+struct Worker {
+    handle : Option<thread::JoinHandle<()>>,    // None variant indicates going to shutdown.
+}
+
+impl Worker {
+
+    // Initialize a worker by spawing a thread that loops to receive jobs until terminated.
+    fn new(receiver : Arc<Mutex<mpsc::Receiver<Message>>>) -> Worker {
+        let handle = thread::spawn(move || {
+            loop {
+
+                // Only hold the lock while receiving, not while running the job.
+                let msg = receiver.lock().unwrap().recv().unwrap();
+
+                match msg {
+                    Message::NewJob(job) => {
+                        let _ = panic::catch_unwind(AssertUnwindSafe(job));
+                    },
+                    Message::Terminate => break,
+                }
+            }
+        });
+
+        Worker { handle : Some(handle) }
+    }
+}
+
+
+// Alias `Job` as the type of a box over a closure. Boxed `FnOnce` closures can be called
+// directly nowadays, so no `FnBox` helper trait is needed.
+type Job = Box<dyn FnOnce() + Send + 'static>;
+
+
+// A message sent is either a new job or a terminate signal.
+enum Message {
+    NewJob(Job),
+    Terminate,
+}
+
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    #[test]
+    fn panicking_job() {
+        let (sender, receiver) = mpsc::channel();
+        {
+            // A single worker, which must outlive the panic to run the next job.
+            let pool = ThreadPool::new(1);
+            pool.exec(|| panic!("job failed"));
+            for i in 0..3 {
+                let sender = sender.clone();
+                pool.exec(move || sender.send(i).unwrap());
+            }
+        }
+        drop(sender);
+        assert_eq!(vec![0, 1, 2], receiver.iter().collect::<Vec<_>>());
+    }
+}
@@ -0,0 +1,165 @@
+//
+// `printer.rs` formats search results the way grep does:
+//
+//   [path:][line number:][byte offset:]line
+//
+// Selected lines use `:` as the field separator, context lines use `-`, and a `--` line
+//   separates context windows that are apart. The other output modes print counts, paths, or
+//   only the matched parts of the lines.
+//
+// A block of lines selected in multi-line mode is printed line by line, each with its own
+//   number and offset, so it reads like as many selected lines.
+//
+// When coloring, the matched parts are highlighted from the spans found by the search, and the
+//   paths, numbers, and separators get their own colors, using grep's default palette.
+//
+
+use std::fmt::Display;
+use std::io::{self, Write};
+use std::path::Path;
+
+use crate::search::{Line, Match};
+
+
+// ANSI escape sequences.
+const MATCH_COLOR : &str = "\x1b[1;31m";     // Bold red.
+const PATH_COLOR : &str = "\x1b[35m";        // Magenta.
+const NUMBER_COLOR : &str = "\x1b[32m";      // Green.
+const SEPARATOR_COLOR : &str = "\x1b[36m";   // Cyan.
+const RESET : &str = "\x1b[0m";
+
+
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Printer {
+    pub line_number : bool,
+    pub byte_offset : bool,
+    pub with_filename : bool,   // Prefix lines with the path of their input.
+    pub color : bool,           // Highlight with ANSI escape sequences.
+}
+
+impl Printer {
+
+    pub fn print_line<W : Write>(&self, out : &mut W, path : &Path, line : &Line)
+                                 -> io::Result<()> {
+        match line {
+            Line::Match(m) => self.print_block(out, path, m)?,
+            Line::Context { line_number, offset, line } => {
+                self.print_prefix(out, path, '-', *line_number, *offset)?;
+                write!(out, "{}", line)?;
+            },
+            Line::Break => self.paint(out, SEPARATOR_COLOR, "--")?,
+        }
+        writeln!(out)
+    }
+
+    // Print each matched part of a selected line on its own line. The byte offset is the one
+    // of the part itself.
+    pub fn print_only_matching<W : Write>(&self, out : &mut W, path : &Path, m : &Match)
+                                          -> io::Result<()> {
+        for &(start, end) in &m.spans {
+            if start == end {
+                continue;   // Nothing to show for empty matches.
+            }
+            let line_number = m.line_number + m.line[..start].matches('\n').count();
+            self.print_prefix(out, path, ':', line_number, m.offset + start)?;
+            self.paint(out, MATCH_COLOR, &m.line[start..end])?;
+            writeln!(out)?;
+        }
+        Ok(())
+    }
+
+    pub fn print_count<W : Write>(&self, out : &mut W, path : &Path, count : usize)
+                                  -> io::Result<()> {
+        if self.with_filename {
+            self.paint(out, PATH_COLOR, path.display())?;
+            self.paint(out, SEPARATOR_COLOR, ':')?;
+        }
+        writeln!(out, "{}", count)
+    }
+
+    pub fn print_path<W : Write>(&self, out : &mut W, path : &Path) -> io::Result<()> {
+        self.paint(out, PATH_COLOR, path.display())?;
+        writeln!(out)
+    }
+
+    // Note that a binary input has selected lines, instead of printing them.
+    pub fn print_binary_match<W : Write>(&self, out : &mut W, path : &Path) -> io::Result<()> {
+        write!(out, "Binary file ")?;
+        self.paint(out, PATH_COLOR, path.display())?;
+        writeln!(out, " matches")
+    }
+
+    fn print_prefix<W : Write>(&self, out : &mut W, path : &Path, sep : char, line_number : usize,
+                               offset : usize) -> io::Result<()> {
+        if self.with_filename {
+            self.paint(out, PATH_COLOR, path.display())?;
+            self.paint(out, SEPARATOR_COLOR, sep)?;
+        }
+        if self.line_number {
+            self.paint(out, NUMBER_COLOR, line_number)?;
+            self.paint(out, SEPARATOR_COLOR, sep)?;
+        }
+        if self.byte_offset {
+            self.paint(out, NUMBER_COLOR, offset)?;
+            self.paint(out, SEPARATOR_COLOR, sep)?;
+        }
+        Ok(())
+    }
+
+    // Write the lines of a selected block (a single one, unless multi-line) with their prefix,
+    // but the terminator of the last one.
+    fn print_block<W : Write>(&self, out : &mut W, path : &Path, m : &Match) -> io::Result<()> {
+        let mut start = 0;
+        for (i, line) in m.line.split('\n').enumerate() {
+            if i > 0 {
+                writeln!(out)?;
+            }
+            let next = start + line.len() + 1;
+            // The lines within the block still have the `\r` of their terminator.
+            let line = match line.strip_suffix('\r') {
+                Some(stripped) if next <= m.line.len() => stripped,
+                _ => line,
+            };
+            let end = start + line.len();
+            let spans : Vec<_> = m.spans.iter()
+                                        .map(|&(s, e)| (s.max(start), e.min(end)))
+                                        .filter(|&(s, e)| s < e)
+                                        .map(|(s, e)| (s - start, e - start))
+                                        .collect();
+            self.print_prefix(out, path, ':', m.line_number + i, m.offset + start)?;
+            self.print_highlighted(out, line, &spans)?;
+            start = next;
+        }
+        Ok(())
+    }
+
+    // Write a line with the given byte spans highlighted. The spans come from the search, so
+    // the line is not searched again.
+    fn print_highlighted<W : Write>(&self, out : &mut W, line : &str, spans : &[(usize, usize)])
+                                    -> io::Result<()> {
+        if !self.color {
+            return write!(out, "{}", line);
+        }
+
+        let mut last = 0;
+        for &(start, end) in spans {
+            if start == end {
+                continue;
+            }
+            write!(out, "{}", &line[last..start])?;
+            self.paint(out, MATCH_COLOR, &line[start..end])?;
+            last = end;
+        }
+        write!(out, "{}", &line[last..])
+    }
+
+    // Write `text` in the given color, if coloring.
+    fn paint<W : Write, T : Display>(&self, out : &mut W, color : &str, text : T)
+                                      -> io::Result<()> {
+        if self.color {
+            write!(out, "{}{}{}", color, text, RESET)
+        } else {
+            write!(out, "{}", text)
+        }
+    }
+}
@@ -0,0 +1,376 @@
+//
+// `record.rs` splits lines into the fields of CSV, TSV or logfmt records, so that patterns can
+//   be matched within a single field, and only some fields printed.
+//
+// CSV fields are separated by commas and TSV ones by tabs. Either may be quoted with `"`, a
+//   doubled `""` standing for a quote within, as in RFC 4180; but a record is a single line, so
+//   a quoted field still open at the end of its line ends there. The first line of an input is
+//   its header, naming the columns.
+//
+// logfmt records are `key=value` pairs separated by spaces, whose values may be quoted with `"`
+//   and then hold backslash escapes. A key alone has an empty value.
+//
+// Matches are found in the values of the fields, unquoted and unescaped, and their spans are
+//   mapped back to the line.
+//
+
+use std::borrow::Cow;
+use std::fmt;
+use std::path::Path;
+
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Format {
+    Csv,
+    Tsv,
+    Logfmt,
+}
+
+impl Format {
+
+    // The format of the input at `path`, from its extension: `.csv` and `.tsv` files are CSV and
+    // TSV, anything else logfmt.
+    pub fn detect(path : &Path) -> Format {
+        match path.extension().and_then(|extension| extension.to_str()) {
+            Some(extension) if extension.eq_ignore_ascii_case("csv") => Format::Csv,
+            Some(extension) if extension.eq_ignore_ascii_case("tsv") => Format::Tsv,
+            _ => Format::Logfmt,
+        }
+    }
+
+    // Whether the first line names the fields, rather than being a record.
+    pub fn has_header(self) -> bool {
+        self != Format::Logfmt
+    }
+
+    fn separator(self) -> char {
+        match self {
+            Format::Csv => ',',
+            Format::Tsv => '\t',
+            Format::Logfmt => ' ',
+        }
+    }
+}
+
+
+// A field as given on the command line: by name (a column of the header, or a logfmt key), or
+// by position, from 1.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Selector {
+    Name(String),
+    Column(usize),
+}
+
+impl Selector {
+
+    // Parse a comma separated list of fields, the numbers being positions. `None` if a field
+    // is empty or the position 0.
+    pub fn parse_list(list : &str) -> Option<Vec<Selector>> {
+        list.split(',').map(|field| match field.parse::<usize>() {
+                           Ok(0) => None,
+                           Ok(column) => Some(Selector::Column(column)),
+                           Err(_) if field.is_empty() => None,
+                           Err(_) => Some(Selector::Name(field.to_string())),
+                       })
+                       .collect()
+    }
+}
+
+impl fmt::Display for Selector {
+    fn fmt(&self, f : &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Selector::Name(name) => write!(f, "field '{}'", name),
+            Selector::Column(column) => write!(f, "column {}", column),
+        }
+    }
+}
+
+
+// A field found in the records of a given format: at a position from 0, or by its logfmt key.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Target {
+    Index(usize),
+    Key(String),
+}
+
+impl Target {
+
+    // Where `selector` is in the records of `format`, whose header has the fields `header`.
+    // `None` if it names no column of the header, or the column 0, as they are numbered from 1.
+    pub fn resolve(selector : &Selector, format : Format, header : &[Field]) -> Option<Target> {
+        match (selector, format) {
+            (Selector::Column(column), _) => column.checked_sub(1).map(Target::Index),
+            (Selector::Name(name), Format::Logfmt) => Some(Target::Key(name.clone())),
+            (Selector::Name(name), _) => {
+                header.iter().position(|field| field.value == name.as_str()).map(Target::Index)
+            },
+        }
+    }
+
+    // The field of a record, which may not have it.
+    pub fn find<'f, 'a>(&self, fields : &'f [Field<'a>]) -> Option<&'f Field<'a>> {
+        match self {
+            Target::Index(index) => fields.get(*index),
+            Target::Key(key) => fields.iter().find(|field| field.key == Some(key.as_str())),
+        }
+    }
+}
+
+
+// A field of a record.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Field<'a> {
+    pub key : Option<&'a str>,      // Of a logfmt pair.
+    pub value : Cow<'a, str>,       // Unquoted and unescaped.
+    pub raw : (usize, usize),       // Span of the whole field in the line, key included.
+    start : usize,                  // Where the value starts in the line, past any quote.
+    removed : Vec<usize>,           // Positions in `value` past a quote or backslash dropped.
+}
+
+impl<'a> Field<'a> {
+
+    fn plain(key : Option<&'a str>, line : &'a str, raw : (usize, usize), start : usize)
+             -> Field<'a> {
+        Field { key, value : Cow::Borrowed(&line[start..raw.1]), raw, start, removed : vec![] }
+    }
+
+    // The span in the line of a span of the value.
+    pub fn original(&self, start : usize, end : usize) -> (usize, usize) {
+        let shift = |pos : usize| {
+            self.start + pos + self.removed.iter().take_while(|&&removed| removed <= pos).count()
+        };
+        (shift(start), shift(end))
+    }
+}
+
+// The fields of a record.
+pub fn split(line : &str, format : Format) -> Vec<Field<'_>> {
+    match format {
+        Format::Csv | Format::Tsv => split_delimited(line, format.separator()),
+        Format::Logfmt => split_logfmt(line),
+    }
+}
+
+fn split_delimited(line : &str, separator : char) -> Vec<Field<'_>> {
+    let next_separator = |from : usize| {
+        line[from..].find(separator).map_or(line.len(), |i| from + i)
+    };
+    let mut fields = vec![];
+    let mut pos = 0;
+    loop {
+        let start = pos;
+        if !line[pos..].starts_with('"') {
+            pos = next_separator(pos);
+            fields.push(Field::plain(None, line, (start, pos), start));
+        } else {
+            // Up to the closing quote, `""` standing for `"`.
+            let mut value = String::new();
+            let mut removed = vec![];
+            pos += 1;
+            loop {
+                let quote = line[pos..].find('"').map_or(line.len(), |i| pos + i);
+                value.push_str(&line[pos..quote]);
+                pos = (quote + 1).min(line.len());
+                if !line[pos..].starts_with('"') {
+                    break;
+                }
+                value.push('"');
+                removed.push(value.len());
+                pos += 1;
+            }
+            // Anything between the closing quote and the separator is kept.
+            let end = next_separator(pos);
+            if pos < end {
+                removed.push(value.len());
+                value.push_str(&line[pos..end]);
+            }
+            fields.push(Field { key : None, value : Cow::Owned(value), raw : (start, end),
+                                start : start + 1, removed });
+            pos = end;
+        }
+        if pos >= line.len() {
+            return fields;
+        }
+        pos += separator.len_utf8();
+    }
+}
+
+fn split_logfmt(line : &str) -> Vec<Field<'_>> {
+    let is_space = |ch : char| ch == ' ' || ch == '\t';
+    let mut fields = vec![];
+    let mut pos = 0;
+    loop {
+        pos += line[pos..].find(|ch| !is_space(ch)).unwrap_or(line.len() - pos);
+        if pos == line.len() {
+            return fields;
+        }
+        let start = pos;
+        pos += line[pos..].find(|ch| ch == '=' || is_space(ch)).unwrap_or(line.len() - pos);
+        let key = Some(&line[start..pos]);
+        if !line[pos..].starts_with('=') {
+            fields.push(Field::plain(key, line, (start, pos), pos));
+            continue;
+        }
+        pos += 1;
+        if !line[pos..].starts_with('"') {
+            let value = pos;
+            pos += line[pos..].find(is_space).unwrap_or(line.len() - pos);
+            fields.push(Field::plain(key, line, (start, pos), value));
+            continue;
+        }
+
+        // Up to the closing quote, with backslash escapes.
+        let value_start = pos + 1;
+        let mut value = String::new();
+        let mut removed = vec![];
+        let mut chars = line[value_start..].char_indices();
+        pos = line.len();
+        while let Some((i, ch)) = chars.next() {
+            match ch {
+                '"' => {
+                    pos = value_start + i + 1;
+                    break;
+                },
+                '\\' => match chars.next() {
+                    Some((_, escaped)) => {
+                        value.push(match escaped {
+                            'n' => '\n',
+                            't' => '\t',
+                            'r' => '\r',
+                            other => other,
+                        });
+                        removed.push(value.len());
+                    },
+                    None => value.push('\\'),
+                },
+                _ => value.push(ch),
+            }
+        }
+        fields.push(Field { key, value : Cow::Owned(value), raw : (start, pos), start : value_start,
+                            removed });
+    }
+}
+
+
+// Some fields of a record, joined by the separator of its format.
+pub struct Projection {
+    pub text : String,
+    pieces : Vec<(usize, usize, usize)>,    // Span of each field in the line, start in `text`.
+}
+
+impl Projection {
+
+    // The fields `targets` of the record `line`, as they are in the line. The missing columns
+    // of CSV and TSV records are left empty; missing logfmt keys are left out.
+    pub fn new(line : &str, format : Format, targets : &[Target]) -> Projection {
+        let fields = split(line, format);
+        let mut text = String::new();
+        let mut pieces = vec![];
+        for (i, target) in targets.iter().enumerate() {
+            let field = target.find(&fields);
+            if field.is_none() && format == Format::Logfmt {
+                continue;
+            }
+            if i > 0 && (format != Format::Logfmt || !pieces.is_empty()) {
+                text.push(format.separator());
+            }
+            if let Some(field) = field {
+                let (start, end) = field.raw;
+                pieces.push((start, end, text.len()));
+                text.push_str(&line[start..end]);
+            }
+        }
+        Projection { text, pieces }
+    }
+
+    // The spans of `text` for the parts of `spans`, spans of the line, within the fields kept.
+    pub fn spans(&self, spans : &[(usize, usize)]) -> Vec<(usize, usize)> {
+        let mut kept = vec![];
+        for &(start, end, at) in &self.pieces {
+            for &(s, e) in spans {
+                let (s, e) = (s.max(start), e.min(end));
+                if s < e {
+                    kept.push((s - start + at, e - start + at));
+                }
+            }
+        }
+        kept
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    fn values(line : &str, format : Format) -> Vec<String> {
+        split(line, format).into_iter().map(|field| field.value.into_owned()).collect()
+    }
+
+    #[test]
+    fn delimited() {
+        assert_eq!(vec!["a", "", "c d", ""], values("a,,c d,", Format::Csv));
+        assert_eq!(vec!["a,b", "say \"hi\"", "x"], values(r#""a,b","say ""hi""",x"#, Format::Csv));
+        assert_eq!(vec!["a,b", "c\td"], values("a,b\t\"c\td\"", Format::Tsv));
+        assert_eq!(vec![""], values("", Format::Csv));
+
+        // An unclosed quote runs to the end of the line, text after a closing one is kept.
+        assert_eq!(vec!["a", "b,c"], values(r#"a,"b,c"#, Format::Csv));
+        assert_eq!(vec!["ab c", "d"], values(r#""ab" c,d"#, Format::Csv));
+
+        let fields = split(r#"1,"say ""hi"" now""#, Format::Csv);
+        assert_eq!((2, 18), fields[1].raw);
+        let hi = fields[1].value.find("hi").unwrap();
+        assert_eq!((9, 11), fields[1].original(hi, hi + 2));
+        assert_eq!((3, 17), fields[1].original(0, fields[1].value.len()));
+    }
+
+    #[test]
+    fn logfmt() {
+        let line = r#"level=info msg="disk \"sda\" full"  debug user=42"#;
+        let fields = split(line, Format::Logfmt);
+        let pairs : Vec<_> = fields.iter().map(|field| (field.key.unwrap(), &*field.value))
+                                          .collect();
+        assert_eq!(vec![("level", "info"), ("msg", "disk \"sda\" full"), ("debug", ""),
+                        ("user", "42")],
+                   pairs);
+        assert_eq!("msg=\"disk \\\"sda\\\" full\"", &line[fields[1].raw.0..fields[1].raw.1]);
+        let sda = fields[1].value.find("sda").unwrap();
+        let (start, end) = fields[1].original(sda - 1, sda + 4);
+        assert_eq!(r#"\"sda\""#, &line[start..end]);
+        assert!(split("  ", Format::Logfmt).is_empty());
+    }
+
+    #[test]
+    fn selectors_and_projections() {
+        assert_eq!(Some(vec![Selector::Column(2), Selector::Name("user".to_string())]),
+                   Selector::parse_list("2,user"));
+        assert_eq!(None, Selector::parse_list("0"));
+        assert_eq!(None, Selector::parse_list("a,,b"));
+        assert_eq!(Format::Csv, Format::detect(Path::new("dir/data.CSV")));
+        assert_eq!(Format::Logfmt, Format::detect(Path::new("app.log")));
+
+        let header = split("id,name,city", Format::Csv);
+        let name = Selector::Name("name".to_string());
+        assert_eq!(Some(Target::Index(1)), Target::resolve(&name, Format::Csv, &header));
+        assert_eq!(None, Target::resolve(&Selector::Name("age".to_string()), Format::Csv,
+                                         &header));
+        assert_eq!(Some(Target::Key("name".to_string())),
+                   Target::resolve(&name, Format::Logfmt, &[]));
+        assert_eq!(Some(Target::Index(2)), Target::resolve(&Selector::Column(3), Format::Csv,
+                                                           &header));
+        assert_eq!(None, Target::resolve(&Selector::Column(0), Format::Csv, &header));
+
+        let line = r#"7,"Doe, Jane",Paris"#;
+        let targets = [Target::Index(2), Target::Index(1), Target::Index(5)];
+        let projection = Projection::new(line, Format::Csv, &targets);
+        assert_eq!(r#"Paris,"Doe, Jane","#, projection.text);
+        assert_eq!(vec![(0, 3), (6, 11)], projection.spans(&[(2, 7), (14, 17)]));
+
+        let line = "a=1 b=2 c=3";
+        let targets = [Target::Key("c".to_string()), Target::Key("z".to_string()),
+                       Target::Key("a".to_string())];
+        assert_eq!("c=3 a=1", Projection::new(line, Format::Logfmt, &targets).text);
+    }
+}
@@ -0,0 +1,966 @@
+//
+// `regex.rs` is a small self-contained regular expression engine.
+//
+//   1. The pattern is parsed into an AST by a recursive descent parser;
+//   2. The AST is compiled into a program of simple instructions;
+//   3. The program is simulated as a Thompson NFA (the "Pike VM"), which walks the input only
+//      once and keeps all alive threads in lockstep, so matching time is linear in the input.
+//
+// Supported syntax: literals and escapes, `.`, `[...]` classes with ranges and negation,
+//   `\d \w \s \D \W \S`, anchors `^ $ \b \B`, alternation `|`, capturing `( )` and
+//   non-capturing `(?: )` groups, and the quantifiers `* + ? {n} {n,} {n,m}` with their lazy
+//   `?` variants.
+//
+// Several patterns can be compiled into one program, matching any of them, and matches can be
+//   restricted to whole words or whole lines (see `Regex::new_many`).
+//
+// In multi-line mode, `^` and `$` also match at the start and end of every line of the text,
+//   so a pattern can span lines with `\n` or `\s`; `.` never matches a line feed.
+//
+// The literal texts any match must contain can be told from the AST (see `query`), for the
+//   trigram index to skip the files without them.
+//
+
+use std::error::Error;
+use std::fmt;
+
+use crate::config::Boundary;
+use crate::fold;
+use crate::index::Query;
+
+
+// Error when compiling a pattern, carrying the (character) position where parsing stopped.
+#[derive(Debug, Clone, PartialEq)]
+pub struct RegexError {
+    pub pos : usize,
+    pub msg : &'static str,
+    pub pattern : usize,    // Index of the pattern in error, among those compiled together.
+}
+
+impl fmt::Display for RegexError {
+    fn fmt(&self, f : &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} at position {}", self.msg, self.pos)
+    }
+}
+
+impl Error for RegexError {}
+
+
+// Perl-style shorthand classes.
+#[derive(Debug, Clone, Copy)]
+enum Perl { Digit, Word, Space }
+
+impl Perl {
+    fn matches(self, ch : char) -> bool {
+        match self {
+            Perl::Digit => ch.is_ascii_digit(),
+            Perl::Word => is_word_char(ch),
+            Perl::Space => ch.is_whitespace(),
+        }
+    }
+}
+
+// A single item inside a bracketed class.
+#[derive(Debug, Clone)]
+enum ClassItem {
+    Range(char, char),
+    Perl(Perl, bool),   // The flag indicates a negated shorthand, e.g. `\D`.
+}
+
+#[derive(Debug, Clone)]
+struct Class {
+    items : Vec<ClassItem>,
+    negated : bool,
+}
+
+impl Class {
+    fn perl(perl : Perl, negated : bool) -> Class {
+        Class { items : vec![ClassItem::Perl(perl, false)], negated }
+    }
+
+    fn contains(&self, ch : char) -> bool {
+        self.items.iter().any(|item| match *item {
+            ClassItem::Range(lo, hi) => (lo..=hi).contains(&ch),
+            ClassItem::Perl(perl, negated) => perl.matches(ch) != negated,
+        })
+    }
+
+    fn matches(&self, ch : char, insensitive : bool) -> bool {
+        let hit = if insensitive {
+            case_variants(ch).into_iter().any(|c| self.contains(c))
+        } else {
+            self.contains(ch)
+        };
+        hit != self.negated
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+enum Look {
+    Start,
+    End,
+    WordBoundary,
+    NotWordBoundary,
+    NoWordBefore,   // Not preceded by a word character, for whole-word matching.
+    NoWordAfter,    // Not followed by a word character.
+}
+
+
+// Abstract syntax tree of a pattern.
+#[derive(Debug)]
+enum Node {
+    Empty,
+    Char(char),
+    Any,
+    Class(Class),
+    Look(Look),
+    Group(Box<Node>, Option<usize>),    // Capture index if it is a capturing group.
+    Concat(Vec<Node>),
+    Alternate(Vec<Node>),
+    Repeat { node : Box<Node>, min : u32, max : Option<u32>, greedy : bool },
+}
+
+
+// Recursive descent parser.
+struct Parser {
+    chars : Vec<char>,
+    pos : usize,
+    ngroups : usize,
+    depth : usize,      // Groups open at the current position.
+    height : usize,     // Groups and quantifiers nested in one another in the node last parsed.
+}
+
+// Upper bound of counted repetitions, to keep compiled programs reasonably sized.
+const MAX_REPEAT : u32 = 1000;
+
+// Upper bound of the groups and quantifiers nested in one another, as the parser, the compiler
+// and the AST itself recurse over them.
+const MAX_NESTING : usize = 250;
+
+// Upper bound of the instructions of a compiled program, as nested repetitions multiply them.
+const MAX_PROGRAM : usize = 1 << 18;
+
+impl Parser {
+
+    fn new(pattern : &str, ngroups : usize) -> Parser {
+        Parser { chars : pattern.chars().collect(), pos : 0, ngroups, depth : 0, height : 0 }
+    }
+
+    fn error<T>(&self, msg : &'static str) -> Result<T, RegexError> {
+        Err(RegexError { pos : self.pos, msg, pattern : 0 })
+    }
+
+    fn peek(&self) -> Option<char> {
+        self.chars.get(self.pos).copied()
+    }
+
+    fn eat(&mut self, ch : char) -> bool {
+        if self.peek() == Some(ch) {
+            self.pos += 1;
+            true
+        } else {
+            false
+        }
+    }
+
+    // alternation := concat ('|' concat)*
+    fn parse_alternation(&mut self) -> Result<Node, RegexError> {
+        let mut branches = vec![self.parse_concat()?];
+        let mut height = self.height;
+        while self.eat('|') {
+            branches.push(self.parse_concat()?);
+            height = height.max(self.height);
+        }
+        self.height = height;
+
+        if branches.len() == 1 {
+            Ok(branches.pop().unwrap())
+        } else {
+            Ok(Node::Alternate(branches))
+        }
+    }
+
+    // concat := repeat*
+    fn parse_concat(&mut self) -> Result<Node, RegexError> {
+        let mut nodes = vec![];
+        let mut height = 0;
+        while let Some(ch) = self.peek() {
+            if ch == '|' || ch == ')' {
+                break;
+            }
+            nodes.push(self.parse_repeat()?);
+            height = height.max(self.height);
+        }
+        self.height = height;
+
+        match nodes.len() {
+            0 => Ok(Node::Empty),
+            1 => Ok(nodes.pop().unwrap()),
+            _ => Ok(Node::Concat(nodes)),
+        }
+    }
+
+    // repeat := atom quantifier*
+    fn parse_repeat(&mut self) -> Result<Node, RegexError> {
+        let mut node = self.parse_atom()?;
+        let mut height = match node {
+            Node::Group(..) => self.height,
+            _ => 0,
+        };
+
+        loop {
+            let start = self.pos;
+            let (min, max) = match self.peek() {
+                Some('*') => { self.pos += 1; (0, None) },
+                Some('+') => { self.pos += 1; (1, None) },
+                Some('?') => { self.pos += 1; (0, Some(1)) },
+                Some('{') => match self.parse_counted()? {
+                    Some(bounds) => bounds,
+                    None => break,
+                },
+                _ => break,
+            };
+
+            if let Node::Look(_) | Node::Empty = node {
+                self.pos = start;
+                return self.error("Quantifier without a repeatable target");
+            }
+
+            height += 1;
+            if height > MAX_NESTING {
+                self.pos = start;
+                return self.error("Pattern nested too deeply");
+            }
+            let greedy = !self.eat('?');
+            node = Node::Repeat { node : Box::new(node), min, max, greedy };
+        }
+
+        self.height = height;
+        Ok(node)
+    }
+
+    // Parse `{n}`, `{n,}` or `{n,m}`. A `{` not followed by a valid bound is a plain literal,
+    // in which case `None` is returned and the position is left untouched.
+    fn parse_counted(&mut self) -> Result<Option<(u32, Option<u32>)>, RegexError> {
+        let start = self.pos;
+        self.pos += 1;
+
+        let min = match self.parse_number()? {
+            Some(n) => n,
+            None => { self.pos = start; return Ok(None) },
+        };
+        let max = if self.eat(',') {
+            self.parse_number()?
+        } else {
+            Some(min)
+        };
+        if !self.eat('}') {
+            self.pos = start;
+            return Ok(None);
+        }
+
+        if let Some(max) = max {
+            if max < min {
+                self.pos = start;
+                return self.error("Invalid repetition range");
+            }
+        }
+        if min > MAX_REPEAT || max.is_some_and(|max| max > MAX_REPEAT) {
+            self.pos = start;
+            return self.error("Repetition count too large");
+        }
+        Ok(Some((min, max)))
+    }
+
+    fn parse_number(&mut self) -> Result<Option<u32>, RegexError> {
+        let start = self.pos;
+        while let Some(ch) = self.peek() {
+            if !ch.is_ascii_digit() {
+                break;
+            }
+            self.pos += 1;
+        }
+        if start == self.pos {
+            return Ok(None);
+        }
+
+        let digits : String = self.chars[start..self.pos].iter().collect();
+        match digits.parse() {
+            Ok(n) => Ok(Some(n)),
+            Err(_) => { self.pos = start; self.error("Repetition count too large") },
+        }
+    }
+
+    fn parse_atom(&mut self) -> Result<Node, RegexError> {
+        let ch = match self.peek() {
+            Some(ch) => ch,
+            None => return self.error("Unexpected end of pattern"),
+        };
+
+        match ch {
+            '(' => self.parse_group(),
+            '[' => self.parse_class(),
+            '\\' => self.parse_escape(false),
+            '.' => { self.pos += 1; Ok(Node::Any) },
+            '^' => { self.pos += 1; Ok(Node::Look(Look::Start)) },
+            '$' => { self.pos += 1; Ok(Node::Look(Look::End)) },
+            '*' | '+' | '?' => self.error("Quantifier without a repeatable target"),
+            _ => { self.pos += 1; Ok(Node::Char(ch)) },
+        }
+    }
+
+    fn parse_group(&mut self) -> Result<Node, RegexError> {
+        let open = self.pos;
+        if self.depth == MAX_NESTING {
+            return self.error("Pattern nested too deeply");
+        }
+        self.pos += 1;
+
+        let index = if self.eat('?') {
+            if !self.eat(':') {
+                return self.error("Unsupported group flag");
+            }
+            None
+        } else {
+            self.ngroups += 1;
+            Some(self.ngroups)
+        };
+
+        self.depth += 1;
+        let inner = self.parse_alternation()?;
+        self.depth -= 1;
+        if !self.eat(')') {
+            self.pos = open;
+            return self.error("Unclosed group");
+        }
+        self.height += 1;
+        if self.height > MAX_NESTING {
+            self.pos = open;
+            return self.error("Pattern nested too deeply");
+        }
+        Ok(Node::Group(Box::new(inner), index))
+    }
+
+    // Parse an escape sequence. Inside a bracketed class, `\b` means backspace and the
+    // assertions are not allowed.
+    fn parse_escape(&mut self, in_class : bool) -> Result<Node, RegexError> {
+        self.pos += 1;
+        let ch = match self.peek() {
+            Some(ch) => ch,
+            None => return self.error("Trailing backslash"),
+        };
+        self.pos += 1;
+
+        let node = match ch {
+            'd' => Node::Class(Class::perl(Perl::Digit, false)),
+            'D' => Node::Class(Class::perl(Perl::Digit, true)),
+            'w' => Node::Class(Class::perl(Perl::Word, false)),
+            'W' => Node::Class(Class::perl(Perl::Word, true)),
+            's' => Node::Class(Class::perl(Perl::Space, false)),
+            'S' => Node::Class(Class::perl(Perl::Space, true)),
+            'b' if in_class => Node::Char('\u{8}'),
+            'b' => Node::Look(Look::WordBoundary),
+            'B' if !in_class => Node::Look(Look::NotWordBoundary),
+            'n' => Node::Char('\n'),
+            'r' => Node::Char('\r'),
+            't' => Node::Char('\t'),
+            '0' => Node::Char('\0'),
+            _ if ch.is_ascii_alphanumeric() => {
+                self.pos -= 2;
+                return self.error("Unknown escape sequence");
+            },
+            _ => Node::Char(ch),
+        };
+        Ok(node)
+    }
+
+    fn parse_class(&mut self) -> Result<Node, RegexError> {
+        let open = self.pos;
+        self.pos += 1;
+
+        let negated = self.eat('^');
+        let mut items = vec![];
+
+        // A leading `]` is taken literally.
+        if self.eat(']') {
+            items.push(ClassItem::Range(']', ']'));
+        }
+
+        loop {
+            let item = self.pos;
+            let lo = match self.peek() {
+                None => { self.pos = open; return self.error("Unclosed character class") },
+                Some(']') => { self.pos += 1; break },
+                Some('\\') => match self.parse_escape(true)? {
+                    Node::Char(ch) => ch,
+                    Node::Class(class) => { items.extend(class_items(class)); continue },
+                    _ => unreachable!(),
+                },
+                Some(ch) => { self.pos += 1; ch },
+            };
+
+            // A `-` forms a range unless it is the last item in the class.
+            let is_range = self.peek() == Some('-')
+                && self.chars.get(self.pos + 1).is_some_and(|&ch| ch != ']');
+            if is_range {
+                self.pos += 1;
+                let hi = match self.peek() {
+                    Some('\\') => match self.parse_escape(true)? {
+                        Node::Char(ch) => ch,
+                        _ => return self.error("Invalid range end in character class"),
+                    },
+                    Some(ch) => { self.pos += 1; ch },
+                    None => unreachable!(),
+                };
+                if hi < lo {
+                    self.pos = item;
+                    return self.error("Invalid range in character class");
+                }
+                items.push(ClassItem::Range(lo, hi));
+            } else {
+                items.push(ClassItem::Range(lo, lo));
+            }
+        }
+
+        Ok(Node::Class(Class { items, negated }))
+    }
+}
+
+// Items of a shorthand class placed inside brackets, e.g. the `\D` in `[\D_]`.
+fn class_items(class : Class) -> Vec<ClassItem> {
+    if !class.negated {
+        return class.items;
+    }
+    class.items.into_iter().map(|item| match item {
+        ClassItem::Perl(perl, negated) => ClassItem::Perl(perl, !negated),
+        range => range,
+    }).collect()
+}
+
+
+// Instructions of the compiled program.
+#[derive(Debug, Clone)]
+enum Inst {
+    Char(char),
+    Any,
+    Class(Class),
+    Look(Look),
+    Save(usize),
+    Split(usize, usize),    // Try the first branch with higher priority.
+    Jmp(usize),
+    Match,
+}
+
+struct Compiler {
+    prog : Vec<Inst>,
+}
+
+impl Compiler {
+
+    fn emit(&mut self, inst : Inst) -> usize {
+        self.prog.push(inst);
+        self.prog.len() - 1
+    }
+
+    // Compile `node`, unless the program outgrows `MAX_PROGRAM`.
+    fn compile(&mut self, node : &Node) -> Result<(), RegexError> {
+        if self.prog.len() > MAX_PROGRAM {
+            return Err(RegexError { pos : 0, msg : "Pattern too large", pattern : 0 });
+        }
+        match node {
+            Node::Empty => {},
+            Node::Char(ch) => { self.emit(Inst::Char(*ch)); },
+            Node::Any => { self.emit(Inst::Any); },
+            Node::Class(class) => { self.emit(Inst::Class(class.clone())); },
+            Node::Look(look) => { self.emit(Inst::Look(*look)); },
+            Node::Group(inner, index) => match index {
+                Some(index) => {
+                    self.emit(Inst::Save(2 * index));
+                    self.compile(inner)?;
+                    self.emit(Inst::Save(2 * index + 1));
+                },
+                None => self.compile(inner)?,
+            },
+            Node::Concat(nodes) => {
+                for node in nodes {
+                    self.compile(node)?;
+                }
+            },
+            Node::Alternate(branches) => self.alternate(branches).map_err(|(_, err)| err)?,
+            Node::Repeat { node, min, max, greedy } => {
+                for _ in 0..*min {
+                    self.compile(node)?;
+                }
+                match max {
+                    // Kleene star over the remaining part: L: split body, end; body; jmp L.
+                    None => {
+                        let split = self.emit(Inst::Split(0, 0));
+                        self.compile(node)?;
+                        self.emit(Inst::Jmp(split));
+                        let end = self.prog.len();
+                        self.prog[split] = self.split(split + 1, end, *greedy);
+                    },
+                    // Nested optional copies: split body, end; body; split body, end; ...
+                    Some(max) => {
+                        let mut splits = vec![];
+                        for _ in *min..*max {
+                            splits.push(self.emit(Inst::Split(0, 0)));
+                            self.compile(node)?;
+                        }
+                        let end = self.prog.len();
+                        for split in splits {
+                            self.prog[split] = self.split(split + 1, end, *greedy);
+                        }
+                    },
+                }
+            },
+        }
+        Ok(())
+    }
+
+    // Compile one of `branches`, the first that matches winning. An error tells the index of
+    // the branch in which the program outgrew `MAX_PROGRAM`.
+    fn alternate(&mut self, branches : &[Node]) -> Result<(), (usize, RegexError)> {
+        // split L1, next; L1: branch; jmp end; next: split L2, ... last branch; end:
+        let mut jumps = vec![];
+        for (i, branch) in branches.iter().enumerate() {
+            if i + 1 < branches.len() {
+                let split = self.emit(Inst::Split(0, 0));
+                self.compile(branch).map_err(|err| (i, err))?;
+                jumps.push(self.emit(Inst::Jmp(0)));
+                let next = self.prog.len();
+                self.prog[split] = Inst::Split(split + 1, next);
+            } else {
+                self.compile(branch).map_err(|err| (i, err))?;
+            }
+        }
+        let end = self.prog.len();
+        for jump in jumps {
+            self.prog[jump] = Inst::Jmp(end);
+        }
+        Ok(())
+    }
+
+    fn split(&self, body : usize, end : usize, greedy : bool) -> Inst {
+        if greedy { Inst::Split(body, end) } else { Inst::Split(end, body) }
+    }
+}
+
+
+// Compiled regular expression.
+#[derive(Debug, Clone)]
+pub struct Regex {
+    prog : Vec<Inst>,
+    nslots : usize,
+    insensitive : bool,
+    multi_line : bool,      // `^` and `$` match around line terminators too.
+}
+
+impl Regex {
+
+    // Compile a case sensitive pattern.
+    pub fn new(pattern : &str) -> Result<Regex, RegexError> {
+        Regex::compile(pattern, false)
+    }
+
+    // Compile a case insensitive pattern.
+    pub fn new_insensitive(pattern : &str) -> Result<Regex, RegexError> {
+        Regex::compile(pattern, true)
+    }
+
+    // Compile patterns matching any of `patterns`, only where `boundary` allows. The capture
+    // groups of each pattern are numbered after the ones of the patterns before it. An error
+    // tells the index of the pattern in error, and a position relative to it.
+    pub fn new_many<S : AsRef<str>>(patterns : &[S], insensitive : bool, boundary : Boundary)
+                                    -> Result<Regex, RegexError> {
+        let mut ngroups = 0;
+        let mut alternatives = vec![];
+        for (i, pattern) in patterns.iter().enumerate() {
+            let mut parser = Parser::new(pattern.as_ref(), ngroups);
+            let ast = parser.parse_alternation().and_then(|ast| {
+                if parser.pos < parser.chars.len() {
+                    return parser.error("Unmatched closing parenthesis");
+                }
+                Ok(ast)
+            });
+            alternatives.push(ast.map_err(|err| RegexError { pattern : i, ..err })?);
+            ngroups = parser.ngroups;
+        }
+        if alternatives.is_empty() {
+            // An empty class, which never matches.
+            alternatives.push(Node::Class(Class { items : vec![], negated : false }));
+        }
+        let (before, after) = match boundary {
+            Boundary::Anywhere => (None, None),
+            Boundary::Word => (Some(Look::NoWordBefore), Some(Look::NoWordAfter)),
+            Boundary::Line => (Some(Look::Start), Some(Look::End)),
+        };
+
+        // The whole match is implicitly group 0, around the patterns compiled one by one to
+        // tell which one grows the program too large.
+        let mut compiler = Compiler { prog : vec![] };
+        compiler.emit(Inst::Save(0));
+        if let Some(look) = before {
+            compiler.emit(Inst::Look(look));
+        }
+        compiler.alternate(&alternatives).map_err(|(i, err)| RegexError { pattern : i, ..err })?;
+        if let Some(look) = after {
+            compiler.emit(Inst::Look(look));
+        }
+        compiler.emit(Inst::Save(1));
+        compiler.emit(Inst::Match);
+
+        Ok(Regex { prog : compiler.prog, nslots : 2 * (ngroups + 1), insensitive,
+                   multi_line : false })
+    }
+
+    // Whether `^` and `$` (and whole lines) also match at the line terminators of the text,
+    // which is then searched as a whole rather than line by line.
+    pub fn multi_line(mut self, multi_line : bool) -> Regex {
+        self.multi_line = multi_line;
+        self
+    }
+
+    fn compile(pattern : &str, insensitive : bool) -> Result<Regex, RegexError> {
+        Regex::new_many(&[pattern], insensitive, Boundary::Anywhere)
+    }
+
+    // Number of capture groups, including the implicit whole-match group 0.
+    pub fn captures_len(&self) -> usize {
+        self.nslots / 2
+    }
+
+    pub fn is_match(&self, text : &str) -> bool {
+        self.find(text).is_some()
+    }
+
+    // Byte span of the leftmost match.
+    pub fn find(&self, text : &str) -> Option<(usize, usize)> {
+        self.find_at(text, 0)
+    }
+
+    // Byte span of the leftmost match starting at or after `start`.
+    pub fn find_at(&self, text : &str, start : usize) -> Option<(usize, usize)> {
+        self.captures_at(text, start).and_then(|caps| caps[0])
+    }
+
+    // Byte spans of all capture groups of the leftmost match starting at or after `start`.
+    pub fn captures_at(&self, text : &str, start : usize) -> Option<Vec<Option<(usize, usize)>>> {
+        let slots = self.exec(text, start)?;
+        Some(slots.chunks(2).map(|pair| match (pair[0], pair[1]) {
+            (Some(s), Some(e)) => Some((s, e)),
+            _ => None,
+        }).collect())
+    }
+
+    // All non-overlapping matches, left to right.
+    pub fn find_iter(&self, text : &str) -> Vec<(usize, usize)> {
+        self.captures_iter(text).into_iter().filter_map(|caps| caps[0]).collect()
+    }
+
+    // Capture groups of all non-overlapping matches, left to right.
+    pub fn captures_iter(&self, text : &str) -> Vec<Vec<Option<(usize, usize)>>> {
+        let mut all = vec![];
+        let mut start = 0;
+        while start <= text.len() {
+            let caps = match self.captures_at(text, start) {
+                Some(caps) => caps,
+                None => break,
+            };
+            let (s, e) = caps[0].unwrap();     // Group 0 is always set on a match.
+            all.push(caps);
+
+            // Step over empty matches so the iteration always makes progress.
+            start = if e > s { e } else { e + text[e..].chars().next().map_or(1, char::len_utf8) };
+        }
+        all
+    }
+
+    // Pike VM simulation. Threads are kept in priority order, so the first thread reaching
+    // `Match` gives the leftmost-first (Perl-like) match; lower priority threads are cut then.
+    fn exec(&self, text : &str, start : usize) -> Option<Vec<Option<usize>>> {
+        let mut clist = Threads::new(self.prog.len());
+        let mut nlist = Threads::new(self.prog.len());
+        let mut matched = None;
+        let mut pos = start;
+
+        loop {
+            // Keep seeding a new thread at every position until something matched.
+            if matched.is_none() {
+                let slots = vec![None; self.nslots];
+                self.add_thread(&mut clist, 0, pos, text, slots);
+            }
+            let cur = text[pos..].chars().next();
+            if clist.is_empty() && (matched.is_some() || cur.is_none()) {
+                break;
+            }
+
+            let next = pos + cur.map_or(0, char::len_utf8);
+
+            for (pc, slots) in clist.drain() {
+                let step = match (&self.prog[pc], cur) {
+                    (Inst::Match, _) => {
+                        matched = Some(slots);
+                        break;
+                    },
+                    (_, None) => false,
+                    (Inst::Char(want), Some(ch)) => chars_eq(*want, ch, self.insensitive),
+                    (Inst::Any, Some(ch)) => ch != '\n',
+                    (Inst::Class(class), Some(ch)) => class.matches(ch, self.insensitive),
+                    _ => unreachable!(),
+                };
+                if step {
+                    self.add_thread(&mut nlist, pc + 1, next, text, slots);
+                }
+            }
+
+            if cur.is_none() {
+                break;
+            }
+            pos = next;
+            std::mem::swap(&mut clist, &mut nlist);
+        }
+
+        matched
+    }
+
+    // Follow the empty transitions from `pc`, adding the reached consuming instructions.
+    // The transitions are followed depth first on an explicit stack, as their chains can be as
+    // long as the program: the first branch of a split is pushed last, to keep its priority.
+    fn add_thread(&self, list : &mut Threads, pc : usize, pos : usize, text : &str,
+                  slots : Vec<Option<usize>>) {
+        let mut stack = vec![(pc, slots)];
+        while let Some((pc, mut slots)) = stack.pop() {
+            if !list.mark(pc) {
+                continue;
+            }
+
+            match &self.prog[pc] {
+                Inst::Jmp(to) => stack.push((*to, slots)),
+                Inst::Split(x, y) => {
+                    stack.push((*y, slots.clone()));
+                    stack.push((*x, slots));
+                },
+                Inst::Save(slot) => {
+                    slots[*slot] = Some(pos);
+                    stack.push((pc + 1, slots));
+                },
+                Inst::Look(look) => {
+                    if is_look_at(*look, text, pos, self.multi_line) {
+                        stack.push((pc + 1, slots));
+                    }
+                },
+                _ => list.push(pc, slots),
+            }
+        }
+    }
+}
+
+
+// Ordered list of threads, with a dense membership set to avoid adding the same pc twice.
+struct Threads {
+    on : Vec<bool>,
+    list : Vec<(usize, Vec<Option<usize>>)>,
+    seen : Vec<usize>,
+}
+
+impl Threads {
+    fn new(size : usize) -> Threads {
+        Threads { on : vec![false; size], list : vec![], seen : vec![] }
+    }
+
+    fn is_empty(&self) -> bool {
+        self.list.is_empty()
+    }
+
+    // Return false if `pc` has already been visited at this step.
+    fn mark(&mut self, pc : usize) -> bool {
+        if self.on[pc] {
+            return false;
+        }
+        self.on[pc] = true;
+        self.seen.push(pc);
+        true
+    }
+
+    fn push(&mut self, pc : usize, slots : Vec<Option<usize>>) {
+        self.list.push((pc, slots));
+    }
+
+    fn drain(&mut self) -> Vec<(usize, Vec<Option<usize>>)> {
+        for pc in self.seen.drain(..) {
+            self.on[pc] = false;
+        }
+        std::mem::take(&mut self.list)
+    }
+}
+
+
+// `text` as a pattern matching it literally.
+pub fn escape(text : &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for ch in text.chars() {
+        if "\\.+*?()|[]{}^$".contains(ch) {
+            escaped.push('\\');
+        }
+        escaped.push(ch);
+    }
+    escaped
+}
+
+
+// The literal texts the matches of any of `patterns` must contain, see `index.rs`. The case
+// insensitive patterns only keep their ASCII characters as texts, as the others may have case
+// variants folding to other texts.
+pub fn query<S : AsRef<str>>(patterns : &[S], insensitive : bool) -> Result<Query, RegexError> {
+    let mut alternatives = vec![];
+    for (i, pattern) in patterns.iter().enumerate() {
+        let mut parser = Parser::new(pattern.as_ref(), 0);
+        let ast = parser.parse_alternation().map_err(|err| RegexError { pattern : i, ..err })?;
+        alternatives.push(Literals::of(&ast, insensitive).into_query());
+    }
+    Ok(Query::Any(alternatives))
+}
+
+// Most texts a node is known to match, beyond which only what they must contain is kept.
+const MAX_EXACT : usize = 16;
+
+// What the matches of a node are made of.
+enum Literals {
+    Exact(Vec<String>),     // One of these texts.
+    Inexact(Query),         // Texts holding the ones of the query.
+}
+
+impl Literals {
+
+    fn of(node : &Node, insensitive : bool) -> Literals {
+        let literal = |ch : char| !insensitive || ch.is_ascii();
+        match node {
+            Node::Empty | Node::Look(_) => Literals::Exact(vec![String::new()]),
+            Node::Char(ch) if literal(*ch) => Literals::Exact(vec![ch.to_string()]),
+            Node::Class(class) if !class.negated => {
+                let mut chars = vec![];
+                for item in &class.items {
+                    match *item {
+                        ClassItem::Range(lo, hi)
+                            if literal(lo) && literal(hi)
+                               && chars.len() + (hi as usize - lo as usize) < MAX_EXACT => {
+                            chars.extend((lo..=hi).map(String::from));
+                        },
+                        _ => return Literals::Inexact(Query::All(vec![])),
+                    }
+                }
+                Literals::Exact(chars)
+            },
+            Node::Char(_) | Node::Any | Node::Class(_) => Literals::Inexact(Query::All(vec![])),
+            Node::Group(node, _) => Literals::of(node, insensitive),
+            Node::Concat(nodes) => {
+                // Runs of exact nodes make up longer texts, as long as there are few of them.
+                let mut required = vec![];
+                let mut run = vec![String::new()];
+                let mut exact = true;
+                for node in nodes {
+                    match Literals::of(node, insensitive) {
+                        Literals::Exact(texts) if run.len() * texts.len() <= MAX_EXACT => {
+                            run = run.iter()
+                                     .flat_map(|head| texts.iter().map(move |tail| {
+                                         format!("{}{}", head, tail)
+                                     }))
+                                     .collect();
+                        },
+                        Literals::Exact(texts) => {
+                            required.push(Literals::Exact(run).into_query());
+                            run = texts;
+                            exact = false;
+                        },
+                        Literals::Inexact(query) => {
+                            required.push(Literals::Exact(run).into_query());
+                            required.push(query);
+                            run = vec![String::new()];
+                            exact = false;
+                        },
+                    }
+                }
+                if exact {
+                    return Literals::Exact(run);
+                }
+                required.push(Literals::Exact(run).into_query());
+                Literals::Inexact(Query::All(required))
+            },
+            Node::Alternate(nodes) => {
+                let branches : Vec<_> = nodes.iter().map(|node| Literals::of(node, insensitive))
+                                             .collect();
+                let count = branches.iter().try_fold(0, |count, branch| match branch {
+                    Literals::Exact(texts) => Some(count + texts.len()),
+                    Literals::Inexact(_) => None,
+                });
+                if count.is_some_and(|count| count <= MAX_EXACT) {
+                    Literals::Exact(branches.into_iter().flat_map(|branch| match branch {
+                        Literals::Exact(texts) => texts,
+                        Literals::Inexact(_) => unreachable!(),
+                    }).collect())
+                } else {
+                    Literals::Inexact(Query::Any(branches.into_iter().map(Literals::into_query)
+                                                         .collect()))
+                }
+            },
+            // At least one repetition is there, if any must be.
+            Node::Repeat { node, min, max, .. } => match (min, max) {
+                (0, _) => Literals::Inexact(Query::All(vec![])),
+                (_, Some(1)) => Literals::of(node, insensitive),
+                _ => Literals::Inexact(Literals::of(node, insensitive).into_query()),
+            },
+        }
+    }
+
+    fn into_query(self) -> Query {
+        match self {
+            Literals::Exact(texts) => Query::Any(texts.into_iter().map(Query::Text).collect()),
+            Literals::Inexact(query) => query,
+        }
+    }
+}
+
+pub(crate) fn is_word_char(ch : char) -> bool {
+    ch.is_alphanumeric() || ch == '_'
+}
+
+// Whether `look` holds at `pos`. In multi-line mode, lines end before their `\n` or `\r\n`.
+fn is_look_at(look : Look, text : &str, pos : usize, multi_line : bool) -> bool {
+    let before = text[..pos].chars().next_back();
+    let after = text[pos..].chars().next();
+    match look {
+        Look::Start => pos == 0 || (multi_line && before == Some('\n')),
+        Look::End => {
+            let rest = &text[pos..];
+            rest.is_empty() || (multi_line && (rest.starts_with('\n') || rest.starts_with("\r\n")))
+        },
+        Look::WordBoundary | Look::NotWordBoundary => {
+            let boundary = before.is_some_and(is_word_char) != after.is_some_and(is_word_char);
+            boundary == matches!(look, Look::WordBoundary)
+        },
+        Look::NoWordBefore => !before.is_some_and(is_word_char),
+        Look::NoWordAfter => !after.is_some_and(is_word_char),
+    }
+}
+
+// Single-character case variants of `ch`, including itself: its simple case folding and the
+// single-character case mappings of both that fold the same.
+fn case_variants(ch : char) -> Vec<char> {
+    let folded = fold::simple_fold(ch);
+    let mut variants = vec![ch];
+    for base in &[ch, folded] {
+        for other in base.to_lowercase().chain(base.to_uppercase()).chain(Some(folded)) {
+            if !variants.contains(&other) && fold::simple_fold(other) == folded {
+                variants.push(other);
+            }
+        }
+    }
+    variants
+}
+
+// Characters are compared case insensitively with simple case folding, as the engine matches
+// one character at a time.
+fn chars_eq(want : char, ch : char, insensitive : bool) -> bool {
+    want == ch || (insensitive && fold::simple_fold(want) == fold::simple_fold(ch))
+}
@@ -0,0 +1,416 @@
+//
+// `replace.rs` substitutes the matches of the selected lines, sed-style, and edits whole files
+//   with the result.
+//
+// In regex mode, the replacement text may refer to the capture groups of each match: `$1` or
+//   `${1}` is the text of group 1, `$0` the whole match, and `$$` a single `$`. A group that did
+//   not take part in the match is replaced by nothing. In literal mode, the text is used as is.
+//
+// Files are edited atomically: the new contents go to a temporary file next to the original,
+//   which is then renamed over it, so the file is never seen half written. A symbolic link is
+//   followed, the file it points to being the one replaced. The new file keeps the permissions
+//   of the old one, but belongs to whoever runs the edit, as with `sed -i`. A dry run prints the
+//   change as a unified diff instead, with three lines of context like `diff -u`.
+//
+
+use std::fs::{self, OpenOptions};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::process;
+
+use crate::matcher::Matcher;
+use crate::search::Stats;
+
+
+// Lines of unchanged context around each change of a diff.
+const CONTEXT : usize = 3;
+
+#[derive(Debug, Clone, PartialEq)]
+enum Part {
+    Text(String),
+    Group(usize),
+}
+
+// A parsed replacement text.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Replacement {
+    parts : Vec<Part>,
+}
+
+impl Replacement {
+
+    // Parse `template`, expanding the group references only if `groups` is set.
+    pub fn new(template : &str, groups : bool) -> Replacement {
+        if !groups {
+            return Replacement { parts : vec![Part::Text(template.to_string())] };
+        }
+
+        let mut parts = vec![];
+        let mut text = String::new();
+        let mut rest = template;
+        while let Some(dollar) = rest.find('$') {
+            text.push_str(&rest[..dollar]);
+            rest = &rest[dollar + 1..];
+
+            let (group, len) = parse_reference(rest);
+            match group {
+                Some(group) => {
+                    if !text.is_empty() {
+                        parts.push(Part::Text(std::mem::take(&mut text)));
+                    }
+                    parts.push(Part::Group(group));
+                    rest = &rest[len..];
+                },
+                // `$$` stands for `$`, and a `$` not followed by a reference is kept as is.
+                None if rest.starts_with('$') => {
+                    text.push('$');
+                    rest = &rest[1..];
+                },
+                None => text.push('$'),
+            }
+        }
+        text.push_str(rest);
+        if !text.is_empty() {
+            parts.push(Part::Text(text));
+        }
+        Replacement { parts }
+    }
+
+    // Check the references against the number of groups of the pattern, group 0 included.
+    pub fn check(&self, captures_len : usize) -> Result<(), String> {
+        for part in &self.parts {
+            if let Part::Group(group) = *part {
+                if group >= captures_len {
+                    return Err(format!("Invalid replacement: the pattern has no group {}.", group));
+                }
+            }
+        }
+        Ok(())
+    }
+
+    // Append the expansion for one match of `line`, given its capture groups.
+    fn expand(&self, line : &str, captures : &[Option<(usize, usize)>], out : &mut String) {
+        for part in &self.parts {
+            match part {
+                Part::Text(text) => out.push_str(text),
+                Part::Group(group) => {
+                    if let Some(&Some((start, end))) = captures.get(*group) {
+                        out.push_str(&line[start..end]);
+                    }
+                },
+            }
+        }
+    }
+
+    // `line` with all its matches replaced, and the byte spans of the replacements within it.
+    pub fn replace_line(&self, matcher : &Matcher, line : &str) -> (String, Vec<(usize, usize)>) {
+        self.replace_captures(line, matcher.captures_iter(line))
+    }
+
+    // `line` with the given matches replaced, like `replace_line`.
+    fn replace_captures(&self, line : &str, all : Vec<Vec<Option<(usize, usize)>>>)
+                        -> (String, Vec<(usize, usize)>) {
+        let mut replaced = String::with_capacity(line.len());
+        let mut spans = vec![];
+        let mut last = 0;
+        for captures in all {
+            let (start, end) = match captures[0] {
+                Some(span) => span,
+                None => continue,
+            };
+            replaced.push_str(&line[last..start]);
+            let from = replaced.len();
+            self.expand(line, &captures, &mut replaced);
+            spans.push((from, replaced.len()));
+            last = end;
+        }
+        replaced.push_str(&line[last..]);
+        (replaced, spans)
+    }
+}
+
+// Parse the group reference following a `$`, returning the group and the length of the
+// reference, or `None` if there is none.
+fn parse_reference(text : &str) -> (Option<usize>, usize) {
+    let (digits, len) = match text.strip_prefix('{') {
+        Some(braced) => match braced.find('}') {
+            Some(close) => (&braced[..close], close + 2),
+            None => return (None, 0),
+        },
+        None => {
+            let len = text.find(|ch : char| !ch.is_ascii_digit()).unwrap_or(text.len());
+            (&text[..len], len)
+        },
+    };
+    if digits.is_empty() || !digits.bytes().all(|b| b.is_ascii_digit()) {
+        return (None, 0);
+    }
+    match digits.parse() {
+        Ok(group) => (Some(group), len),
+        Err(_) => (None, 0),
+    }
+}
+
+
+// The result of replacing the matches of a whole text, line by line.
+pub struct Edit<'a> {
+    lines : Vec<&'a str>,               // The original lines, with their terminators.
+    replaced : Vec<Option<String>>,     // The new text of each changed line, terminator included.
+    pub stats : Stats,
+}
+
+impl<'a> Edit<'a> {
+
+    // Replace the matches of the first `max_count` (if any) lines with a match.
+    pub fn new(replacement : &Replacement, matcher : &Matcher, text : &'a str,
+               max_count : Option<usize>) -> Edit<'a> {
+        let lines : Vec<&str> = text.split_inclusive('\n').collect();
+        let mut replaced = vec![None; lines.len()];
+        let mut stats = Stats::default();
+
+        for (i, &line) in lines.iter().enumerate() {
+            if max_count.is_some_and(|max| stats.matched_lines >= max) {
+                break;
+            }
+            let content = line.strip_suffix('\n').unwrap_or(line);
+            let content = content.strip_suffix('\r').unwrap_or(content);
+
+            let all = matcher.captures_iter(content);
+            if all.is_empty() {
+                continue;
+            }
+            stats.matched_lines += 1;
+            stats.matches += all.iter().filter(|caps| caps[0].is_some_and(|(s, e)| s < e)).count();
+            let (new, _) = replacement.replace_captures(content, all);
+            if new != content {
+                replaced[i] = Some(new + &line[content.len()..]);
+            }
+        }
+        Edit { lines, replaced, stats }
+    }
+
+    pub fn is_changed(&self) -> bool {
+        self.replaced.iter().any(Option::is_some)
+    }
+
+    // The whole new text.
+    pub fn text(&self) -> String {
+        let mut text = String::new();
+        for (line, replaced) in self.lines.iter().zip(&self.replaced) {
+            text.push_str(replaced.as_deref().unwrap_or(line));
+        }
+        text
+    }
+
+    // Write the change as a unified diff of the file at `path`; nothing if there is none.
+    pub fn write_diff<W : Write>(&self, out : &mut W, path : &Path) -> io::Result<()> {
+        if !self.is_changed() {
+            return Ok(());
+        }
+        writeln!(out, "--- {}", path.display())?;
+        writeln!(out, "+++ {}", path.display())?;
+
+        // Group the changed lines into hunks, merging the ones whose contexts touch.
+        let mut hunks : Vec<(usize, usize)> = vec![];
+        let changed = self.replaced.iter().enumerate().filter(|(_, new)| new.is_some());
+        for (i, _) in changed {
+            let start = i.saturating_sub(CONTEXT);
+            let end = (i + 1 + CONTEXT).min(self.lines.len());
+            match hunks.last_mut() {
+                Some(last) if start <= last.1 => last.1 = end,
+                _ => hunks.push((start, end)),
+            }
+        }
+
+        // Line counts on the new side, as a replacement may add or remove line terminators.
+        let new_len = |i : usize| self.replaced[i].as_deref().map_or(1, count_lines);
+        let mut new_start = 1;
+        let mut done = 0;
+        for (start, end) in hunks {
+            new_start += (done..start).map(new_len).sum::<usize>();
+            let new_count = (start..end).map(new_len).sum();
+            writeln!(out, "@@ -{} +{} @@", range(start + 1, end - start), range(new_start, new_count))?;
+
+            let mut i = start;
+            while i < end {
+                if self.replaced[i].is_none() {
+                    write_lines(out, ' ', self.lines[i])?;
+                    i += 1;
+                    continue;
+                }
+                // A run of changed lines: all the removed lines, then all the added ones.
+                let run = i + self.replaced[i..end].iter().take_while(|new| new.is_some()).count();
+                for line in &self.lines[i..run] {
+                    write_lines(out, '-', line)?;
+                }
+                for new in &self.replaced[i..run] {
+                    write_lines(out, '+', new.as_deref().unwrap())?;
+                }
+                i = run;
+            }
+
+            new_start += new_count;
+            done = end;
+        }
+        Ok(())
+    }
+}
+
+fn count_lines(text : &str) -> usize {
+    text.split_inclusive('\n').count()
+}
+
+// A hunk range as `diff -u` writes it: the start is the line before an empty range, and the
+// count is left out when one.
+fn range(start : usize, count : usize) -> String {
+    match count {
+        0 => format!("{},0", start - 1),
+        1 => format!("{}", start),
+        _ => format!("{},{}", start, count),
+    }
+}
+
+// Write the lines of `text` with the given prefix, marking a last line without terminator.
+fn write_lines<W : Write>(out : &mut W, prefix : char, text : &str) -> io::Result<()> {
+    for line in text.split_inclusive('\n') {
+        write!(out, "{}{}", prefix, line)?;
+        if !line.ends_with('\n') {
+            write!(out, "\n\\ No newline at end of file\n")?;
+        }
+    }
+    Ok(())
+}
+
+
+// Replace the contents of the file at `path` atomically, keeping its permissions. A link at
+// `path` is kept, and the file it points to replaced.
+pub fn write_atomically(path : &Path, contents : &[u8]) -> io::Result<()> {
+    let path = fs::canonicalize(path)?;
+    let permissions = fs::metadata(&path)?.permissions();
+    let temp = temp_path(&path);
+    let result = (|| {
+        let mut file = OpenOptions::new().write(true).create_new(true).open(&temp)?;
+        file.write_all(contents)?;
+        file.set_permissions(permissions)?;
+        file.sync_all()?;
+        fs::rename(&temp, &path)
+    })();
+    if result.is_err() {
+        let _ = fs::remove_file(&temp);
+    }
+    result
+}
+
+// Hidden file next to `path`, unique to this process.
+fn temp_path(path : &Path) -> PathBuf {
+    let name = path.file_name().map_or("".into(), |name| name.to_string_lossy());
+    path.with_file_name(format!(".{}.minigrep-{}.tmp", name, process::id()))
+}
+
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use crate::config::{Boundary, MatchMode};
+
+    fn replace(pattern : &str, mode : MatchMode, template : &str, line : &str) -> String {
+        let matcher = Matcher::with_patterns(&[pattern], mode, true, Boundary::Anywhere).unwrap();
+        let replacement = Replacement::new(template, mode == MatchMode::Regex);
+        replacement.replace_line(&matcher, line).0
+    }
+
+    #[test]
+    fn templates() {
+        use Part::*;
+        let parse = |template| Replacement::new(template, true).parts;
+        assert_eq!(vec![Text("plain".into())], parse("plain"));
+        assert_eq!(vec![Group(2), Text("-".into()), Group(1)], parse("$2-$1"));
+        assert_eq!(vec![Group(1), Text("0".into())], parse("${1}0"));
+        assert_eq!(vec![Group(10)], parse("$10"));
+        assert_eq!(vec![Text("$1 costs $".into())], parse("$$1 costs $"));
+        assert_eq!(vec![Text("${x} ${1".into())], parse("${x} ${1"));
+        assert_eq!(vec![Text("$1".into())], Replacement::new("$1", false).parts);
+        assert_eq!(Vec::<Part>::new(), parse(""));
+    }
+
+    #[test]
+    fn check_groups() {
+        assert!(Replacement::new("$0 $2", true).check(3).is_ok());
+        assert_eq!(
+            Err("Invalid replacement: the pattern has no group 3.".to_string()),
+            Replacement::new("$3", true).check(3)
+        );
+    }
+
+    #[test]
+    fn lines() {
+        assert_eq!("a-b-c", replace(",", MatchMode::Literal, "-", "a,b,c"));
+        assert_eq!("cost: $1", replace("1", MatchMode::Literal, "$1", "cost: 1"));
+        assert_eq!("Doe, John", replace(r"(\w+) (\w+)", MatchMode::Regex, "$2, $1", "John Doe"));
+        assert_eq!("[a][b]", replace("(x)?([ab])", MatchMode::Regex, "[$1$2]", "ab"));
+        assert_eq!("> text", replace("^", MatchMode::Regex, "> ", "text"));
+        assert_eq!("unchanged", replace("z", MatchMode::Regex, "$0$0", "unchanged"));
+    }
+
+    fn diff(pattern : &str, template : &str, text : &str) -> String {
+        let matcher = Matcher::new(pattern, MatchMode::Regex, true).unwrap();
+        let edit = Edit::new(&Replacement::new(template, true), &matcher, text, None);
+        let mut out = vec![];
+        edit.write_diff(&mut out, Path::new("f.txt")).unwrap();
+        String::from_utf8(out).unwrap()
+    }
+
+    #[test]
+    fn diffs() {
+        let text : String = (1..=12).map(|i| format!("line {}\n", i)).collect();
+        assert_eq!("", diff("none", "x", &text));
+        assert_eq!(
+            "--- f.txt\n+++ f.txt\n@@ -1,4 +1,4 @@\n-line 1\n+LINE 1\n line 2\n line 3\n line 4\n",
+            diff("^line 1$", "LINE 1", &text)
+        );
+        // Changes with more than 6 lines between them get their own hunks, else they share one.
+        assert_eq!(
+            "--- f.txt\n+++ f.txt\n\
+             @@ -1,5 +1,5 @@\n line 1\n-line 2\n+# 2\n line 3\n line 4\n line 5\n\
+             @@ -7,6 +7,6 @@\n line 7\n line 8\n line 9\n-line 10\n+# 10\n line 11\n line 12\n",
+            diff("^line (2|10)$", "# $1", &text)
+        );
+        assert_eq!(
+            "--- f.txt\n+++ f.txt\n@@ -1,12 +1,12 @@\n line 1\n-line 2\n+# 2\n line 3\n line 4\n\
+             \x20line 5\n line 6\n line 7\n line 8\n-line 9\n+# 9\n line 10\n line 11\n line 12\n",
+            diff("^line ([29])$", "# $1", &text)
+        );
+    }
+
+    #[test]
+    fn diff_line_counts_and_missing_newline() {
+        assert_eq!(
+            "--- f.txt\n+++ f.txt\n@@ -1,2 +1,3 @@\n-a b\n+a\n+b\n c\n\\ No newline at end of file\n",
+            diff(" ", "\n", "a b\nc")
+        );
+        assert_eq!(
+            "--- f.txt\n+++ f.txt\n@@ -1,2 +1,2 @@\n a\n-c\n\\ No newline at end of file\n\
+             +d\n\\ No newline at end of file\n",
+            diff("c", "d", "a\nc")
+        );
+        assert_eq!(
+            "--- f.txt\n+++ f.txt\n@@ -1 +1,2 @@\n-a;b\r\n+a\n+b\r\n",
+            diff(";", "\n", "a;b\r\n")
+        );
+    }
+
+    #[test]
+    fn edit_text_and_stats() {
+        let matcher = Matcher::new("o", MatchMode::Literal, true).unwrap();
+        let replacement = Replacement::new("0", false);
+        let edit = Edit::new(&replacement, &matcher, "foo\nbar\r\nboo", Some(5));
+        assert_eq!("f00\nbar\r\nb00", edit.text());
+        assert_eq!(Stats { matched_lines : 2, matches : 4 }, edit.stats);
+
+        let edit = Edit::new(&replacement, &matcher, "foo\nbar\r\nboo", Some(1));
+        assert_eq!("f00\nbar\r\nboo", edit.text());
+        assert!(edit.is_changed());
+        assert!(!Edit::new(&replacement, &matcher, "bar\n", None).is_changed());
+    }
+}
@@ -0,0 +1,545 @@
+//
+// `search.rs` walks the lines of a text and reports the selected ones with their position,
+//   plus the context lines around them. Texts are read line by line from any `BufRead`, so
+//   files of any size as well as pipes can be searched in bounded memory.
+//
+// Context windows that overlap or touch are merged, and a break is reported between windows
+//   that are apart, which the printer shows as a `--` separator like grep does.
+//
+// Lines are searched as UTF-8, and invalid sequences are replaced with U+FFFD (see `encoding.rs`
+//   for the inputs in other encodings), so the spans are within the replaced lines; offsets count
+//   the raw bytes.
+//
+// Texts are searched as a whole for a literal query when only the selected lines are wanted:
+//   the matches are found across the buffer first, and only the lines holding them are
+//   delimited (see `literal.rs`). Texts read are searched that way in chunks of whole lines, and
+//   only the selected lines are decoded, and copied out of the chunk.
+//
+// In multi-line mode, the whole text is searched at once with a multi-line matcher, so a match
+//   may span lines: the lines it covers make up a single selected block, and blocks sharing a
+//   line are merged. Context and inverted selection work line by line around the blocks.
+//
+
+use std::borrow::Cow;
+use std::collections::VecDeque;
+use std::io::{self, BufRead};
+
+use crate::literal::{self, Finder};
+use crate::matcher::Matcher;
+
+
+// Least size of the chunks a reader is searched in for a literal, which then extend to the end
+// of their last line.
+const CHUNK_LEN : usize = 64 << 10;
+
+
+// A selected line.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Match<'a> {
+    pub line_number : usize,            // Starting from 1.
+    pub offset : usize,                 // Byte offset of the line start in the whole text.
+    pub line : &'a str,                 // Without the line terminator.
+    pub spans : Vec<(usize, usize)>,    // Byte spans of the matched parts, within `line`.
+}
+
+impl<'a> Match<'a> {
+
+    // Number of the last line, which is not the first one for a multi-line block: `line` then
+    // holds the lines it spans, with the terminators between them.
+    pub fn end_line_number(&self) -> usize {
+        self.line_number + literal::count(b'\n', self.line.as_bytes())
+    }
+}
+
+// What the searcher reports, in text order.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Line<'a> {
+    Match(Match<'a>),
+    Context { line_number : usize, offset : usize, line : &'a str },
+    Break,      // Gap between two non-adjacent context windows.
+}
+
+impl<'a> Line<'a> {
+
+    // The line as it is in a text starting `line_number` lines and `offset` bytes later, like
+    // the part of an input searched on its own.
+    pub fn shifted(self, line_number : usize, offset : usize) -> Line<'a> {
+        match self {
+            Line::Match(m) => Line::Match(Match {
+                line_number : m.line_number + line_number,
+                offset : m.offset + offset,
+                ..m
+            }),
+            Line::Context { line_number : number, offset : start, line } => Line::Context {
+                line_number : number + line_number,
+                offset : start + offset,
+                line,
+            },
+            Line::Break => Line::Break,
+        }
+    }
+}
+
+
+// Options controlling which lines are reported.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct LineOptions {
+    pub invert : bool,
+    pub before : usize,     // Lines of context before each selected line.
+    pub after : usize,      // Lines of context after each selected line.
+    pub max_count : Option<usize>,  // Stop after this many selected lines (or blocks).
+}
+
+
+// Counts of what was selected in one input.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct Stats {
+    pub matched_lines : usize,
+    pub matches : usize,    // Non-empty matched parts of the selected lines.
+}
+
+impl Stats {
+    pub fn record(&mut self, line : &Line) {
+        if let Line::Match(m) = line {
+            self.matched_lines += m.end_line_number() + 1 - m.line_number;
+            self.matches += m.spans.iter().filter(|(start, end)| start < end).count();
+        }
+    }
+}
+
+// Counts over all the inputs of a run.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct Summary {
+    pub searches : usize,
+    pub searches_with_match : usize,
+    pub matched_lines : usize,
+    pub matches : usize,
+}
+
+impl Summary {
+    pub fn add(&mut self, stats : &Stats) {
+        self.searches += 1;
+        self.searches_with_match += (stats.matched_lines > 0) as usize;
+        self.matched_lines += stats.matched_lines;
+        self.matches += stats.matches;
+    }
+}
+
+
+// All selected lines of `contents`, without context.
+pub fn find_matches<'a>(matcher : &Matcher, contents : &'a str, invert : bool) -> Vec<Match<'a>> {
+    let options = LineOptions { invert, ..LineOptions::default() };
+    if let Some(finder) = whole_literal(matcher, options) {
+        return LiteralLines::new(finder, contents).collect();
+    }
+
+    let mut matches = vec![];
+    search_lines(matcher, contents, options, |line| {
+        if let Line::Match(m) = line {
+            // Re-borrow the line from `contents` rather than from the search buffer.
+            let line = &contents[m.offset..m.offset + m.line.len()];
+            matches.push(Match { line, ..m });
+        }
+        Ok(())
+    }).unwrap();    // Reading from memory never fails, and `contents` is valid UTF-8.
+    matches
+}
+
+// Report the selected lines of `contents` and their context to `emit`, in order.
+pub fn search_lines<F>(matcher : &Matcher, contents : &str, options : LineOptions, mut emit : F)
+                       -> io::Result<()>
+    where F : FnMut(Line) -> io::Result<()>
+{
+    if let Some(finder) = whole_literal(matcher, options) {
+        let max_count = options.max_count.unwrap_or(usize::MAX);
+        for m in LiteralLines::new(finder, contents).take(max_count) {
+            emit(Line::Match(m))?;
+        }
+        return Ok(());
+    }
+    search_reader(matcher, contents.as_bytes(), options, emit)
+}
+
+// The literal of `matcher`, if the lines selected with `options` can be found by searching a
+// whole text for it: only the selected lines are wanted, and `LiteralLines` supports it.
+pub fn whole_literal(matcher : &Matcher, options : LineOptions) -> Option<&Finder> {
+    match matcher {
+        Matcher::Literal(finder) if !options.invert && options.before == 0 && options.after == 0
+                                    && LiteralLines::supports(finder) => Some(finder),
+        _ => None,
+    }
+}
+
+// Iterator over the lines of a whole text holding a literal, found by searching the text as a
+// whole rather than line by line.
+pub struct LiteralLines<'f, 'a> {
+    found : FoundLines<'f, 'a>,
+    contents : &'a str,
+}
+
+impl<'f, 'a> LiteralLines<'f, 'a> {
+
+    pub fn new(finder : &'f Finder, contents : &'a str) -> LiteralLines<'f, 'a> {
+        LiteralLines { found : FoundLines::new(finder, contents.as_bytes()), contents }
+    }
+
+    // Whether the results are the same as a line by line search: a match must not span or
+    // touch a line terminator, and an empty needle matches every line. The texts read may also
+    // have invalid sequences, which only match U+FFFD once replaced.
+    pub fn supports(finder : &Finder) -> bool {
+        let needle = finder.needle();
+        !needle.is_empty() && !needle.iter().any(|&b| b == b'\n' || b == b'\r')
+        && !needle.windows(3).any(|window| window == "\u{fffd}".as_bytes())
+    }
+}
+
+impl<'f, 'a> Iterator for LiteralLines<'f, 'a> {
+    type Item = Match<'a>;
+
+    fn next(&mut self) -> Option<Match<'a>> {
+        let (line_number, (start, end), spans) = self.found.next()?;
+        Some(Match { line_number, offset : start, line : &self.contents[start..end], spans })
+    }
+}
+
+// Iterator over the lines of a whole buffer holding a literal: their number, their span in the
+// buffer without the line terminator, and the spans of the matches within them.
+struct FoundLines<'f, 'a> {
+    finder : &'f Finder,
+    bytes : &'a [u8],
+    next : Option<usize>,   // Start of the next match, if any.
+    line_number : usize,    // Of the line starting at `counted`.
+    counted : usize,        // Offset up to which the line terminators have been counted.
+}
+
+impl<'f, 'a> FoundLines<'f, 'a> {
+
+    fn new(finder : &'f Finder, bytes : &'a [u8]) -> FoundLines<'f, 'a> {
+        let next = finder.find(bytes, 0);
+        FoundLines { finder, bytes, next, line_number : 1, counted : 0 }
+    }
+}
+
+impl<'f, 'a> Iterator for FoundLines<'f, 'a> {
+    type Item = (usize, (usize, usize), Vec<(usize, usize)>);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let bytes = self.bytes;
+        let first = self.next?;
+
+        // Delimit the line around the match, and number it. The bytes before `counted` were
+        // already scanned, so they are not looked at again.
+        let gap = &bytes[self.counted..first];
+        let start = literal::memrchr(b'\n', gap).map_or(self.counted, |pos| self.counted + pos + 1);
+        let end = literal::memchr(b'\n', &bytes[first..]).map_or(bytes.len(), |pos| first + pos);
+        let line_number = self.line_number + literal::count(b'\n', &gap[..start - self.counted]);
+        self.line_number = line_number + 1;
+        self.counted = (end + 1).min(bytes.len());
+
+        // Collect the other matches on the same line.
+        let len = self.finder.needle().len();
+        let mut spans = vec![];
+        let mut found = Some(first);
+        while let Some(pos) = found.filter(|&pos| pos < end) {
+            spans.push((pos - start, pos - start + len));
+            found = self.finder.find(bytes, pos + len);
+        }
+        self.next = found;
+
+        let end = if bytes[start..end].ends_with(b"\r") { end - 1 } else { end };
+        Some((line_number, (start, end), spans))
+    }
+}
+
+// Report the lines of `bytes` holding the literal of `finder` to `emit`, at most `max_count` of
+// them, and return how many. The bytes are searched in place, and only the selected lines are
+// copied out of them, then decoded like the lines read by `search_reader`.
+pub fn search_literal<F>(finder : &Finder, bytes : &[u8], max_count : usize, mut emit : F)
+                         -> io::Result<usize>
+    where F : FnMut(Line) -> io::Result<()>
+{
+    let mut selected = 0;
+    for (line_number, (start, end), spans) in FoundLines::new(finder, bytes) {
+        if selected == max_count {
+            break;
+        }
+        let (line, spans) = match String::from_utf8(bytes[start..end].to_vec()) {
+            Ok(line) => (line, spans),
+            // The replaced sequences move the matches past them.
+            Err(err) => {
+                let line = String::from_utf8_lossy(err.as_bytes()).into_owned();
+                let spans = finder.find_iter(line.as_bytes());
+                (line, spans)
+            },
+        };
+        emit(Line::Match(Match { line_number, offset : start, line : &line, spans }))?;
+        selected += 1;
+    }
+    Ok(selected)
+}
+
+// Report the lines read from `reader` holding the literal of `finder` to `emit`, at most
+// `max_count` of them. The input is read in chunks of whole lines, each searched as a whole.
+fn search_literal_reader<R, F>(finder : &Finder, mut reader : R, max_count : usize, mut emit : F)
+                               -> io::Result<()>
+    where R : BufRead,
+          F : FnMut(Line) -> io::Result<()>
+{
+    let mut chunk = vec![];
+    let mut line_number = 0;
+    let mut offset = 0;
+    let mut left = max_count;
+    while left > 0 {
+        chunk.clear();
+        while chunk.len() < CHUNK_LEN {
+            let buf = reader.fill_buf()?;
+            if buf.is_empty() {
+                break;
+            }
+            let len = buf.len().min(CHUNK_LEN - chunk.len());
+            chunk.extend_from_slice(&buf[..len]);
+            reader.consume(len);
+        }
+        if !chunk.ends_with(b"\n") {
+            reader.read_until(b'\n', &mut chunk)?;
+        }
+        if chunk.is_empty() {
+            break;
+        }
+
+        left -= search_literal(finder, &chunk, left, |line| {
+            emit(line.shifted(line_number, offset))
+        })?;
+        line_number += literal::count(b'\n', &chunk);
+        offset += chunk.len();
+    }
+    Ok(())
+}
+
+// Report the selected lines read from `reader` and their context to `emit`, in order, stopping
+// at the first error. Lines are streamed: only the current line (or chunk of lines) and the
+// before-context lines are kept in memory, so inputs of any size (or pipes) can be searched.
+// The lines handed to `emit` are only borrowed for the duration of the call. Literals are
+// searched for in chunks of lines rather than line by line, where `whole_literal` allows.
+pub fn search_reader<R, F>(matcher : &Matcher, reader : R, options : LineOptions, emit : F)
+                           -> io::Result<()>
+    where R : BufRead,
+          F : FnMut(Line) -> io::Result<()>
+{
+    if let Some(finder) = whole_literal(matcher, options) {
+        let max_count = options.max_count.unwrap_or(usize::MAX);
+        return search_literal_reader(finder, reader, max_count, emit);
+    }
+    search_each_line(matcher, reader, options, emit)
+}
+
+// Same as `search_reader`, always line by line.
+pub fn search_each_line<R, F>(matcher : &Matcher, mut reader : R, options : LineOptions,
+                              mut emit : F) -> io::Result<()>
+    where R : BufRead,
+          F : FnMut(Line) -> io::Result<()>
+{
+    // Recent unreported lines, kept as potential before-context.
+    let mut pending : VecDeque<(usize, usize, String)> = VecDeque::with_capacity(options.before);
+    // Remaining after-context lines to report.
+    let mut after_left = 0;
+    // Line number of the last reported line.
+    let mut last_reported : Option<usize> = None;
+
+    let mut buf = vec![];
+    let mut offset = 0;
+    let mut line_number = 0;
+    let mut selected_count = 0;
+
+    loop {
+        // Once the maximum is reached, only the trailing after-context is still reported.
+        let exhausted = options.max_count.is_some_and(|max| selected_count >= max);
+        if exhausted && after_left == 0 {
+            break;
+        }
+
+        buf.clear();
+        let len = reader.read_until(b'\n', &mut buf)?;
+        if len == 0 {
+            break;
+        }
+        line_number += 1;
+        let line = to_line(&buf);
+        let line : &str = &line;
+
+        let spans = if exhausted { vec![] } else { matcher.find_iter(line) };
+        let selected = !exhausted && spans.is_empty() == options.invert;
+
+        if selected {
+            // Flush the before-context, separating it from the previous window if apart.
+            let first = pending.front().map_or(line_number, |(number, _, _)| *number);
+            let has_context = options.before > 0 || options.after > 0;
+            if has_context && last_reported.is_some_and(|last| last + 1 < first) {
+                emit(Line::Break)?;
+            }
+            for (line_number, offset, line) in pending.drain(..) {
+                emit(Line::Context { line_number, offset, line : &line })?;
+            }
+
+            let spans = if options.invert { vec![] } else { spans };
+            emit(Line::Match(Match { line_number, offset, line, spans }))?;
+            last_reported = Some(line_number);
+            after_left = options.after;
+            selected_count += 1;
+        } else if after_left > 0 {
+            emit(Line::Context { line_number, offset, line })?;
+            last_reported = Some(line_number);
+            after_left -= 1;
+        } else if options.before > 0 {
+            // Recycle the oldest buffered line once the window is full.
+            let mut text = if pending.len() == options.before {
+                pending.pop_front().unwrap().2
+            } else {
+                String::new()
+            };
+            text.clear();
+            text.push_str(line);
+            pending.push_back((line_number, offset, text));
+        }
+
+        offset += len;
+    }
+
+    Ok(())
+}
+
+// Report the blocks of lines of `text` holding the matches of a multi-line matcher, and their
+// context, to `emit`, in order. A block is reported as a single `Match`, whose line is the text
+// of all its lines and whose spans are within it.
+pub fn search_multiline<F>(matcher : &Matcher, text : &str, options : LineOptions, mut emit : F)
+                           -> io::Result<()>
+    where F : FnMut(Line) -> io::Result<()>
+{
+    let lines = Lines::new(text);
+
+    // The matches, grouped by the lines they cover.
+    let mut blocks : Vec<Block> = vec![];
+    for (start, end) in matcher.find_iter(text) {
+        if start == text.len() && (text.is_empty() || text.ends_with('\n')) {
+            continue;   // Past the last line.
+        }
+        let first = lines.index(start);
+        let last = if end > start { lines.index(end - 1) } else { first };
+        match blocks.last_mut() {
+            Some(block) if block.last >= first => {
+                block.last = block.last.max(last);
+                block.spans.push((start, end));
+            },
+            _ => blocks.push(Block { first, last, spans : vec![(start, end)] }),
+        }
+    }
+
+    // The selected blocks, the lines outside of any when inverted.
+    let selected = if options.invert {
+        let mut outside = vec![];
+        let mut next = 0;
+        let end = Block { first : lines.len(), last : lines.len(), spans : vec![] };
+        for block in blocks.iter().chain(Some(&end)) {
+            outside.extend((next..block.first).map(|index| {
+                Block { first : index, last : index, spans : vec![] }
+            }));
+            next = block.last + 1;
+        }
+        outside
+    } else {
+        blocks
+    };
+    let max_count = options.max_count.unwrap_or(usize::MAX);
+    let selected = &selected[..selected.len().min(max_count)];
+
+    let has_context = options.before > 0 || options.after > 0;
+    let mut reported = 0;   // Lines before this one are reported, or skipped.
+    for (i, block) in selected.iter().enumerate() {
+        let from = block.first.saturating_sub(options.before).max(reported);
+        if has_context && reported > 0 && reported < from {
+            emit(Line::Break)?;
+        }
+        for index in from..block.first {
+            emit(lines.context(index))?;
+        }
+
+        // A match may take in the terminator of the last line, which is not part of the block.
+        let (start, end) = (lines.start(block.first), lines.end(block.last));
+        let spans = block.spans.iter()
+                               .map(|&(s, e)| (s.min(end) - start, e.min(end) - start))
+                               .collect();
+        let line_number = block.first + 1;
+        emit(Line::Match(Match { line_number, offset : start, line : &text[start..end], spans }))?;
+
+        // The after-context stops short of the next selected line.
+        let next = selected.get(i + 1).map_or(lines.len(), |next| next.first);
+        let until = (block.last + 1 + options.after).min(next);
+        for index in block.last + 1..until {
+            emit(lines.context(index))?;
+        }
+        reported = until.max(block.last + 1);
+    }
+    Ok(())
+}
+
+// Lines selected together, by index, and the spans of their matches in the whole text.
+struct Block {
+    first : usize,
+    last : usize,
+    spans : Vec<(usize, usize)>,
+}
+
+// The lines of a whole text, by index from 0.
+struct Lines<'a> {
+    text : &'a str,
+    starts : Vec<usize>,
+}
+
+impl<'a> Lines<'a> {
+
+    fn new(text : &'a str) -> Lines<'a> {
+        let mut starts = vec![];
+        let mut start = 0;
+        while start < text.len() {
+            starts.push(start);
+            start = literal::memchr(b'\n', &text.as_bytes()[start..]).map_or(text.len(), |pos| {
+                start + pos + 1
+            });
+        }
+        Lines { text, starts }
+    }
+
+    fn len(&self) -> usize {
+        self.starts.len()
+    }
+
+    // Index of the line holding the byte at `offset`.
+    fn index(&self, offset : usize) -> usize {
+        self.starts.partition_point(|&start| start <= offset) - 1
+    }
+
+    fn start(&self, index : usize) -> usize {
+        self.starts[index]
+    }
+
+    // End of the line, before its terminator.
+    fn end(&self, index : usize) -> usize {
+        let end = self.starts.get(index + 1).copied().unwrap_or(self.text.len());
+        let line = &self.text[..end];
+        let line = line.strip_suffix('\n').unwrap_or(line);
+        line.strip_suffix('\r').unwrap_or(line).len()
+    }
+
+    fn context(&self, index : usize) -> Line<'a> {
+        let (offset, end) = (self.start(index), self.end(index));
+        Line::Context { line_number : index + 1, offset, line : &self.text[offset..end] }
+    }
+}
+
+// Strip the `\n` or `\r\n` terminator off a raw line, replacing its invalid UTF-8 sequences
+// with U+FFFD, which only copies the lines having some.
+fn to_line(raw : &[u8]) -> Cow<'_, str> {
+    let raw = raw.strip_suffix(b"\n").unwrap_or(raw);
+    let raw = raw.strip_suffix(b"\r").unwrap_or(raw);
+    String::from_utf8_lossy(raw)
+}
@@ -0,0 +1,450 @@
+//
+// `searcher.rs` is the library API of minigrep, to embed it without an argument vector nor the
+//   standard output.
+//
+// `SearchOptions` holds owned settings and builds a `Searcher` out of them, which can then search
+//   any number of inputs (from any number of threads, once cloned), reporting what it finds to a
+//   `Sink`. The command line is itself a sink printing the results, see `lib.rs`.
+//
+// Inputs made of CSV, TSV or logfmt records can be searched within one field, and have only some
+//   fields reported, see `record.rs`. Fields named after the header of an input are resolved
+//   when it is searched, as each input has its own.
+//
+
+use std::fs::File;
+use std::io::{self, BufRead, BufReader};
+use std::path::Path;
+use std::sync::Arc;
+
+use crate::config::{BinaryFiles, Boundary, MatchMode};
+use crate::encoding::MaybeTranscoded;
+use crate::error::MinigrepError;
+use crate::inflate::MaybeCompressed;
+#[cfg(feature = "mmap")]
+use crate::literal::Finder;
+use crate::matcher::{InField, Matcher};
+#[cfg(feature = "mmap")]
+use crate::mmap::Mmap;
+use crate::record::{self, Format, Projection, Selector, Target};
+use crate::search::{self, Line, LineOptions, Match, Stats};
+use crate::walk::is_binary;
+
+
+// Receiver of the results of the searches, input by input.
+pub trait Sink {
+
+    // A selected or context line of the input `path`, in order. An error stops the search.
+    fn line(&mut self, path : &Path, line : Line) -> io::Result<()>;
+
+    // The input `path` looks binary and has a selected line, its lines not being reported (see
+    // `BinaryFiles::Binary`).
+    fn binary_match(&mut self, _path : &Path) -> io::Result<()> {
+        Ok(())
+    }
+
+    // The input `path` is searched, with these counts. Not called for the inputs skipped.
+    fn finish(&mut self, _path : &Path, _stats : &Stats) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+// A closure is a sink of the lines alone.
+impl<F> Sink for F
+    where F : FnMut(&Path, Line) -> io::Result<()>
+{
+    fn line(&mut self, path : &Path, line : Line) -> io::Result<()> {
+        self(path, line)
+    }
+}
+
+
+// Builder of a `Searcher`. By default, patterns are case sensitive literals matched anywhere,
+// the inputs are searched as they are, and binary ones only tell whether they match.
+#[derive(Debug, Clone)]
+pub struct SearchOptions {
+    patterns : Vec<String>,
+    mode : MatchMode,
+    sensitive : bool,
+    boundary : Boundary,
+    lines : LineOptions,
+    multiline : bool,
+    format : Option<Format>,        // Unless given, depends on the input.
+    field : Option<Selector>,
+    print_fields : Vec<Selector>,
+    decompress : bool,
+    binary_files : BinaryFiles,
+}
+
+impl Default for SearchOptions {
+    fn default() -> SearchOptions {
+        SearchOptions {
+            patterns : vec![],
+            mode : MatchMode::Literal,
+            sensitive : true,
+            boundary : Boundary::Anywhere,
+            lines : LineOptions::default(),
+            multiline : false,
+            format : None,
+            field : None,
+            print_fields : vec![],
+            decompress : false,
+            binary_files : BinaryFiles::Binary,
+        }
+    }
+}
+
+impl SearchOptions {
+
+    pub fn new() -> SearchOptions {
+        SearchOptions::default()
+    }
+
+    // Add a pattern: lines matching any of them are selected, and no pattern selects nothing.
+    pub fn pattern<S : Into<String>>(&mut self, pattern : S) -> &mut Self {
+        self.patterns.push(pattern.into());
+        self
+    }
+
+    pub fn mode(&mut self, mode : MatchMode) -> &mut Self {
+        self.mode = mode;
+        self
+    }
+
+    pub fn ignore_case(&mut self, ignore_case : bool) -> &mut Self {
+        self.sensitive = !ignore_case;
+        self
+    }
+
+    pub fn boundary(&mut self, boundary : Boundary) -> &mut Self {
+        self.boundary = boundary;
+        self
+    }
+
+    // Select the lines not matching instead.
+    pub fn invert(&mut self, invert : bool) -> &mut Self {
+        self.lines.invert = invert;
+        self
+    }
+
+    // Lines of context to report before and after each selected line.
+    pub fn context(&mut self, before : usize, after : usize) -> &mut Self {
+        self.lines.before = before;
+        self.lines.after = after;
+        self
+    }
+
+    // Stop searching an input after this many selected lines.
+    pub fn max_count(&mut self, max_count : Option<usize>) -> &mut Self {
+        self.lines.max_count = max_count;
+        self
+    }
+
+    // Let the matches span lines, each input being searched as a whole: the lines a match covers
+    // are reported as one block (see `search::search_multiline`). Fuzzy queries are unaffected.
+    pub fn multiline(&mut self, multiline : bool) -> &mut Self {
+        self.multiline = multiline;
+        self
+    }
+
+    // The format of the records in the inputs, by default `Format::detect`ed from their path.
+    pub fn format(&mut self, format : Option<Format>) -> &mut Self {
+        self.format = format;
+        self
+    }
+
+    // Only match within this field of the records; the inputs are then searched line by line,
+    // even if `multiline`.
+    pub fn field(&mut self, field : Option<Selector>) -> &mut Self {
+        self.field = field;
+        self
+    }
+
+    // Only report these fields of the records, in this order, rather than whole lines.
+    pub fn print_fields(&mut self, fields : Vec<Selector>) -> &mut Self {
+        self.print_fields = fields;
+        self
+    }
+
+    // Decompress the inputs compressed with gzip or zlib, see `inflate.rs`.
+    pub fn decompress(&mut self, decompress : bool) -> &mut Self {
+        self.decompress = decompress;
+        self
+    }
+
+    pub fn binary_files(&mut self, binary_files : BinaryFiles) -> &mut Self {
+        self.binary_files = binary_files;
+        self
+    }
+
+    // Compile the patterns. The options can be changed and built again afterwards.
+    pub fn build(&self) -> Result<Searcher, MinigrepError> {
+        let multiline = self.multiline && self.mode != MatchMode::Fuzzy;
+        let matcher = if multiline {
+            Matcher::multi_line(&self.patterns, self.mode, self.sensitive, self.boundary)
+        } else {
+            Matcher::with_patterns(&self.patterns, self.mode, self.sensitive, self.boundary)
+        };
+        let matcher = matcher.map_err(|err| {
+            MinigrepError::Pattern { pattern : self.patterns[err.pattern].clone(), err }
+        })?;
+        Ok(Searcher {
+            matcher : Arc::new(matcher),
+            options : self.lines,
+            multiline,
+            format : self.format,
+            field : self.field.clone(),
+            print_fields : self.print_fields.clone(),
+            decompress : self.decompress,
+            binary_files : self.binary_files,
+        })
+    }
+}
+
+
+// Compiled search, cheap to clone and shared between threads.
+#[derive(Clone)]
+pub struct Searcher {
+    pub(crate) matcher : Arc<Matcher>,
+    pub(crate) options : LineOptions,
+    pub(crate) multiline : bool,
+    pub(crate) format : Option<Format>,
+    pub(crate) field : Option<Selector>,
+    pub(crate) print_fields : Vec<Selector>,
+    pub(crate) decompress : bool,
+    pub(crate) binary_files : BinaryFiles,
+}
+
+impl Searcher {
+
+    pub fn matcher(&self) -> &Matcher {
+        &self.matcher
+    }
+
+    // Search the file at `path`, reporting to `sink`. Returns the counts of what was selected.
+    pub fn search_path<S : Sink>(&self, path : &Path, sink : &mut S)
+                                 -> Result<Stats, MinigrepError> {
+        self.search_file(path, self.binary_files, sink)
+            .map_err(|err| MinigrepError::Io { path : Some(path.to_path_buf()), err })
+    }
+
+    // Search `reader`, named `path` for the sink.
+    pub fn search_reader<R : BufRead, S : Sink>(&self, path : &Path, reader : R, sink : &mut S)
+                                                -> Result<Stats, MinigrepError> {
+        self.search_decoded(reader, path, self.binary_files, sink)
+            .map_err(|err| MinigrepError::Io { path : Some(path.to_path_buf()), err })
+    }
+
+    // Search the file at `path`, read, or with the `mmap` feature mapped in memory and searched
+    // in place if large enough and the search allows (see `search_mapped`).
+    pub(crate) fn search_file<S : Sink>(&self, path : &Path, policy : BinaryFiles, sink : &mut S)
+                                        -> io::Result<Stats> {
+        let file = File::open(path)?;
+        #[cfg(feature = "mmap")]
+        {
+            if let Some(finder) = self.whole_literal() {
+                // SAFETY: as with any memory map, the file could still be changed by another
+                // process while searched. The map is only searched as bytes, never as text, the
+                // selected lines being copied out of it, and its truncation is caught, which
+                // keeps what such a change breaks to the lines found.
+                if let Some(map) = unsafe { Mmap::open(&file)? } {
+                    if let Some(stats) = self.search_mapped(finder, &map, path, policy, sink)? {
+                        if map.is_truncated() {
+                            return Err(io::Error::new(io::ErrorKind::UnexpectedEof,
+                                                      "file truncated while searched"));
+                        }
+                        return Ok(stats);
+                    }
+                }
+            }
+        }
+        self.search_decoded(BufReader::new(file), path, policy, sink)
+    }
+
+    // The literal searched for, if the inputs can be searched as bytes for it: only the lines
+    // selected are wanted, on their own (see `search::whole_literal`).
+    #[cfg(feature = "mmap")]
+    fn whole_literal(&self) -> Option<&Finder> {
+        if self.multiline || self.splits_records() {
+            return None;
+        }
+        search::whole_literal(&self.matcher, self.options)
+    }
+
+    // Search a mapped input in place for the literal of `finder`, unless it needs decompressing
+    // or transcoding, in which case `None` is returned for the file to be read instead.
+    #[cfg(feature = "mmap")]
+    fn search_mapped<S : Sink>(&self, finder : &Finder, bytes : &[u8], path : &Path,
+                               policy : BinaryFiles, sink : &mut S) -> io::Result<Option<Stats>> {
+        let compressed = self.decompress && crate::inflate::detect(bytes).is_some();
+        let bytes = match crate::encoding::utf8_start(bytes) {
+            Some(start) if !compressed => &bytes[start..],
+            _ => return Ok(None),
+        };
+        let binary = policy != BinaryFiles::Text && is_binary(bytes);
+        self.search_lines(&self.matcher, binary, path, policy, sink, |_, options, emit| {
+            let max_count = options.max_count.unwrap_or(usize::MAX);
+            search::search_literal(finder, bytes, max_count, emit).map(|_| ())
+        }).map(Some)
+    }
+
+    // Search an input once decompressed (if asked to) and transcoded to UTF-8, the binary ones
+    // according to `policy`.
+    pub(crate) fn search_decoded<R : BufRead, S : Sink>(&self, reader : R, path : &Path,
+                                                        policy : BinaryFiles, sink : &mut S)
+                                                        -> io::Result<Stats> {
+        if self.decompress {
+            let reader = MaybeCompressed::new(reader)?;
+            return self.search_text(MaybeTranscoded::new(reader)?, path, policy, sink);
+        }
+        self.search_text(MaybeTranscoded::new(reader)?, path, policy, sink)
+    }
+
+    // The lines of a binary input are not reported, which would garble the output: only
+    // whether it matches is, like grep does. In multi-line mode, the input is read whole, its
+    // invalid UTF-8 sequences replaced, so the offsets are those of the replaced text.
+    fn search_text<R : BufRead, S : Sink>(&self, mut reader : R, path : &Path,
+                                          policy : BinaryFiles, sink : &mut S)
+                                          -> io::Result<Stats> {
+        let binary = policy != BinaryFiles::Text && is_binary(reader.fill_buf()?);
+        if self.splits_records() {
+            return self.search_records(reader, binary, path, policy, sink);
+        }
+        if self.multiline {
+            let mut bytes = vec![];
+            reader.read_to_end(&mut bytes)?;
+            let text = String::from_utf8_lossy(&bytes);
+            return self.search_lines(&self.matcher, binary, path, policy, sink,
+                                     |matcher, options, emit| {
+                search::search_multiline(matcher, &text, options, emit)
+            });
+        }
+        self.search_lines(&self.matcher, binary, path, policy, sink, |matcher, options, emit| {
+            search::search_reader(matcher, reader, options, emit)
+        })
+    }
+
+    // Whether the lines are records to split into fields.
+    fn splits_records(&self) -> bool {
+        self.field.is_some() || !self.print_fields.is_empty()
+    }
+
+    // Search the records of an input within the field asked for, if any. The first line of CSV
+    // and TSV inputs is not searched, but names their columns: a name missing from it is an
+    // error.
+    fn search_records<R : BufRead, S : Sink>(&self, mut reader : R, binary : bool, path : &Path,
+                                             policy : BinaryFiles, sink : &mut S)
+                                             -> io::Result<Stats> {
+        let format = self.format.unwrap_or_else(|| Format::detect(path));
+        let mut raw = vec![];
+        if format.has_header() && reader.read_until(b'\n', &mut raw)? == 0 {
+            return self.search_lines(&self.matcher, binary, path, policy, sink, |_, _, _| Ok(()));
+        }
+        let header = String::from_utf8_lossy(&raw);
+        let header = header.strip_suffix('\n').unwrap_or(&header);
+        let header = record::split(header.strip_suffix('\r').unwrap_or(header), format);
+        let resolve = |selector : &Selector| {
+            Target::resolve(selector, format, &header).ok_or_else(|| {
+                io::Error::new(io::ErrorKind::InvalidData,
+                               format!("no {} in the header", selector))
+            })
+        };
+
+        let matcher = match &self.field {
+            Some(field) => {
+                let target = resolve(field)?;
+                Arc::new(Matcher::InField(InField::new(Arc::clone(&self.matcher), format, target)))
+            },
+            None => Arc::clone(&self.matcher),
+        };
+        let printed = if self.print_fields.is_empty() {
+            None
+        } else {
+            Some(self.print_fields.iter().map(resolve).collect::<io::Result<Vec<_>>>()?)
+        };
+        let mut records = Records {
+            sink,
+            format,
+            printed,
+            line_number : (!raw.is_empty()) as usize,
+            offset : raw.len(),
+        };
+        self.search_lines(&matcher, binary, path, policy, &mut records, |matcher, options, emit| {
+            search::search_reader(matcher, reader, options, emit)
+        })
+    }
+
+    // Report the lines found by `search` with `matcher` and the options of the input, `binary` or
+    // not.
+    fn search_lines<S, F>(&self, matcher : &Matcher, binary : bool, path : &Path,
+                          policy : BinaryFiles, sink : &mut S, search : F) -> io::Result<Stats>
+        where S : Sink,
+              F : FnOnce(&Matcher, LineOptions, &mut dyn FnMut(Line) -> io::Result<()>)
+                         -> io::Result<()>
+    {
+        let mut options = self.options;
+        if binary {
+            if policy == BinaryFiles::WithoutMatch {
+                return Ok(Stats::default());
+            }
+            options.before = 0;
+            options.after = 0;
+            options.max_count = Some(options.max_count.map_or(1, |max| max.min(1)));
+        }
+
+        let mut stats = Stats::default();
+        search(matcher, options, &mut |line| {
+            stats.record(&line);
+            if binary {
+                return Ok(());
+            }
+            sink.line(path, line)
+        })?;
+        if binary && stats.matched_lines > 0 {
+            sink.binary_match(path)?;
+        }
+        sink.finish(path, &stats)?;
+        Ok(stats)
+    }
+}
+
+
+// Sink of the records past the header of an input, which has their lines numbered from the
+// start of the input, and reduced to the fields printed if any.
+struct Records<'s, S> {
+    sink : &'s mut S,
+    format : Format,
+    printed : Option<Vec<Target>>,
+    line_number : usize,    // Lines and bytes of the header.
+    offset : usize,
+}
+
+impl<'s, S : Sink> Sink for Records<'s, S> {
+
+    fn line(&mut self, path : &Path, line : Line) -> io::Result<()> {
+        let line = line.shifted(self.line_number, self.offset);
+        let printed = match &self.printed {
+            Some(printed) => printed,
+            None => return self.sink.line(path, line),
+        };
+        match line {
+            Line::Match(m) => {
+                let projection = Projection::new(m.line, self.format, printed);
+                let spans = projection.spans(&m.spans);
+                self.sink.line(path, Line::Match(Match { line : &projection.text, spans, ..m }))
+            },
+            Line::Context { line_number, offset, line } => {
+                let projection = Projection::new(line, self.format, printed);
+                self.sink.line(path, Line::Context { line_number, offset, line : &projection.text })
+            },
+            Line::Break => self.sink.line(path, Line::Break),
+        }
+    }
+
+    fn binary_match(&mut self, path : &Path) -> io::Result<()> {
+        self.sink.binary_match(path)
+    }
+
+    fn finish(&mut self, path : &Path, stats : &Stats) -> io::Result<()> {
+        self.sink.finish(path, stats)
+    }
+}
@@ -0,0 +1,119 @@
+//
+// `walk.rs` is a recursive directory walker honoring ignore files (see `ignore.rs`).
+//
+// Entries are visited depth-first in file name order, so the output is deterministic.
+//   Symbolic links and `.git` directories are never followed, and only regular files are
+//   yielded: FIFOs, sockets and devices could block or never end. The trigram indexes of
+//   minigrep (see `index.rs`) are left out too.
+//
+
+use std::error::Error;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+
+use crate::ignore::Ignore;
+use crate::index::INDEX_NAME;
+
+
+// Bytes inspected when guessing whether a file is binary.
+const BINARY_PROBE_LEN : usize = 8192;
+
+// Like git and grep, consider a file binary if a NUL byte shows up near its beginning.
+pub fn is_binary(bytes : &[u8]) -> bool {
+    bytes.iter().take(BINARY_PROBE_LEN).any(|&byte| byte == 0)
+}
+
+
+// `path` relative to the `root` it was walked from, with `/` separators.
+pub fn relative_path(root : &Path, path : &Path) -> String {
+    let parts : Vec<_> = path.strip_prefix(root).unwrap_or(path).iter()
+                             .map(|part| part.to_string_lossy())
+                             .collect();
+    parts.join("/")
+}
+
+
+// Failure to read a directory during the walk; the walk itself goes on.
+#[derive(Debug)]
+pub struct WalkError {
+    pub path : PathBuf,
+    pub err : io::Error,
+}
+
+impl fmt::Display for WalkError {
+    fn fmt(&self, f : &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}: {}", self.path.display(), self.err)
+    }
+}
+
+impl Error for WalkError {}
+
+
+// An entry waiting to be visited, with the ignore rules in effect for it.
+struct Pending {
+    path : PathBuf,
+    is_dir : bool,
+    ignore : Rc<Ignore>,
+}
+
+// Iterator over the regular files under a root path.
+pub struct Walk {
+    stack : Vec<Pending>,
+}
+
+impl Walk {
+
+    pub fn new<P : AsRef<Path>>(root : P) -> Walk {
+        let path = root.as_ref().to_path_buf();
+        let is_dir = path.is_dir();
+        Walk {
+            stack : vec![Pending { path, is_dir, ignore : Ignore::empty() }],
+        }
+    }
+
+    // Push the children of a directory, in reverse name order so the first pops first.
+    fn expand(&mut self, dir : &Path, ignore : &Rc<Ignore>) -> io::Result<()> {
+        let ignore = Ignore::load(ignore, dir);
+
+        let mut children = vec![];
+        for entry in fs::read_dir(dir)? {
+            let entry = entry?;
+            let file_type = entry.file_type()?;
+            // Symbolic links are neither directories nor files here, as they are not followed.
+            if !(file_type.is_dir() || file_type.is_file())
+               || (file_type.is_dir() && entry.file_name() == ".git")
+               || (file_type.is_file() && entry.file_name() == INDEX_NAME) {
+                continue;
+            }
+
+            let path = entry.path();
+            let is_dir = file_type.is_dir();
+            if !ignore.is_ignored(&path, is_dir) {
+                children.push(Pending { path, is_dir, ignore : Rc::clone(&ignore) });
+            }
+        }
+
+        children.sort_by(|a, b| b.path.cmp(&a.path));
+        self.stack.extend(children);
+        Ok(())
+    }
+}
+
+impl Iterator for Walk {
+    type Item = Result<PathBuf, WalkError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        while let Some(Pending { path, is_dir, ignore }) = self.stack.pop() {
+            if !is_dir {
+                return Some(Ok(path));
+            }
+            if let Err(err) = self.expand(&path, &ignore) {
+                return Some(Err(WalkError { path, err }));
+            }
+        }
+        None
+    }
+}
@@ -0,0 +1,315 @@
+//
+// `watch.rs` runs the long-lived searches, until interrupted:
+//   - `--follow` searches files, then keeps them open and searches the lines appended to them,
+//     like `tail -f`. A file truncated, or replaced by another one at its path (say rotated),
+//     is read again from its start, the end of the old one being searched first.
+//   - `--watch` searches the inputs, then searches them all again whenever one changes, is
+//     added, or removed.
+//
+// Changes are found by polling the sizes and modification times, which works on any file
+// system. Ctrl-C (or SIGTERM) stops the loops once the current poll is done, so that the
+// output is flushed and the exit status is the one of what was searched; a second Ctrl-C ends
+// the process right away.
+//
+
+use std::fs::{self, File, Metadata};
+use std::io::{self, Read, Write};
+use std::mem;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::thread;
+use std::time::{Duration, Instant, SystemTime};
+
+use crate::config::Config;
+use crate::error::MinigrepError;
+use crate::search::{Line, Stats};
+use crate::searcher::Sink;
+use crate::walk::WalkError;
+use crate::{Input, Output, Report, STDIN_NAME, Settings, Tracked, collect_inputs, search_once};
+
+
+// How often the inputs are checked for changes.
+const POLL : Duration = Duration::from_millis(250);
+
+// How often the stop flag is checked while waiting.
+const TICK : Duration = Duration::from_millis(25);
+
+// Set once interrupted, after `catch_interrupt`.
+static INTERRUPTED : AtomicBool = AtomicBool::new(false);
+
+
+// Catch Ctrl-C and SIGTERM, which then set the flag of `interrupted` instead of ending the
+// process. Only does something on Unix.
+pub fn catch_interrupt() {
+    #[cfg(unix)]
+    signal::catch();
+}
+
+// The flag set when interrupted, which stops `--follow` and `--watch`.
+pub fn interrupted() -> &'static AtomicBool {
+    &INTERRUPTED
+}
+
+#[cfg(unix)]
+mod signal {
+
+    use std::os::raw::c_int;
+    use std::sync::Once;
+    use std::sync::atomic::Ordering;
+
+    const SIGINT : c_int = 2;
+    const SIGTERM : c_int = 15;
+    const SIG_DFL : usize = 0;
+
+    extern "C" {
+        fn signal(signum : c_int, handler : usize) -> usize;
+    }
+
+    // Only stores to an atomic, and restores the default handler, which is signal safe.
+    extern "C" fn handle(signum : c_int) {
+        super::INTERRUPTED.store(true, Ordering::SeqCst);
+        unsafe {
+            signal(signum, SIG_DFL);
+        }
+    }
+
+    pub fn catch() {
+        static CAUGHT : Once = Once::new();
+        CAUGHT.call_once(|| {
+            let handler = handle as extern "C" fn(c_int) as usize;
+            unsafe {
+                signal(SIGINT, handler);
+                signal(SIGTERM, handler);
+            }
+        });
+    }
+}
+
+// Wait for the next poll, unless stopped meanwhile. Returns whether to go on.
+fn pause(stop : &AtomicBool) -> bool {
+    let start = Instant::now();
+    while start.elapsed() < POLL {
+        if stop.load(Ordering::SeqCst) {
+            return false;
+        }
+        thread::sleep(TICK);
+    }
+    !stop.load(Ordering::SeqCst)
+}
+
+
+// Search the files, then the lines appended to them, until stopped or, with a maximum count,
+// until that many lines are selected in each.
+pub(crate) fn follow<W : Write>(config : &Config, color : bool, out : &mut W, stop : &AtomicBool)
+                                -> Result<Report, MinigrepError> {
+    let searcher = config.search_options().build()?;
+    let settings = Settings::new(config, searcher, None, config.filenames.len() > 1, color);
+
+    let mut report = Report::default();
+    let mut followed = vec![];
+    for &filename in &config.filenames {
+        let path = PathBuf::from(filename);
+        match Followed::open(&path) {
+            Ok(file) => followed.push(file),
+            Err(err) => report.fail(MinigrepError::Io { path : Some(path), err }),
+        }
+    }
+
+    loop {
+        let mut i = 0;
+        while i < followed.len() {
+            let mut tracked = Tracked { out : &mut *out, failed : false };
+            match followed[i].poll(&settings, &mut tracked) {
+                Ok(()) => i += 1,
+                Err(err) => {
+                    let file = followed.remove(i);
+                    let err = tracked.blame(file.path, err);
+                    if err.is_output() {
+                        return Err(err);
+                    }
+                    report.summary.add(&file.stats);
+                    report.fail(err);
+                },
+            }
+        }
+        out.flush()?;
+        if followed.iter().all(|file| file.is_done(&settings)) || !pause(stop) {
+            break;
+        }
+    }
+    for file in &followed {
+        report.summary.add(&file.stats);
+    }
+    Ok(report)
+}
+
+// A file followed, and how far it is searched.
+struct Followed {
+    path : PathBuf,
+    file : File,
+    identity : Option<(u64, u64)>,  // Device and inode, to tell when the path is replaced.
+    read : u64,                     // Bytes read from the file.
+    partial : Vec<u8>,              // The last line read, until complete.
+    line_number : usize,            // Lines searched.
+    offset : usize,                 // Bytes searched.
+    stats : Stats,
+}
+
+impl Followed {
+
+    fn open(path : &Path) -> io::Result<Followed> {
+        let file = File::open(path)?;
+        let identity = identity(&file.metadata()?);
+        Ok(Followed {
+            path : path.to_path_buf(),
+            file,
+            identity,
+            read : 0,
+            partial : vec![],
+            line_number : 0,
+            offset : 0,
+            stats : Stats::default(),
+        })
+    }
+
+    // Whether the maximum count of selected lines is reached.
+    fn is_done(&self, settings : &Settings) -> bool {
+        settings.searcher.options.max_count.is_some_and(|max| self.stats.matched_lines >= max)
+    }
+
+    // Search what was appended since the last poll, then start over if the file was truncated
+    // or replaced. A missing file is waited for, as it is while being rotated.
+    fn poll<W : Write>(&mut self, settings : &Settings, out : &mut W) -> io::Result<()> {
+        self.search_appended(settings, out)?;
+        let metadata = match fs::metadata(&self.path) {
+            Ok(metadata) => metadata,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(()),
+            Err(err) => return Err(err),
+        };
+        let replaced = identity(&metadata) != self.identity;
+        if !replaced && metadata.len() >= self.read {
+            return Ok(());
+        }
+
+        eprintln!("minigrep: {}: file {}", self.path.display(),
+                  if replaced { "replaced" } else { "truncated" });
+        // The last line of the old file is complete, even without a line terminator.
+        let partial = mem::take(&mut self.partial);
+        self.search(settings, &partial, out)?;
+        let stats = self.stats;
+        *self = Followed::open(&self.path)?;
+        self.stats = stats;
+        self.search_appended(settings, out)
+    }
+
+    fn search_appended<W : Write>(&mut self, settings : &Settings, out : &mut W)
+                                  -> io::Result<()> {
+        let mut bytes = mem::take(&mut self.partial);
+        self.read += self.file.read_to_end(&mut bytes)? as u64;
+        let complete = bytes.iter().rposition(|&byte| byte == b'\n').map_or(0, |i| i + 1);
+        self.partial = bytes.split_off(complete);
+        self.search(settings, &bytes, out)
+    }
+
+    // Search complete lines, numbered after those already searched.
+    fn search<W : Write>(&mut self, settings : &Settings, bytes : &[u8], out : &mut W)
+                         -> io::Result<()> {
+        if bytes.is_empty() || self.is_done(settings) {
+            return Ok(());
+        }
+        let mut searcher = settings.searcher.clone();
+        searcher.options.max_count = searcher.options.max_count.map(|max| {
+            max - self.stats.matched_lines
+        });
+        let mut output = Output { settings, out, begun : false, ranked : vec![] };
+        let mut shifted = Shifted {
+            sink : &mut output,
+            line_number : self.line_number,
+            offset : self.offset,
+        };
+        let stats = searcher.search_decoded(bytes, &self.path, settings.binary_policy(false),
+                                            &mut shifted)?;
+        self.stats.matched_lines += stats.matched_lines;
+        self.stats.matches += stats.matches;
+
+        self.line_number += bytes.iter().filter(|&&byte| byte == b'\n').count();
+        if !bytes.ends_with(b"\n") {
+            self.line_number += 1;
+        }
+        self.offset += bytes.len();
+        Ok(())
+    }
+}
+
+// The device and inode of a file, where there are such things.
+#[cfg(unix)]
+fn identity(metadata : &Metadata) -> Option<(u64, u64)> {
+    use std::os::unix::fs::MetadataExt;
+    Some((metadata.dev(), metadata.ino()))
+}
+
+#[cfg(not(unix))]
+fn identity(_metadata : &Metadata) -> Option<(u64, u64)> {
+    None
+}
+
+// Sink numbering the lines after `line_number` lines and `offset` bytes.
+struct Shifted<'s, S> {
+    sink : &'s mut S,
+    line_number : usize,
+    offset : usize,
+}
+
+impl<'s, S : Sink> Sink for Shifted<'s, S> {
+
+    fn line(&mut self, path : &Path, line : Line) -> io::Result<()> {
+        self.sink.line(path, line.shifted(self.line_number, self.offset))
+    }
+
+    fn binary_match(&mut self, path : &Path) -> io::Result<()> {
+        self.sink.binary_match(path)
+    }
+
+    fn finish(&mut self, path : &Path, stats : &Stats) -> io::Result<()> {
+        self.sink.finish(path, stats)
+    }
+}
+
+
+// Search the inputs, then again whenever they change, until stopped. Returns the report of
+// the last search.
+pub(crate) fn watch<W : Write>(config : &Config, color : bool, out : &mut W, stop : &AtomicBool)
+                               -> Result<Report, MinigrepError> {
+    let mut searched = None;
+    let mut report = Report::default();
+    loop {
+        // Taken before searching, so that changes made meanwhile are searched next time.
+        let snapshot = snapshot(config)?;
+        if searched.as_ref() != Some(&snapshot) {
+            report = search_once(config, color, out)?;
+            out.flush()?;
+            searched = Some(snapshot);
+        }
+        if !pause(stop) {
+            return Ok(report);
+        }
+    }
+}
+
+// The size and modification time of an input, if it can be read.
+type Stamp = Option<(u64, Option<SystemTime>)>;
+
+// The inputs, in order, and their stamps.
+fn snapshot(config : &Config) -> Result<Vec<(PathBuf, Stamp)>, MinigrepError> {
+    let stamp = |path : &Path| {
+        fs::metadata(path).ok().map(|metadata| (metadata.len(), metadata.modified().ok()))
+    };
+    Ok(collect_inputs(config)?.into_iter().map(|input| match input {
+        Input::File(path) | Input::Walked(path) | Input::Pruned(path) => {
+            let stamp = stamp(&path);
+            (path, stamp)
+        },
+        Input::Unreadable(WalkError { path, .. }) => (path, None),
+        Input::Stdin => (PathBuf::from(STDIN_NAME), None),
+    }).collect())
+}
@@ -0,0 +1,97 @@
+mod common;
+
+#[cfg(test)]
+mod tests {
+
+    use std::fs;
+
+    use minigrep::*;
+    use crate::common::*;
+
+    fn run(args : &[&str]) -> (String, bool) {
+        let mut argv = vec!["minigrep".to_string()];
+        argv.extend(args.iter().map(|arg| arg.to_string()));
+        let config = Config::new(&argv).unwrap();
+
+        let mut out = vec![];
+        let ok = run_to(&config, &mut out).is_ok_and(|report| report.errors.is_empty());
+        (String::from_utf8(out).unwrap(), ok)
+    }
+
+    // Search `data` as if it were the only input, named `data.bin`.
+    fn run_data(args : &[&str], data : &[u8]) -> String {
+        let mut argv = vec!["minigrep".to_string()];
+        argv.extend(args.iter().map(|arg| arg.to_string()));
+        let config = Config::new(&argv).unwrap();
+
+        let mut out = vec![];
+        run_reader(&config, "data.bin", data, &mut out).unwrap();
+        String::from_utf8(out).unwrap()
+    }
+
+    const DATA : &[u8] = b"foo\0bar\nbaz\nfoo again\n";
+
+    #[test]
+    fn policies() {
+        assert_eq!("Binary file data.bin matches\n", run_data(&["foo"], DATA));
+        assert_eq!("Binary file data.bin matches\n", run_data(&["-o", "--binary-files=binary", "foo"], DATA));
+        assert_eq!("", run_data(&["nothing"], DATA));
+        assert_eq!("foo\0bar\nfoo again\n", run_data(&["--binary-files=text", "foo"], DATA));
+        assert_eq!("", run_data(&["--binary-files=without-match", "foo"], DATA));
+
+        // Counts and paths are not garbled, so they are printed as usual.
+        assert_eq!("2\n", run_data(&["-c", "foo"], DATA));
+        assert_eq!("data.bin\n", run_data(&["-l", "foo"], DATA));
+        assert_eq!("", run_data(&["-c", "--binary-files=without-match", "foo"], DATA));
+
+        // Only a NUL makes an input binary.
+        assert_eq!("foo\n", run_data(&["foo"], b"foo\n\x01\x02\n"));
+    }
+
+    #[test]
+    fn lossy_lines() {
+        assert_eq!("caf\u{fffd} au lait\n", run_data(&["lait"], b"caf\xe9 au lait\n"));
+        assert_eq!("1:\u{fffd}\n2:ok\n", run_data(&["-nv", "nothing"], b"\xff\nok\n"));
+    }
+
+    #[test]
+    fn utf16() {
+        let mut data = b"\xff\xfe".to_vec();
+        for unit in "Größe: 42\r\nno\r\nGröße: 7\r\n".encode_utf16() {
+            data.extend_from_slice(&unit.to_le_bytes());
+        }
+        assert_eq!("1:Größe: 42\n3:Größe: 7\n", run_data(&["-n", "Größe"], &data));
+
+        let mut data = b"\xfe\xff".to_vec();
+        for unit in "🐸 frog\n".encode_utf16() {
+            data.extend_from_slice(&unit.to_be_bytes());
+        }
+        assert_eq!("🐸 frog\n", run_data(&["frog"], &data));
+        assert_eq!("frog\n", run_data(&["frog"], b"\xef\xbb\xbffrog\n"));
+    }
+
+    #[test]
+    fn walked_and_given() {
+        let root = make_tree("binary-walked", &[
+            ("data.bin", DATA),
+            ("text.txt", b"foo\n"),
+        ]);
+        let dir = root.display().to_string();
+        let data = root.join("data.bin").display().to_string();
+        let text = root.join("text.txt").display().to_string();
+
+        // Found while walking, binary files are skipped, unless a policy is given.
+        assert_eq!((format!("{}:foo\n", text), true), run(&["-r", "foo", &dir]));
+        assert_eq!(
+            (format!("Binary file {} matches\n{}:foo\n", data, text), true),
+            run(&["-r", "--binary-files=binary", "foo", &dir])
+        );
+
+        // Given on the command line, they are searched for a match.
+        assert_eq!(
+            (format!("Binary file {} matches\n{}:foo\n", data, text), true),
+            run(&["foo", &data, &text])
+        );
+        fs::remove_dir_all(&root).unwrap();
+    }
+}
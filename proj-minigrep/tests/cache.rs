@@ -0,0 +1,86 @@
+mod common;
+
+#[cfg(test)]
+mod tests {
+
+    use std::fs;
+    use std::path::{Path, PathBuf};
+
+    use minigrep::*;
+    use crate::common::*;
+
+    // Run the command line behavior, returning the output and the number of selected lines.
+    fn run(args : &[&str]) -> (String, usize) {
+        let mut argv = vec!["minigrep".to_string()];
+        argv.extend(args.iter().map(|arg| arg.to_string()));
+        let config = Config::new(&argv).unwrap();
+
+        let mut out = vec![];
+        let report = run_to(&config, &mut out).unwrap();
+        (String::from_utf8(out).unwrap(), report.summary.matched_lines)
+    }
+
+    fn entries(cache : &Path) -> Vec<PathBuf> {
+        fs::read_dir(cache).map_or(vec![], |dir| dir.map(|entry| entry.unwrap().path()).collect())
+    }
+
+    #[test]
+    fn unchanged_files_answered() {
+        let root = make_tree("cache", &[
+            ("t/a.txt", b"foo\nbar foo\n"),
+            ("t/b.txt", b"nothing\n"),
+        ]);
+        let (tree, cache) = (root.join("t"), root.join("cache"));
+        let tree = tree.to_str().unwrap();
+        let cache_dir = format!("--cache-dir={}", cache.display());
+        let a = Path::new(tree).join("a.txt");
+        let expected = format!("{0}:1:foo\n{0}:2:bar foo\n", a.display());
+
+        assert_eq!((expected.clone(), 2), run(&["-rn", &cache_dir, "foo", tree]));
+        assert_eq!(2, entries(&cache).len());
+
+        // Tamper with the entries, to tell the files are not searched again.
+        for entry in entries(&cache) {
+            let text = fs::read(&entry).unwrap();
+            let text = String::from_utf8(text).unwrap().replace("bar foo", "cached");
+            fs::write(&entry, text).unwrap();
+        }
+        let cached = expected.replace("bar foo", "cached");
+        assert_eq!((cached.clone(), 2), run(&["-rn", &cache_dir, "foo", tree]));
+        assert_eq!((expected.clone(), 2), run(&["-rn", &cache_dir, "--no-cache", "foo", tree]));
+
+        // Other options are other entries.
+        let counts = format!("{}:2\n{}:0\n", a.display(), Path::new(tree).join("b.txt").display());
+        assert_eq!((counts, 2), run(&["-rc", &cache_dir, "foo", tree]));
+        assert_eq!(4, entries(&cache).len());
+
+        // A changed file is searched again.
+        fs::write(&a, "foo\nbar foo\nfoo again\n").unwrap();
+        let changed = format!("{}{}:3:foo again\n", expected, a.display());
+        assert_eq!((changed, 3), run(&["-rn", &cache_dir, "foo", tree]));
+
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn size_and_inputs() {
+        let root = make_tree("cache-size", &[("a.txt", b"foo\n")]);
+        let cache = root.join("cache");
+        let a = root.join("a.txt");
+        let a = a.to_str().unwrap();
+        let cache_dir = format!("--cache-dir={}", cache.display());
+
+        // Results larger than the cache are not kept.
+        assert_eq!(("foo\n".to_string(), 1), run(&[&cache_dir, "--cache-size=8", "foo", a]));
+        assert!(entries(&cache).is_empty());
+        assert_eq!(("foo\n".to_string(), 1), run(&[&cache_dir, "--cache-size=1K", "foo", a]));
+        assert_eq!(1, entries(&cache).len());
+
+        // Neither is anything with `--no-cache`.
+        let other = format!("--cache-dir={}", root.join("other").display());
+        assert_eq!(("foo\n".to_string(), 1), run(&["--no-cache", &other, "foo", a]));
+        assert!(!root.join("other").exists());
+
+        fs::remove_dir_all(&root).unwrap();
+    }
+}
@@ -0,0 +1,36 @@
+//
+// Common helpers for the integration tests that need files on disk.
+//
+
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process;
+
+// Create a fresh directory tree under the system temp dir. Paths ending with `/` are
+// directories, the others are files with the given contents.
+pub fn make_tree(name : &str, entries : &[(&str, &[u8])]) -> PathBuf {
+    let root = env::temp_dir().join(format!("minigrep-{}-{}", process::id(), name));
+    let _ = fs::remove_dir_all(&root);
+    fs::create_dir_all(&root).unwrap();
+
+    for (path, contents) in entries {
+        let path = root.join(path);
+        if path.to_string_lossy().ends_with('/') {
+            fs::create_dir_all(&path).unwrap();
+        } else {
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(&path, contents).unwrap();
+        }
+    }
+    root
+}
+
+// `path` relative to `root`, with `/` separators.
+#[allow(dead_code)]     // Not every test crate uses every helper.
+pub fn relative(root : &Path, path : &Path) -> String {
+    let parts : Vec<_> = path.strip_prefix(root).unwrap().iter()
+                             .map(|part| part.to_string_lossy().into_owned())
+                             .collect();
+    parts.join("/")
+}
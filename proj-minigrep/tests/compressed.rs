@@ -0,0 +1,111 @@
+mod common;
+
+#[cfg(test)]
+mod tests {
+
+    use std::fs;
+
+    use minigrep::*;
+    use crate::common::*;
+
+    const LINES_GZ : &[u8] = include_bytes!("golden/lines.txt.gz");
+    const POEM_GZ : &[u8] = &[
+        0x1f, 0x8b, 0x08, 0x00, 0x00, 0x00, 0x00, 0x00, 0x02, 0x03, 0x0b, 0xc9, 0x48, 0xad, 0x54,
+        0x4f, 0x51, 0x48, 0x4a, 0xcc, 0xcb, 0x2c, 0xce, 0x50, 0x28, 0x2d, 0xd6, 0x51, 0xa8, 0xcc,
+        0x2f, 0x55, 0xc8, 0xce, 0xcb, 0x2f, 0xd7, 0xe3, 0xf2, 0xc8, 0x2f, 0x57, 0x28, 0x28, 0x4d,
+        0xca, 0xc9, 0x4c, 0xd6, 0x51, 0xc8, 0xc9, 0xcc, 0x4e, 0x55, 0x48, 0x54, 0x48, 0x2b, 0xca,
+        0x4f, 0xd7, 0xe3, 0x02, 0x00, 0xb1, 0xb5, 0x1d, 0x22, 0x35, 0x00, 0x00, 0x00,
+    ];
+
+    fn run(args : &[&str]) -> (String, bool) {
+        let mut argv = vec!["minigrep".to_string()];
+        argv.extend(args.iter().map(|arg| arg.to_string()));
+        let config = Config::new(&argv).unwrap();
+
+        let mut out = vec![];
+        let ok = run_to(&config, &mut out).is_ok_and(|report| report.errors.is_empty());
+        (String::from_utf8(out).unwrap(), ok)
+    }
+
+    #[test]
+    fn search_inside() {
+        let root = make_tree("compressed-search", &[("lines.txt.gz", LINES_GZ)]);
+        let file = root.join("lines.txt.gz").display().to_string();
+
+        assert_eq!(("1000\n".to_string(), true), run(&["-z", "-c", "abcabcabcabc", &file]));
+        assert_eq!(
+            ("4322:line 4321: abc 571\n".to_string(), true),
+            run(&["--search-zip", "-n", "line 4321:", &file])
+        );
+        // Without `-z`, the compressed bytes are searched as they are.
+        assert_eq!(("0\n".to_string(), true), run(&["-c", "line", &file]));
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn recursive() {
+        let root = make_tree("compressed-recursive", &[
+            ("poem.txt.gz", POEM_GZ),
+            ("plain.txt", b"a frog\n"),
+        ]);
+        let dir = root.display().to_string();
+
+        // Compressed files look binary, so they are skipped unless decompressed.
+        let (out, ok) = run(&["-r", "-l", "frog", &dir]);
+        assert!(ok);
+        assert_eq!(vec!["plain.txt"], out.lines().map(|line| relative(&root, line.as_ref()))
+                                                  .collect::<Vec<_>>());
+        let (out, ok) = run(&["-rz", "frog", &dir]);
+        assert!(ok);
+        assert_eq!(
+            format!("{}:a frog\n{}:How public, like a frog.\n", root.join("plain.txt").display(),
+                    root.join("poem.txt.gz").display()),
+            out
+        );
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn text_like_headers() {
+        // `x ` could be the header of a zlib stream, but not one an encoder writes.
+        let root = make_tree("compressed-text", &[("x.txt", b"x = 1\ny = foo\n")]);
+        let file = root.join("x.txt").display().to_string();
+        assert_eq!(("y = foo\n".to_string(), true), run(&["-z", "foo", &file]));
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn corrupt_input() {
+        let mut corrupt = POEM_GZ.to_vec();
+        corrupt[40] ^= 0xff;
+        let root = make_tree("compressed-corrupt", &[
+            ("bad.gz", &corrupt),
+            ("good.gz", POEM_GZ),
+        ]);
+        let bad = root.join("bad.gz").display().to_string();
+        let good = root.join("good.gz").display().to_string();
+        let (out, ok) = run(&["-z", "-c", "frog", &bad, &good]);
+        assert!(!ok);
+        assert!(out.ends_with(&format!("{}:1\n", good)));
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn replace() {
+        let root = make_tree("compressed-replace", &[("poem.txt.gz", POEM_GZ)]);
+        let file = root.join("poem.txt.gz").display().to_string();
+        let (out, ok) = run(&["-z", "--dry-run", "--replace=toad", "frog", &file]);
+        assert!(ok);
+        assert_eq!(
+            format!("--- {0}\n+++ {0}\n@@ -1,2 +1,2 @@\n They'd banish us, you know.\n\
+                     -How public, like a frog.\n+How public, like a toad.\n", file),
+            out
+        );
+
+        // Compressed files are not compressed back, so they cannot be edited in place.
+        let (_, ok) = run(&["-z", "--in-place", "--replace=toad", "frog", &file]);
+        assert!(!ok);
+        assert_eq!(POEM_GZ, fs::read(&file).unwrap().as_slice());
+        fs::remove_dir_all(&root).unwrap();
+    }
+}
@@ -0,0 +1,134 @@
+mod common;
+
+#[cfg(test)]
+mod tests {
+
+    use std::error::Error;
+    use std::fs;
+
+    use minigrep::*;
+    use crate::common::*;
+
+    fn to_args(list : &[&str]) -> Vec<String> {
+        let mut args = vec!["minigrep".to_string()];
+        args.extend(list.iter().map(|arg| arg.to_string()));
+        args
+    }
+
+    fn run(list : &[&str]) -> Report {
+        run_to(&Config::new(&to_args(list)).unwrap(), &mut vec![]).unwrap()
+    }
+
+    #[test]
+    fn exit_codes() {
+        let root = make_tree("errors-exit-codes", &[("a.txt", b"one\ntwo\n")]);
+        let a = root.join("a.txt").display().to_string();
+        let missing = root.join("missing.txt").display().to_string();
+
+        assert_eq!(0, run(&["two", &a]).exit_code());
+        assert_eq!(1, run(&["three", &a]).exit_code());
+        assert_eq!(1, run(&["-c", "three", &a]).exit_code());
+
+        // A failure wins over a match, as with grep.
+        let report = run(&["two", &missing, &a]);
+        assert_eq!(1, report.summary.matched_lines);
+        assert_eq!(2, report.exit_code());
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn per_file_errors() {
+        let root = make_tree("errors-per-file", &[
+            ("a.txt", b"old\n"),
+            ("b.txt", b"old\n"),
+            ("bad.txt", b"old \xff\n"),
+        ]);
+        let a = root.join("a.txt").display().to_string();
+        let b = root.join("b.txt").display().to_string();
+        let bad = root.join("bad.txt").display().to_string();
+        let missing = root.join("missing.txt").display().to_string();
+
+        // Each failure names its input, in the order given, and the others are still searched.
+        for threads in ["-j1", "-j4"] {
+            let mut out = vec![];
+            let args = to_args(&[threads, "old", &a, &missing, &b, &missing]);
+            let config = Config::new(&args).unwrap();
+            let report = run_to(&config, &mut out).unwrap();
+            assert_eq!(format!("{}:old\n{}:old\n", a, b), String::from_utf8(out).unwrap());
+            assert_eq!(2, report.summary.matched_lines);
+            assert_eq!(2, report.errors.len());
+            for err in &report.errors {
+                match err {
+                    MinigrepError::Io { path : Some(path), err } => {
+                        assert_eq!(missing, path.display().to_string());
+                        assert_eq!(std::io::ErrorKind::NotFound, err.kind());
+                    },
+                    _ => panic!("unexpected error {:?}", err),
+                }
+            }
+        }
+
+        // Files to edit must be valid UTF-8.
+        let report = run(&["--dry-run", "--replace=new", "old", &bad]);
+        match &report.errors[..] {
+            [err @ MinigrepError::Encoding { .. }] => {
+                assert_eq!(format!("{}: not valid UTF-8", bad), err.to_string());
+            },
+            errors => panic!("unexpected errors {:?}", errors),
+        }
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    // Output failing like a full disk.
+    struct Full;
+
+    impl std::io::Write for Full {
+        fn write(&mut self, _buf : &[u8]) -> std::io::Result<usize> {
+            Err(std::io::Error::other("No space left on device"))
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn write_errors() {
+        let root = make_tree("errors-write", &[("a.txt", b"hello\n"), ("b.txt", b"hello\n")]);
+        let a = root.join("a.txt").display().to_string();
+        let b = root.join("b.txt").display().to_string();
+
+        // Failing to write stops the run, and is not blamed on the input searched.
+        for threads in ["-j1", "-j4"] {
+            for mode in ["-n", "-c", "--json"] {
+                let args = to_args(&[threads, mode, "hello", &a, &b]);
+                let config = Config::new(&args).unwrap();
+                match run_to(&config, &mut Full) {
+                    Err(err @ MinigrepError::Io { path : None, .. }) => {
+                        assert!(err.is_output());
+                        assert_eq!("No space left on device", err.to_string());
+                    },
+                    result => panic!("unexpected result {:?}", result),
+                }
+            }
+        }
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn run_errors() {
+        // A pattern that does not compile stops the run, telling which one and where.
+        let args = to_args(&["-E", "-e", "fine", "-e", "a(b", "-"]);
+        let config = Config::new(&args).unwrap();
+        let err = run_to(&config, &mut vec![]).unwrap_err();
+        assert!(matches!(err, MinigrepError::Pattern { ref pattern, .. } if pattern == "a(b"));
+        assert!(err.to_string().starts_with("Invalid pattern 'a(b': "));
+        assert!(err.to_string().ends_with(" at position 1"));
+        assert!(err.source().is_some());
+
+        match Config::new(&to_args(&["--binary-files=maybe", "x"])) {
+            Err(err @ MinigrepError::Usage(_)) => assert!(err.source().is_none()),
+            _ => panic!("bad value accepted"),
+        }
+    }
+}
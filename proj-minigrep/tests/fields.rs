@@ -0,0 +1,108 @@
+#[cfg(test)]
+mod tests {
+
+    use std::path::Path;
+
+    use minigrep::*;
+
+    const PEOPLE : &str = "\
+id,name,city
+1,\"Doe, Jane\",Paris
+2,Smith,\"New \"\"York\"\"\"
+3,Paris Hilton,London
+";
+
+    const LOG : &str = "\
+level=info msg=\"disk full\" host=a
+level=error msg=\"disk \\\"sda\\\" full\" host=b
+level=info msg=ok host=disk
+";
+
+    // Run the command line behavior over `text`, as if it were read from the file `name`.
+    fn run_text(name : &str, text : &str, args : &[&str]) -> Result<String, String> {
+        let mut argv = vec!["minigrep".to_string()];
+        argv.extend(args.iter().map(|arg| arg.to_string()));
+        let config = Config::new(&argv).unwrap();
+
+        let mut out = vec![];
+        match run_reader(&config, name, text.as_bytes(), &mut out) {
+            Ok(_) => Ok(String::from_utf8(out).unwrap()),
+            Err(err) => Err(err.to_string()),
+        }
+    }
+
+    fn run_csv(args : &[&str]) -> String {
+        run_text("people.csv", PEOPLE, args).unwrap()
+    }
+
+    fn run_log(args : &[&str]) -> String {
+        run_text("app.log", LOG, args).unwrap()
+    }
+
+    #[test]
+    fn csv_fields() {
+        // Only the city is searched, and the header is never selected.
+        assert_eq!("2:1,\"Doe, Jane\",Paris\n", run_csv(&["-n", "--field=city", "Paris"]));
+        assert_eq!("3:2,Smith,\"New \"\"York\"\"\"\n4:3,Paris Hilton,London\n",
+                   run_csv(&["-nv", "--field=city", "Paris"]));
+        assert_eq!("2\n", run_csv(&["-c", "--column", "2", "-E", "^[A-Z][a-z]+$|Doe"]));
+        assert_eq!("", run_csv(&["--field=name", "name"]));
+
+        // Quotes are matched as the value has them, the spans being those of the line.
+        assert_eq!("2,Smith,\"New \"\"\x1b[1;31mYork\"\"\x1b[0m\"\n",
+                   run_csv(&["--color=always", "--field=city", "York\""]));
+        assert_eq!("1,\"Doe, Jane\",Paris\n", run_csv(&["-x", "--column=2", "Doe, Jane"]));
+
+        // A missing column is an error for the input.
+        assert_eq!(Err("people.csv: no field 'age' in the header".to_string()),
+                   run_text("people.csv", PEOPLE, &["--field=age", "x"]));
+        assert_eq!(Ok(String::new()), run_text("empty.csv", "", &["--field=age", "x"]));
+    }
+
+    #[test]
+    fn printed_fields() {
+        assert_eq!("London,3\n", run_csv(&["-i", "--column=2", "--print-fields=city,1", "paris"]));
+        assert_eq!("\"Doe, Jane\"\n\x1b[1;31mParis\x1b[0m Hilton\n",
+                   run_csv(&["--color=always", "--print-fields=name", "Paris"]));
+        // Missing columns are left empty.
+        assert_eq!("Paris,\n", run_csv(&["--field=city", "--print-fields=3,9", "Paris"]));
+
+        // Context lines are printed the same way.
+        assert_eq!("2:\"Doe, Jane\"\n3-Smith\n",
+                   run_csv(&["-n", "--print-fields=name", "-A1", "Jane"]));
+    }
+
+    #[test]
+    fn logfmt_fields() {
+        assert_eq!("1:level=info msg=\"disk full\" host=a\n\
+                    2:level=error msg=\"disk \\\"sda\\\" full\" host=b\n",
+                   run_log(&["-n", "--field", "msg", "disk"]));
+        assert_eq!("level=info msg=ok\n", run_log(&["--field=host", "--print-fields=level,msg",
+                                                    "disk"]));
+        assert_eq!("1\n", run_log(&["-c", "--field=level", "error"]));
+        assert_eq!("host=b\n", run_log(&["--column=2", "--print-fields=host,nothing", "sda"]));
+
+        // The format is told rather than detected.
+        assert_eq!(Ok(String::new()), run_text("people", PEOPLE, &["--column=3", "London"]));
+        assert_eq!("3,Paris Hilton,London\n",
+                   run_text("people", PEOPLE, &["--format=csv", "--column=3", "London"]).unwrap());
+    }
+
+    #[test]
+    fn library() {
+        let mut options = SearchOptions::new();
+        options.pattern("b").field(Some(record::Selector::Name("host".to_string())))
+                            .print_fields(vec![record::Selector::Column(1)]);
+        let searcher = options.build().unwrap();
+        let mut found = vec![];
+        let stats = searcher.search_reader(Path::new("app.log"), LOG.as_bytes(),
+                                           &mut |_ : &Path, line : Line| {
+            if let Line::Match(m) = line {
+                found.push((m.line_number, m.line.to_string()));
+            }
+            Ok(())
+        }).unwrap();
+        assert_eq!(vec![(2, "level=error".to_string())], found);
+        assert_eq!(1, stats.matches);
+    }
+}
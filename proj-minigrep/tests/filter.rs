@@ -0,0 +1,65 @@
+mod common;
+
+#[cfg(test)]
+mod tests {
+
+    use std::fs;
+    use std::path::Path;
+
+    use minigrep::*;
+    use crate::common::*;
+
+    // The files with a selected line, relative to `root`.
+    fn matched(root : &Path, args : &[&str]) -> Vec<String> {
+        let mut argv = vec!["minigrep".to_string(), "-l".to_string(), "needle".to_string()];
+        argv.extend(args.iter().map(|arg| arg.to_string()));
+        let config = Config::new(&argv).unwrap();
+
+        let mut out = vec![];
+        run_to(&config, &mut out).unwrap();
+        String::from_utf8(out).unwrap().lines()
+                                       .map(|line| relative(root, line.as_ref()))
+                                       .collect()
+    }
+
+    #[test]
+    fn recursive_filters() {
+        let big = [&b"needle\n"[..], &[b'x'; 2048]].concat();
+        let root = make_tree("filters", &[
+            ("Cargo.toml", b"needle"),
+            ("README.md", b"needle"),
+            ("src/main.rs", b"needle"),
+            ("src/big.rs", &big),
+            ("src/gen/parser.rs", b"needle"),
+            ("src/main_test.rs", b"needle"),
+            ("docs/guide.md", b"needle"),
+            ("scripts/build.py", b"needle"),
+        ]);
+        let dir = root.display().to_string();
+
+        let everything = vec![
+            "Cargo.toml", "README.md", "docs/guide.md", "scripts/build.py", "src/big.rs",
+            "src/gen/parser.rs", "src/main.rs", "src/main_test.rs",
+        ];
+        assert_eq!(everything, matched(&root, &["-r", &dir]));
+
+        assert_eq!(vec!["src/big.rs", "src/gen/parser.rs", "src/main.rs", "src/main_test.rs"],
+                   matched(&root, &["-r", &dir, "--include=*.rs"]));
+        assert_eq!(vec!["Cargo.toml", "src/gen/parser.rs", "src/main.rs"],
+                   matched(&root, &["-r", &dir, "--include=*.{rs,toml}", "--exclude=*_test.rs",
+                                    "--exclude", "big.*"]));
+        assert_eq!(vec!["docs/guide.md"],
+                   matched(&root, &["-r", &dir, "--include=docs/**/*.md"]));
+        assert_eq!(vec!["Cargo.toml", "README.md", "docs/guide.md", "scripts/build.py"],
+                   matched(&root, &["-r", &dir, "--exclude=src"]));
+        assert_eq!(vec!["scripts/build.py", "src/gen/parser.rs", "src/main.rs",
+                        "src/main_test.rs"],
+                   matched(&root, &["-r", &dir, "-t", "rust", "--type=py",
+                                    "--max-filesize=1K"]));
+
+        // The files given on the command line are searched regardless.
+        let file = root.join("src/big.rs").display().to_string();
+        assert_eq!(vec!["src/big.rs"], matched(&root, &["--include=*.md", &file]));
+        fs::remove_dir_all(&root).unwrap();
+    }
+}
@@ -0,0 +1,142 @@
+mod common;
+
+#[cfg(test)]
+mod tests {
+
+    use std::fs::{self, File};
+    use std::path::Path;
+    use std::time::{Duration, SystemTime};
+
+    use minigrep::*;
+    use minigrep::index::{self, Candidates, Index, Query, Update};
+    use crate::common::*;
+
+    // Run the command line behavior, returning the output and the exit code.
+    fn run(args : &[&str]) -> (String, i32) {
+        let mut argv = vec!["minigrep".to_string()];
+        argv.extend(args.iter().map(|arg| arg.to_string()));
+        let config = Config::new(&argv).unwrap();
+
+        let mut out = vec![];
+        let report = run_to(&config, &mut out).unwrap();
+        (String::from_utf8(out).unwrap(), report.exit_code())
+    }
+
+    // Check that searching `root` with each of `searches` gives the same with and without its
+    // index.
+    fn compare(root : &Path, searches : &[&[&str]]) {
+        let root = root.to_str().unwrap();
+        for search in searches {
+            let brute : Vec<_> = ["-r"].iter().chain(search.iter()).chain(&[root])
+                                           .copied().collect();
+            let indexed : Vec<_> = ["--indexed"].iter().chain(&brute).copied().collect();
+            assert_eq!(run(&brute), run(&indexed), "{:?}", search);
+        }
+    }
+
+    // Date the files of `root` back, for the index to trust them.
+    fn age(root : &Path, paths : &[&str]) {
+        let past = SystemTime::now() - Duration::from_secs(3600);
+        for path in paths {
+            File::options().append(true).open(root.join(path)).unwrap().set_modified(past).unwrap();
+        }
+    }
+
+    const FILES : [&str; 7] = [
+        "src/main.rs", "src/config.rs", "docs/readme.md", "docs/german.txt", "utf16.txt", "bin.dat",
+        "people.csv",
+    ];
+
+    fn make_repo(name : &str) -> std::path::PathBuf {
+        let root = make_tree(name, &[
+            ("src/main.rs", b"fn main() {\n    let config = Config::new(&args);\n}\n"),
+            ("src/config.rs", b"pub struct Config {\n    pub name : String,\n}\n"),
+            ("docs/readme.md", b"# Minigrep\n\nSearch for a pattern, like grep.\n"),
+            ("docs/german.txt", "STRASSE\nstra\u{df}e\noﬀset\n".as_bytes()),
+            ("utf16.txt", b"\xff\xfeU\0T\0F\0-\x001\x006\0 \0t\0e\0x\0t\0\n\0"),
+            ("bin.dat", b"binary\0config\n"),
+            ("people.csv", b"id,name\n1,\"Doe \"\"Jr\"\"\"\n"),
+        ]);
+        age(&root, &FILES);
+        root
+    }
+
+    #[test]
+    fn same_as_brute_force() {
+        let root = make_repo("index-same");
+        let update = index::update(&root).unwrap();
+        assert_eq!(Update { files : 7, read : 7, removed : 0 }, update);
+
+        compare(&root, &[
+            &["config"], &["-n", "Config"], &["-i", "CONFIG"], &["-w", "main"], &["-x", "}"],
+            &["-c", "config"], &["-l", "grep"], &["-L", "grep"], &["--json", "pattern"],
+            &["-o", "Config::new"], &["-v", "config"], &["-e", "struct", "-e", "readme"],
+            &["missing"], &["-E", r"fn \w+\(\)"], &["-E", "(Conf|Mini)(ig|grep)"],
+            &["-iE", "str[aä]sse"], &["-i", "stra\u{df}e"], &["-i", "offset"], &["utf-16"],
+            &["-E", "pub [a-z]+ : String"], &["-UE", r"config =.*\n\}"], &["--fuzzy", "cfg"],
+            &["--binary-files=text", "config"], &["--field=name", "Doe \"Jr"],
+        ]);
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn pruned_files() {
+        let root = make_repo("index-pruned");
+        index::update(&root).unwrap();
+        let candidates = Candidates::new(Index::open(&root).unwrap(),
+                                         &Query::Text("config".to_string()));
+        let skipped : Vec<_> = FILES.iter().filter(|path| candidates.skips(path, &root.join(path)))
+                                    .collect();
+        // Binary files are not indexed, and the UTF-16 one is indexed once transcoded.
+        assert_eq!(vec![&"docs/readme.md", &"docs/german.txt", &"utf16.txt", &"people.csv"],
+                   skipped);
+
+        // Queries without a long enough text do not prune anything.
+        let candidates = Candidates::new(Index::open(&root).unwrap(),
+                                         &Query::Text("fn".to_string()));
+        assert!(FILES.iter().all(|path| !candidates.skips(path, &root.join(path))));
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn incremental_updates() {
+        let root = make_repo("index-update");
+        index::update(&root).unwrap();
+
+        // Changed and new files are searched, even before the index is updated.
+        fs::write(root.join("docs/readme.md"), "Configure with --config.\n").unwrap();
+        fs::write(root.join("src/new.rs"), "use config::Config;\n").unwrap();
+        fs::remove_file(root.join("src/config.rs")).unwrap();
+        let searches : &[&[&str]] = &[&["config"], &["-l", "Config"], &["-c", "grep"]];
+        compare(&root, searches);
+
+        // Only those are read again, and files changed in the second the index is built are
+        // read once more on the next update.
+        let update = index::update(&root).unwrap();
+        assert_eq!(Update { files : 7, read : 2, removed : 1 }, update);
+        compare(&root, searches);
+        age(&root, &["docs/readme.md", "src/new.rs"]);
+        assert_eq!(Update { files : 7, read : 2, removed : 0 }, index::update(&root).unwrap());
+        assert_eq!(Update { files : 7, read : 0, removed : 0 }, index::update(&root).unwrap());
+        compare(&root, searches);
+
+        // The index itself is never searched.
+        assert_eq!((String::new(), 1), run(&["-r", "minigrep-index", root.to_str().unwrap()]));
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn missing_index() {
+        let root = make_tree("index-missing", &[("a.txt", b"a\n")]);
+        let args = ["minigrep", "-r", "--indexed", "a", root.to_str().unwrap()];
+        let args : Vec<_> = args.iter().map(|arg| arg.to_string()).collect();
+        let config = Config::new(&args).unwrap();
+        let err = run_to(&config, &mut vec![]).unwrap_err();
+        let index = format!("{}: ", root.join(".minigrep-index").display());
+        assert!(err.to_string().starts_with(&index), "{}", err);
+
+        // Nor is a file indexed.
+        assert!(index::update(&root.join("a.txt")).is_err());
+        fs::remove_dir_all(&root).unwrap();
+    }
+}
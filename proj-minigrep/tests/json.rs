@@ -0,0 +1,82 @@
+mod common;
+
+#[cfg(test)]
+mod tests {
+
+    use minigrep::*;
+
+    use super::common;
+
+    fn to_args(list : &[&str]) -> Vec<String> {
+        let mut args = vec!["minigrep".to_string()];
+        args.extend(list.iter().map(|arg| arg.to_string()));
+        args
+    }
+
+    fn gen_content() -> &'static str {
+        "\
+I'm nobody! Who are you?
+Are you nobody, too?
+Then there's a pair of us - don't tell!
+They'd banish us, you know.
+
+How dreary to be somebody!
+How public, like a frog.
+To tell your name the livelong day.
+To an admiring bog!"
+    }
+
+    // Compare with a golden file of `tests/golden`, line by line for readable failures.
+    fn assert_golden(golden : &str, output : &str) {
+        for (i, (expected, actual)) in golden.lines().zip(output.lines()).enumerate() {
+            assert_eq!(expected, actual, "line {} differs", i + 1);
+        }
+        assert_eq!(golden.lines().count(), output.lines().count(), "line counts differ");
+    }
+
+    #[test]
+    fn golden_poem() {
+        let args = to_args(&["--json", "-C1", "-e", "frog", "-e", "bog", "-e", "nobody"]);
+        let config = Config::new(&args).unwrap();
+        let mut out = vec![];
+        run_reader(&config, "poem.txt", gen_content().as_bytes(), &mut out).unwrap();
+        assert_golden(include_str!("golden/poem.jsonl"), &String::from_utf8(out).unwrap());
+    }
+
+    #[test]
+    fn golden_no_match() {
+        let args = to_args(&["--json", "notsuchphrase"]);
+        let config = Config::new(&args).unwrap();
+        let mut out = vec![];
+        run_reader(&config, "poem.txt", gen_content().as_bytes(), &mut out).unwrap();
+        assert_golden(include_str!("golden/no_match.jsonl"), &String::from_utf8(out).unwrap());
+    }
+
+    #[test]
+    fn golden_tree() {
+        let root = common::make_tree("json", &[
+            ("a.txt", "say \"hi\"\ttwice: hi\n\\hi\\\n".as_bytes()),
+            ("b.txt", b"nothing here\n"),
+            ("c/d.txt", "Здравствуйте, hi!\r\n".as_bytes()),
+        ]);
+        let root_name = root.display().to_string();
+        let args = to_args(&["--json", "-r", "-w", "hi", &root_name]);
+        let config = Config::new(&args).unwrap();
+        let mut out = vec![];
+        run_to(&config, &mut out).unwrap();
+
+        // Paths are made relative to the tree, to be the same on every machine.
+        let output = String::from_utf8(out).unwrap().replace(&root_name, "ROOT");
+        assert_golden(include_str!("golden/tree.jsonl"), &output);
+    }
+
+    #[test]
+    fn inverted_lines_have_no_submatches() {
+        let args = to_args(&["--json", "-v", "o"]);
+        let config = Config::new(&args).unwrap();
+        let mut out = vec![];
+        run_reader(&config, "poem.txt", gen_content().as_bytes(), &mut out).unwrap();
+        let output = String::from_utf8(out).unwrap();
+        assert!(output.contains(r#""line_number":5,"offset":114,"line":"","submatches":[]}"#));
+    }
+}
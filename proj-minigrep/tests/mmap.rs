@@ -0,0 +1,145 @@
+mod common;
+
+#[cfg(test)]
+mod tests {
+
+    use std::fs::{self, File, OpenOptions};
+    use std::io::{self, BufReader};
+    use std::path::Path;
+
+    use minigrep::*;
+    use crate::common::*;
+
+    // Larger than the files mapped with the `mmap` feature need to be.
+    const LARGE : usize = 1 << 20;
+
+    // Sink keeping the lines, as text.
+    fn collect(found : &mut Vec<String>) -> impl FnMut(&Path, Line) -> io::Result<()> + '_ {
+        move |_, line| {
+            found.push(format!("{:?}", line));
+            Ok(())
+        }
+    }
+
+    // The selected lines and context of `path`, searched by path then read.
+    fn both_ways(options : &SearchOptions, path : &Path) -> (Vec<String>, Vec<String>) {
+        let searcher = options.build().unwrap();
+        let mut by_path = vec![];
+        searcher.search_path(path, &mut collect(&mut by_path)).unwrap();
+        let mut read = vec![];
+        let reader = BufReader::new(File::open(path).unwrap());
+        searcher.search_reader(path, reader, &mut collect(&mut read)).unwrap();
+        (by_path, read)
+    }
+
+    // Lines numbered from 0, `frog` every 1000 of them, up to `len` bytes.
+    fn gen_lines(len : usize) -> String {
+        let mut text = String::new();
+        let mut i = 0;
+        while text.len() < len {
+            let word = if i % 1000 == 999 { "frog" } else { "toad" };
+            text.push_str(&format!("{} {}\r\n", i, word));
+            i += 1;
+        }
+        text
+    }
+
+    #[test]
+    fn large_files_same_results() {
+        let text = gen_lines(LARGE);
+        let utf16 : Vec<u8> = [0xff, 0xfe].iter().copied()
+            .chain(text.encode_utf16().flat_map(|unit| unit.to_le_bytes()))
+            .collect();
+        let latin1 : Vec<u8> = text.replace("toad", "t\u{f4}ad").chars()
+                                                                 .map(|ch| ch as u8)
+                                                                 .collect();
+        let root = make_tree("mmap-same", &[
+            ("plain.txt", text.as_bytes()),
+            ("bom.txt", &[&b"\xef\xbb\xbf"[..], text.as_bytes()].concat()),
+            ("utf16.txt", &utf16),
+            ("latin1.txt", &latin1),
+            ("binary.txt", &[&b"\0"[..], text.as_bytes()].concat()),
+        ]);
+
+        let mut literal = SearchOptions::new();
+        literal.pattern("frog");
+        let mut context = SearchOptions::new();
+        context.pattern(r"9\d frog").mode(MatchMode::Regex).context(1, 2).max_count(Some(5));
+        let mut inverted = SearchOptions::new();
+        inverted.pattern("toad").invert(true).decompress(true);
+        let mut text_binary = SearchOptions::new();
+        text_binary.pattern("frog").binary_files(BinaryFiles::Text);
+        let mut multiline = SearchOptions::new();
+        multiline.pattern(r"ad\s+^\d+ frog").mode(MatchMode::Regex).multiline(true)
+                 .context(1, 0);
+
+        for name in &["plain.txt", "bom.txt", "utf16.txt", "latin1.txt", "binary.txt"] {
+            let all = [(&literal, false), (&context, false), (&inverted, false),
+                       (&text_binary, true), (&multiline, false)];
+            for &(options, as_text) in &all {
+                let (by_path, read) = both_ways(options, &root.join(name));
+                // Only whether a binary file matches is told, unless searched as text.
+                let binary = *name == "binary.txt" && !as_text;
+                assert_eq!(binary, by_path.is_empty(), "{} found {:?}", name, by_path.first());
+                assert_eq!(read, by_path, "{} searched differently", name);
+            }
+        }
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn small_and_special_files() {
+        let root = make_tree("mmap-small", &[("small.txt", b"frog\n"), ("empty.txt", b"")]);
+        let mut options = SearchOptions::new();
+        options.pattern("frog");
+        assert_eq!(1, both_ways(&options, &root.join("small.txt")).0.len());
+        assert_eq!(0, both_ways(&options, &root.join("empty.txt")).0.len());
+        if Path::new("/dev/null").exists() {
+            assert_eq!(0, both_ways(&options, Path::new("/dev/null")).0.len());
+        }
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn truncated_during_search() {
+        let root = make_tree("mmap-truncated", &[("cut.txt", gen_lines(4 * LARGE).as_bytes())]);
+        let path = root.join("cut.txt");
+        let searcher = SearchOptions::new().pattern("frog").build().unwrap();
+
+        // Truncate the file as soon as the first line is found.
+        let mut found = vec![];
+        let result = searcher.search_path(&path, &mut |_ : &Path, line : Line| {
+            if let Line::Match(m) = line {
+                if found.is_empty() {
+                    OpenOptions::new().write(true).open(&path)?.set_len(1000)?;
+                }
+                found.push(m.line_number);
+            }
+            Ok(())
+        });
+
+        // The lines found before are reported, and a mapped file tells it changed, rather than
+        // crashing the process.
+        assert_eq!(Some(&1000), found.first());
+        if cfg!(feature = "mmap") {
+            match result {
+                Err(MinigrepError::Io { path : Some(failed), err }) => {
+                    assert_eq!(path, failed);
+                    assert_eq!(io::ErrorKind::UnexpectedEof, err.kind());
+                },
+                _ => panic!("truncation not reported"),
+            }
+        } else {
+            assert!(result.is_ok());
+        }
+
+        // Searched again, it is a small file.
+        let mut count = 0;
+        searcher.search_path(&path, &mut |_ : &Path, _ : Line| {
+            count += 1;
+            Ok(())
+        }).unwrap();
+        assert_eq!(0, count);
+        fs::remove_dir_all(&root).unwrap();
+    }
+}
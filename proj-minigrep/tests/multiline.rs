@@ -0,0 +1,143 @@
+#[cfg(test)]
+mod tests {
+
+    use minigrep::*;
+
+    fn gen_source() -> &'static str {
+        "\
+fn parse(input : &str,
+         strict : bool) -> Config {
+    todo!()
+}
+
+fn main() {
+    parse(\"x\", true);
+}
+"
+    }
+
+    // Run the command line behavior over the source, as if it were read from a file.
+    fn run_source(args : &[&str]) -> String {
+        let mut argv = vec!["minigrep".to_string()];
+        argv.extend(args.iter().map(|arg| arg.to_string()));
+        let config = Config::new(&argv).unwrap();
+
+        let mut out = vec![];
+        run_reader(&config, "lib.rs", gen_source().as_bytes(), &mut out).unwrap();
+        String::from_utf8(out).unwrap()
+    }
+
+    // The lines reported for a multi-line regex, as `number:line` or `number-line`.
+    fn search(pattern : &str, options : LineOptions) -> Vec<String> {
+        let regex = regex::Regex::new(pattern).unwrap().multi_line(true);
+        let mut found = vec![];
+        search_multiline(&matcher::Matcher::Regex(regex), gen_source(), options, |line| {
+            found.push(match line {
+                Line::Match(m) => format!("{}:{}", m.line_number, m.line),
+                Line::Context { line_number, line, .. } => format!("{}-{}", line_number, line),
+                Line::Break => "--".to_string(),
+            });
+            Ok(())
+        }).unwrap();
+        found
+    }
+
+    #[test]
+    fn anchors() {
+        let regex = regex::Regex::new("^b").unwrap();
+        assert_eq!(Vec::<(usize, usize)>::new(), regex.find_iter("a\nb\r\nb"));
+        assert_eq!(vec![(2, 3), (5, 6)], regex.multi_line(true).find_iter("a\nb\r\nb"));
+
+        let regex = regex::Regex::new("a$").unwrap();
+        assert_eq!(vec![(5, 6)], regex.find_iter("a\r\na\na"));
+        assert_eq!(vec![(0, 1), (3, 4), (5, 6)], regex.multi_line(true).find_iter("a\r\na\na"));
+
+        // `.` stays within a line, unlike `\s`.
+        let regex = regex::Regex::new(r"a.b|c\sd").unwrap().multi_line(true);
+        assert_eq!(vec![(5, 8)], regex.find_iter("a\nb, c\nd"));
+
+        assert_eq!(r"a\.b\(\)\[c\]\{2\}\*\+\?\|\^\$\\", regex::escape(r"a.b()[c]{2}*+?|^$\"));
+    }
+
+    #[test]
+    fn blocks() {
+        let regex = regex::Regex::new(r"str,\s+strict").unwrap().multi_line(true);
+        let matcher = matcher::Matcher::Regex(regex);
+        let mut found = vec![];
+        search_multiline(&matcher, gen_source(), LineOptions::default(), |line| {
+            if let Line::Match(m) = line {
+                found.push((m.line_number, m.end_line_number(), m.offset, m.line.to_string(),
+                            m.spans));
+            }
+            Ok(())
+        }).unwrap();
+        let block = "fn parse(input : &str,\n         strict : bool) -> Config {";
+        assert_eq!(vec![(1, 2, 0, block.to_string(), vec![(18, 38)])], found);
+
+        // Matches sharing a line make up a single block.
+        let all = LineOptions::default();
+        let block = "1:fn parse(input : &str,\n         strict : bool) -> Config {\n    todo!()";
+        assert_eq!(vec![block], search(r"input : &str,\n\s+strict|Config \{\n\s+todo", all));
+        // An empty match at the very end is past the last line.
+        assert_eq!(8, search("$", all).len());
+    }
+
+    #[test]
+    fn context_and_invert() {
+        let options = LineOptions { before : 1, after : 1, ..LineOptions::default() };
+        assert_eq!(vec!["1:fn parse(input : &str,\n         strict : bool) -> Config {",
+                        "3-    todo!()",
+                        "--",
+                        "5-",
+                        "6:fn main() {\n    parse(\"x\", true);",
+                        "8-}"],
+                   search(r"\(input[^)]*\)|main\(\) \{\n\s+parse", options));
+
+        let options = LineOptions { invert : true, ..LineOptions::default() };
+        assert_eq!(vec!["4:}", "5:", "8:}"],
+                   search(r"fn \w+\([^)]*\)[^{]*\{\n.*", options));
+
+        let options = LineOptions { max_count : Some(1), after : 1, ..LineOptions::default() };
+        assert_eq!(vec!["2:         strict : bool) -> Config {\n    todo!()", "4-}"],
+                   search(r"Config \{\n\s+todo|main", options));
+    }
+
+    #[test]
+    fn function_signature() {
+        let pattern = r"fn parse\(input : &str,\s+strict";
+        assert_eq!("1:fn parse(input : &str,\n2:         strict : bool) -> Config {\n",
+                   run_source(&["-nUE", pattern]));
+        assert_eq!("", run_source(&["-nE", pattern]));
+
+        // Each line of the block is counted, and gets its offset.
+        assert_eq!("2\n", run_source(&["-cUE", pattern]));
+        assert_eq!("0:fn parse(input : &str,\n23:         strict : bool) -> Config {\n",
+                   run_source(&["-bUE", pattern]));
+
+        // Only the parts of the lines matched are highlighted.
+        assert_eq!("fn parse(input : &\x1b[1;31mstr,\x1b[0m\n\
+                    \x1b[1;31m         strict\x1b[0m : bool) -> Config {\n",
+                   run_source(&["-UE", "--color=always", r"str,\s+strict"]));
+
+        // A matched part is numbered after the line it starts on.
+        assert_eq!("2:Config {\n    todo\n", run_source(&["-onUE", r"Config \{\s+todo"]));
+    }
+
+    #[test]
+    fn literals_and_anchors() {
+        // Literals may hold line feeds, and whole lines are lines of the text.
+        assert_eq!("3:    todo!()\n4:}\n", run_source(&["-nU", "todo!()\n}"]));
+        assert_eq!("4:}\n8:}\n", run_source(&["-nUx", "}"]));
+        assert_eq!("4:}\n5:\n6:fn main() {\n", run_source(&["-nUE", r"^\}\n^$\n^fn"]));
+    }
+
+    #[test]
+    fn json() {
+        let out = run_source(&["--json", "-UE", r"\{\n\s+todo"]);
+        let event = out.lines().nth(1).unwrap();
+        let start = r#"{"type":"match","path":"lib.rs","line_number":2,"end_line_number":3,"#;
+        assert!(event.starts_with(start), "{}", event);
+        let out = run_source(&["--json", "-UE", "todo"]);
+        assert!(!out.contains("end_line_number"));
+    }
+}
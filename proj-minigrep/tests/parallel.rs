@@ -0,0 +1,102 @@
+mod common;
+
+#[cfg(test)]
+mod tests {
+
+    use std::fs;
+    use std::path::Path;
+
+    use minigrep::*;
+    use crate::common::*;
+
+    fn search(args : &[&str]) -> (String, bool) {
+        let mut argv = vec!["minigrep".to_string()];
+        argv.extend(args.iter().map(|arg| arg.to_string()));
+        let config = Config::new(&argv).unwrap();
+
+        let mut out = vec![];
+        let ok = run_to(&config, &mut out).is_ok_and(|report| report.errors.is_empty());
+        (String::from_utf8(out).unwrap(), ok)
+    }
+
+    // Files of very different sizes, so that workers finish them out of order.
+    fn make_files(name : &str) -> std::path::PathBuf {
+        let contents : Vec<(String, Vec<u8>)> = (0..40).map(|i| {
+            let lines = if i % 3 == 0 { 3000 } else { 2 };
+            let mut text = String::new();
+            for j in 0..lines {
+                text.push_str(&format!("file {} line {}{}\n", i, j, if j % 1000 == 1 { " needle" } else { "" }));
+            }
+            (format!("dir{}/f{:02}.txt", i % 4, i), text.into_bytes())
+        }).collect();
+        let entries : Vec<(&str, &[u8])> = contents.iter()
+                                                   .map(|(path, text)| (path.as_str(), text.as_slice()))
+                                                   .collect();
+        make_tree(name, &entries)
+    }
+
+    #[test]
+    fn recursive_order_independent_of_threads() {
+        let root = make_files("parallel-recursive");
+        let root_str = root.to_str().unwrap();
+
+        let (serial, ok) = search(&["-rn", "-j", "1", "needle", root_str]);
+        assert!(ok);
+        assert_eq!(14 * 3 + 26, serial.lines().count());
+
+        // Grouped per file, in walk order.
+        let first = format!("{}:2:file 0 line 1 needle", Path::new(root_str).join("dir0/f00.txt").display());
+        assert_eq!(Some(first.as_str()), serial.lines().next());
+
+        for threads in &["2", "3", "8", "0"] {
+            for _ in 0..3 {
+                assert_eq!((serial.clone(), true), search(&["-rn", "-j", threads, "needle", root_str]));
+            }
+        }
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn operand_order_is_kept() {
+        let root = make_tree("parallel-operands", &[
+            ("a.txt", b"needle in a\n"),
+            ("b.txt", b"no match\n"),
+            ("c.txt", b"needle in c\nneedle again\n"),
+        ]);
+        let path = |name : &str| root.join(name).to_str().unwrap().to_string();
+        let (a, b, c) = (path("a.txt"), path("b.txt"), path("c.txt"));
+
+        let expected = format!("{}:needle in c\n{}:needle again\n{}:needle in a\n", c, c, a);
+        for threads in &["1", "4"] {
+            assert_eq!((expected.clone(), true), search(&["-j", threads, "needle", &c, &b, &a]));
+        }
+
+        let expected = format!("{}:1\n{}:0\n{}:2\n", a, b, c);
+        assert_eq!((expected, true), search(&["-c", "-j4", "needle", &a, &b, &c]));
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn unreadable_input_does_not_stop_others() {
+        let root = make_tree("parallel-missing", &[
+            ("a.txt", b"needle\n"),
+            ("c.txt", b"needle\n"),
+        ]);
+        let path = |name : &str| root.join(name).to_str().unwrap().to_string();
+        let (a, b, c) = (path("a.txt"), path("missing.txt"), path("c.txt"));
+
+        let expected = format!("{}:needle\n{}:needle\n", a, c);
+        for threads in &["1", "4"] {
+            assert_eq!((expected.clone(), false), search(&["-j", threads, "needle", &a, &b, &c]));
+        }
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn single_file_has_no_prefix() {
+        let root = make_tree("parallel-single", &[("a.txt", b"needle\nhay\n")]);
+        let a = root.join("a.txt");
+        assert_eq!(("needle\n".to_string(), true), search(&["-j4", "needle", a.to_str().unwrap()]));
+        fs::remove_dir_all(&root).unwrap();
+    }
+}
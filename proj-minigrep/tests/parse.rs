@@ -1,8 +1,12 @@
+mod common;
+
 #[cfg(test)]
 mod tests {
 
     use minigrep::*;
 
+    use super::common;
+
     #[test]
     fn correct_number() {
         let args = vec![
@@ -18,20 +22,524 @@ mod tests {
     fn too_few_args() {
         let args = vec![
             "minigrep".to_string(),
-            "lalalala".to_string(),
         ];
         Config::new(&args).unwrap();
     }
 
     #[test]
-    #[should_panic(expected = "Wrong number")]
-    fn too_many_args() {
+    fn many_files() {
         let args = vec![
             "minigrep".to_string(),
             "lalalala".to_string(),
             "hahahaha".to_string(),
             "fafafafa".to_string(),
         ];
-        Config::new(&args).unwrap();
+        assert_eq!(["hahahaha", "fafafafa"], Config::new(&args).unwrap().filenames());
+    }
+
+    #[test]
+    fn regex_mode() {
+        let args = vec![
+            "minigrep".to_string(),
+            "--regex".to_string(),
+            "pat+ern".to_string(),
+            "file-name.txt".to_string(),
+        ];
+        assert_eq!(MatchMode::Regex, Config::new(&args).unwrap().mode());
+    }
+
+    #[test]
+    fn literal_mode_by_default() {
+        let args = vec![
+            "minigrep".to_string(),
+            "pattern".to_string(),
+            "file-name.txt".to_string(),
+        ];
+        assert_eq!(MatchMode::Literal, Config::new(&args).unwrap().mode());
+    }
+
+    fn parse(list : &[&str]) -> Result<(), String> {
+        let args = to_args(list);
+        Config::new(&args).map(|_| ()).map_err(|err| err.to_string())
+    }
+
+    fn to_args(list : &[&str]) -> Vec<String> {
+        let mut args = vec!["minigrep".to_string()];
+        args.extend(list.iter().map(|arg| arg.to_string()));
+        args
+    }
+
+    #[test]
+    fn short_flags() {
+        let args = to_args(&["-i", "-n", "-c", "-v", "-r", "-E", "pattern", "file-name.txt"]);
+        let config = Config::new(&args).unwrap();
+        assert!(!config.sensitive());
+        assert!(config.line_number());
+        assert_eq!(OutputMode::Count, config.output());
+        assert!(config.invert());
+        assert!(config.recursive());
+        assert_eq!(MatchMode::Regex, config.mode());
+    }
+
+    #[test]
+    fn long_flags() {
+        let args = to_args(&["--ignore-case", "--line-number", "--count", "--invert-match",
+                             "--recursive", "--regex", "pattern", "file-name.txt"]);
+        let config = Config::new(&args).unwrap();
+        assert!(!config.sensitive());
+        assert!(config.line_number());
+        assert_eq!(OutputMode::Count, config.output());
+        assert!(config.invert());
+        assert!(config.recursive());
+        assert_eq!(MatchMode::Regex, config.mode());
+    }
+
+    #[test]
+    fn no_flags() {
+        let args = to_args(&["pattern", "file-name.txt"]);
+        let config = Config::new(&args).unwrap();
+        assert_eq!(["pattern"], config.patterns());
+        assert_eq!(["file-name.txt"], config.filenames());
+        assert!(!config.line_number());
+        assert_eq!(OutputMode::Lines, config.output());
+        assert!(!config.invert());
+        assert!(!config.recursive());
+        assert_eq!(Action::Search, config.action());
+    }
+
+    #[test]
+    fn combined_short_flags() {
+        let args = to_args(&["-inv", "pattern", "file-name.txt"]);
+        let config = Config::new(&args).unwrap();
+        assert!(!config.sensitive());
+        assert!(config.line_number());
+        assert!(config.invert());
+        assert_eq!(OutputMode::Lines, config.output());
+    }
+
+    #[test]
+    fn flags_after_positionals() {
+        let args = to_args(&["pattern", "file-name.txt", "-c"]);
+        let config = Config::new(&args).unwrap();
+        assert_eq!(OutputMode::Count, config.output());
+        assert_eq!(["file-name.txt"], config.filenames());
+    }
+
+    #[test]
+    fn double_dash_ends_options() {
+        let args = to_args(&["-n", "--", "-v", "--count"]);
+        let config = Config::new(&args).unwrap();
+        assert_eq!(["-v"], config.patterns());
+        assert_eq!(["--count"], config.filenames());
+        assert!(config.line_number());
+        assert!(!config.invert());
+    }
+
+    #[test]
+    fn single_dash_is_positional() {
+        let args = to_args(&["pattern", "-"]);
+        assert_eq!(["-"], Config::new(&args).unwrap().filenames());
+    }
+
+    #[test]
+    fn help_and_version() {
+        for (flag, action) in &[("-h", Action::Help), ("--help", Action::Help),
+                                ("-V", Action::Version), ("--version", Action::Version)] {
+            let args = to_args(&[flag]);
+            assert_eq!(*action, Config::new(&args).unwrap().action());
+        }
+
+        // Help short-circuits whatever follows, even in a cluster.
+        let args = to_args(&["-nh", "--bogus"]);
+        assert_eq!(Action::Help, Config::new(&args).unwrap().action());
+        assert!(USAGE.contains("--ignore-case"));
+        assert!(version().starts_with("minigrep "));
+    }
+
+    #[test]
+    fn unknown_long_option() {
+        assert_eq!(Err("Unknown option '--colour'.".to_string()),
+                   parse(&["--colour", "pattern", "file-name.txt"]));
+    }
+
+    #[test]
+    fn unknown_short_option() {
+        assert_eq!(Err("Unknown option '-y'.".to_string()),
+                   parse(&["-y", "pattern", "file-name.txt"]));
+        assert_eq!(Err("Unknown option '-q' in '-nqv'.".to_string()),
+                   parse(&["-nqv", "pattern", "file-name.txt"]));
+    }
+
+    #[test]
+    fn flag_with_value() {
+        assert_eq!(Err("Option '--count' does not take a value.".to_string()),
+                   parse(&["--count=3", "pattern", "file-name.txt"]));
+    }
+
+    #[test]
+    fn missing_positionals() {
+        assert_eq!(Err("Wrong number of arguments: missing PATTERN.".to_string()),
+                   parse(&["-i"]));
+    }
+
+    #[test]
+    fn context_options() {
+        let args = to_args(&["-A", "2", "-B3", "pattern", "file-name.txt"]);
+        assert_eq!((3, 2), Config::new(&args).unwrap().context());
+
+        let args = to_args(&["--context=4", "--after-context", "1", "pattern", "file-name.txt"]);
+        assert_eq!((4, 1), Config::new(&args).unwrap().context());
+
+        // `-C` given after `-A` still does not override it.
+        let args = to_args(&["-nA1", "-C", "5", "pattern", "file-name.txt"]);
+        let config = Config::new(&args).unwrap();
+        assert_eq!((5, 1), config.context());
+        assert!(config.line_number());
+
+        let args = to_args(&["pattern", "file-name.txt"]);
+        assert_eq!((0, 0), Config::new(&args).unwrap().context());
+    }
+
+    #[test]
+    fn byte_offset() {
+        let args = to_args(&["-bn", "pattern", "file-name.txt"]);
+        assert!(Config::new(&args).unwrap().byte_offset());
+        let args = to_args(&["--byte-offset", "pattern", "file-name.txt"]);
+        assert!(Config::new(&args).unwrap().byte_offset());
+    }
+
+    #[test]
+    fn bad_option_values() {
+        assert_eq!(Err("Option '-A' requires a value.".to_string()),
+                   parse(&["pattern", "file-name.txt", "-A"]));
+        assert_eq!(Err("Option '--context' requires a value.".to_string()),
+                   parse(&["pattern", "file-name.txt", "--context"]));
+        assert_eq!(Err("Invalid value 'two' for '-B': expected a non-negative number.".to_string()),
+                   parse(&["-B", "two", "pattern", "file-name.txt"]));
+        assert_eq!(Err("Invalid value '-1' for '--context': expected a non-negative number.".to_string()),
+                   parse(&["--context=-1", "pattern", "file-name.txt"]));
+    }
+
+    #[test]
+    fn default_input() {
+        // Standard input when FILE is omitted, the working directory when recursive.
+        let args = to_args(&["pattern"]);
+        assert_eq!(["-"], Config::new(&args).unwrap().filenames());
+        let args = to_args(&["-r", "pattern"]);
+        assert_eq!(["."], Config::new(&args).unwrap().filenames());
+        let args = to_args(&["-r", "pattern", "-"]);
+        assert_eq!(["-"], Config::new(&args).unwrap().filenames());
+    }
+
+    #[test]
+    fn threads() {
+        let args = to_args(&["-j", "4", "pattern", "a", "b"]);
+        assert_eq!(4, Config::new(&args).unwrap().threads());
+        let args = to_args(&["--threads=1", "pattern"]);
+        assert_eq!(1, Config::new(&args).unwrap().threads());
+        let args = to_args(&["-cj8", "pattern"]);
+        assert_eq!(8, Config::new(&args).unwrap().threads());
+        let args = to_args(&["pattern"]);
+        assert_eq!(0, Config::new(&args).unwrap().threads());
+        assert_eq!(Err("Invalid value 'many' for '-j': expected a non-negative number.".to_string()),
+                   parse(&["-j", "many", "pattern"]));
+    }
+
+    #[test]
+    fn output_modes() {
+        for (flag, mode) in &[("-c", OutputMode::Count), ("--count", OutputMode::Count),
+                              ("-l", OutputMode::FilesWithMatches),
+                              ("--files-with-matches", OutputMode::FilesWithMatches),
+                              ("-L", OutputMode::FilesWithoutMatch),
+                              ("--files-without-match", OutputMode::FilesWithoutMatch),
+                              ("-o", OutputMode::OnlyMatching),
+                              ("--only-matching", OutputMode::OnlyMatching)] {
+            let args = to_args(&[flag, "pattern"]);
+            assert_eq!(*mode, Config::new(&args).unwrap().output());
+        }
+
+        // The last output mode given wins.
+        let args = to_args(&["-cl", "-o", "pattern"]);
+        assert_eq!(OutputMode::OnlyMatching, Config::new(&args).unwrap().output());
+    }
+
+    #[test]
+    fn max_count() {
+        let args = to_args(&["-m", "3", "pattern"]);
+        assert_eq!(Some(3), Config::new(&args).unwrap().max_count());
+        let args = to_args(&["--max-count=0", "pattern"]);
+        assert_eq!(Some(0), Config::new(&args).unwrap().max_count());
+        let args = to_args(&["pattern"]);
+        assert_eq!(None, Config::new(&args).unwrap().max_count());
+    }
+
+    #[test]
+    fn color() {
+        let args = to_args(&["pattern"]);
+        assert_eq!(ColorChoice::Auto, Config::new(&args).unwrap().color());
+        let args = to_args(&["--color=always", "pattern"]);
+        assert_eq!(ColorChoice::Always, Config::new(&args).unwrap().color());
+        let args = to_args(&["--color", "never", "pattern"]);
+        assert_eq!(ColorChoice::Never, Config::new(&args).unwrap().color());
+        assert_eq!(Err("Invalid value 'red' for '--color': expected auto, always or never."
+                       .to_string()),
+                   parse(&["--color=red", "pattern"]));
+
+        // Auto only colors terminals; explicit choices ignore where the output goes.
+        assert!(!ColorChoice::Auto.enabled(false));
+        assert!(ColorChoice::Always.enabled(false));
+        assert!(!ColorChoice::Never.enabled(true));
+    }
+
+    #[test]
+    fn multiple_patterns() {
+        let args = to_args(&["-e", "one", "--regexp=two", "-e-v", "file"]);
+        let config = Config::new(&args).unwrap();
+        assert_eq!(["one", "two", "-v"], config.patterns());
+        assert_eq!(["file"], config.filenames());
+
+        // With `-e`, there may be no positional at all.
+        let args = to_args(&["-e", "one"]);
+        assert_eq!(["-"], Config::new(&args).unwrap().filenames());
+        assert_eq!(Err("Option '-e' requires a value.".to_string()), parse(&["pattern", "-e"]));
+    }
+
+    #[test]
+    fn pattern_file() {
+        let root = common::make_tree("pattern-file", &[
+            ("patterns.txt", b"first\r\nsecond\n\nfourth\n"),
+            ("empty.txt", b""),
+        ]);
+        let patterns = root.join("patterns.txt").display().to_string();
+        let args = to_args(&["-e", "zeroth", "-f", &patterns, "file"]);
+        let config = Config::new(&args).unwrap();
+        assert_eq!(["zeroth", "first", "second", "", "fourth"], config.patterns());
+        assert_eq!(["file"], config.filenames());
+
+        // An empty pattern file gives no pattern, and the positionals are still files.
+        let empty = root.join("empty.txt").display().to_string();
+        let args = to_args(&["-f", &empty, "file"]);
+        let config = Config::new(&args).unwrap();
+        assert!(config.patterns().is_empty());
+        assert_eq!(["file"], config.filenames());
+
+        let missing = root.join("missing.txt").display().to_string();
+        match Config::new(&to_args(&["-f", &missing])) {
+            Err(MinigrepError::Io { path : Some(path), .. }) => assert_eq!(missing, path.display().to_string()),
+            _ => panic!("missing pattern file not reported"),
+        }
+    }
+
+    #[test]
+    fn boundaries() {
+        let args = to_args(&["pattern"]);
+        assert_eq!(Boundary::Anywhere, Config::new(&args).unwrap().boundary());
+        let args = to_args(&["-w", "pattern"]);
+        assert_eq!(Boundary::Word, Config::new(&args).unwrap().boundary());
+        let args = to_args(&["--line-regexp", "pattern"]);
+        assert_eq!(Boundary::Line, Config::new(&args).unwrap().boundary());
+
+        // Whole lines win over whole words, whatever the order.
+        let args = to_args(&["-xw", "pattern"]);
+        assert_eq!(Boundary::Line, Config::new(&args).unwrap().boundary());
+    }
+
+    #[test]
+    fn fuzzy() {
+        let args = to_args(&["--fuzzy", "pattern"]);
+        assert_eq!(MatchMode::Fuzzy, Config::new(&args).unwrap().mode());
+        // The last mode given wins.
+        let args = to_args(&["--fuzzy", "-E", "pattern"]);
+        assert_eq!(MatchMode::Regex, Config::new(&args).unwrap().mode());
+
+        let error = Err("Option '--fuzzy' cannot be combined with '-w', '-x', '-v', context, or \
+                         '--replace'.".to_string());
+        for flag in ["-w", "-x", "-v", "-C1", "--replace=x"] {
+            assert_eq!(error, parse(&["--fuzzy", flag, "pattern"]));
+        }
+        assert!(parse(&["--fuzzy", "-c", "-m3", "pattern"]).is_ok());
+    }
+
+    #[test]
+    fn replace() {
+        let args = to_args(&["pattern"]);
+        let config = Config::new(&args).unwrap();
+        assert_eq!(None, config.replacement());
+        assert!(!config.in_place() && !config.dry_run());
+
+        let args = to_args(&["--replace", "$1", "--in-place", "pattern", "file"]);
+        let config = Config::new(&args).unwrap();
+        assert_eq!(Some("$1"), config.replacement());
+        assert!(config.in_place());
+        let args = to_args(&["--replace=", "--dry-run", "pattern"]);
+        let config = Config::new(&args).unwrap();
+        assert_eq!(Some(""), config.replacement());
+        assert!(config.dry_run());
+
+        assert_eq!(Err("Option '--in-place' requires '--replace'.".to_string()),
+                   parse(&["--in-place", "pattern"]));
+        assert_eq!(Err("Option '--dry-run' requires '--replace'.".to_string()),
+                   parse(&["--dry-run", "pattern"]));
+        assert_eq!(Err("Options '--in-place' and '--dry-run' cannot be combined with another \
+                        output mode.".to_string()),
+                   parse(&["--replace=x", "--in-place", "-c", "pattern"]));
+        let error = Err("Options '--in-place' and '--dry-run' cannot be combined with '-v'."
+                        .to_string());
+        assert_eq!(error, parse(&["-v", "--replace=x", "--in-place", "pattern", "file"]));
+        assert_eq!(error, parse(&["--invert-match", "--replace=x", "--dry-run", "pattern"]));
+        assert_eq!(Err("Option '--replace' cannot be combined with '--json'.".to_string()),
+                   parse(&["--json", "--replace=x", "pattern"]));
+        assert!(parse(&["-c", "--replace=x", "pattern"]).is_ok());
+    }
+
+    #[test]
+    fn search_zip() {
+        assert!(!Config::new(&to_args(&["pattern"])).unwrap().decompress());
+        assert!(Config::new(&to_args(&["-z", "pattern"])).unwrap().decompress());
+        assert!(Config::new(&to_args(&["--search-zip", "pattern"])).unwrap().decompress());
+    }
+
+    #[test]
+    fn binary_files() {
+        assert_eq!(None, Config::new(&to_args(&["pattern"])).unwrap().binary_files());
+        let args = to_args(&["--binary-files=text", "pattern"]);
+        assert_eq!(Some(BinaryFiles::Text), Config::new(&args).unwrap().binary_files());
+        let args = to_args(&["--binary-files", "without-match", "pattern"]);
+        assert_eq!(Some(BinaryFiles::WithoutMatch), Config::new(&args).unwrap().binary_files());
+        assert_eq!(Err("Invalid value 'skip' for '--binary-files': expected binary, text or \
+                        without-match.".to_string()),
+                   parse(&["--binary-files=skip", "pattern"]));
+    }
+
+    #[test]
+    fn filters() {
+        let args = to_args(&["-r", "--include=*.{rs,toml}", "--exclude", "target", "pattern"]);
+        let config = Config::new(&args).unwrap();
+        let filter = config.filter();
+        assert!(filter.allows("src/main.rs", || None));
+        assert!(filter.allows("Cargo.toml", || None));
+        assert!(!filter.allows("README.md", || None));
+        assert!(!filter.allows("target/debug/build.rs", || None));
+
+        let args = to_args(&["-tpy", "--type", "rust", "--max-filesize=1K", "pattern"]);
+        let config = Config::new(&args).unwrap();
+        let filter = config.filter();
+        assert!(filter.allows("setup.py", || Some(1024)));
+        assert!(filter.allows("lib.rs", || Some(10)));
+        assert!(!filter.allows("lib.rs", || Some(1025)));
+        assert!(!filter.allows("lib.c", || Some(10)));
+
+        assert_eq!(Err("Invalid value '*.{rs' for '--include': Unclosed brace at position 2."
+                       .to_string()),
+                   parse(&["--include=*.{rs", "pattern"]));
+        assert_eq!(Err("Invalid value 'cobol' for '-t': expected one of c, cpp, css, go, html, \
+                        java, js, json, markdown, py, rust, sh, toml, ts, txt, xml, yaml."
+                       .to_string()),
+                   parse(&["-t", "cobol", "pattern"]));
+        assert_eq!(Err("Invalid value '1T' for '--max-filesize': expected a size in bytes, with \
+                        an optional K, M or G suffix.".to_string()),
+                   parse(&["--max-filesize=1T", "pattern"]));
+    }
+
+    #[test]
+    fn multiline() {
+        assert!(!Config::new(&to_args(&["pattern"])).unwrap().multiline());
+        assert!(Config::new(&to_args(&["-UE", "a\\nb"])).unwrap().multiline());
+        let args = to_args(&["--multiline", "-C2", "-v", "pattern"]);
+        assert!(Config::new(&args).unwrap().multiline());
+
+        let error = Err("Option '--multiline' cannot be combined with '--fuzzy', '--follow', \
+                         '--in-place' or '--dry-run'.".to_string());
+        assert_eq!(error, parse(&["-U", "--fuzzy", "pattern"]));
+        assert_eq!(error, parse(&["-U", "--follow", "pattern", "file"]));
+        assert_eq!(error, parse(&["-U", "--replace=x", "--in-place", "pattern", "file"]));
+        assert_eq!(error, parse(&["-U", "--replace=x", "--dry-run", "pattern", "file"]));
+    }
+
+    #[test]
+    fn fields() {
+        use minigrep::record::{Format, Selector};
+
+        let args = to_args(&["pattern"]);
+        let config = Config::new(&args).unwrap();
+        assert_eq!((None, 0, None), (config.field(), config.print_fields().len(), config.format()));
+
+        let args = to_args(&["--column", "3", "--print-fields=name,2", "--format=tsv", "pattern"]);
+        let config = Config::new(&args).unwrap();
+        assert_eq!(Some(&Selector::Column(3)), config.field());
+        assert_eq!(&[Selector::Name("name".to_string()), Selector::Column(2)],
+                   config.print_fields());
+        assert_eq!(Some(Format::Tsv), config.format());
+        let args = to_args(&["--field=host", "pattern"]);
+        let config = Config::new(&args).unwrap();
+        assert_eq!(Some(&Selector::Name("host".to_string())), config.field());
+
+        assert_eq!(Err("Invalid value '0' for '--column': columns are numbered from 1."
+                       .to_string()),
+                   parse(&["--column=0", "pattern"]));
+        assert_eq!(Err("Invalid value 'a,,b' for '--print-fields': expected field names or \
+                        positions from 1, separated by commas.".to_string()),
+                   parse(&["--print-fields=a,,b", "pattern"]));
+        assert_eq!(Err("Invalid value 'json' for '--format': expected csv, tsv or logfmt."
+                       .to_string()),
+                   parse(&["--field=a", "--format=json", "pattern"]));
+
+        let error = Err("Options '--field', '--column' and '--print-fields' cannot be combined \
+                         with '-U', '--fuzzy', '--follow' or '--replace'.".to_string());
+        assert_eq!(error, parse(&["--field=a", "-U", "pattern"]));
+        assert_eq!(error, parse(&["--print-fields=a", "--fuzzy", "pattern"]));
+        assert_eq!(error, parse(&["--column=1", "--replace=x", "pattern"]));
+        assert_eq!(Err("Option '--print-fields' cannot be combined with '-o'.".to_string()),
+                   parse(&["--print-fields=a", "-o", "pattern"]));
+        assert_eq!(Err("Option '--format' requires '--field', '--column' or '--print-fields'."
+                       .to_string()),
+                   parse(&["--format=csv", "pattern"]));
+    }
+
+    #[test]
+    fn cache() {
+        let args = to_args(&["pattern"]);
+        assert_eq!(None, Config::new(&args).unwrap().cache_dir());
+        let args = to_args(&["--cache-dir", "/tmp/c", "--cache-size=2M", "pattern"]);
+        let config = Config::new(&args).unwrap();
+        assert_eq!(Some(std::path::Path::new("/tmp/c")), config.cache_dir());
+        assert_eq!(2 << 20, config.cache_size());
+        let args = to_args(&["--cache-dir=/tmp/c", "pattern", "--no-cache"]);
+        assert_eq!(None, Config::new(&args).unwrap().cache_dir());
+
+        assert_eq!(Err("Invalid value '2X' for '--cache-size': expected a size in bytes, with an \
+                        optional K, M or G suffix.".to_string()),
+                   parse(&["--cache-size=2X", "pattern"]));
+        let error = Err("Option '--cache' cannot be combined with '--follow', '--watch', \
+                         '--in-place' or '--dry-run'.".to_string());
+        assert_eq!(error, parse(&["--cache", "--follow", "pattern", "file"]));
+        let args = ["--cache-dir=c", "--replace=x", "--in-place", "pattern", "file"];
+        assert_eq!(error, parse(&args));
+        assert_eq!(Ok(()), parse(&["--cache", "--watch", "--no-cache", "pattern", "file"]));
+    }
+
+    #[test]
+    fn index() {
+        let args = to_args(&["index"]);
+        let config = Config::new(&args).unwrap();
+        assert_eq!((Action::Index, &["."][..]), (config.action(), config.filenames()));
+        let args = to_args(&["index", "src", "docs"]);
+        let config = Config::new(&args).unwrap();
+        assert_eq!((Action::Index, &["src", "docs"][..]), (config.action(), config.filenames()));
+        assert_eq!(Err("Unknown option '-r': 'minigrep index' only takes directories.".to_string()),
+                   parse(&["index", "-r", "src"]));
+
+        // Only the first argument is the subcommand.
+        let args = to_args(&["-e", "index", "file"]);
+        assert_eq!(Action::Search, Config::new(&args).unwrap().action());
+        let args = to_args(&["-r", "index"]);
+        assert_eq!(&["index".to_string()], Config::new(&args).unwrap().patterns());
+
+        let args = to_args(&["-r", "--indexed", "pattern"]);
+        assert!(Config::new(&args).unwrap().indexed());
+        let error = Err("Option '--indexed' requires '-r', and cannot be combined with \
+                         '--watch'.".to_string());
+        assert_eq!(error, parse(&["--indexed", "pattern", "file"]));
+        assert_eq!(error, parse(&["-r", "--indexed", "--watch", "pattern"]));
     }
-}
\ No newline at end of file
+}
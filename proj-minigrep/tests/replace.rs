@@ -0,0 +1,116 @@
+mod common;
+
+#[cfg(test)]
+mod tests {
+
+    use std::fs;
+    use std::path::Path;
+
+    use minigrep::*;
+    use crate::common::*;
+
+    fn run(args : &[&str]) -> (String, bool) {
+        let mut argv = vec!["minigrep".to_string()];
+        argv.extend(args.iter().map(|arg| arg.to_string()));
+        let config = Config::new(&argv).unwrap();
+
+        let mut out = vec![];
+        let ok = run_to(&config, &mut out).is_ok_and(|report| report.errors.is_empty());
+        (String::from_utf8(out).unwrap(), ok)
+    }
+
+    fn read(path : &Path) -> String {
+        fs::read_to_string(path).unwrap()
+    }
+
+    #[test]
+    fn in_place() {
+        let root = make_tree("replace-in-place", &[
+            ("a.txt", b"colour\ncolor\r\nno match\ncolours"),
+            ("sub/b.txt", b"my colour\n"),
+            ("sub/untouched.txt", b"nothing here\n"),
+            ("sub/binary.dat", b"colour\0"),
+        ]);
+        let dir = root.display().to_string();
+        let (out, ok) = run(&["-r", "--in-place", "-E", "--replace=${1}or", "(col)our", &dir]);
+        assert!(ok);
+        assert_eq!("", out);
+
+        // Line terminators, including a missing last one, are kept.
+        assert_eq!("color\ncolor\r\nno match\ncolors", read(&root.join("a.txt")));
+        assert_eq!("my color\n", read(&root.join("sub/b.txt")));
+        assert_eq!("nothing here\n", read(&root.join("sub/untouched.txt")));
+        assert_eq!(b"colour\0", fs::read(root.join("sub/binary.dat")).unwrap().as_slice());
+
+        // No temporary file is left behind.
+        let names : Vec<_> = fs::read_dir(root.join("sub")).unwrap()
+                                                           .map(|entry| entry.unwrap().file_name())
+                                                           .collect();
+        assert_eq!(3, names.len());
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn in_place_through_link() {
+        let root = make_tree("replace-link", &[("target/a.txt", b"colour
+")]);
+        let link = root.join("link.txt");
+        std::os::unix::fs::symlink(root.join("target/a.txt"), &link).unwrap();
+        assert!(run(&["--in-place", "--replace=color", "colour", &link.display().to_string()]).1);
+
+        // The link is kept, and the file it points to edited, with no temporary file left.
+        assert!(fs::symlink_metadata(&link).unwrap().file_type().is_symlink());
+        assert_eq!("color
+", read(&root.join("target/a.txt")));
+        assert_eq!(1, fs::read_dir(root.join("target")).unwrap().count());
+        assert_eq!(2, fs::read_dir(&root).unwrap().count());
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn in_place_max_count() {
+        let root = make_tree("replace-max-count", &[("a.txt", b"x\nx\nx\n")]);
+        let file = root.join("a.txt").display().to_string();
+        assert!(run(&["-m2", "--in-place", "--replace=y", "x", &file]).1);
+        assert_eq!("y\ny\nx\n", read(&root.join("a.txt")));
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn dry_run() {
+        let root = make_tree("replace-dry-run", &[
+            ("a.txt", b"one\ntwo\nthree\n"),
+            ("b.txt", b"none\n"),
+        ]);
+        let a = root.join("a.txt").display().to_string();
+        let b = root.join("b.txt").display().to_string();
+        let (out, ok) = run(&["--dry-run", "--replace=2", "two", &a, &b]);
+        assert!(ok);
+        assert_eq!(
+            format!("--- {0}\n+++ {0}\n@@ -1,3 +1,3 @@\n one\n-two\n+2\n three\n", a),
+            out
+        );
+        // Nothing is written.
+        assert_eq!("one\ntwo\nthree\n", read(&root.join("a.txt")));
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn failures_do_not_stop_the_others() {
+        let root = make_tree("replace-failures", &[
+            ("a.txt", b"old\n"),
+            ("bad.txt", b"old \xff\n"),
+        ]);
+        let a = root.join("a.txt").display().to_string();
+        let bad = root.join("bad.txt").display().to_string();
+        let (_, ok) = run(&["--in-place", "--replace=new", "old", &bad, &a]);
+        assert!(!ok);
+        assert_eq!("new\n", read(&root.join("a.txt")));
+        assert_eq!(b"old \xff\n", fs::read(root.join("bad.txt")).unwrap().as_slice());
+
+        let (_, ok) = run(&["--in-place", "--replace=new", "old", "-"]);
+        assert!(!ok);
+        fs::remove_dir_all(&root).unwrap();
+    }
+}
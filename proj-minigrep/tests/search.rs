@@ -68,4 +68,749 @@ To an admiring bog!"
             search_insensitive("TO", gen_content())
         );
     }
+
+    fn regex_search(pattern : &str) -> Vec<&'static str> {
+        search_regex(&regex::Regex::new(pattern).unwrap(), gen_content())
+    }
+
+    #[test]
+    fn regex_literal() {
+        assert_eq!(
+            vec!["They'd banish us, you know."],
+            regex_search("banis")
+        );
+    }
+
+    #[test]
+    fn regex_dot_and_escape() {
+        assert_eq!(
+            vec!["To tell your name the livelong day."],
+            regex_search(r"d.y\.")
+        );
+        assert!(regex_search(r"frog\.\.").is_empty());
+    }
+
+    #[test]
+    fn regex_character_classes() {
+        assert_eq!(
+            vec!["I'm nobody! Who are you?", "Are you nobody, too?"],
+            regex_search("[Nn]ob[aeiou]dy")
+        );
+        assert_eq!(
+            vec!["Then there's a pair of us - don't tell!"],
+            regex_search(r"\s-\s")
+        );
+        assert_eq!(
+            vec!["How public, like a frog."],
+            regex_search(r"^[^a-z ]\w+ p")
+        );
+        assert!(regex_search(r"\d").is_empty());
+    }
+
+    #[test]
+    fn regex_anchors() {
+        assert_eq!(
+            vec!["How dreary to be somebody!", "How public, like a frog."],
+            regex_search("^How")
+        );
+        assert_eq!(
+            vec!["Then there's a pair of us - don't tell!", "To an admiring bog!"],
+            regex_search("g!$|l!$")
+        );
+        assert_eq!(
+            vec!["They'd banish us, you know."],
+            regex_search(r"\bus,")
+        );
+        assert_eq!(
+            vec!["I'm nobody! Who are you?", "Are you nobody, too?", "How dreary to be somebody!"],
+            regex_search(r"\Bbody")
+        );
+    }
+
+    #[test]
+    fn regex_alternation() {
+        assert_eq!(
+            vec!["How public, like a frog.", "To an admiring bog!"],
+            regex_search("frog|bog")
+        );
+    }
+
+    #[test]
+    fn regex_groups() {
+        assert_eq!(
+            vec!["How dreary to be somebody!", "To an admiring bog!"],
+            regex_search("(dr|adm)(ea|i)r")
+        );
+        assert_eq!(
+            vec!["To tell your name the livelong day."],
+            regex_search("(?:li(ve)){1}long")
+        );
+    }
+
+    #[test]
+    fn regex_quantifiers() {
+        assert_eq!(
+            vec!["To tell your name the livelong day."],
+            regex_search("tel+ ")
+        );
+        assert_eq!(
+            vec!["Then there's a pair of us - don't tell!", "To tell your name the livelong day."],
+            regex_search("l{2}")
+        );
+        assert_eq!(
+            vec!["How dreary to be somebody!"],
+            regex_search("^H.*y t")
+        );
+        assert_eq!(
+            vec!["Then there's a pair of us - don't tell!"],
+            regex_search("there'?s a")
+        );
+        assert!(regex_search("o{3,}").is_empty());
+    }
+
+    #[test]
+    fn regex_spans() {
+        let re = regex::Regex::new("a+?").unwrap();
+        assert_eq!(Some((0, 1)), re.find("aab"));
+        let re = regex::Regex::new("(a+)(b*)").unwrap();
+        assert_eq!(
+            Some(vec![Some((1, 5)), Some((1, 3)), Some((3, 5))]),
+            re.captures_at("xaabb", 0)
+        );
+        assert_eq!(vec![(0, 2), (3, 5)], regex::Regex::new("é|ü").unwrap().find_iter("éaü"));
+    }
+
+    #[test]
+    fn regex_insensitive() {
+        assert_eq!(
+            vec!["How dreary to be somebody!", "How public, like a frog."],
+            search_regex(&regex::Regex::new_insensitive("^hOW").unwrap(), gen_content())
+        );
+    }
+
+    #[test]
+    fn regex_errors() {
+        assert_eq!(1, regex::Regex::new("a(b").unwrap_err().pos);
+        assert_eq!(2, regex::Regex::new("ab)").unwrap_err().pos);
+        assert_eq!(0, regex::Regex::new("*a").unwrap_err().pos);
+        assert_eq!(1, regex::Regex::new("[z-a]").unwrap_err().pos);
+        assert!(regex::Regex::new(r"a\").is_err());
+
+        // Errors tell which of the patterns compiled together is in error.
+        let error = |patterns : &[&str]| {
+            regex::Regex::new_many(patterns, false, Boundary::Word).unwrap_err()
+        };
+        let err = error(&["a", "b(", "c)"]);
+        assert_eq!((1, 1), (err.pattern, err.pos));
+        let err = error(&["a", "b", "c)"]);
+        assert_eq!((2, 1), (err.pattern, err.pos));
+        let err = error(&["a", "(b{1000}){1000}", "c"]);
+        assert_eq!((1, "Pattern too large"), (err.pattern, err.msg));
+    }
+
+    #[test]
+    fn regex_limits() {
+        let error = |pattern : &str| regex::Regex::new(pattern).unwrap_err().msg;
+        assert_eq!("Pattern too large", error("((a{1000}){1000}){1000}"));
+        assert_eq!("Pattern too large", error("((a?){1000}){100}"));
+        let nested = format!("{}a{}", "(".repeat(50000), ")".repeat(50000));
+        assert_eq!("Pattern nested too deeply", error(&nested));
+        assert_eq!("Pattern nested too deeply", error(&format!("a{}", "*?".repeat(50000))));
+
+        // Long chains of empty transitions are followed without recursing.
+        let regex = regex::Regex::new("((a?){1000}){20}b").unwrap();
+        assert_eq!(Some((0, 4)), regex.find("aaab"));
+        let nested = format!("{}a{}", "(".repeat(200), ")".repeat(200));
+        assert!(regex::Regex::new(&nested).unwrap().is_match("a"));
+    }
+
+    fn context_search(query : &str, invert : bool, before : usize, after : usize) -> Vec<String> {
+        context_search_mode(query, MatchMode::Literal, invert, before, after)
+    }
+
+    fn context_search_regex(pattern : &str, before : usize, after : usize) -> Vec<String> {
+        context_search_mode(pattern, MatchMode::Regex, false, before, after)
+    }
+
+    fn context_search_mode(query : &str, mode : MatchMode, invert : bool, before : usize,
+                           after : usize) -> Vec<String> {
+        let matcher = matcher::Matcher::new(query, mode, true).unwrap();
+        let options = LineOptions { invert, before, after, ..LineOptions::default() };
+        let mut lines = vec![];
+        search_lines(&matcher, gen_content(), options, |line| {
+            lines.push(match line {
+                Line::Match(m) => format!("{}:{}", m.line_number, m.line),
+                Line::Context { line_number, line, .. } => format!("{}-{}", line_number, line),
+                Line::Break => "--".to_string(),
+            });
+            Ok(())
+        }).unwrap();
+        lines
+    }
+
+    #[test]
+    fn match_positions() {
+        let matcher = matcher::Matcher::new("us", MatchMode::Literal, true).unwrap();
+        assert_eq!(
+            vec![
+                Match {
+                    line_number : 3,
+                    offset : 46,
+                    line : "Then there's a pair of us - don't tell!",
+                    spans : vec![(23, 25)],
+                },
+                Match {
+                    line_number : 4,
+                    offset : 86,
+                    line : "They'd banish us, you know.",
+                    spans : vec![(14, 16)],
+                },
+            ],
+            find_matches(&matcher, gen_content(), false)
+        );
+    }
+
+    #[test]
+    fn match_multiple_spans() {
+        let matcher = matcher::Matcher::new("o", MatchMode::Literal, true).unwrap();
+        let matches = find_matches(&matcher, "foo\nbar\nboo", false);
+        assert_eq!(vec![1, 3], matches.iter().map(|m| m.line_number).collect::<Vec<_>>());
+        assert_eq!(vec![(1, 2), (2, 3)], matches[0].spans);
+        assert_eq!(8, matches[1].offset);
+    }
+
+    #[test]
+    fn match_crlf_offsets() {
+        let matcher = matcher::Matcher::new("b", MatchMode::Literal, true).unwrap();
+        let matches = find_matches(&matcher, "a\r\nb\r\n", false);
+        assert_eq!("b", matches[0].line);
+        assert_eq!(3, matches[0].offset);
+    }
+
+    #[test]
+    fn match_insensitive_spans() {
+        // Full case folding: `ß` matches `SS`, and the span covers it in the original line.
+        let matcher = matcher::Matcher::new("STRASSE", MatchMode::Literal, false).unwrap();
+        assert_eq!(vec![(0, 7)], find_matches(&matcher, "Straße", false)[0].spans);
+        let matcher = matcher::Matcher::new("İS", MatchMode::Literal, false).unwrap();
+        let matches = find_matches(&matcher, "xİsx", false);
+        assert_eq!(vec![(1, 4)], matches[0].spans);
+
+        // Matches within the folding of one character are one span.
+        let matcher = matcher::Matcher::new("s", MatchMode::Literal, false).unwrap();
+        assert_eq!(vec![(0, 2), (2, 3)], find_matches(&matcher, "ßs", false)[0].spans);
+        assert_eq!(vec![(0, 2)], matcher.find_iter("ß"));
+        let matcher = matcher::Matcher::with_patterns(&["s", "x"], MatchMode::Literal, false,
+                                                      Boundary::Anywhere).unwrap();
+        assert_eq!(vec![(0, 2), (2, 3), (3, 4)], matcher.find_iter("ßxs"));
+    }
+
+    // Multilingual fixture, in the spirit of the strings of `07-collections`.
+    fn gen_multilingual() -> &'static str {
+        "\
+Здравствуйте, ЗДРАВСТВУЙТЕ!
+नमस्ते दुनिया
+Die Straße ist groß, die STRASSE ist GROSS.
+ΣΊΣΥΦΟΣ και σίσυφος
+Iğdır, İstanbul, ırmak, IRMAK
+Kelvin: 273 \u{212a}"
+    }
+
+    fn insensitive_spans(query : &str) -> Vec<(usize, Vec<&'static str>)> {
+        let contents = gen_multilingual();
+        let matcher = matcher::Matcher::new(query, MatchMode::Literal, false).unwrap();
+        find_matches(&matcher, contents, false).into_iter().map(|m| {
+            let parts = m.spans.iter().map(|&(start, end)| &m.line[start..end]).collect();
+            (m.line_number, parts)
+        }).collect()
+    }
+
+    #[test]
+    fn insensitive_multilingual() {
+        assert_eq!(vec![(1, vec!["Здравствуйте", "ЗДРАВСТВУЙТЕ"])],
+                   insensitive_spans("здравствуйте"));
+        assert_eq!(vec![(2, vec!["नमस्ते"])], insensitive_spans("नमस्ते"));
+        assert_eq!(vec![(3, vec!["Straße", "STRASSE"])], insensitive_spans("strasse"));
+        assert_eq!(vec![(3, vec!["Straße", "STRASSE"])], insensitive_spans("STRAßE"));
+        assert_eq!(vec![(3, vec!["groß", "GROSS"])], insensitive_spans("gross"));
+        assert_eq!(vec![(4, vec!["ΣΊΣΥΦΟΣ", "σίσυφος"])], insensitive_spans("σίσυφοσ"));
+        assert_eq!(vec![(6, vec!["273 \u{212a}"])], insensitive_spans("273 k"));
+    }
+
+    #[test]
+    fn insensitive_turkish_i() {
+        // Default folding: the dotless `ı` only matches itself, `I` is an `i`, and `İ` is an `i`
+        // with a combining dot, whose match covers the whole character.
+        assert_eq!(vec![(5, vec!["ırmak"])], insensitive_spans("ırmak"));
+        assert_eq!(vec![(5, vec!["IRMAK"])], insensitive_spans("irmak"));
+        assert_eq!(vec![(5, vec!["Iğ"])], insensitive_spans("iğ"));
+        assert_eq!(vec![(5, vec!["İstanbul"])], insensitive_spans("i\u{307}stanbul"));
+    }
+
+    #[test]
+    fn insensitive_regex_folding() {
+        let regex = regex::Regex::new_insensitive("stra(ss|ß)e").unwrap();
+        assert_eq!(vec![(4, 11), (27, 34)],
+                   regex.find_iter("Die Straße ist groß, die STRASSE ist GROSS."));
+        let regex = regex::Regex::new_insensitive("σίσυφοσ").unwrap();
+        assert!(regex.is_match("ΣΊΣΥΦΟΣ και"));
+        let regex = regex::Regex::new_insensitive("[a-z]+ı").unwrap();
+        assert_eq!(Some((0, 5)), regex.find("RMAı"));
+        assert!(!regex::Regex::new_insensitive("ı").unwrap().is_match("I"));
+    }
+
+    fn describe(m : &Match) -> String {
+        format!("{}:{}:{}:{:?}", m.line_number, m.offset, m.line, m.spans)
+    }
+
+    // Selected lines found by streaming the text line by line.
+    fn streamed_matches(matcher : &matcher::Matcher, contents : &str, max_count : Option<usize>)
+                        -> Vec<String> {
+        let options = LineOptions { max_count, ..LineOptions::default() };
+        let mut matches = vec![];
+        search_each_line(matcher, contents.as_bytes(), options, |line| {
+            if let Line::Match(m) = line {
+                matches.push(describe(&m));
+            }
+            Ok(())
+        }).unwrap();
+        matches
+    }
+
+    #[test]
+    fn whole_buffer_literal_agrees_with_streaming() {
+        let texts = [
+            gen_content(),
+            "body\r\nno\r\nbodybody body\r\n\r\nsomebody",
+            "\n\nbody\n\n",
+            "",
+        ];
+        for &text in &texts {
+            for &query in &["body", "o", "y\n", "\r", ""] {
+                let matcher = matcher::Matcher::new(query, MatchMode::Literal, true).unwrap();
+                for &max_count in &[None, Some(0), Some(2)] {
+                    let options = LineOptions { max_count, ..LineOptions::default() };
+                    let mut whole = vec![];
+                    search_lines(&matcher, text, options, |line| {
+                        if let Line::Match(m) = line {
+                            whole.push(describe(&m));
+                        }
+                        Ok(())
+                    }).unwrap();
+                    assert_eq!(streamed_matches(&matcher, text, max_count), whole,
+                               "query {:?} in {:?}", query, text);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn literal_lines_numbers_and_spans() {
+        let finder = literal::Finder::new("ab");
+        let lines : Vec<_> = search::LiteralLines::new(&finder, "x\nabab\n\n\nzab").collect();
+        assert_eq!(2, lines.len());
+        assert_eq!((2, 2, "abab", vec![(0, 2), (2, 4)]),
+                   (lines[0].line_number, lines[0].offset, lines[0].line, lines[0].spans.clone()));
+        assert_eq!((5, 9, "zab", vec![(1, 3)]),
+                   (lines[1].line_number, lines[1].offset, lines[1].line, lines[1].spans.clone()));
+    }
+
+    #[test]
+    fn invert_has_no_spans() {
+        let matcher = matcher::Matcher::new("o", MatchMode::Literal, true).unwrap();
+        let matches = find_matches(&matcher, gen_content(), true);
+        assert_eq!(vec![5], matches.iter().map(|m| m.line_number).collect::<Vec<_>>());
+        assert!(matches[0].spans.is_empty());
+    }
+
+    #[test]
+    fn context_after() {
+        assert_eq!(
+            vec!["4:They'd banish us, you know.", "5-", "6-How dreary to be somebody!"],
+            context_search("banish", false, 0, 2)
+        );
+    }
+
+    #[test]
+    fn context_before() {
+        assert_eq!(
+            vec!["5-", "6-How dreary to be somebody!", "7:How public, like a frog."],
+            context_search("frog", false, 2, 0)
+        );
+    }
+
+    #[test]
+    fn context_separators() {
+        assert_eq!(
+            vec![
+                "1:I'm nobody! Who are you?",
+                "2-Are you nobody, too?",
+                "--",
+                "6-How dreary to be somebody!",
+                "7:How public, like a frog.",
+                "8-To tell your name the livelong day.",
+            ],
+            context_search_regex("frog|I'm", 1, 1)
+        );
+    }
+
+    #[test]
+    fn no_separators_without_context() {
+        assert_eq!(
+            vec!["1:I'm nobody! Who are you?", "7:How public, like a frog."],
+            context_search_regex("frog|I'm", 0, 0)
+        );
+    }
+
+    #[test]
+    fn context_windows_merge() {
+        // Windows of lines 3 and 4 overlap, and the one of line 7 overlaps them too.
+        assert_eq!(
+            vec![
+                "2-Are you nobody, too?",
+                "3:Then there's a pair of us - don't tell!",
+                "4:They'd banish us, you know.",
+                "5-",
+                "6-How dreary to be somebody!",
+                "7:How public, like a frog.",
+                "8-To tell your name the livelong day.",
+                "9-To an admiring bog!",
+            ],
+            context_search_regex("us|frog", 1, 2)
+        );
+    }
+
+    #[test]
+    fn context_with_invert() {
+        assert_eq!(
+            vec!["4-They'd banish us, you know.", "5:", "6-How dreary to be somebody!"],
+            context_search("o", true, 1, 1)
+        );
+    }
+
+    // Lazily generated input of `total` lines, `line 1` to `line N`, never held in memory.
+    struct Generated {
+        next : usize,
+        total : usize,
+        chunk : Vec<u8>,
+    }
+
+    impl std::io::Read for Generated {
+        fn read(&mut self, buf : &mut [u8]) -> std::io::Result<usize> {
+            if self.chunk.is_empty() && self.next < self.total {
+                self.next += 1;
+                self.chunk = format!("line {}\n", self.next).into_bytes();
+            }
+            let len = buf.len().min(self.chunk.len());
+            buf[..len].copy_from_slice(&self.chunk[..len]);
+            self.chunk.drain(..len);
+            Ok(len)
+        }
+    }
+
+    #[test]
+    fn stream_large_input() {
+        let input = Generated { next : 0, total : 50_000, chunk : vec![] };
+        let matcher = matcher::Matcher::new("^line 1999\\d$", MatchMode::Regex, true).unwrap();
+        let options = LineOptions { before : 1, after : 1, ..LineOptions::default() };
+
+        let mut lines = vec![];
+        search_reader(&matcher, std::io::BufReader::new(input), options, |line| {
+            if let Line::Match(m) = line {
+                lines.push((m.line_number, m.line.to_string()));
+            }
+            Ok(())
+        }).unwrap();
+        assert_eq!(10, lines.len());
+        assert_eq!((19990, "line 19990".to_string()), lines[0]);
+    }
+
+    #[test]
+    fn stream_small_buffer() {
+        // A tiny buffer forces lines to span several reads.
+        let reader = std::io::BufReader::with_capacity(3, gen_content().as_bytes());
+        let matcher = matcher::Matcher::new("frog", MatchMode::Literal, true).unwrap();
+        let options = LineOptions { before : 2, after : 1, ..LineOptions::default() };
+
+        let mut lines = vec![];
+        search_reader(&matcher, reader, options, |line| {
+            lines.push(match line {
+                Line::Match(m) => format!("{}@{}:{}", m.line_number, m.offset, m.line),
+                Line::Context { line_number, offset, line } => {
+                    format!("{}@{}-{}", line_number, offset, line)
+                },
+                Line::Break => "--".to_string(),
+            });
+            Ok(())
+        }).unwrap();
+        assert_eq!(
+            vec![
+                "5@114-",
+                "6@115-How dreary to be somebody!",
+                "7@142:How public, like a frog.",
+                "8@167-To tell your name the livelong day.",
+            ],
+            lines
+        );
+    }
+
+    #[test]
+    fn stream_invalid_utf8() {
+        // Invalid sequences are replaced, and the lines still searched.
+        let matcher = matcher::Matcher::new("b", MatchMode::Literal, true).unwrap();
+        let mut found = vec![];
+        search_reader(&matcher, &b"ok\n\xff\xfea\xe9b\nb\n"[..], LineOptions::default(), |line| {
+            if let Line::Match(m) = line {
+                found.push((m.offset, m.line.to_string(), m.spans));
+            }
+            Ok(())
+        }).unwrap();
+        assert_eq!(
+            vec![(3, "\u{fffd}\u{fffd}a\u{fffd}b".to_string(), vec![(10, 11)]), (9, "b".to_string(), vec![(0, 1)])],
+            found
+        );
+    }
+
+    #[test]
+    fn stream_emit_error_stops() {
+        let matcher = matcher::Matcher::new("o", MatchMode::Literal, true).unwrap();
+        let mut calls = 0;
+        let result = search_lines(&matcher, gen_content(), LineOptions::default(), |_| {
+            calls += 1;
+            Err(std::io::Error::new(std::io::ErrorKind::BrokenPipe, "closed"))
+        });
+        assert!(result.is_err());
+        assert_eq!(1, calls);
+    }
+
+    // Run the command line behavior over the poem, as if it were read from a file.
+    fn run_poem(args : &[&str]) -> String {
+        let mut argv = vec!["minigrep".to_string()];
+        argv.extend(args.iter().map(|arg| arg.to_string()));
+        let config = Config::new(&argv).unwrap();
+
+        let mut out = vec![];
+        run_reader(&config, "poem.txt", gen_content().as_bytes(), &mut out).unwrap();
+        String::from_utf8(out).unwrap()
+    }
+
+    #[test]
+    fn output_lines() {
+        assert_eq!("They'd banish us, you know.\n", run_poem(&["banis"]));
+    }
+
+    #[test]
+    fn output_invert() {
+        assert_eq!("5:\n", run_poem(&["-vn", "o"]));
+        assert_eq!(
+            "Then there's a pair of us - don't tell!\nThey'd banish us, you know.\n",
+            run_poem(&["-v", "-E", "body|^$|How|To"])
+        );
+    }
+
+    #[test]
+    fn output_count() {
+        assert_eq!("3\n", run_poem(&["-c", "body"]));
+        assert_eq!("6\n", run_poem(&["-vc", "body"]));
+        assert_eq!("0\n", run_poem(&["-c", "notsuchphrase"]));
+        assert_eq!("2\n", run_poem(&["-c", "-m2", "body"]));
+    }
+
+    #[test]
+    fn output_files_with_matches() {
+        assert_eq!("poem.txt\n", run_poem(&["-l", "frog"]));
+        assert_eq!("", run_poem(&["-l", "notsuchphrase"]));
+        assert_eq!("", run_poem(&["-L", "frog"]));
+        assert_eq!("poem.txt\n", run_poem(&["-L", "notsuchphrase"]));
+    }
+
+    #[test]
+    fn output_only_matching() {
+        assert_eq!("body\nbody\nbody\n", run_poem(&["-o", "body"]));
+        assert_eq!("6:How\n6:some\n7:How\n", run_poem(&["-onE", "^How|some"]));
+
+        // Byte offsets are the ones of the matched parts.
+        assert_eq!("161:frog\n", run_poem(&["-ob", "frog"]));
+
+        // Inverted lines have no matched parts to show.
+        assert_eq!("", run_poem(&["-ov", "o"]));
+    }
+
+    #[test]
+    fn output_max_count() {
+        assert_eq!("I'm nobody! Who are you?\nAre you nobody, too?\n", run_poem(&["-m", "2", "body"]));
+        assert_eq!("", run_poem(&["-m0", "body"]));
+
+        // Trailing context after the last selected line is still printed.
+        assert_eq!(
+            "I'm nobody! Who are you?\nAre you nobody, too?\n",
+            run_poem(&["-m1", "-A1", "body"])
+        );
+    }
+
+    #[test]
+    fn output_color() {
+        // Matched parts are highlighted, line numbers and separators colored.
+        assert_eq!(
+            "\x1b[32m4\x1b[0m\x1b[36m:\x1b[0mThey'd \x1b[1;31mbanish\x1b[0m us, you know.\n",
+            run_poem(&["--color=always", "-n", "banish"])
+        );
+        assert_eq!(
+            "Then there's a p\x1b[1;31mai\x1b[0mr of us - don't tell!\n",
+            run_poem(&["--color=always", "-i", "AI"])
+        );
+
+        // Context lines are not highlighted, breaks are colored.
+        assert_eq!(
+            "\x1b[1;31mAre\x1b[0m you nobody, too?\nThen there's a pair of us - don't tell!\n\
+             \x1b[36m--\x1b[0m\n\
+             How public, like a \x1b[1;31mfrog\x1b[0m.\nTo tell your name the livelong day.\n",
+            run_poem(&["--color=always", "-A1", "-E", "^Are|frog"])
+        );
+        assert_eq!("\x1b[1;31mfrog\x1b[0m\n", run_poem(&["--color=always", "-o", "frog"]));
+
+        // Nothing to highlight in inverted lines.
+        assert_eq!("\n", run_poem(&["--color=always", "-v", "o"]));
+
+        // Never colored unless asked to when the output is not a terminal.
+        assert_eq!("They'd banish us, you know.\n", run_poem(&["banish"]));
+        assert_eq!("They'd banish us, you know.\n", run_poem(&["--color=auto", "banish"]));
+    }
+
+    #[test]
+    fn output_multiple_patterns() {
+        assert_eq!(
+            "How public, like a frog.\nTo an admiring bog!\n",
+            run_poem(&["-e", "frog", "-e", "bog"])
+        );
+        // Overlapping patterns are matched in one pass, reporting the longest at each start.
+        assert_eq!(
+            "body!\nsomebody\n",
+            run_poem(&["-o", "-e", "some", "-e", "somebody", "-e", "body!"])
+        );
+        assert_eq!(
+            "I'm\nbody\nbody\nsomebody\n",
+            run_poem(&["-oi", "-e", "BODY", "-e", "i'm", "-e", "SOMEBODY"])
+        );
+        assert_eq!(
+            "Then there's a pair of us - don't tell!\nTo tell your name the livelong day.\n",
+            run_poem(&["-E", "-e", "^T.*l!$", "-e", "l{2} y"])
+        );
+    }
+
+    #[test]
+    fn output_whole_words() {
+        assert_eq!("", run_poem(&["-w", "body"]));
+        assert_eq!(
+            "Then there's a pair of us - don't tell!\nTo tell your name the livelong day.\n",
+            run_poem(&["-w", "tell"])
+        );
+        assert_eq!(
+            "1:nobody\n2:Are\n2:nobody\n",
+            run_poem(&["-wno", "-e", "body", "-e", "nobody", "-e", "Are"])
+        );
+        assert_eq!("How\nHow\n", run_poem(&["-wio", "-e", "ho", "-e", "how"]));
+
+        // A match failing the word test does not hide a later one.
+        assert_eq!("us\nus\n", run_poem(&["-wo", "us"]));
+        assert_eq!("us\nus\n", run_poem(&["-woE", "u[a-z]"]));
+        assert_eq!("a\na\n", run_poem(&["-woE", "a"]));
+    }
+
+    #[test]
+    fn output_whole_lines() {
+        assert_eq!("To an admiring bog!\n", run_poem(&["-x", "To an admiring bog!"]));
+        assert_eq!("", run_poem(&["-x", "To an admiring bog"]));
+        assert_eq!("5:\n", run_poem(&["-nx", "-e", ""]));
+        assert_eq!(
+            "Are you nobody, too?\nTo an admiring bog!\n",
+            run_poem(&["-xi", "-e", "to an ADMIRING bog!", "-e", "are you nobody, too?"])
+        );
+        assert_eq!("How dreary to be somebody!\n", run_poem(&["-xE", "How.*y!"]));
+    }
+
+    #[test]
+    fn output_replace() {
+        assert_eq!(
+            "I'm NOBODY! Who are you?\nAre you NOBODY, too?\n",
+            run_poem(&["--replace=NOBODY", "nobody"])
+        );
+        assert_eq!("4:They'd BANISH us, you know.\n", run_poem(&["-n", "--replace", "BANISH", "banish"]));
+
+        // Group references in regex mode, kept as is in literal mode.
+        assert_eq!(
+            "How frog, like a public.\n",
+            run_poem(&["-E", "--replace=$2, like a $1", r"(\w+), like a (\w+)"])
+        );
+        assert_eq!("To an admiring $0\n", run_poem(&["--replace=$0", "bog!"]));
+        assert_eq!("To an admiring [bog]!\n", run_poem(&["-E", "--replace=[${0}]", "bog"]));
+
+        // Only the selected lines are replaced, and only the replacements are highlighted.
+        assert_eq!(
+            "To tell your name the livelong day.\nTo an admiring toad!\n",
+            run_poem(&["-B1", "--replace=toad", "bog"])
+        );
+        assert_eq!(
+            "To an admiring \x1b[1;31mfrog\x1b[0m!\n",
+            run_poem(&["--color=always", "--replace=frog", "bog"])
+        );
+        assert_eq!("$\n$\n", run_poem(&["-oE", "--replace=$$", "To"]));
+
+        let argv : Vec<String> = ["minigrep", "-E", "--replace=$2", "(b)og"].iter()
+                                                                          .map(|arg| arg.to_string())
+                                                                          .collect();
+        let config = Config::new(&argv).unwrap();
+        let err = run_reader(&config, "poem.txt", gen_content().as_bytes(), &mut vec![]);
+        assert_eq!("Invalid replacement: the pattern has no group 2.", err.unwrap_err().to_string());
+    }
+
+    #[test]
+    fn fuzzy_search() {
+        let lines : Vec<_> = search_fuzzy("ady", gen_content(), None)
+            .into_iter()
+            .map(|m| (m.line_number, m.spans))
+            .collect();
+        assert_eq!(vec![(2, vec![(0, 1), (12, 14)]), (6, vec![(7, 8), (23, 25)]),
+                        (8, vec![(14, 15), (31, 32), (33, 34)])], lines);
+
+        // Lines rank by their best alignment (`don't tell`, then `To tell`), and at most `top`
+        // of them are kept.
+        let lines : Vec<_> = search_fuzzy("tt", gen_content(), Some(2)).into_iter()
+                                                                       .map(|m| m.line)
+                                                                       .collect();
+        assert_eq!(vec!["Then there's a pair of us - don't tell!",
+                        "To tell your name the livelong day."], lines);
+        assert!(search_fuzzy("zz", gen_content(), None).is_empty());
+    }
+
+    #[test]
+    fn output_fuzzy() {
+        assert_eq!(
+            "9:To an admiring bog!\n7:How public, like a frog.\n",
+            run_poem(&["--fuzzy", "-n", "bog"])
+        );
+        assert_eq!("9:To an admiring bog!\n", run_poem(&["--fuzzy", "-n", "-m1", "bog"]));
+        assert_eq!("2\n", run_poem(&["--fuzzy", "-c", "bog"]));
+
+        // Smart case, unless told otherwise.
+        assert_eq!("", run_poem(&["--fuzzy", "BOG"]));
+        assert_eq!("To an admiring bog!\n", run_poem(&["--fuzzy", "-i", "-m1", "BOG"]));
+
+        // The characters matched are highlighted.
+        assert_eq!(
+            "How dreary to \x1b[1;31mbe\x1b[0m s\x1b[1;31mo\x1b[0mmebody!\n",
+            run_poem(&["--fuzzy", "--color=always", "-m1", "beo"])
+        );
+    }
+
+    #[test]
+    fn no_patterns_match_nothing() {
+        let matcher = matcher::Matcher::with_patterns(&[] as &[&str], MatchMode::Literal, true,
+                                                      Boundary::Anywhere).unwrap();
+        assert!(find_matches(&matcher, gen_content(), false).is_empty());
+        let matcher = matcher::Matcher::with_patterns(&[] as &[&str], MatchMode::Regex, true,
+                                                      Boundary::Anywhere).unwrap();
+        assert!(find_matches(&matcher, gen_content(), false).is_empty());
+        assert_eq!(9, find_matches(&matcher, gen_content(), true).len());
+    }
 }
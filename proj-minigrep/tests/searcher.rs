@@ -0,0 +1,199 @@
+mod common;
+
+#[cfg(test)]
+mod tests {
+
+    use std::fs;
+    use std::io;
+    use std::path::{Path, PathBuf};
+    use std::sync::Arc;
+    use std::thread;
+
+    use minigrep::*;
+    use crate::common::*;
+
+    // Sink keeping everything it is told, as text.
+    #[derive(Default)]
+    struct Events(Vec<String>);
+
+    impl Sink for Events {
+
+        fn line(&mut self, path : &Path, line : Line) -> io::Result<()> {
+            let event = match line {
+                Line::Match(m) => {
+                    format!("{}:{}:{}:{:?}", path.display(), m.line_number, m.line, m.spans)
+                },
+                Line::Context { line_number, line, .. } => {
+                    format!("{}-{}-{}", path.display(), line_number, line)
+                },
+                Line::Break => "--".to_string(),
+            };
+            self.0.push(event);
+            Ok(())
+        }
+
+        fn binary_match(&mut self, path : &Path) -> io::Result<()> {
+            self.0.push(format!("binary {}", path.display()));
+            Ok(())
+        }
+
+        fn finish(&mut self, path : &Path, stats : &Stats) -> io::Result<()> {
+            self.0.push(format!("end {} {}", path.display(), stats.matched_lines));
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn reused_searcher() {
+        let searcher = SearchOptions::new().pattern("frog")
+                                           .pattern("toad")
+                                           .ignore_case(true)
+                                           .context(1, 0)
+                                           .build()
+                                           .unwrap();
+        let mut events = Events::default();
+        let stats = searcher.search_reader(Path::new("a"), &b"pond\nFrog\n"[..], &mut events)
+                            .unwrap();
+        assert_eq!(1, stats.matched_lines);
+        searcher.search_reader(Path::new("b"), &b"none\n"[..], &mut events).unwrap();
+        searcher.search_reader(Path::new("c"), &b"a\nb\ntoad\nc\nd\nfrog\0\n"[..], &mut events)
+                .unwrap();
+        assert_eq!(vec![
+            "a-1-pond", "a:2:Frog:[(0, 4)]", "end a 1",
+            "end b 0",
+            "binary c", "end c 1",
+        ], events.0);
+    }
+
+    #[test]
+    fn closure_sink() {
+        let mut options = SearchOptions::new();
+        options.pattern(r"(\w+)@").mode(MatchMode::Regex).binary_files(BinaryFiles::Text);
+        let searcher = options.build().unwrap();
+
+        let mut found = vec![];
+        let text = "mail joe@home\nnothing\n\0 ann@work\n";
+        searcher.search_reader(Path::new("text"), text.as_bytes(), &mut |_ : &Path, line : Line| {
+            if let Line::Match(m) = line {
+                found.push((m.line_number, m.line.to_string()));
+            }
+            Ok(())
+        }).unwrap();
+        assert_eq!(vec![(1, "mail joe@home".to_string()), (3, "\0 ann@work".to_string())], found);
+
+        // Built again with other options.
+        let searcher = options.invert(true).build().unwrap();
+        let mut count = 0;
+        searcher.search_reader(Path::new("text"), text.as_bytes(), &mut |_ : &Path, _ : Line| {
+            count += 1;
+            Ok(())
+        }).unwrap();
+        assert_eq!(1, count);
+    }
+
+    #[test]
+    fn paths_and_threads() {
+        let root = make_tree("searcher-paths", &[
+            ("a.txt", b"one\ntwo\n"),
+            ("b.txt", b"two\ntwo\n"),
+        ]);
+        let searcher = Arc::new(SearchOptions::new().pattern("two").build().unwrap());
+
+        let handles : Vec<_> = ["a.txt", "b.txt"].iter().map(|name| {
+            let searcher = Arc::clone(&searcher);
+            let path = root.join(name);
+            thread::spawn(move || {
+                let mut events = Events::default();
+                searcher.search_path(&path, &mut events).unwrap();
+                events.0.len()
+            })
+        }).collect();
+        let counts : Vec<_> = handles.into_iter().map(|handle| handle.join().unwrap()).collect();
+        assert_eq!(vec![2, 3], counts);
+
+        // Errors name the input.
+        let missing = root.join("missing.txt");
+        match searcher.search_path(&missing, &mut Events::default()) {
+            Err(MinigrepError::Io { path : Some(path), .. }) => assert_eq!(missing, path),
+            _ => panic!("missing file not reported"),
+        }
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn errors() {
+        match SearchOptions::new().pattern("ok").pattern("[z-a]").mode(MatchMode::Regex).build() {
+            Err(MinigrepError::Pattern { pattern, err }) => {
+                assert_eq!("[z-a]", pattern);
+                assert_eq!(1, err.pos);
+            },
+            _ => panic!("bad pattern accepted"),
+        }
+
+        // The sink stops the search.
+        let searcher = SearchOptions::new().pattern("x").build().unwrap();
+        let mut seen = 0;
+        let mut sink = |_ : &Path, _ : Line| {
+            seen += 1;
+            Err(io::Error::other("enough"))
+        };
+        let result = searcher.search_reader(Path::new("x"), &b"x\nx\nx\n"[..], &mut sink);
+        assert_eq!(1, seen);
+        match result {
+            Err(MinigrepError::Io { path : Some(path), .. }) => assert_eq!(PathBuf::from("x"), path),
+            _ => panic!("error of the sink lost"),
+        }
+    }
+
+    #[test]
+    fn from_config() {
+        let args : Vec<String> = ["minigrep", "-i", "-w", "-m1", "FROG", "file"]
+            .iter()
+            .map(|arg| arg.to_string())
+            .collect();
+        let searcher = Config::new(&args).unwrap().search_options().build().unwrap();
+        let mut events = Events::default();
+        searcher.search_reader(Path::new("f"), &b"frogs\na frog\nFrog\n"[..], &mut events).unwrap();
+        assert_eq!(vec!["f:2:a frog:[(2, 6)]", "end f 1"], events.0);
+    }
+
+    #[test]
+    fn literal_in_chunks() {
+        // Lines over several of the chunks a literal is searched in, some of them invalid or
+        // ending with CRLF, and a last one without a terminator.
+        let mut text = vec![];
+        for i in 0..20_000 {
+            match i % 7 {
+                0 => text.extend(b"frog \xff frog\n"),
+                1 => text.extend(format!("{} frog\r\n", i).as_bytes()),
+                3 => text.extend(format!("{} frogs and toads\n", i).as_bytes()),
+                _ => text.extend(format!("{} nothing much\n", i).as_bytes()),
+            }
+        }
+        text.extend(b"last frog");
+
+        let search = |options : &mut SearchOptions| {
+            let mut found = vec![];
+            let stats = options.build().unwrap().search_reader(Path::new("t"), &text[..],
+                                                              &mut |_ : &Path, line : Line| {
+                if let Line::Match(m) = line {
+                    found.push(format!("{}:{}:{}:{:?}", m.line_number, m.offset, m.line, m.spans));
+                }
+                Ok(())
+            }).unwrap();
+            (found, stats)
+        };
+        // The same as the regex, which is matched line by line.
+        for &max_count in &[None, Some(0), Some(3000)] {
+            let (literal, stats) = search(SearchOptions::new().pattern("frog")
+                                                              .max_count(max_count));
+            let regex = search(SearchOptions::new().pattern("frog").mode(MatchMode::Regex)
+                                                   .max_count(max_count));
+            assert_eq!(regex, (literal.clone(), stats));
+            assert_eq!(max_count.unwrap_or(8573), stats.matched_lines);
+        }
+        let (found, _) = search(SearchOptions::new().pattern("frog"));
+        assert_eq!("1:0:frog \u{fffd} frog:[(0, 4), (9, 13)]", found[0]);
+        assert_eq!("20001:339045:last frog:[(5, 9)]", found[found.len() - 1]);
+    }
+}
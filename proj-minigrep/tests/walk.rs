@@ -0,0 +1,81 @@
+mod common;
+
+#[cfg(test)]
+mod tests {
+
+    use std::env;
+    use std::fs;
+    use std::path::Path;
+    use std::process;
+
+    use minigrep::walk::*;
+    use crate::common::*;
+
+    fn walk(root : &Path) -> Vec<String> {
+        Walk::new(root).map(|path| relative(root, &path.unwrap())).collect()
+    }
+
+    #[test]
+    fn sorted_depth_first() {
+        let root = make_tree("sorted", &[
+            ("b.txt", b"b"),
+            ("a/z.txt", b"z"),
+            ("a/y/x.txt", b"x"),
+            ("c/", b""),
+        ]);
+        assert_eq!(vec!["a/y/x.txt", "a/z.txt", "b.txt"], walk(&root));
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn honors_ignore_files() {
+        let root = make_tree("ignores", &[
+            (".gitignore", b"*.log\ntarget/\n!important.log\n"),
+            ("app.log", b"noise"),
+            ("important.log", b"keep"),
+            ("main.rs", b"fn main() {}"),
+            ("target/debug.rs", b"built"),
+            ("sub/.ignore", b"/local.txt\n"),
+            ("sub/local.txt", b"ignored"),
+            ("sub/deeper/local.txt", b"kept"),
+            ("sub/trace.log", b"noise"),
+            (".git/config", b"[core]"),
+        ]);
+        assert_eq!(
+            vec![".gitignore", "important.log", "main.rs", "sub/.ignore", "sub/deeper/local.txt"],
+            walk(&root)
+        );
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn single_file_root() {
+        let root = make_tree("single", &[("only.txt", b"x")]);
+        let file = root.join("only.txt");
+        assert_eq!(vec![file.clone()], Walk::new(&file).map(Result::unwrap).collect::<Vec<_>>());
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn special_files_skipped() {
+        let root = make_tree("special", &[("a.txt", b"a"), ("sub/b.txt", b"b")]);
+        let status = process::Command::new("mkfifo").arg(root.join("sub/fifo")).status();
+        assert!(status.unwrap().success());
+        assert_eq!(vec!["a.txt", "sub/b.txt"], walk(&root));
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn missing_root_yielded_as_is() {
+        let root = env::temp_dir().join(format!("minigrep-walk-{}-missing", process::id()));
+        // A missing root is not a directory, so it is yielded as is for the caller to open.
+        assert_eq!(vec![root.clone()], Walk::new(&root).map(Result::unwrap).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn binary_detection() {
+        assert!(is_binary(b"ELF\0\x01\x02"));
+        assert!(!is_binary("plain text, ünïcödé".as_bytes()));
+    }
+}
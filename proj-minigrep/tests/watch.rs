@@ -0,0 +1,160 @@
+mod common;
+
+#[cfg(test)]
+mod tests {
+
+    use std::fs::{self, OpenOptions};
+    use std::io::{self, Write};
+    use std::path::Path;
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use std::sync::{Arc, Mutex};
+    use std::thread::{self, JoinHandle};
+    use std::time::{Duration, Instant};
+
+    use minigrep::*;
+    use crate::common::*;
+
+    // Output shared with the test while the search runs.
+    #[derive(Clone, Default)]
+    struct Shared(Arc<Mutex<Vec<u8>>>);
+
+    impl Shared {
+        fn text(&self) -> String {
+            String::from_utf8(self.0.lock().unwrap().clone()).unwrap()
+        }
+
+        // Wait for the output to be `expected`, failing after a while.
+        fn wait_for(&self, expected : &str) {
+            let start = Instant::now();
+            while self.text() != expected {
+                if start.elapsed() > Duration::from_secs(10) {
+                    assert_eq!(expected, self.text());
+                }
+                thread::sleep(Duration::from_millis(10));
+            }
+        }
+    }
+
+    impl Write for Shared {
+        fn write(&mut self, buf : &[u8]) -> io::Result<usize> {
+            self.0.lock().unwrap().write(buf)
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    // Run in the background until `stop` is set.
+    fn spawn(args : &[&str], stop : &Arc<AtomicBool>) -> (Shared, JoinHandle<Report>) {
+        let mut argv = vec!["minigrep".to_string()];
+        argv.extend(args.iter().map(|arg| arg.to_string()));
+        let shared = Shared::default();
+        let mut out = shared.clone();
+        let stop = Arc::clone(stop);
+        let handle = thread::spawn(move || {
+            let config = Config::new(&argv).unwrap();
+            run_until(&config, &mut out, &stop).unwrap()
+        });
+        (shared, handle)
+    }
+
+    fn append(path : &Path, text : &str) {
+        OpenOptions::new().append(true).open(path).unwrap().write_all(text.as_bytes()).unwrap();
+    }
+
+    #[test]
+    fn follow_appended_lines() {
+        let root = make_tree("follow", &[("app.log", b"frog 1\ntoad\n")]);
+        let log = root.join("app.log");
+        let stop = Arc::new(AtomicBool::new(false));
+        let (out, handle) = spawn(&["-n", "--follow", "frog", &log.display().to_string()], &stop);
+        out.wait_for("1:frog 1\n");
+
+        // Lines are searched once complete.
+        append(&log, "frog 2\nhalf a fr");
+        out.wait_for("1:frog 1\n3:frog 2\n");
+        append(&log, "og\n");
+        out.wait_for("1:frog 1\n3:frog 2\n4:half a frog\n");
+
+        // Truncated, then rotated: read again from the start.
+        fs::write(&log, "").unwrap();
+        thread::sleep(Duration::from_millis(600));
+        append(&log, "frog 3\n");
+        out.wait_for("1:frog 1\n3:frog 2\n4:half a frog\n1:frog 3\n");
+        fs::rename(&log, root.join("app.log.1")).unwrap();
+        fs::write(&log, "x\nfrog 4\n").unwrap();
+        out.wait_for("1:frog 1\n3:frog 2\n4:half a frog\n1:frog 3\n2:frog 4\n");
+
+        stop.store(true, Ordering::SeqCst);
+        let report = handle.join().unwrap();
+        assert_eq!(5, report.summary.matched_lines);
+        assert_eq!(0, report.exit_code());
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn follow_until_max_count() {
+        let root = make_tree("follow-max", &[("a.log", b"frog\n"), ("b.log", b"")]);
+        let (a, b) = (root.join("a.log"), root.join("b.log"));
+        let stop = Arc::new(AtomicBool::new(false));
+        let (out, handle) = spawn(&["--follow", "-m1", "frog", &a.display().to_string(),
+                                    &b.display().to_string()], &stop);
+        out.wait_for(&format!("{}:frog\n", a.display()));
+        append(&a, "frog again\n");
+        append(&b, "no\nfrog\n");
+
+        // Done once each file has its line, without being stopped.
+        let report = handle.join().unwrap();
+        assert_eq!(format!("{}:frog\n{}:frog\n", a.display(), b.display()), out.text());
+        assert_eq!(2, report.summary.matched_lines);
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn watch_searches_again() {
+        let root = make_tree("watch", &[("a.txt", b"frog\n"), ("sub/b.txt", b"toad\n")]);
+        let dir = root.display().to_string();
+        let (a, b, c) = (root.join("a.txt"), root.join("sub/b.txt"), root.join("sub/c.txt"));
+        let stop = Arc::new(AtomicBool::new(false));
+        let (out, handle) = spawn(&["-c", "-r", "--watch", "frog", &dir], &stop);
+        let first = format!("{}:1\n{}:0\n", a.display(), b.display());
+        out.wait_for(&first);
+
+        // Changed, then added.
+        append(&b, "frog\n");
+        let second = format!("{}:1\n{}:1\n", a.display(), b.display());
+        out.wait_for(&format!("{}{}", first, second));
+        fs::write(&c, "frog\nfrog\n").unwrap();
+        let third = format!("{}:1\n{}:1\n{}:2\n", a.display(), b.display(), c.display());
+        out.wait_for(&format!("{}{}{}", first, second, third));
+
+        // Nothing changes, nothing is searched.
+        thread::sleep(Duration::from_millis(600));
+        assert_eq!(format!("{}{}{}", first, second, third), out.text());
+
+        stop.store(true, Ordering::SeqCst);
+        let report = handle.join().unwrap();
+        assert_eq!(4, report.summary.matched_lines);
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn options() {
+        let parse = |args : &[&str]| {
+            let mut argv = vec!["minigrep".to_string()];
+            argv.extend(args.iter().map(|arg| arg.to_string()));
+            Config::new(&argv).map(|config| (config.follow(), config.watch()))
+                              .map_err(|err| err.to_string())
+        };
+        assert_eq!(Ok((true, false)), parse(&["--follow", "x", "file"]));
+        assert_eq!(Ok((false, true)), parse(&["--watch", "-r", "x"]));
+        assert!(parse(&["--follow", "x"]).is_err());
+        assert!(parse(&["--follow", "-r", "x", "dir"]).is_err());
+        assert!(parse(&["--follow", "-c", "x", "file"]).is_err());
+        assert!(parse(&["--follow", "-C1", "x", "file"]).is_err());
+        assert!(parse(&["--follow", "--watch", "x", "file"]).is_err());
+        assert!(parse(&["--watch", "x"]).is_err());
+        assert!(parse(&["--watch", "--replace=y", "--in-place", "x", "file"]).is_err());
+    }
+}